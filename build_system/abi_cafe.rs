@@ -38,6 +38,8 @@ pub(crate) fn run(
 
     eprintln!("Running abi-cafe");
 
+    // FIXME add soft-float pairs once cg_clif supports soft-float ABIs. This also needs abi-cafe to
+    // build the C side with the matching float ABI.
     let pairs = ["rustc_calls_cgclif", "cgclif_calls_rustc", "cgclif_calls_cc", "cc_calls_cgclif"];
 
     let mut cmd = ABI_CAFE.run(bootstrap_host_compiler, dirs);
@@ -1,10 +1,13 @@
 use std::env;
+use std::fs;
 use std::io::Write;
 use std::path::Path;
+use std::process;
 
 use crate::path::{Dirs, RelPath};
 use crate::prepare::GitRepo;
 use crate::rustc_info::get_file_name;
+use crate::shared_utils::{rustflags_from_env, rustflags_to_cmd_env};
 use crate::utils::{hyperfine_command, spawn_and_wait, Compiler};
 
 static SIMPLE_RAYTRACER_REPO: GitRepo = GitRepo::github(
@@ -15,11 +18,14 @@
     "<none>",
 );
 
-pub(crate) fn benchmark(dirs: &Dirs, bootstrap_host_compiler: &Compiler) {
-    benchmark_simple_raytracer(dirs, bootstrap_host_compiler);
-}
-
-fn benchmark_simple_raytracer(dirs: &Dirs, bootstrap_host_compiler: &Compiler) {
+pub(crate) fn benchmark(
+    dirs: &Dirs,
+    bootstrap_host_compiler: &Compiler,
+    program_rustflags: &[String],
+    opt_levels: &[String],
+    baseline: Option<&Path>,
+    regression_threshold: f64,
+) {
     if std::process::Command::new("hyperfine").output().is_err() {
         eprintln!("Hyperfine not installed");
         eprintln!("Hint: Try `cargo install hyperfine` to install hyperfine");
@@ -29,6 +35,234 @@ fn benchmark_simple_raytracer(dirs: &Dirs, bootstrap_host_compiler: &Compiler) {
     SIMPLE_RAYTRACER_REPO.fetch(dirs);
     SIMPLE_RAYTRACER_REPO.patch(dirs);
 
+    let results_path = RelPath::DIST.to_path(dirs).join("bench.json");
+    let results = if opt_levels.is_empty() {
+        let results =
+            benchmark_simple_raytracer(dirs, bootstrap_host_compiler, program_rustflags, None);
+        fs::write(&results_path, write_bench_results(&results)).unwrap();
+        results
+    } else {
+        let results_by_opt_level = opt_levels
+            .iter()
+            .map(|opt_level| {
+                let results = benchmark_simple_raytracer(
+                    dirs,
+                    bootstrap_host_compiler,
+                    program_rustflags,
+                    Some(opt_level),
+                );
+                (format!("opt-level={opt_level}"), results)
+            })
+            .collect::<Vec<_>>();
+        eprintln!("{}", format_opt_level_table(&results_by_opt_level));
+        fs::write(&results_path, write_bench_results_by_opt_level(&results_by_opt_level)).unwrap();
+
+        // Flatten the results the same way as `parse_bench_results` does for the baseline.
+        results_by_opt_level
+            .into_iter()
+            .flat_map(|(opt_level, results)| {
+                results.into_iter().map(move |(name, mean)| (format!("{opt_level}/{name}"), mean))
+            })
+            .collect()
+    };
+    eprintln!("Benchmark results written to {}", results_path.display());
+
+    if let Some(baseline) = baseline {
+        let baseline_results = parse_bench_results(&fs::read_to_string(baseline).unwrap());
+        eprintln!("[BENCH COMPARE] {}", baseline.display());
+        if compare_bench_results(&baseline_results, &results, regression_threshold) {
+            eprintln!("Benchmarks regressed by more than {regression_threshold}%");
+            process::exit(1);
+        }
+    }
+}
+
+/// Compares the mean runtime of each benchmark against the baseline and prints the delta.
+/// Returns true if any benchmark regressed by more than `regression_threshold` percent.
+fn compare_bench_results(
+    baseline: &[(String, f64)],
+    current: &[(String, f64)],
+    regression_threshold: f64,
+) -> bool {
+    let mut regressed = false;
+
+    for (name, mean) in current {
+        let Some((_, baseline_mean)) =
+            baseline.iter().find(|(baseline_name, _)| baseline_name == name)
+        else {
+            eprintln!("{name}: {mean:.3}s (new)");
+            continue;
+        };
+        let delta = (mean - baseline_mean) / baseline_mean * 100.0;
+        if delta > regression_threshold {
+            regressed = true;
+            eprintln!("{name}: {baseline_mean:.3}s -> {mean:.3}s ({delta:+.2}%) REGRESSED");
+        } else {
+            eprintln!("{name}: {baseline_mean:.3}s -> {mean:.3}s ({delta:+.2}%)");
+        }
+    }
+
+    for (name, baseline_mean) in baseline {
+        if !current.iter().any(|(current_name, _)| current_name == name) {
+            eprintln!("{name}: {baseline_mean:.3}s (removed)");
+        }
+    }
+
+    regressed
+}
+
+/// Serializes the benchmark results as a flat JSON object mapping benchmark names to the mean
+/// time in seconds.
+fn write_bench_results(results: &[(String, f64)]) -> String {
+    let mut json = "{\n".to_owned();
+    for (i, (name, mean)) in results.iter().enumerate() {
+        let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+        let separator = if i + 1 == results.len() { "" } else { "," };
+        json.push_str(&format!("  \"{name}\": {mean}{separator}\n"));
+    }
+    json.push_str("}\n");
+    json
+}
+
+/// Like [`write_bench_results`], but with the results of each opt-level nested in a separate
+/// object.
+fn write_bench_results_by_opt_level(
+    results_by_opt_level: &[(String, Vec<(String, f64)>)],
+) -> String {
+    let mut json = "{\n".to_owned();
+    for (i, (opt_level, results)) in results_by_opt_level.iter().enumerate() {
+        json.push_str(&format!("  \"{opt_level}\": {{\n"));
+        for (j, (name, mean)) in results.iter().enumerate() {
+            let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+            let separator = if j + 1 == results.len() { "" } else { "," };
+            json.push_str(&format!("    \"{name}\": {mean}{separator}\n"));
+        }
+        let separator = if i + 1 == results_by_opt_level.len() { "" } else { "," };
+        json.push_str(&format!("  }}{separator}\n"));
+    }
+    json.push_str("}\n");
+    json
+}
+
+/// Formats the benchmark results as a table with a row per benchmark and a column per opt-level.
+fn format_opt_level_table(results_by_opt_level: &[(String, Vec<(String, f64)>)]) -> String {
+    let names = results_by_opt_level[0].1.iter().map(|(name, _)| name).collect::<Vec<_>>();
+    let name_width = names.iter().map(|name| name.len()).max().unwrap_or(0).max("benchmark".len());
+
+    let mut table = format!("{:name_width$}", "benchmark");
+    for (opt_level, _) in results_by_opt_level {
+        table.push_str(&format!("  {opt_level:>9}"));
+    }
+    for name in names {
+        table.push_str(&format!("\n{name:name_width$}"));
+        for (opt_level, results) in results_by_opt_level {
+            let width = opt_level.len().max(9);
+            match results.iter().find(|(result_name, _)| result_name == name) {
+                Some((_, mean)) => table.push_str(&format!("  {:>width$}", format!("{mean:.3}s"))),
+                None => table.push_str(&format!("  {:>width$}", "-")),
+            }
+        }
+    }
+    table
+}
+
+/// Parses the output of [`write_bench_results`] or [`write_bench_results_by_opt_level`]. Nested
+/// results are flattened by joining the names with a `/`.
+fn parse_bench_results(json: &str) -> Vec<(String, f64)> {
+    let mut results = vec![];
+    let rest = parse_bench_object(json.trim(), "", &mut results);
+    if !rest.trim().is_empty() {
+        eprintln!("Unexpected `{}` after the benchmark results in baseline", rest.trim());
+        process::exit(1);
+    }
+    results
+}
+
+/// Parses a single JSON object of benchmark results and returns the remaining input.
+fn parse_bench_object<'a>(
+    json: &'a str,
+    prefix: &str,
+    results: &mut Vec<(String, f64)>,
+) -> &'a str {
+    let Some(mut rest) = json.strip_prefix('{') else {
+        eprintln!("Benchmark baseline is not a JSON object");
+        process::exit(1);
+    };
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix('}') {
+            return after;
+        }
+
+        let Some(string) = rest.strip_prefix('"') else {
+            eprintln!("Expected benchmark name in baseline, found `{rest}`");
+            process::exit(1);
+        };
+
+        let mut name = String::new();
+        let mut chars = string.char_indices();
+        let value = loop {
+            match chars.next() {
+                Some((_, '\\')) => name.extend(chars.next().map(|(_, c)| c)),
+                Some((i, '"')) => break &string[i + 1..],
+                Some((_, c)) => name.push(c),
+                None => {
+                    eprintln!("Unterminated benchmark name in baseline");
+                    process::exit(1);
+                }
+            }
+        };
+
+        let Some(value) = value.trim_start().strip_prefix(':') else {
+            eprintln!("Expected `:` after benchmark name `{name}` in baseline");
+            process::exit(1);
+        };
+        let value = value.trim_start();
+        let name = format!("{prefix}{name}");
+        rest = if value.starts_with('{') {
+            parse_bench_object(value, &format!("{name}/"), results)
+        } else {
+            let end = value.find([',', '}']).unwrap_or(value.len());
+            let Ok(mean) = value[..end].trim().parse() else {
+                eprintln!(
+                    "Invalid time `{}` for benchmark `{name}` in baseline",
+                    value[..end].trim()
+                );
+                process::exit(1);
+            };
+            results.push((name, mean));
+            &value[end..]
+        };
+
+        rest = rest.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+/// Reads the mean times from a csv file exported by hyperfine in the order in which the commands
+/// were passed to hyperfine.
+fn read_hyperfine_csv(csv_export: &Path) -> Vec<f64> {
+    let csv = fs::read_to_string(csv_export).unwrap();
+    let mut lines = csv.lines();
+    let columns = lines.next().unwrap().split(',').count();
+    lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            // The command is the first column and may itself contain commas.
+            let mut fields = line.rsplitn(columns, ',').collect::<Vec<_>>();
+            fields.reverse();
+            fields[1].parse().unwrap()
+        })
+        .collect()
+}
+
+fn benchmark_simple_raytracer(
+    dirs: &Dirs,
+    bootstrap_host_compiler: &Compiler,
+    program_rustflags: &[String],
+    opt_level: Option<&str>,
+) -> Vec<(String, f64)> {
     let bench_runs = env::var("BENCH_RUNS").unwrap_or_else(|_| "10".to_string()).parse().unwrap();
 
     let mut gha_step_summary = if let Ok(file) = std::env::var("GITHUB_STEP_SUMMARY") {
@@ -37,7 +271,16 @@ fn benchmark_simple_raytracer(dirs: &Dirs, bootstrap_host_compiler: &Compiler) {
         None
     };
 
-    eprintln!("[BENCH COMPILE] ebobby/simple-raytracer");
+    // When benchmarking a specific opt-level it is passed to both the debug builds of LLVM and
+    // cg_clif, which makes the release build redundant.
+    let mut rustflags = program_rustflags.to_vec();
+    let mut suffix = String::new();
+    if let Some(opt_level) = opt_level {
+        rustflags.push(format!("-Copt-level={opt_level}"));
+        suffix = format!(" (-Copt-level={opt_level})");
+    }
+
+    eprintln!("[BENCH COMPILE] ebobby/simple-raytracer{suffix}");
     let cargo_clif = RelPath::DIST
         .to_path(dirs)
         .join(get_file_name(&bootstrap_host_compiler.rustc, "cargo_clif", "bin").replace('_', "-"));
@@ -68,30 +311,45 @@ fn benchmark_simple_raytracer(dirs: &Dirs, bootstrap_host_compiler: &Compiler) {
     );
 
     let bench_compile_markdown = RelPath::DIST.to_path(dirs).join("bench_compile.md");
+    let bench_compile_csv = RelPath::DIST.to_path(dirs).join("bench_compile.csv");
 
-    let bench_compile = hyperfine_command(
+    let mut compile_cmds = vec![
+        ("cargo build", &*llvm_build_cmd),
+        ("cargo-clif build", &*clif_build_cmd),
+        ("cargo-clif build --release", &*clif_build_opt_cmd),
+    ];
+    if opt_level.is_some() {
+        compile_cmds.pop();
+    }
+    let mut bench_compile = hyperfine_command(
         1,
         bench_runs,
         Some(&clean_cmd),
-        &[
-            ("cargo build", &llvm_build_cmd),
-            ("cargo-clif build", &clif_build_cmd),
-            ("cargo-clif build --release", &clif_build_opt_cmd),
-        ],
+        &compile_cmds,
         &bench_compile_markdown,
+        &bench_compile_csv,
     );
 
+    // Applies to both the LLVM and cg_clif builds to keep the comparison fair.
+    if !rustflags.is_empty() {
+        let rustflags =
+            rustflags_from_env("RUSTFLAGS").into_iter().chain(rustflags).collect::<Vec<_>>();
+        rustflags_to_cmd_env(&mut bench_compile, "RUSTFLAGS", &rustflags);
+    }
     spawn_and_wait(bench_compile);
 
     if let Some(gha_step_summary) = gha_step_summary.as_mut() {
-        gha_step_summary.write_all(b"## Compile ebobby/simple-raytracer\n\n").unwrap();
+        gha_step_summary
+            .write_all(format!("## Compile ebobby/simple-raytracer{suffix}\n\n").as_bytes())
+            .unwrap();
         gha_step_summary.write_all(&std::fs::read(bench_compile_markdown).unwrap()).unwrap();
         gha_step_summary.write_all(b"\n").unwrap();
     }
 
-    eprintln!("[BENCH RUN] ebobby/simple-raytracer");
+    eprintln!("[BENCH RUN] ebobby/simple-raytracer{suffix}");
 
     let bench_run_markdown = RelPath::DIST.to_path(dirs).join("bench_run.md");
+    let bench_run_csv = RelPath::DIST.to_path(dirs).join("bench_run.csv");
 
     let raytracer_cg_llvm = Path::new(".").join(get_file_name(
         &bootstrap_host_compiler.rustc,
@@ -108,23 +366,31 @@ fn benchmark_simple_raytracer(dirs: &Dirs, bootstrap_host_compiler: &Compiler) {
         "raytracer_cg_clif_opt",
         "bin",
     ));
-    let mut bench_run = hyperfine_command(
-        0,
-        bench_runs,
-        None,
-        &[
-            ("", raytracer_cg_llvm.to_str().unwrap()),
-            ("", raytracer_cg_clif.to_str().unwrap()),
-            ("", raytracer_cg_clif_opt.to_str().unwrap()),
-        ],
-        &bench_run_markdown,
-    );
+    let mut run_cmds = vec![
+        ("", raytracer_cg_llvm.to_str().unwrap()),
+        ("", raytracer_cg_clif.to_str().unwrap()),
+        ("", raytracer_cg_clif_opt.to_str().unwrap()),
+    ];
+    run_cmds.truncate(compile_cmds.len());
+    let mut bench_run =
+        hyperfine_command(0, bench_runs, None, &run_cmds, &bench_run_markdown, &bench_run_csv);
     bench_run.current_dir(RelPath::BUILD.to_path(dirs));
     spawn_and_wait(bench_run);
 
     if let Some(gha_step_summary) = gha_step_summary.as_mut() {
-        gha_step_summary.write_all(b"## Run ebobby/simple-raytracer\n\n").unwrap();
+        gha_step_summary
+            .write_all(format!("## Run ebobby/simple-raytracer{suffix}\n\n").as_bytes())
+            .unwrap();
         gha_step_summary.write_all(&std::fs::read(bench_run_markdown).unwrap()).unwrap();
         gha_step_summary.write_all(b"\n").unwrap();
     }
+
+    let compile_names = ["cargo build", "cargo-clif build", "cargo-clif build --release"];
+    let run_names = ["raytracer_cg_llvm", "raytracer_cg_clif", "raytracer_cg_clif_opt"];
+    let compile_results = compile_names.into_iter().zip(read_hyperfine_csv(&bench_compile_csv));
+    let run_results = run_names.into_iter().zip(read_hyperfine_csv(&bench_run_csv));
+    compile_results
+        .map(|(name, mean)| (format!("compile/{name}"), mean))
+        .chain(run_results.map(|(name, mean)| (format!("run/{name}"), mean)))
+        .collect()
 }
@@ -4,10 +4,10 @@
 use std::process::Command;
 
 use crate::path::{Dirs, RelPath};
-use crate::rustc_info::get_file_name;
+use crate::rustc_info::{find_llvm_ar_path, get_default_sysroot, get_file_name};
 use crate::utils::{
-    maybe_incremental, remove_dir_if_exists, spawn_and_wait, try_hard_link, CargoProject, Compiler,
-    LogGroup,
+    maybe_incremental, remove_dir_if_exists, spawn_and_wait, spawn_cargo_and_wait_with_progress,
+    try_hard_link, CargoProject, Compiler, LogGroup,
 };
 use crate::{config, CodegenBackend, SysrootKind};
 
@@ -24,6 +24,32 @@ pub(crate) fn build_sysroot(
     rustup_toolchain_name: Option<&str>,
     target_triple: String,
 ) -> Compiler {
+    build_sysroots(
+        dirs,
+        channel,
+        sysroot_kind,
+        cg_clif_dylib_src,
+        bootstrap_host_compiler,
+        rustup_toolchain_name,
+        &[target_triple],
+    )
+    .pop()
+    .unwrap()
+}
+
+/// Build a single sysroot in the dist dir containing the standard library for every target in
+/// `target_triples` and return a compiler for each of them. The libraries of every target end up
+/// in `lib/rustlib/<triple>/lib`. The host standard library is always built as it is needed for
+/// the jit mode.
+pub(crate) fn build_sysroots(
+    dirs: &Dirs,
+    channel: &str,
+    sysroot_kind: SysrootKind,
+    cg_clif_dylib_src: &CodegenBackend,
+    bootstrap_host_compiler: &Compiler,
+    rustup_toolchain_name: Option<&str>,
+    target_triples: &[String],
+) -> Vec<Compiler> {
     let _guard = LogGroup::guard("Build sysroot");
 
     eprintln!("[BUILD] sysroot {:?}", sysroot_kind);
@@ -32,20 +58,10 @@ pub(crate) fn build_sysroot(
     BIN_DIR.ensure_exists(dirs);
     LIB_DIR.ensure_exists(dirs);
 
-    let is_native = bootstrap_host_compiler.triple == target_triple;
-
     let cg_clif_dylib_path = match cg_clif_dylib_src {
         CodegenBackend::Local(src_path) => {
             // Copy the backend
-            let cg_clif_dylib_path = if cfg!(windows) {
-                // Windows doesn't have rpath support, so the cg_clif dylib needs to be next to the
-                // binaries.
-                BIN_DIR
-            } else {
-                LIB_DIR
-            }
-            .to_path(dirs)
-            .join(src_path.file_name().unwrap());
+            let cg_clif_dylib_path = dist_backend_path(dirs, src_path);
             try_hard_link(src_path, &cg_clif_dylib_path);
             CodegenBackend::Local(cg_clif_dylib_path)
         }
@@ -93,7 +109,12 @@ pub(crate) fn build_sysroot(
     );
     host.install_into_sysroot(&DIST_DIR.to_path(dirs));
 
-    if !is_native {
+    let mut built_triples = vec![&bootstrap_host_compiler.triple];
+    for target_triple in target_triples {
+        if built_triples.contains(&target_triple) {
+            continue;
+        }
+        built_triples.push(target_triple);
         build_sysroot_for_triple(
             dirs,
             channel,
@@ -118,27 +139,129 @@ pub(crate) fn build_sysroot(
         }
     }
 
-    let mut target_compiler = {
-        let dirs: &Dirs = &dirs;
-        let rustc_clif =
-            RelPath::DIST.to_path(&dirs).join(wrapper_base_name.replace("____", "rustc-clif"));
-        let rustdoc_clif =
-            RelPath::DIST.to_path(&dirs).join(wrapper_base_name.replace("____", "rustdoc-clif"));
-
-        Compiler {
-            cargo: bootstrap_host_compiler.cargo.clone(),
-            rustc: rustc_clif.clone(),
-            rustdoc: rustdoc_clif.clone(),
-            rustflags: vec![],
-            rustdocflags: vec![],
-            triple: target_triple,
-            runner: vec![],
-        }
+    let rustc_clif =
+        RelPath::DIST.to_path(dirs).join(wrapper_base_name.replace("____", "rustc-clif"));
+    let rustdoc_clif =
+        RelPath::DIST.to_path(dirs).join(wrapper_base_name.replace("____", "rustdoc-clif"));
+    target_triples
+        .iter()
+        .map(|target_triple| {
+            let mut target_compiler = Compiler {
+                cargo: bootstrap_host_compiler.cargo.clone(),
+                rustc: rustc_clif.clone(),
+                rustdoc: rustdoc_clif.clone(),
+                rustflags: vec![],
+                rustdocflags: vec![],
+                triple: target_triple.clone(),
+                runner: vec![],
+            };
+            if *target_triple != bootstrap_host_compiler.triple {
+                target_compiler.set_cross_linker_and_runner();
+            }
+            target_compiler
+        })
+        .collect()
+}
+
+fn dist_backend_path(dirs: &Dirs, src_path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        // Windows doesn't have rpath support, so the cg_clif dylib needs to be next to the
+        // binaries.
+        BIN_DIR
+    } else {
+        LIB_DIR
+    }
+    .to_path(dirs)
+    .join(src_path.file_name().unwrap())
+}
+
+/// Print a JSON manifest of everything `build_sysroot` put in the dist dir.
+///
+/// The format is versioned and only extended in backwards compatible ways:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "target": "x86_64-unknown-linux-gnu",
+///   "backend": "/path/to/dist/lib/librustc_codegen_cranelift.so",
+///   "sysroot": "/path/to/dist",
+///   "artifacts": [
+///     { "role": "codegen-backend", "path": "/path/to/dist/lib/librustc_codegen_cranelift.so" },
+///     { "role": "rustc-wrapper", "path": "/path/to/dist/rustc-clif" },
+///     { "role": "sysroot-library", "path": "/path/to/dist/lib/rustlib/.../libstd-....rlib" }
+///   ]
+/// }
+/// ```
+///
+/// `backend` is `null` when a backend built into rustc is used.
+pub(crate) fn print_artifacts(
+    dirs: &Dirs,
+    cg_clif_dylib_src: &CodegenBackend,
+    bootstrap_host_compiler: &Compiler,
+    target_triple: &str,
+) {
+    let backend_path = match cg_clif_dylib_src {
+        CodegenBackend::Local(src_path) => Some(dist_backend_path(dirs, src_path)),
+        CodegenBackend::Builtin(_) => None,
     };
-    if !is_native {
-        target_compiler.set_cross_linker_and_runner();
+
+    let mut artifacts = vec![];
+    if let Some(backend_path) = &backend_path {
+        artifacts.push(("codegen-backend", backend_path.clone()));
     }
-    target_compiler
+    let wrapper_base_name = get_file_name(&bootstrap_host_compiler.rustc, "____", "bin");
+    for (wrapper, role) in [
+        ("rustc-clif", "rustc-wrapper"),
+        ("rustdoc-clif", "rustdoc-wrapper"),
+        ("cargo-clif", "cargo-wrapper"),
+    ] {
+        let wrapper_name = wrapper_base_name.replace("____", wrapper);
+        artifacts.push((role, DIST_DIR.to_path(dirs).join(wrapper_name)));
+    }
+    let rustlib_dir = DIST_DIR.join_all(&["lib", "rustlib"]).to_path(dirs);
+    let mut sysroot_libs = vec![];
+    for triple_dir in fs::read_dir(&rustlib_dir).into_iter().flatten() {
+        let lib_dir = triple_dir.unwrap().path().join("lib");
+        for entry in fs::read_dir(&lib_dir).into_iter().flatten() {
+            sysroot_libs.push(entry.unwrap().path());
+        }
+    }
+    sysroot_libs.sort();
+    artifacts.extend(sysroot_libs.into_iter().map(|path| ("sysroot-library", path)));
+
+    let json_path = |path: &Path| json_string(path.to_str().unwrap());
+    let mut json = "{\n".to_owned();
+    json.push_str("  \"version\": 1,\n");
+    json.push_str(&format!("  \"target\": {},\n", json_string(target_triple)));
+    json.push_str(&format!(
+        "  \"backend\": {},\n",
+        backend_path.as_deref().map_or("null".to_owned(), json_path)
+    ));
+    json.push_str(&format!("  \"sysroot\": {},\n", json_path(&DIST_DIR.to_path(dirs))));
+    json.push_str("  \"artifacts\": [\n");
+    for (i, (role, path)) in artifacts.iter().enumerate() {
+        let separator = if i + 1 == artifacts.len() { "" } else { "," };
+        json.push_str(&format!(
+            "    {{ \"role\": \"{role}\", \"path\": {} }}{separator}\n",
+            json_path(path)
+        ));
+    }
+    json.push_str("  ]\n}");
+    println!("{json}");
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = "\"".to_owned();
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
 }
 
 struct SysrootTarget {
@@ -163,7 +286,8 @@ fn install_into_sysroot(&self, sysroot: &Path) {
 
 pub(crate) static STDLIB_SRC: RelPath = RelPath::BUILD.join("stdlib");
 pub(crate) static STANDARD_LIBRARY: CargoProject =
-    CargoProject::new(&STDLIB_SRC.join("library/sysroot"), "stdlib_target");
+    CargoProject::new(&STDLIB_SRC.join_all(&["library", "sysroot"]), "stdlib_target");
+static RTSTARTUP_SRC: RelPath = STDLIB_SRC.join_all(&["library", "rtstartup"]);
 pub(crate) static RTSTARTUP_SYSROOT: RelPath = RelPath::BUILD.join("rtstartup");
 
 #[must_use]
@@ -269,18 +393,32 @@ fn build_clif_sysroot_for_triple(
         ));
     }
     compiler.rustflags.extend(rustflags);
-    let mut build_cmd = STANDARD_LIBRARY.build(&compiler, dirs);
-    maybe_incremental(&mut build_cmd);
-    if channel == "release" {
-        build_cmd.arg("--release");
-    }
-    build_cmd.arg("--features").arg("compiler-builtins-no-asm backtrace panic-unwind");
-    build_cmd.env("CARGO_PROFILE_RELEASE_DEBUG", "true");
-    build_cmd.env("__CARGO_DEFAULT_LIB_METADATA", "cg_clif");
-    if compiler.triple.contains("apple") {
-        build_cmd.env("CARGO_PROFILE_RELEASE_SPLIT_DEBUGINFO", "packed");
-    }
-    spawn_and_wait(build_cmd);
+
+    let default_target_lib_dir = get_default_sysroot(&compiler.rustc)
+        .join("lib")
+        .join("rustlib")
+        .join(&compiler.triple)
+        .join("lib");
+    let profiler_rt = extract_profiler_runtime(&compiler, &default_target_lib_dir, &build_dir);
+
+    spawn_cargo_and_wait_with_progress(|| {
+        let mut build_cmd = STANDARD_LIBRARY.build(&compiler, dirs);
+        maybe_incremental(&mut build_cmd);
+        if channel == "release" {
+            build_cmd.arg("--release");
+        }
+        build_cmd.arg("--features").arg("compiler-builtins-no-asm backtrace panic-unwind");
+        if let Some(profiler_rt) = &profiler_rt {
+            build_cmd.arg("--features").arg("profiler");
+            build_cmd.env("LLVM_PROFILER_RT_LIB", profiler_rt);
+        }
+        build_cmd.env("CARGO_PROFILE_RELEASE_DEBUG", "true");
+        build_cmd.env("__CARGO_DEFAULT_LIB_METADATA", "cg_clif");
+        if compiler.triple.contains("apple") {
+            build_cmd.env("CARGO_PROFILE_RELEASE_SPLIT_DEBUGINFO", "packed");
+        }
+        build_cmd
+    });
 
     for entry in fs::read_dir(build_dir.join("deps")).unwrap() {
         let entry = entry.unwrap();
@@ -294,9 +432,55 @@ fn build_clif_sysroot_for_triple(
         target_libs.libs.push(entry.path());
     }
 
+    // The sanitizer runtimes are not part of the standard library build. Take them from the
+    // sysroot of the bootstrap compiler so that -Zsanitizer can find them.
+    if let Ok(entries) = fs::read_dir(default_target_lib_dir) {
+        for entry in entries {
+            let file = entry.unwrap().path();
+            let file_name_str = file.file_name().unwrap().to_str().unwrap();
+            if file_name_str.starts_with("librustc-") && file_name_str.contains("_rt.") {
+                target_libs.libs.push(file);
+            }
+        }
+    }
+
     target_libs
 }
 
+/// The C sources of the profiler runtime are not part of the rust-src component. Instead extract
+/// the objects of the C code from the prebuilt `profiler_builtins` of the bootstrap compiler into
+/// a static library the `profiler_builtins` crate can link. Returns `None` if the bootstrap
+/// compiler doesn't ship a profiler runtime for the target or llvm-tools isn't installed.
+fn extract_profiler_runtime(
+    compiler: &Compiler,
+    default_target_lib_dir: &Path,
+    build_dir: &Path,
+) -> Option<PathBuf> {
+    let prebuilt = fs::read_dir(default_target_lib_dir).ok()?.find_map(|entry| {
+        let file = entry.unwrap().path();
+        let file_name_str = file.file_name().unwrap().to_str().unwrap();
+        (file_name_str.starts_with("libprofiler_builtins-") && file_name_str.ends_with(".rlib"))
+            .then_some(file)
+    })?;
+    let llvm_ar = find_llvm_ar_path(&compiler.rustc)?;
+
+    fs::create_dir_all(build_dir).unwrap();
+    let profiler_rt = build_dir.join("libprofiler_rt.a");
+    fs::copy(prebuilt, &profiler_rt).unwrap();
+
+    // Remove the crate metadata and the object files of the Rust code.
+    let members = Command::new(&llvm_ar).arg("t").arg(&profiler_rt).output().unwrap();
+    assert!(members.status.success(), "failed to list the members of {}", profiler_rt.display());
+    let members = String::from_utf8(members.stdout).unwrap();
+    let mut delete_cmd = Command::new(&llvm_ar);
+    delete_cmd.arg("d").arg(&profiler_rt).args(
+        members.lines().filter(|member| *member == "lib.rmeta" || member.ends_with(".rcgu.o")),
+    );
+    spawn_and_wait(delete_cmd);
+
+    Some(profiler_rt)
+}
+
 fn build_rtstartup(dirs: &Dirs, compiler: &Compiler) -> Option<SysrootTarget> {
     if !config::get_bool("keep_sysroot") {
         crate::prepare::prepare_stdlib(dirs, &compiler.rustc);
@@ -308,7 +492,7 @@ fn build_rtstartup(dirs: &Dirs, compiler: &Compiler) -> Option<SysrootTarget> {
 
     RTSTARTUP_SYSROOT.ensure_fresh(dirs);
 
-    let rtstartup_src = STDLIB_SRC.to_path(dirs).join("library").join("rtstartup");
+    let rtstartup_src = RTSTARTUP_SRC.to_path(dirs);
     let mut target_libs = SysrootTarget { triple: compiler.triple.clone(), libs: vec![] };
 
     for file in ["rsbegin", "rsend"] {
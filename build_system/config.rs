@@ -1,6 +1,12 @@
 use std::fs;
 use std::process;
 
+use crate::tests;
+
+/// Config keys which configure the build system rather than selecting which tests to run.
+const SETTINGS: &[&str] =
+    &["host", "target", "keep_sysroot", "sysroot", "unstable-features", "channel"];
+
 fn load_config_file() -> Vec<(String, Option<String>)> {
     fs::read_to_string("config.txt")
         .unwrap()
@@ -54,3 +60,29 @@ pub(crate) fn get_value(name: &str) -> Option<String> {
         process::exit(1);
     }
 }
+
+/// Get the value of a config converted using `parse`. Exits with an error if `parse` rejects the
+/// value. `expected` describes the accepted values for the error message.
+pub(crate) fn get_parsed<T>(
+    name: &str,
+    expected: &str,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Option<T> {
+    let value = get_value(name)?;
+    match parse(&value) {
+        Some(value) => Some(value),
+        None => {
+            eprintln!("Config `{}` has invalid value `{}`, expected {}", name, value, expected);
+            process::exit(1);
+        }
+    }
+}
+
+/// Warn about all keys in the config file which are neither a setting nor the name of a test.
+pub(crate) fn warn_unknown_keys() {
+    for (key, _) in load_config_file() {
+        if !SETTINGS.contains(&&*key) && !tests::is_test_config(&key) {
+            eprintln!("Warning: Unknown config `{}` in config.txt", key);
+        }
+    }
+}
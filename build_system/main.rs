@@ -5,6 +5,7 @@
 use std::env;
 use std::path::PathBuf;
 use std::process;
+use std::time::Duration;
 
 use self::utils::{is_ci, is_ci_opt, Compiler};
 
@@ -48,6 +49,17 @@ enum SysrootKind {
     Llvm,
 }
 
+impl SysrootKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "none" => Some(SysrootKind::None),
+            "clif" => Some(SysrootKind::Clif),
+            "llvm" => Some(SysrootKind::Llvm),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 enum CodegenBackend {
     Local(PathBuf),
@@ -58,16 +70,9 @@ fn main() {
     if env::var_os("RUST_BACKTRACE").is_none() {
         env::set_var("RUST_BACKTRACE", "1");
     }
-    env::set_var("CG_CLIF_DISABLE_INCR_CACHE", "1");
-
-    if is_ci() {
-        // Disabling incr comp reduces cache size and incr comp doesn't save as much on CI anyway
-        env::set_var("CARGO_BUILD_INCREMENTAL", "false");
-
-        if !is_ci_opt() {
-            // Enable the Cranelift verifier
-            env::set_var("CG_CLIF_ENABLE_VERIFIER", "1");
-        }
+    if is_ci() && !is_ci_opt() {
+        // Enable the Cranelift verifier
+        env::set_var("CG_CLIF_ENABLE_VERIFIER", "1");
     }
 
     let mut args = env::args().skip(1);
@@ -87,12 +92,23 @@ fn main() {
 
     let mut out_dir = PathBuf::from(".");
     let mut download_dir = None;
-    let mut channel = "release";
-    let mut sysroot_kind = SysrootKind::Clif;
-    let mut use_unstable_features = true;
+    let mut channel = None;
+    let mut sysroot_kind = None;
+    let mut use_unstable_features = None;
     let mut frozen = false;
     let mut skip_tests = vec![];
+    let mut exclude_tests = vec![];
+    let mut shard = None;
+    let mut check_only = false;
     let mut use_backend = None;
+    let mut target_triples = vec![];
+    let mut bench_baseline = None;
+    let mut regression_threshold = 5.0;
+    let mut opt_levels = vec![];
+    let mut print_artifacts = false;
+    let mut incremental = false;
+    let mut test_timeout = tests::DEFAULT_TEST_TIMEOUT;
+    let mut program_rustflags = vec![];
     while let Some(arg) = args.next().as_deref() {
         match arg {
             "--out-dir" => {
@@ -105,17 +121,17 @@ fn main() {
                     arg_error!("--download-dir requires argument");
                 })));
             }
-            "--debug" => channel = "debug",
+            "--debug" => channel = Some("debug"),
             "--sysroot" => {
                 sysroot_kind = match args.next().as_deref() {
-                    Some("none") => SysrootKind::None,
-                    Some("clif") => SysrootKind::Clif,
-                    Some("llvm") => SysrootKind::Llvm,
-                    Some(arg) => arg_error!("Unknown sysroot kind {}", arg),
+                    Some(arg) => match SysrootKind::parse(arg) {
+                        Some(kind) => Some(kind),
+                        None => arg_error!("Unknown sysroot kind {}", arg),
+                    },
                     None => arg_error!("--sysroot requires argument"),
                 }
             }
-            "--no-unstable-features" => use_unstable_features = false,
+            "--no-unstable-features" => use_unstable_features = Some(false),
             "--frozen" => frozen = true,
             "--skip-test" => {
                 // FIXME check that all passed in tests actually exist
@@ -123,6 +139,76 @@ fn main() {
                     arg_error!("--skip-test requires argument");
                 }));
             }
+            "--exclude" => {
+                exclude_tests.push(args.next().unwrap_or_else(|| {
+                    arg_error!("--exclude requires argument");
+                }));
+            }
+            "--shard" => {
+                let arg = args.next().unwrap_or_else(|| {
+                    arg_error!("--shard requires argument");
+                });
+                shard = match tests::Shard::parse(&arg) {
+                    Some(shard) => Some(shard),
+                    None => arg_error!("Invalid shard {}, expected INDEX/COUNT", arg),
+                };
+            }
+            "--check-only" => check_only = true,
+            "--baseline" => {
+                bench_baseline = Some(PathBuf::from(args.next().unwrap_or_else(|| {
+                    arg_error!("--baseline requires argument");
+                })));
+            }
+            "--regression-threshold" => {
+                let threshold = args.next().unwrap_or_else(|| {
+                    arg_error!("--regression-threshold requires argument");
+                });
+                regression_threshold = match threshold.trim_end_matches('%').parse::<f64>() {
+                    Ok(threshold) if threshold >= 0.0 => threshold,
+                    _ => arg_error!("Invalid regression threshold {}", threshold),
+                };
+            }
+            "--opt-levels" => {
+                let levels = args.next().unwrap_or_else(|| {
+                    arg_error!("--opt-levels requires argument");
+                });
+                opt_levels = levels.split(',').map(|level| level.trim().to_owned()).collect();
+                for level in &opt_levels {
+                    if !["0", "1", "2", "3", "s", "z"].contains(&&**level) {
+                        arg_error!("Invalid opt-level {}", level);
+                    }
+                }
+            }
+            "--test-timeout" => {
+                let timeout = args.next().unwrap_or_else(|| {
+                    arg_error!("--test-timeout requires argument");
+                });
+                test_timeout = match timeout.parse::<u64>() {
+                    Ok(timeout) if timeout > 0 => Duration::from_secs(timeout),
+                    _ => arg_error!("Invalid test timeout {}", timeout),
+                };
+            }
+            "--target-cpu" => {
+                let cpu = args.next().unwrap_or_else(|| {
+                    arg_error!("--target-cpu requires argument");
+                });
+                program_rustflags.push(format!("-Ctarget-cpu={cpu}"));
+            }
+            "--target-feature" => {
+                let features = args.next().unwrap_or_else(|| {
+                    arg_error!("--target-feature requires argument");
+                });
+                program_rustflags.push(format!("-Ctarget-feature={features}"));
+            }
+            "--print-artifacts" => print_artifacts = true,
+            "--incremental" => incremental = true,
+            "--progress" => utils::request_progress(),
+            "--target" => {
+                let targets = args.next().unwrap_or_else(|| {
+                    arg_error!("--target requires argument");
+                });
+                target_triples.extend(targets.split(',').map(|target| target.to_owned()));
+            }
             "--use-backend" => {
                 use_backend = Some(match args.next() {
                     Some(name) => name,
@@ -134,6 +220,44 @@ fn main() {
         }
     }
 
+    if incremental {
+        eprintln!(
+            "Warning: --incremental is experimental. Changes to cg_clif itself are not tracked by \
+            the incremental cache, so rebuild from scratch when testing a change to cg_clif."
+        );
+    } else {
+        // The incremental cache doesn't know about changes to cg_clif itself, so reusing object
+        // files could hide the effect of a change to cg_clif. The `aot.incremental_reuse` test
+        // opts back in.
+        env::set_var("CG_CLIF_DISABLE_INCR_CACHE", "1");
+
+        if is_ci() {
+            // Disabling incr comp reduces cache size and incr comp doesn't save as much on CI
+            // anyway
+            env::set_var("CARGO_BUILD_INCREMENTAL", "false");
+        }
+    }
+
+    // Flags passed on the command line take precedence over defaults from config.txt.
+    config::warn_unknown_keys();
+    let channel = channel
+        .or_else(|| {
+            config::get_parsed("channel", "`release` or `debug`", |channel| match channel {
+                "release" => Some("release"),
+                "debug" => Some("debug"),
+                _ => None,
+            })
+        })
+        .unwrap_or("release");
+    let sysroot_kind = sysroot_kind
+        .or_else(|| config::get_parsed("sysroot", "`none`, `clif` or `llvm`", SysrootKind::parse))
+        .unwrap_or(SysrootKind::Clif);
+    let use_unstable_features = use_unstable_features
+        .or_else(|| {
+            config::get_parsed("unstable-features", "`true` or `false`", |value| value.parse().ok())
+        })
+        .unwrap_or(true);
+
     let current_dir = std::env::current_dir().unwrap();
     out_dir = current_dir.join(out_dir);
 
@@ -176,10 +300,18 @@ fn main() {
             runner: vec![],
         }
     };
-    let target_triple = std::env::var("TARGET_TRIPLE")
-        .ok()
-        .or_else(|| config::get_value("target"))
-        .unwrap_or_else(|| bootstrap_host_compiler.triple.clone());
+    if target_triples.is_empty() {
+        target_triples.push(
+            std::env::var("TARGET_TRIPLE")
+                .ok()
+                .or_else(|| config::get_value("target"))
+                .unwrap_or_else(|| bootstrap_host_compiler.triple.clone()),
+        );
+    }
+    if target_triples.len() > 1 && command != Command::Build {
+        arg_error!("Multiple targets are only supported by the build command");
+    }
+    let target_triple = target_triples[0].clone();
 
     let dirs = path::Dirs {
         source_dir: current_dir.clone(),
@@ -223,9 +355,16 @@ fn main() {
             tests::run_tests(
                 &dirs,
                 channel,
-                sysroot_kind,
-                use_unstable_features,
-                &skip_tests.iter().map(|test| &**test).collect::<Vec<_>>(),
+                &tests::TestOptions {
+                    sysroot_kind,
+                    use_unstable_features,
+                    skip_tests: &skip_tests.iter().map(|test| &**test).collect::<Vec<_>>(),
+                    exclude_tests: &exclude_tests.iter().map(|group| &**group).collect::<Vec<_>>(),
+                    shard,
+                    check_only,
+                    timeout: test_timeout,
+                    program_rustflags: &program_rustflags,
+                },
                 &cg_clif_dylib,
                 &bootstrap_host_compiler,
                 rustup_toolchain_name.as_deref(),
@@ -247,15 +386,23 @@ fn main() {
             );
         }
         Command::Build => {
-            build_sysroot::build_sysroot(
+            build_sysroot::build_sysroots(
                 &dirs,
                 channel,
                 sysroot_kind,
                 &cg_clif_dylib,
                 &bootstrap_host_compiler,
                 rustup_toolchain_name.as_deref(),
-                target_triple,
+                &target_triples,
             );
+            if print_artifacts {
+                build_sysroot::print_artifacts(
+                    &dirs,
+                    &cg_clif_dylib,
+                    &bootstrap_host_compiler,
+                    &target_triple,
+                );
+            }
         }
         Command::Bench => {
             build_sysroot::build_sysroot(
@@ -267,7 +414,14 @@ fn main() {
                 rustup_toolchain_name.as_deref(),
                 target_triple,
             );
-            bench::benchmark(&dirs, &bootstrap_host_compiler);
+            bench::benchmark(
+                &dirs,
+                &bootstrap_host_compiler,
+                &program_rustflags,
+                &opt_levels,
+                bench_baseline.map(|baseline| current_dir.join(baseline)).as_deref(),
+                regression_threshold,
+            );
         }
     }
 }
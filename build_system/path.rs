@@ -36,6 +36,7 @@ fn to_path(self, dirs: &Dirs) -> PathBuf {
 pub(crate) enum RelPath {
     Base(PathBase),
     Join(&'static RelPath, &'static str),
+    JoinAll(&'static RelPath, &'static [&'static str]),
 }
 
 impl RelPath {
@@ -51,10 +52,19 @@ pub(crate) const fn join(&'static self, suffix: &'static str) -> RelPath {
         RelPath::Join(self, suffix)
     }
 
+    /// Join several path components at once. `a.join_all(&["b", "c"])` is equivalent to
+    /// `a.join("b").join("c")`, but doesn't need a separate `static` for the intermediate path.
+    pub(crate) const fn join_all(&'static self, suffixes: &'static [&'static str]) -> RelPath {
+        RelPath::JoinAll(self, suffixes)
+    }
+
     pub(crate) fn to_path(&self, dirs: &Dirs) -> PathBuf {
         match self {
             RelPath::Base(base) => base.to_path(dirs),
             RelPath::Join(base, suffix) => base.to_path(dirs).join(suffix),
+            RelPath::JoinAll(base, suffixes) => {
+                suffixes.iter().fold(base.to_path(dirs), |path, suffix| path.join(suffix))
+            }
         }
     }
 
@@ -68,3 +78,28 @@ pub(crate) fn ensure_fresh(&self, dirs: &Dirs) {
         fs::create_dir_all(path).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static A: RelPath = RelPath::BUILD.join("a");
+    static A_B: RelPath = A.join("b");
+    static A_B_C: RelPath = A_B.join("c");
+    static A_B_C_ALL: RelPath = RelPath::BUILD.join_all(&["a", "b", "c"]);
+    static EMPTY: RelPath = RelPath::BUILD.join_all(&[]);
+
+    #[test]
+    fn join_all_equals_chained_join() {
+        let dirs = Dirs {
+            source_dir: PathBuf::from("/source"),
+            download_dir: PathBuf::from("/download"),
+            build_dir: PathBuf::from("/build"),
+            dist_dir: PathBuf::from("/dist"),
+            frozen: false,
+        };
+        assert_eq!(A_B_C_ALL.to_path(&dirs), A_B_C.to_path(&dirs));
+        assert_eq!(A_B_C_ALL.to_path(&dirs), PathBuf::from("/build/a/b/c"));
+        assert_eq!(EMPTY.to_path(&dirs), RelPath::BUILD.to_path(&dirs));
+    }
+}
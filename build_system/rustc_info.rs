@@ -1,5 +1,5 @@
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{self, Command, Stdio};
 
 pub(crate) fn get_host_triple(rustc: &Path) -> String {
     let version_info =
@@ -76,6 +76,47 @@ pub(crate) fn get_default_sysroot(rustc: &Path) -> PathBuf {
     Path::new(String::from_utf8(default_sysroot).unwrap().trim()).to_owned()
 }
 
+/// Returns the path of an LLVM tool shipped with the `llvm-tools` rustup component of the toolchain
+/// `rustc` belongs to.
+fn llvm_tool_path(rustc: &Path, tool: &str) -> PathBuf {
+    get_default_sysroot(rustc)
+        .join("lib/rustlib")
+        .join(get_host_triple(rustc))
+        .join("bin")
+        .join(format!("{tool}{}", std::env::consts::EXE_SUFFIX))
+}
+
+fn get_llvm_tool_path(rustc: &Path, tool: &str) -> PathBuf {
+    let tool_path = llvm_tool_path(rustc, tool);
+    if !tool_path.exists() {
+        eprintln!("`{tool}` not found at {}", tool_path.display());
+        eprintln!("Install it using `rustup component add llvm-tools`");
+        process::exit(1);
+    }
+    tool_path
+}
+
+pub(crate) fn get_llvm_objcopy_path(rustc: &Path) -> PathBuf {
+    get_llvm_tool_path(rustc, "llvm-objcopy")
+}
+
+pub(crate) fn get_llvm_ar_path(rustc: &Path) -> PathBuf {
+    get_llvm_tool_path(rustc, "llvm-ar")
+}
+
+/// Like [`get_llvm_ar_path`], but returns `None` instead of exiting if llvm-tools isn't installed.
+pub(crate) fn find_llvm_ar_path(rustc: &Path) -> Option<PathBuf> {
+    Some(llvm_tool_path(rustc, "llvm-ar")).filter(|path| path.exists())
+}
+
+pub(crate) fn get_llvm_profdata_path(rustc: &Path) -> PathBuf {
+    get_llvm_tool_path(rustc, "llvm-profdata")
+}
+
+pub(crate) fn get_llvm_cov_path(rustc: &Path) -> PathBuf {
+    get_llvm_tool_path(rustc, "llvm-cov")
+}
+
 // FIXME call once for each target and pass result around in struct
 pub(crate) fn get_file_name(rustc: &Path, crate_name: &str, crate_type: &str) -> String {
     let file_name = Command::new(rustc)
@@ -1,19 +1,65 @@
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{self, Command, Output};
+use std::time::Duration;
 
 use crate::build_sysroot;
 use crate::config;
 use crate::path::{Dirs, RelPath};
 use crate::prepare::{apply_patches, GitRepo};
-use crate::rustc_info::get_default_sysroot;
+use crate::rustc_info::{
+    get_default_sysroot, get_llvm_ar_path, get_llvm_cov_path, get_llvm_objcopy_path,
+    get_llvm_profdata_path,
+};
 use crate::shared_utils::rustflags_from_env;
-use crate::utils::{spawn_and_wait, CargoProject, Compiler, LogGroup};
+use crate::utils::{
+    output_with_timeout, spawn_and_wait, spawn_and_wait_with_timeout, CargoProject, Compiler,
+    LogGroup, TimedOut,
+};
 use crate::{CodegenBackend, SysrootKind};
 
 static BUILD_EXAMPLE_OUT_DIR: RelPath = RelPath::BUILD.join("example");
 
+/// The default time after which compiling or running a single test program is aborted.
+pub(crate) const DEFAULT_TEST_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// A subset of the tests selected with `--shard INDEX/COUNT` to split a test run across several
+/// machines.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Shard {
+    /// One-based index of this shard.
+    pub(crate) index: usize,
+    pub(crate) count: usize,
+}
+
+impl Shard {
+    pub(crate) fn parse(shard: &str) -> Option<Self> {
+        let (index, count) = shard.split_once('/')?;
+        let index = index.parse().ok()?;
+        let count = count.parse().ok()?;
+        if index == 0 || index > count {
+            return None;
+        }
+        Some(Shard { index, count })
+    }
+}
+
+/// The options of `./y.sh test` which affect which tests are run and how.
+pub(crate) struct TestOptions<'a> {
+    pub(crate) sysroot_kind: SysrootKind,
+    pub(crate) use_unstable_features: bool,
+    pub(crate) skip_tests: &'a [&'a str],
+    pub(crate) exclude_tests: &'a [&'a str],
+    pub(crate) shard: Option<Shard>,
+    /// Only compile the test programs without running them.
+    pub(crate) check_only: bool,
+    pub(crate) timeout: Duration,
+    /// Extra rustflags for the test programs, but not for the sysroot.
+    pub(crate) program_rustflags: &'a [String],
+}
+
 struct TestCase {
     config: &'static str,
     cmd: TestCaseCmd,
@@ -56,6 +102,12 @@ const fn build_bin_and_run(
     const fn jit_bin(config: &'static str, source: &'static str, args: &'static str) -> Self {
         Self { config, cmd: TestCaseCmd::JitBin { source, args } }
     }
+
+    /// Whether this test builds a library used by other tests and thus has to run on every
+    /// shard.
+    fn is_shared(&self) -> bool {
+        matches!(self.cmd, TestCaseCmd::BuildLib { .. })
+    }
 }
 
 const NO_SYSROOT_SUITE: &[TestCase] = &[
@@ -80,6 +132,7 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
     TestCase::jit_bin("jit.std_example", "example/std_example.rs", ""),
     TestCase::build_bin_and_run("aot.std_example", "example/std_example.rs", &["arg"]),
     TestCase::build_bin_and_run("aot.dst_field_align", "example/dst-field-align.rs", &[]),
+    TestCase::build_bin_and_run("aot.realloc_align", "example/realloc_align.rs", &[]),
     TestCase::build_bin_and_run(
         "aot.subslice-patterns-const-eval",
         "example/subslice-patterns-const-eval.rs",
@@ -91,6 +144,7 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
         &[],
     ),
     TestCase::build_bin_and_run("aot.float-minmax-pass", "example/float-minmax-pass.rs", &[]),
+    TestCase::build_bin_and_run("aot.simd_float_math", "example/simd_float_math.rs", &[]),
     TestCase::build_bin_and_run("aot.mod_bench", "example/mod_bench.rs", &[]),
     TestCase::build_bin_and_run("aot.issue-72793", "example/issue-72793.rs", &[]),
     TestCase::build_bin("aot.issue-59326", "example/issue-59326.rs"),
@@ -99,6 +153,1273 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
         runner.run_out_command("polymorphize_coroutine", &[]);
     }),
     TestCase::build_bin_and_run("aot.neon", "example/neon.rs", &[]),
+    TestCase::build_bin_and_run("aot.ptr_vector", "example/ptr_vector.rs", &[]),
+    TestCase::build_bin_and_run("aot.naked_functions", "example/naked_functions.rs", &[]),
+    TestCase::custom("aot.naked_function_linkage", &|runner| {
+        if !runner.target_compiler.triple.starts_with("x86_64-unknown-linux") {
+            eprintln!("Skipping naked function linkage test: the example only has x86_64 asm");
+            return;
+        }
+        let out_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs);
+        let is_asm_object =
+            |name: &str| name.starts_with("naked_functions.") && name.ends_with(".asm.o");
+        let asm_objects = || {
+            fs::read_dir(&out_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| is_asm_object(path.file_name().unwrap().to_str().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        for path in asm_objects() {
+            fs::remove_file(path).unwrap();
+        }
+        runner.run_rustc(["example/naked_functions.rs", "-Ccodegen-units=1", "-Csave-temps"]);
+        let objects = asm_objects();
+        assert_eq!(objects.len(), 1, "expected a single global asm object: {objects:?}");
+        let mut nm_cmd = Command::new("nm");
+        nm_cmd.arg("--defined-only").arg(&objects[0]);
+        let symbols = runner.checked_output(&mut nm_cmd);
+        // The naked functions are private, so they must not be global definitions which would
+        // clash with copies of the same function in other codegen units.
+        for name in ["naked_functions3add", "naked_functions12return_const"] {
+            let line = symbols.lines().find(|line| line.contains(name)).unwrap_or_else(|| {
+                panic!("naked function {name} not defined in the global asm object:\n{symbols}")
+            });
+            assert_eq!(
+                line.split_whitespace().nth(1),
+                Some("W"),
+                "naked function {name} not a weak definition:\n{symbols}",
+            );
+        }
+    }),
+    TestCase::custom("aot.weak_linkage", &|runner| {
+        if runner.target_compiler.triple.contains("windows") {
+            eprintln!("Skipping weak linkage test: COFF has no weak definitions");
+            return;
+        }
+        runner.run_rustc(["example/weak_linkage_lib.rs", "--crate-type", "lib"]);
+        runner.run_rustc(["example/weak_linkage.rs"]);
+        runner.run_out_command("weak_linkage", &[]);
+    }),
+    TestCase::build_bin_and_run("aot.simd_ffi", "example/simd_ffi.rs", &[]),
+    TestCase::custom("aot.frame_pointers", &|runner| {
+        runner.run_rustc(["example/frame_pointers.rs", "-Cforce-frame-pointers=yes"]);
+        runner.run_out_command("frame_pointers", &[]);
+    }),
+    TestCase::custom("aot.panic_abort", &|runner| {
+        runner.run_rustc(["example/panic_abort.rs", "-Cforce-unwind-tables=no"]);
+        runner.run_out_command("panic_abort", &[]);
+
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping unwind table checks: the test inspects ELF sections");
+            return;
+        }
+        // Unwind tables follow `-Cforce-unwind-tables`. Without it they are still emitted with
+        // `-Cpanic=abort` on targets which use them for backtraces by default, like cg_llvm does.
+        let out_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs);
+        for (force_unwind_tables, has_eh_frame) in
+            [("-Cforce-unwind-tables=no", false), ("-Cforce-unwind-tables=yes", true)]
+        {
+            runner.run_rustc([
+                "example/panic_abort.rs",
+                "--emit",
+                "obj,mir",
+                "-Ccodegen-units=1",
+                force_unwind_tables,
+            ]);
+            let mut readelf_cmd = Command::new("readelf");
+            readelf_cmd.arg("-SW").arg(out_dir.join("panic_abort.o"));
+            let sections = runner.checked_output(&mut readelf_cmd);
+            assert_eq!(
+                sections.contains(".eh_frame"),
+                has_eh_frame,
+                "unexpected .eh_frame presence with {force_unwind_tables}:\n{sections}",
+            );
+        }
+
+        // Cleanup blocks are skipped during codegen, so they must not be necessary either. With
+        // `-Cpanic=abort` rustc removes all unwind edges, which leaves no cleanup blocks.
+        let mir = fs::read_to_string(out_dir.join("panic_abort.mir")).unwrap();
+        assert!(!mir.contains("(cleanup)"), "cleanup block found with -Cpanic=abort:\n{mir}");
+    }),
+    TestCase::custom("aot.panic_immediate_abort", &|runner| {
+        let clif = runner.run_rustc_and_dump_clif(
+            "panic_immediate_abort",
+            // Emitting llvm-ir enables the comments containing the symbol names.
+            &[
+                "--emit",
+                "obj,llvm-ir",
+                "-O",
+                "-Cllvm-args=panic_immediate_abort=true",
+                "-Coverflow-checks=yes",
+            ],
+        );
+        let obj_path = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("panic_immediate_abort.o");
+        let obj = fs::read(obj_path).unwrap();
+        for msg in [
+            "attempt to add with overflow",
+            "attempt to divide by zero",
+            "user panic message",
+            "assert_eq message",
+            "expect message",
+            // The file name of the panic location.
+            "panic_immediate_abort.rs",
+        ] {
+            assert!(
+                !obj.windows(msg.len()).any(|window| window == msg.as_bytes()),
+                "panic message `{msg}` was emitted with panic_immediate_abort",
+            );
+        }
+
+        // Only look at the instructions and not at the comments.
+        let function_insts = |name: &str| {
+            let symbol = format!("panic_immediate_abort{}{name}17h", name.len());
+            let func = clif
+                .split("\nfunction ")
+                .find(|func| {
+                    func.lines().any(|line| line.starts_with("; symbol ") && line.contains(&symbol))
+                })
+                .unwrap_or_else(|| panic!("function `{name}` not found:\n{clif}"));
+            func.lines().filter(|line| line.starts_with(' ')).map(str::trim).collect::<Vec<_>>()
+        };
+        let insts = function_insts("user_panic");
+        assert!(
+            insts.contains(&"trap user0")
+                && insts.iter().all(|inst| ["nop", "jump ", "trap user0"]
+                    .iter()
+                    .any(|allowed| inst.starts_with(allowed))),
+            "`panic!()` not lowered to a bare trap:\n{insts:#?}",
+        );
+        for name in ["user_panic_fmt", "check_eq", "unwrap", "expect"] {
+            let insts = function_insts(name);
+            assert!(
+                insts.contains(&"trap user0") && !insts.iter().any(|inst| inst.contains("call")),
+                "panic in `{name}` not lowered to a trap:\n{insts:#?}",
+            );
+        }
+    }),
+    TestCase::custom("aot.print_layout", &|runner| {
+        let mut cmd = runner.rustc_command(["example/print_layout.rs", "--emit", "obj"]);
+        cmd.env("CG_CLIF_PRINT_LAYOUT", "PrintLayoutStruct");
+        let output = runner.output(&mut cmd);
+        assert!(output.status.success(), "{cmd:?} exited with status {}", output.status);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        for expected in ["layout of PrintLayoutStruct: size=8 align=4", "  field b: u32 offset="] {
+            assert!(stderr.contains(expected), "`{expected}` not found in layout dump:\n{stderr}");
+        }
+    }),
+    TestCase::custom("aot.print_type_sizes", &|runner| {
+        let mut cmd = runner.rustc_command([
+            "example/print_layout.rs",
+            "--emit",
+            "obj",
+            "-Zprint-type-sizes",
+        ]);
+        let stdout = runner.checked_output(&mut cmd);
+        let expected = "PrintLayoutStruct`: 8 bytes, alignment: 4 bytes";
+        assert!(stdout.contains(expected), "`{expected}` not found in type sizes:\n{stdout}");
+    }),
+    TestCase::custom("aot.dump_module", &|runner| {
+        let dump_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("dump_module");
+        let _ = fs::remove_dir_all(&dump_dir);
+        let mut cmd = runner.rustc_command(["example/codegen_units.rs", "-Ccodegen-units=1"]);
+        cmd.env("CG_CLIF_DUMP_MODULE", &dump_dir);
+        runner.spawn_and_wait(cmd);
+        let files =
+            fs::read_dir(&dump_dir).unwrap().map(|entry| entry.unwrap().path()).collect::<Vec<_>>();
+        let clif_file = files
+            .iter()
+            .find(|path| path.extension() == Some("clif".as_ref()))
+            .expect("no clif file written");
+        let clif = fs::read_to_string(clif_file).unwrap();
+        assert!(clif.contains("function u0:"), "no functions found in {}", clif_file.display());
+        assert!(
+            files.iter().any(|path| path.extension() == Some("o".as_ref())),
+            "no object file written: {files:?}",
+        );
+    }),
+    TestCase::custom("aot.size_report", &|runner| {
+        let mut cmd =
+            runner.rustc_command(["example/size_report.rs", "--emit", "obj", "-Ccodegen-units=1"]);
+        cmd.env("CG_CLIF_SIZE_REPORT", "1");
+        let output = runner.output(&mut cmd);
+        assert!(output.status.success(), "{cmd:?} exited with status {}", output.status);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let report = stderr
+            .lines()
+            .find(|line| line.starts_with("size report for "))
+            .unwrap_or_else(|| panic!("no size report found:\n{stderr}"));
+        for category in ["code", "unwind"] {
+            let size = report
+                .split(' ')
+                .find_map(|field| field.strip_prefix(category)?.strip_prefix('='))
+                .unwrap_or_else(|| panic!("`{category}` missing from size report: {report}"));
+            assert_ne!(size, "0", "no {category} in size report: {report}");
+        }
+    }),
+    TestCase::custom("aot.unsupported_intrinsic", &|runner| {
+        if !runner.target_compiler.triple.starts_with("x86_64") {
+            eprintln!("Skipping unsupported intrinsic test: the test uses an x86_64 intrinsic");
+            return;
+        }
+        runner.expect_compile_error(
+            ["example/unsupported_intrinsic.rs", "--emit", "obj"],
+            &["not yet implemented by cg_clif", "-->"],
+        );
+    }),
+    TestCase::custom("aot.intrinsic_target_feature", &|runner| {
+        if !runner.target_compiler.triple.starts_with("x86_64") {
+            eprintln!("Skipping intrinsic target feature test: the test uses an x86_64 intrinsic");
+            return;
+        }
+        let expected = "`llvm.x86.avx2.pmul.hr.sw` requires the `avx2` target feature";
+        let stderr = runner.expect_compile_error(
+            ["example/intrinsic_target_feature.rs", "--emit", "obj"],
+            &[expected],
+        );
+        // Only the call in the function without `#[target_feature(enable = "avx2")]` is rejected.
+        assert_eq!(stderr.matches(expected).count(), 1, "unexpected errors:\n{stderr}");
+
+        runner.run_rustc(["example/intrinsic_target_feature.rs", "-Ctarget-feature=+avx2"]);
+        runner.run_out_command("intrinsic_target_feature", &[]);
+    }),
+    TestCase::custom("aot.disabled_target_feature", &|runner| {
+        if !runner.target_compiler.triple.starts_with("x86_64") {
+            eprintln!("Skipping disabled target feature test: the test uses x86_64 features");
+            return;
+        }
+        // Disabling avx must also disable avx2 and fma of haswell, which imply avx.
+        let mut cmd = runner.rustc_command([
+            "--print",
+            "cfg",
+            "-Ctarget-cpu=haswell",
+            "-Ctarget-feature=-avx",
+        ]);
+        let cfg = runner.checked_output(&mut cmd);
+        let has_feature =
+            |feature: &str| cfg.lines().any(|line| line == format!("target_feature=\"{feature}\""));
+        for feature in ["avx", "avx2", "fma"] {
+            assert!(!has_feature(feature), "`{feature}` enabled despite -avx:\n{cfg}");
+        }
+        for feature in ["sse4.2", "bmi2", "popcnt"] {
+            assert!(has_feature(feature), "`{feature}` of haswell not enabled:\n{cfg}");
+        }
+    }),
+    TestCase::custom("aot.target_feature_flags", &|runner| {
+        // Checks that `--target-feature` of the build system reaches the test programs.
+        let features = runner
+            .target_compiler
+            .rustflags
+            .iter()
+            .filter_map(|flag| flag.strip_prefix("-Ctarget-feature="))
+            .flat_map(|features| features.split(','))
+            .filter_map(|feature| feature.strip_prefix('+'))
+            .collect::<Vec<_>>();
+        if features.is_empty() {
+            eprintln!("Skipping target feature flags test: no target features enabled");
+            return;
+        }
+        let mut cmd = runner.rustc_command(["--print", "cfg"]);
+        let cfg = runner.checked_output(&mut cmd);
+        for feature in features {
+            let expected = format!("target_feature=\"{feature}\"");
+            assert!(cfg.lines().any(|line| line == expected), "`{expected}` not enabled:\n{cfg}");
+        }
+    }),
+    TestCase::custom("aot.uninhabited_discriminant", &|runner| {
+        // Without debuginfo the reference is kept in a register rather than loaded from the stack.
+        let clif = runner.run_rustc_and_dump_clif("uninhabited_discriminant", &["-Cdebuginfo=0"]);
+        // Only look at the instructions and not at the isa flags in the header.
+        let insts = clif.lines().filter(|line| line.starts_with(' ')).collect::<Vec<_>>();
+        assert!(
+            insts.iter().any(|inst| inst.contains("trap unreachable")),
+            "no trap emitted for the discriminant of an uninhabited enum:\n{clif}",
+        );
+        assert!(
+            !insts.iter().any(|inst| inst.contains("load")),
+            "discriminant of an uninhabited enum loaded:\n{clif}",
+        );
+    }),
+    TestCase::custom("aot.unsized_local", &|runner| {
+        runner.expect_compile_error(
+            ["example/unsized_local.rs", "--emit", "obj"],
+            &["unsized locals are not yet supported", "-->"],
+        );
+    }),
+    TestCase::custom("aot.simd_shuffle_out_of_bounds", &|runner| {
+        runner.expect_compile_error(
+            ["example/simd_shuffle_out_of_bounds.rs", "--emit", "obj"],
+            &["shuffle index #1 is out of bounds (limit 4)"],
+        );
+    }),
+    TestCase::custom("aot.llvm_backend_fallback", &|runner| {
+        // cg_clif rejects the unsized local in this crate, see `aot.unsized_local`.
+        let mut cmd = runner.rustc_command([
+            "example/unsized_local.rs",
+            "--emit",
+            "obj",
+            "--with-llvm-backend-fallback",
+        ]);
+        let output = runner.output(&mut cmd);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(output.status.success(), "{cmd:?} failed despite the fallback:\n{stderr}");
+        assert!(
+            stderr.contains("compiling it with the LLVM backend instead"),
+            "LLVM backend fallback not used:\n{stderr}",
+        );
+        assert!(BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("unsized_local.o").exists());
+
+        // Errors in the crate itself are reported without falling back.
+        let type_error = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("type_error.rs");
+        fs::write(&type_error, "fn main() { let _: u32 = \"\"; }\n").unwrap();
+        let stderr = runner.expect_compile_error(
+            [type_error.as_os_str(), "--with-llvm-backend-fallback".as_ref()],
+            &["mismatched types"],
+        );
+        assert!(!stderr.contains("LLVM backend"), "fell back for a type error:\n{stderr}");
+
+        // Cargo relies on the stdout of rustc for `--print`.
+        let mut cmd = runner.rustc_command(["--print", "cfg", "--with-llvm-backend-fallback"]);
+        let stdout = runner.checked_output(&mut cmd);
+        assert!(stdout.contains("target_arch="), "no cfg printed:\n{stdout}");
+    }),
+    TestCase::custom("aot.location_detail", &|runner| {
+        let object_file = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("location_detail.o");
+        for (location_detail, expect_file_name) in [("file,line,column", true), ("none", false)] {
+            let mut cmd = runner.rustc_command(["example/location_detail.rs", "--emit", "obj"]);
+            // Debuginfo contains the file name independently of `-Zlocation-detail`.
+            cmd.arg("-Cdebuginfo=0");
+            cmd.arg(format!("-Zlocation-detail={location_detail}"));
+            runner.spawn_and_wait(cmd);
+            let object = fs::read(&object_file).unwrap();
+            let has_file_name =
+                object.windows(b"location_detail.rs".len()).any(|w| w == b"location_detail.rs");
+            assert_eq!(
+                has_file_name, expect_file_name,
+                "unexpected panic location with -Zlocation-detail={location_detail}",
+            );
+        }
+    }),
+    TestCase::custom("aot.deterministic", &|runner| {
+        let object_file = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("deterministic.o");
+        let working_dir = std::env::current_dir().unwrap();
+        let objects = [(); 2].map(|()| {
+            let mut cmd = runner.rustc_command(["example/deterministic.rs", "--emit", "obj"]);
+            cmd.arg(format!("--remap-path-prefix={}=/remapped", working_dir.display()));
+            runner.spawn_and_wait(cmd);
+            fs::read(&object_file).unwrap()
+        });
+        assert!(objects[0] == objects[1], "compiling the same crate twice gave different objects");
+        let working_dir = working_dir.to_str().unwrap().as_bytes();
+        assert!(
+            !objects[0].windows(working_dir.len()).any(|w| w == working_dir),
+            "working directory not remapped in the object file",
+        );
+    }),
+    TestCase::custom("aot.incremental_reuse", &|runner| {
+        let incr_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("incremental_reuse");
+        let _ = fs::remove_dir_all(&incr_dir);
+        for cfg in ["rpass1", "rpass2"] {
+            // `--emit obj` would force a single codegen unit.
+            let mut cmd = runner.rustc_command([
+                "example/incremental_reuse.rs",
+                "-Zquery-dep-graph",
+                "--check-cfg=cfg(rpass1,rpass2)",
+                "--cfg",
+                cfg,
+            ]);
+            cmd.arg("-Cincremental").arg(&incr_dir);
+            // rustc errors if the codegen units marked with `rustc_partition_reused` aren't reused.
+            cmd.env_remove("CG_CLIF_DISABLE_INCR_CACHE");
+            runner.spawn_and_wait(cmd);
+        }
+    }),
+    TestCase::custom("aot.tls_model", &|runner| {
+        let mut cmd = runner.rustc_command([
+            "example/tls_model.rs",
+            "--emit",
+            "obj",
+            "-Ztls-model=local-exec",
+        ]);
+        let output = runner.output(&mut cmd);
+        assert!(output.status.success(), "{cmd:?} exited with status {}", output.status);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("`-Ztls-model` is not yet supported by Cranelift"),
+            "no warning about the unsupported TLS model:\n{stderr}",
+        );
+    }),
+    TestCase::custom("aot.native_static_libs", &|runner| {
+        if runner.target_compiler.triple.contains("windows") {
+            eprintln!("Skipping native static libs test: MSVC style library names are printed");
+            return;
+        }
+        let mut cmd =
+            runner.rustc_command(["example/native_static_libs.rs", "--print=native-static-libs"]);
+        let output = runner.output(&mut cmd);
+        assert!(output.status.success(), "{cmd:?} exited with status {}", output.status);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let native_static_libs = stderr
+            .lines()
+            .find_map(|line| line.strip_prefix("note: native-static-libs: "))
+            .unwrap_or_else(|| panic!("no native-static-libs line found:\n{stderr}"));
+        assert!(
+            native_static_libs.split(' ').any(|lib| lib == "-lm"),
+            "`-lm` missing from native-static-libs: {native_static_libs}",
+        );
+    }),
+    TestCase::custom("aot.abi_tag", &|runner| {
+        if !runner.target_compiler.triple.ends_with("-linux-gnu") {
+            eprintln!("Skipping ABI tag test: only glibc targets are checked");
+            return;
+        }
+        runner.run_rustc(["example/abi_tag.rs"]);
+        runner.run_out_command("abi_tag", &[]);
+        let executable = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("abi_tag");
+        let mut readelf_cmd = Command::new("readelf");
+        readelf_cmd.arg("-nW").arg(&executable);
+        let notes = runner.checked_output(&mut readelf_cmd);
+        let abi_tag = notes
+            .split("Displaying notes found in: ")
+            .find_map(|section| section.strip_prefix(".note.ABI-tag\n"))
+            .unwrap_or_else(|| panic!("no .note.ABI-tag section:\n{notes}"));
+        assert!(abi_tag.contains("NT_GNU_ABI_TAG"), "not a NT_GNU_ABI_TAG note:\n{abi_tag}");
+        let version = abi_tag
+            .split_once("OS: Linux, ABI: ")
+            .and_then(|(_, version)| version.lines().next())
+            .unwrap_or_else(|| panic!("ABI tag doesn't declare Linux:\n{abi_tag}"))
+            .split('.')
+            .map(|part| part.trim().parse::<u32>().unwrap())
+            .collect::<Vec<_>>();
+        assert!(*version >= [2, 6, 0][..], "unexpectedly old minimum kernel version {version:?}");
+    }),
+    TestCase::custom("aot.eh_frame_order", &|runner| {
+        let triple = &runner.target_compiler.triple;
+        if !triple.contains("linux") || triple.starts_with("s390x") {
+            eprintln!("Skipping eh_frame order test: only little-endian Linux is checked");
+            return;
+        }
+        runner.run_rustc(["example/eh_frame_order.rs", "-Ccodegen-units=1"]);
+        runner.run_out_command("eh_frame_order", &[]);
+        let out_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs);
+        let executable = out_dir.join("eh_frame_order");
+        let parse_hex = |hex: &str| u64::from_str_radix(hex, 16).unwrap();
+
+        let symbols = runner.checked_output(Command::new("nm").arg(&executable));
+        let mut functions =
+            ["eh_frame_order_first", "eh_frame_order_second", "eh_frame_order_third"].map(|name| {
+                let address = symbols
+                    .lines()
+                    .find_map(|line| line.strip_suffix(&format!(" T {name}")))
+                    .unwrap_or_else(|| panic!("{name} not found in symbol table:\n{symbols}"));
+                (parse_hex(address), name)
+            });
+        functions.sort();
+
+        // The initial location of every FDE in .eh_frame, in the order they appear in the section.
+        let frames = runner
+            .checked_output(Command::new("readelf").arg("--debug-dump=frames").arg(&executable));
+        let fde_locations = frames
+            .split("Contents of the ")
+            .find_map(|frames| frames.strip_prefix(".eh_frame section:"))
+            .unwrap_or_else(|| panic!("no .eh_frame section:\n{frames}"))
+            .lines()
+            .filter(|line| line.contains(" FDE "))
+            .map(|line| {
+                let (_, pc_range) = line.split_once(" pc=").unwrap();
+                parse_hex(pc_range.split_once("..").unwrap().0)
+            })
+            .collect::<Vec<_>>();
+        let fde_indices = functions.map(|(address, name)| {
+            fde_locations
+                .iter()
+                .position(|&location| location == address)
+                .unwrap_or_else(|| panic!("no FDE found for {name}"))
+        });
+        assert!(
+            fde_indices.windows(2).all(|pair| pair[0] < pair[1]),
+            "FDEs not sorted by address: {functions:x?} have FDEs {fde_indices:?}",
+        );
+
+        // The linker has to create a sorted .eh_frame_hdr search table covering all functions,
+        // which the dynamic loader and unwinders find through PT_GNU_EH_FRAME.
+        let sections = runner.checked_output(Command::new("readelf").arg("-SW").arg(&executable));
+        let section_range = |name: &str| {
+            let fields = sections
+                .lines()
+                .filter_map(|line| Some(line.split_once(']')?.1.split_whitespace().collect()))
+                .find(|fields: &Vec<&str>| fields[0] == name)
+                .unwrap_or_else(|| panic!("no {name} section:\n{sections}"));
+            let addr = parse_hex(fields[2]);
+            addr..addr + parse_hex(fields[4])
+        };
+        let eh_frame = section_range(".eh_frame");
+        let hdr_addr = section_range(".eh_frame_hdr").start;
+        let segments = runner.checked_output(Command::new("readelf").arg("-lW").arg(&executable));
+        assert!(
+            segments.lines().any(|line| {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                fields.first() == Some(&"GNU_EH_FRAME")
+                    && parse_hex(fields[2].trim_start_matches("0x")) == hdr_addr
+            }),
+            "no PT_GNU_EH_FRAME program header for .eh_frame_hdr:\n{segments}",
+        );
+
+        let hdr_file = out_dir.join("eh_frame_order.eh_frame_hdr");
+        runner.checked_output(
+            Command::new(get_llvm_objcopy_path(&runner.host_rustc))
+                .args(["-O", "binary", "--only-section=.eh_frame_hdr"])
+                .arg(&executable)
+                .arg(&hdr_file),
+        );
+        let hdr = fs::read(&hdr_file).unwrap();
+        let read_offset = |offset: usize| {
+            let value = i32::from_le_bytes(hdr[offset..offset + 4].try_into().unwrap());
+            hdr_addr.wrapping_add(value as u64)
+        };
+        // Version 1 with DW_EH_PE_pcrel | DW_EH_PE_sdata4, DW_EH_PE_udata4 and
+        // DW_EH_PE_datarel | DW_EH_PE_sdata4 encodings.
+        assert_eq!(hdr[..4], [1, 0x1b, 0x03, 0x3b], "unexpected .eh_frame_hdr header");
+        assert_eq!(read_offset(4) + 4, eh_frame.start, ".eh_frame_hdr doesn't point to .eh_frame");
+        let fde_count = u32::from_le_bytes(hdr[8..12].try_into().unwrap()) as usize;
+        let table = (0..fde_count)
+            .map(|i| (read_offset(12 + i * 8), read_offset(16 + i * 8)))
+            .collect::<Vec<_>>();
+        assert!(
+            table.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            ".eh_frame_hdr search table not sorted: {table:x?}",
+        );
+        for (address, name) in functions {
+            let index = table
+                .binary_search_by_key(&address, |&(location, _)| location)
+                .unwrap_or_else(|_| panic!("{name} missing from .eh_frame_hdr"));
+            assert!(eh_frame.contains(&table[index].1), "FDE of {name} outside of .eh_frame");
+        }
+    }),
+    TestCase::custom("aot.relocation_model_static", &|runner| {
+        if !runner.target_compiler.triple.starts_with("x86_64-unknown-linux") {
+            eprintln!("Skipping relocation model test: only x86_64 Linux is checked");
+            return;
+        }
+        runner.run_rustc([
+            "example/relocation_model.rs",
+            "--emit",
+            "obj",
+            "-Ccodegen-units=1",
+            "-Crelocation-model=static",
+        ]);
+        let object = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("relocation_model.o");
+        let mut readelf_cmd = Command::new("readelf");
+        readelf_cmd.arg("-rW").arg(object);
+        let relocs = runner.checked_output(&mut readelf_cmd);
+
+        // Split the output into the relocation types of every relocation section.
+        let mut sections = HashMap::<&str, Vec<&str>>::new();
+        let mut current_section = "";
+        for line in relocs.lines() {
+            if let Some(rest) = line.strip_prefix("Relocation section '") {
+                current_section = rest.split('\'').next().unwrap();
+            } else if let Some(ty) = line.split_whitespace().nth(2) {
+                if ty.starts_with("R_X86_64_") {
+                    sections.entry(current_section).or_default().push(ty);
+                }
+            }
+        }
+        let text = &sections[".rela.text"];
+        assert!(text.contains(&"R_X86_64_64"), "no absolute relocation found:\n{relocs}");
+        assert!(
+            !text.iter().any(|ty| ty.contains("GOT") || ty.contains("PLT")),
+            "GOT or PLT relocation found:\n{relocs}",
+        );
+        let eh_frame = &sections[".rela.eh_frame"];
+        assert!(
+            eh_frame.iter().all(|&ty| ty == "R_X86_64_64"),
+            "pc-relative .eh_frame relocation found:\n{relocs}",
+        );
+    }),
+    TestCase::custom("aot.vectorcall", &|runner| {
+        let triple = &runner.target_compiler.triple;
+        if !triple.starts_with("x86_64") || !triple.contains("-windows") {
+            eprintln!("Skipping vectorcall test: only x86_64 Windows is supported");
+            return;
+        }
+        runner.run_rustc(["example/vectorcall.rs"]);
+        runner.run_out_command("vectorcall", &[]);
+    }),
+    TestCase::custom("aot.pair_return", &|runner| {
+        let clif = runner.run_rustc_and_dump_clif("pair_return", &[]);
+        let signature = clif.lines().find(|line| line.starts_with("function ")).unwrap();
+        assert!(
+            signature.contains("-> i64, i64") && !signature.contains("sret"),
+            "pair not returned in registers: {signature}",
+        );
+    }),
+    TestCase::custom("aot.ctlz_nonzero", &|runner| {
+        let clif = runner.run_rustc_and_dump_clif("ctlz_nonzero", &["-O"]);
+        assert!(clif.contains("clz"), "no clz found:\n{clif}");
+        assert!(!clif.contains("brif"), "zero check emitted for ctlz_nonzero:\n{clif}");
+
+        // Debug assertions enable UB checks, which check for a zero argument. aot.ub_checks
+        // checks that the check fires.
+        let clif = runner.run_rustc_and_dump_clif("ctlz_nonzero", &["-Cdebug-assertions=on"]);
+        assert!(clif.contains("clz"), "no clz found:\n{clif}");
+        assert!(
+            clif.lines().any(|line| line.starts_with(' ') && line.contains("brif")),
+            "no zero check emitted for ctlz_nonzero with debug assertions:\n{clif}",
+        );
+    }),
+    TestCase::custom("aot.unchecked_div", &|runner| {
+        // Only look at the instructions and not at the isa flags in the header.
+        let count_guards = |clif: &str| {
+            clif.lines().filter(|line| line.starts_with(' ') && line.contains("brif")).count()
+        };
+        let has_guard = |clif: &str| count_guards(clif) != 0;
+        let clif = runner.run_rustc_and_dump_clif("unchecked_div", &["--cfg", "checked"]);
+        assert!(has_guard(&clif), "no guard emitted for `/` and `%`:\n{clif}");
+        // `/` and `%` are each guarded once by the MIR assertions for a zero divisor and for
+        // overflow. UB checks must not guard them a second time.
+        let clif = runner.run_rustc_and_dump_clif(
+            "unchecked_div",
+            &["--cfg", "checked", "-Cllvm-args=ub_checks=true"],
+        );
+        assert_eq!(count_guards(&clif), 4, "UB checks guard `/` and `%` a second time:\n{clif}");
+        let clif =
+            runner.run_rustc_and_dump_clif("unchecked_div", &["-Cllvm-args=ub_checks=false"]);
+        assert!(!has_guard(&clif), "guard emitted for unchecked_div and unchecked_rem:\n{clif}");
+        let clif = runner.run_rustc_and_dump_clif("unchecked_div", &["-Cllvm-args=ub_checks=true"]);
+        assert!(
+            has_guard(&clif),
+            "no UB check emitted for unchecked_div and unchecked_rem:\n{clif}"
+        );
+    }),
+    TestCase::custom("aot.neon_vcnt", &|runner| {
+        if !runner.target_compiler.triple.starts_with("aarch64") {
+            eprintln!("Skipping vector popcnt test: only lowered natively on AArch64");
+            return;
+        }
+        let clif = runner.run_rustc_and_dump_clif("neon_vcnt", &["-O"]);
+        let popcnts = clif.matches(" popcnt ").count();
+        assert_eq!(popcnts, 1, "vcntq_u8 not lowered to a single vector popcnt:\n{clif}");
+    }),
+    TestCase::custom("aot.volatile_mmio", &|runner| {
+        // `-Copt-level=3` enables the alias analysis of Cranelift, which `-O` doesn't.
+        let clif = runner.run_rustc_and_dump_clif("volatile_mmio", &["-Copt-level=3"]);
+        let loads = clif.matches(" = load.i32 heap ").count();
+        assert_eq!(loads, 3, "volatile loads were merged or removed:\n{clif}");
+        // The stores in the `heap` alias region are emitted for the volatile loads.
+        assert!(
+            clif.lines().any(|line| line.contains(" store ") && !line.contains(" heap ")),
+            "volatile store was removed:\n{clif}",
+        );
+        assert!(!clif.contains("fence"), "fence emitted for volatile loads:\n{clif}");
+        assert!(
+            clif.lines().any(|line| line.contains(" = explicit_slot 1")),
+            "stack slot for the `heap` store of a volatile load is not 1 byte:\n{clif}",
+        );
+
+        // Without optimizations loads are never merged, so the `heap` stores are not necessary.
+        let clif = runner.run_rustc_and_dump_clif("volatile_mmio", &[]);
+        let loads = clif.matches(" = load.i32 heap ").count();
+        assert_eq!(loads, 3, "volatile loads were merged or removed:\n{clif}");
+        assert!(
+            !clif.contains(" store heap "),
+            "`heap` store emitted without optimizations:\n{clif}"
+        );
+    }),
+    TestCase::custom("aot.offset_of", &|runner| {
+        let clif = runner.run_rustc_and_dump_clif("offset_of", &[]);
+        for offset in [8, 16] {
+            let constant = format!(" {offset}");
+            assert!(
+                clif.lines().any(|line| line.contains(" = iconst.") && line.ends_with(&constant)),
+                "offset {offset} not emitted as constant:\n{clif}",
+            );
+        }
+        // Only look at the instructions and not at the isa flags in the header.
+        let insts = clif.lines().filter(|line| line.starts_with(' ')).collect::<Vec<_>>();
+        for op in ["iadd", "stack_addr", "load"] {
+            assert!(
+                !insts.iter().any(|inst| inst.contains(op)),
+                "`{op}` emitted for offset_of:\n{clif}",
+            );
+        }
+    }),
+    TestCase::custom("aot.atomic_i128", &|runner| {
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping 128bit atomics test: libatomic is only linked on Linux");
+            return;
+        }
+        runner.run_rustc(["example/atomic_i128.rs"]);
+        runner.run_out_command("atomic_i128", &[]);
+    }),
+    TestCase::custom("aot.codegen_units", &|runner| {
+        let out_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs);
+        let is_cgu_object = |name: &str| {
+            name.starts_with("codegen_units.") && name.contains("-cgu.") && name.ends_with(".o")
+        };
+        let cgu_objects = || {
+            fs::read_dir(&out_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| is_cgu_object(path.file_name().unwrap().to_str().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        for path in cgu_objects() {
+            fs::remove_file(path).unwrap();
+        }
+        runner.run_rustc(["example/codegen_units.rs", "-Ccodegen-units=4", "-Csave-temps"]);
+        let objects = cgu_objects();
+        assert_eq!(objects.len(), 4, "expected one object file per codegen unit: {objects:?}");
+    }),
+    TestCase::custom("aot.asan", &|runner| {
+        if !runner.target_compiler.triple.starts_with("x86_64-unknown-linux") {
+            eprintln!("Skipping ASan test: the sanitizer runtime is only tested on x86_64 Linux");
+            return;
+        }
+        runner.run_rustc(["example/asan_heap_oob.rs", "-Zsanitizer=address"]);
+        if !runner.is_native || runner.check_only {
+            eprintln!("Cross-Compiling or check-only: Not running asan_heap_oob");
+            return;
+        }
+        for access in ["read", "copy"] {
+            let output = runner.output(
+                Command::new(BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("asan_heap_oob"))
+                    .arg(access),
+            );
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            assert!(
+                !output.status.success() && stderr.contains("heap-buffer-overflow"),
+                "out-of-bounds {access} not caught by ASan:\n{stderr}",
+            );
+        }
+    }),
+    TestCase::custom("aot.ub_checks", &|runner| {
+        runner.run_rustc([
+            "example/ub_checks.rs",
+            "-Cdebug-assertions=off",
+            "-Cllvm-args=ub_checks=true",
+        ]);
+        if !runner.is_native || runner.check_only {
+            eprintln!("Cross-Compiling or check-only: Not running ub_checks");
+            return;
+        }
+        for (check, message) in [
+            ("copy_nonoverlapping", "`copy_nonoverlapping` called with overlapping memory ranges"),
+            ("exact_div", "`exact_div` called with an inexact division"),
+            (
+                "unchecked_div",
+                "`unchecked_div` or `unchecked_rem` called with a zero divisor or overflowing operands",
+            ),
+            ("ctlz_nonzero", "`ctlz_nonzero` called with zero"),
+            ("offset", "in-bounds pointer offset overflowed"),
+        ] {
+            let output = runner.output(
+                Command::new(BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("ub_checks"))
+                    .arg(check),
+            );
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            assert!(
+                !output.status.success() && stderr.contains(message),
+                "invalid {check} not caught by UB checks:\n{stderr}",
+            );
+        }
+    }),
+    TestCase::custom("aot.dylib_visibility", &|runner| {
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping dylib visibility test: the test inspects ELF dynamic symbols");
+            return;
+        }
+        runner.run_rustc(["example/dylib_visibility.rs", "-Ccodegen-units=2"]);
+        let dylib = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("libdylib_visibility.so");
+        let mut nm_cmd = Command::new("nm");
+        nm_cmd.arg("-D").arg("--defined-only").arg(&dylib);
+        let symbols = runner.checked_output(&mut nm_cmd);
+        assert!(symbols.contains("exported_entry"), "exported_entry not exported:\n{symbols}");
+        assert!(
+            !symbols.contains("internal_helper"),
+            "internal_helper leaked into the dynamic symbol table:\n{symbols}",
+        );
+    }),
+    TestCase::custom("aot.used_static", &|runner| {
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping used static test: the test relies on ELF section GC");
+            return;
+        }
+        runner.run_rustc(["example/used_static.rs"]);
+        let executable =
+            fs::read(BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("used_static")).unwrap();
+        let contains = |needle: &[u8]| executable.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"cg_clif_used_linker!"), "#[used(linker)] static removed by the linker");
+        assert!(
+            !contains(b"cg_clif_used_compiler!"),
+            "#[used(compiler)] static unexpectedly kept by the linker",
+        );
+    }),
+    TestCase::custom("aot.linker_script", &|runner| {
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!(
+                "Skipping linker script test: only GNU style linkers accept implicit scripts"
+            );
+            return;
+        }
+        let linker_script = runner.dirs.source_dir.join("example/linker_script.ld");
+        runner.run_rustc([
+            "example/linker_script.rs".as_ref(),
+            "-Clink-arg".as_ref(),
+            linker_script.as_os_str(),
+        ]);
+        runner.run_out_command("linker_script", &[]);
+    }),
+    TestCase::custom("aot.closure_symbol", &|runner| {
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping closure symbol test: backtraces are only symbolized on Linux");
+            return;
+        }
+        for (mangling, expected) in [
+            ("legacy", "closure_symbol::parent_function::{{closure}}"),
+            ("v0", "closure_symbol::parent_function::{closure#0}"),
+        ] {
+            runner.run_rustc([
+                "example/closure_symbol.rs",
+                &format!("-Csymbol-mangling-version={mangling}"),
+            ]);
+            runner.run_out_command("closure_symbol", &[expected]);
+        }
+    }),
+    TestCase::custom("aot.emit_llvm_bc", &|runner| {
+        let stderr = runner.expect_compile_error(
+            ["example/ctlz_nonzero.rs", "--emit", "llvm-bc"],
+            &["error: `--emit llvm-bc` is not supported by the Cranelift backend"],
+        );
+        assert!(!stderr.contains("panicked"), "unexpected panic for --emit llvm-bc:\n{stderr}");
+    }),
+    TestCase::custom("aot.emit_asm", &|runner| {
+        let asm_file = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("emit_asm.s");
+        let _ = fs::remove_file(&asm_file);
+        runner.run_rustc([
+            "example/ctlz_nonzero.rs",
+            "-Ccodegen-units=1",
+            &format!("--emit=asm={}", asm_file.display()),
+        ]);
+        let asm = fs::read_to_string(&asm_file).unwrap();
+        let lines = asm
+            .lines()
+            .skip_while(|line| *line != "ctlz_nonzero:")
+            .skip(1)
+            .take_while(|line| !line.trim().is_empty())
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        assert!(
+            lines.iter().any(|line| !line.starts_with('.') && !line.ends_with(':')),
+            "no instructions for ctlz_nonzero found in the assembly:\n{asm}",
+        );
+
+        // Cranelift always sets up a frame pointer on x86_64, so there is unwind info to describe.
+        // Windows uses its own unwind info, for which no directives are emitted.
+        let triple = &runner.target_compiler.triple;
+        if triple.starts_with("x86_64") && !triple.contains("windows") {
+            assert!(
+                lines.first() == Some(&".cfi_startproc")
+                    && lines.last() == Some(&".cfi_endproc")
+                    && lines.iter().any(|line| line.starts_with(".cfi_def_cfa"))
+                    && lines.iter().any(|line| line.starts_with(".cfi_offset"))
+                    && !lines.iter().any(|line| line.starts_with("unwind ")),
+                "missing cfi directives for ctlz_nonzero in the assembly:\n{asm}",
+            );
+        }
+    }),
+    TestCase::custom("aot.instrument_coverage", &|runner| {
+        let triple = &runner.target_compiler.triple;
+        if !runner.has_profiler_runtime() || !triple.contains("linux") {
+            eprintln!("Skipping coverage test: no profiler runtime available for {triple}");
+            return;
+        }
+        runner.run_rustc(["example/instrument_coverage.rs", "-Cinstrument-coverage"]);
+        if !runner.is_native || runner.check_only {
+            eprintln!("Cross-Compiling or check-only: Not running instrument_coverage");
+            return;
+        }
+
+        let out_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs);
+        let profraw = out_dir.join("instrument_coverage.profraw");
+        let _ = fs::remove_file(&profraw);
+        let mut run_cmd = Command::new(out_dir.join("instrument_coverage"));
+        run_cmd.env("LLVM_PROFILE_FILE", &profraw);
+        runner.spawn_and_wait(run_cmd);
+        assert!(profraw.exists(), "no profile written to {}", profraw.display());
+
+        let profdata = out_dir.join("instrument_coverage.profdata");
+        let mut merge_cmd = Command::new(get_llvm_profdata_path(&runner.host_rustc));
+        merge_cmd.arg("merge").arg("-o").arg(&profdata).arg(&profraw);
+        runner.spawn_and_wait(merge_cmd);
+
+        // Check the line counts `llvm-cov` computes using the coverage mappings.
+        let mut show_cmd = Command::new(get_llvm_cov_path(&runner.host_rustc));
+        show_cmd
+            .arg("show")
+            .arg(out_dir.join("instrument_coverage"))
+            .arg(format!("-instr-profile={}", profdata.display()));
+        let stdout = runner.checked_output(&mut show_cmd);
+        for (source, count) in [("if x > 1 {", "3"), ("x * 2", "3"), ("x + 1", "0")] {
+            let line_count = stdout.lines().find_map(|line| {
+                let mut columns = line.splitn(3, '|');
+                let (_line_number, line_count) = (columns.next()?, columns.next()?);
+                (columns.next()?.trim() == source).then(|| line_count.trim())
+            });
+            assert_eq!(
+                line_count,
+                Some(count),
+                "wrong count for line `{source}` in the coverage report:\n{stdout}",
+            );
+        }
+    }),
+    TestCase::custom("aot.profile_generate", &|runner| {
+        let triple = &runner.target_compiler.triple;
+        if !runner.has_profiler_runtime() || !triple.contains("linux") {
+            eprintln!("Skipping profile generate test: no profiler runtime available for {triple}");
+            return;
+        }
+        let out_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs);
+        let profile_dir = out_dir.join("profile_generate_profiles");
+        let _ = fs::remove_dir_all(&profile_dir);
+        runner.run_rustc([
+            "example/profile_generate.rs".to_owned(),
+            format!("-Cprofile-generate={}", profile_dir.display()),
+        ]);
+        if !runner.is_native || runner.check_only {
+            eprintln!("Cross-Compiling or check-only: Not running profile_generate");
+            return;
+        }
+
+        runner.spawn_and_wait(Command::new(out_dir.join("profile_generate")));
+        let profraws = fs::read_dir(&profile_dir)
+            .into_iter()
+            .flatten()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension() == Some("profraw".as_ref()))
+            .collect::<Vec<_>>();
+        assert!(!profraws.is_empty(), "no profile written to {}", profile_dir.display());
+
+        let profdata = out_dir.join("profile_generate.profdata");
+        let mut merge_cmd = Command::new(get_llvm_profdata_path(&runner.host_rustc));
+        merge_cmd.arg("merge").arg("-o").arg(&profdata).args(&profraws);
+        runner.spawn_and_wait(merge_cmd);
+
+        let mut show_cmd = Command::new(get_llvm_profdata_path(&runner.host_rustc));
+        show_cmd.arg("show").arg("--all-functions").arg(&profdata);
+        let stdout = runner.checked_output(&mut show_cmd);
+        for (function, calls) in
+            [("profile_generate_called", 3), ("profile_generate_not_called", 0)]
+        {
+            let record = stdout
+                .split_once(&format!("  {function}:\n"))
+                .map(|(_, rest)| rest.lines().take_while(|line| line.starts_with("    ")))
+                .unwrap_or_else(|| panic!("{function} is missing from the profile:\n{stdout}"))
+                .collect::<Vec<_>>();
+            assert!(
+                record.iter().any(|line| line.trim() == format!("Function count: {calls}")),
+                "expected {calls} calls of {function} in the profile:\n{stdout}",
+            );
+        }
+
+        runner.run_rustc([
+            "example/profile_generate.rs".to_owned(),
+            format!("-Cprofile-use={}", profdata.display()),
+        ]);
+        runner.run_out_command("profile_generate", &[]);
+    }),
+    TestCase::custom("aot.embed_bitcode", &|runner| {
+        for embed_bitcode in ["-Cembed-bitcode=no", "-Cembed-bitcode=yes"] {
+            runner.run_rustc(["example/ctlz_nonzero.rs", embed_bitcode]);
+        }
+    }),
+    TestCase::custom("aot.embed_metadata", &|runner| {
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping embed metadata test: metadata is only embedded for ELF targets");
+            return;
+        }
+        runner.run_rustc([
+            "example/embed_metadata.rs",
+            "--emit",
+            "metadata,link",
+            "-Cllvm-args=embed_metadata=true",
+        ]);
+        let out_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs);
+        let metadata = fs::read(out_dir.join("libembed_metadata.rmeta")).unwrap();
+
+        let extract_dir = out_dir.join("embed_metadata.extracted");
+        let _ = fs::remove_dir_all(&extract_dir);
+        fs::create_dir_all(&extract_dir).unwrap();
+        let mut ar_cmd = Command::new(get_llvm_ar_path(&runner.host_rustc));
+        ar_cmd.arg("x").arg(out_dir.join("libembed_metadata.rlib")).current_dir(&extract_dir);
+        runner.spawn_and_wait(ar_cmd);
+
+        let section_file = extract_dir.join("rustc_section");
+        let embedded = fs::read_dir(&extract_dir).unwrap().find_map(|entry| {
+            let object = entry.unwrap().path();
+            if object.extension() != Some("o".as_ref()) {
+                return None;
+            }
+            // llvm-objcopy fails when the section doesn't exist, so check for it first.
+            let mut readelf_cmd = Command::new("readelf");
+            readelf_cmd.arg("-SW").arg(&object);
+            let sections = runner.checked_output(&mut readelf_cmd);
+            if !sections.lines().any(|line| line.split_whitespace().any(|field| field == ".rustc"))
+            {
+                return None;
+            }
+
+            let mut objcopy_cmd = Command::new(get_llvm_objcopy_path(&runner.host_rustc));
+            objcopy_cmd
+                .arg(format!("--dump-section=.rustc={}", section_file.display()))
+                .arg(&object)
+                .arg(extract_dir.join("objcopy.out"));
+            runner.spawn_and_wait(objcopy_cmd);
+            Some(fs::read(&section_file).unwrap())
+        });
+        let embedded = embedded.expect("no object file with a .rustc section in the rlib");
+        assert!(embedded == metadata, "embedded metadata differs from the .rmeta file");
+
+        // Dylibs already contain the metadata in a `.rustc` section, which must not be corrupted.
+        runner.run_rustc([
+            "example/embed_metadata.rs",
+            "--crate-type",
+            "dylib",
+            "-Cprefer-dynamic",
+            "-Cllvm-args=embed_metadata=true",
+        ]);
+        let dylib = out_dir.join("libembed_metadata.so");
+        runner.run_rustc([
+            "example/embed_metadata_user.rs".to_owned(),
+            "--edition=2021".to_owned(),
+            "--extern".to_owned(),
+            format!("embed_metadata={}", dylib.display()),
+        ]);
+    }),
+    TestCase::custom("aot.emit_paths", &|runner| {
+        let emit_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("emit_paths");
+        let _ = fs::remove_dir_all(&emit_dir);
+        fs::create_dir_all(&emit_dir).unwrap();
+        let outputs = [
+            ("obj", "custom_obj.o"),
+            ("metadata", "custom_metadata.rmeta"),
+            ("dep-info", "custom_dep_info.d"),
+            ("link", "custom_link.rlib"),
+        ];
+        let emit = outputs
+            .iter()
+            .map(|(kind, file)| format!("{kind}={}", emit_dir.join(file).display()))
+            .collect::<Vec<_>>()
+            .join(",");
+        runner.run_rustc([
+            "example/ctlz_nonzero.rs",
+            "-Ccodegen-units=1",
+            &format!("--emit={emit}"),
+        ]);
+        for (kind, file) in outputs {
+            let len = fs::metadata(emit_dir.join(file)).map_or(0, |metadata| metadata.len());
+            assert_ne!(len, 0, "--emit {kind} not written to {file}");
+        }
+    }),
+    TestCase::custom("aot.object_cache", &|runner| {
+        let out_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs);
+        let cache_dir = out_dir.join("object_cache");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let build = |output: &str| {
+            let mut cmd = runner.rustc_command([
+                "example/ctlz_nonzero.rs",
+                "-Cdebuginfo=0",
+                "-Ccodegen-units=1",
+                &format!("--emit=obj={}", out_dir.join(output).display()),
+            ]);
+            cmd.env("CG_CLIF_OBJECT_CACHE_DIR", &cache_dir);
+            runner.spawn_and_wait(cmd);
+            fs::read(out_dir.join(output)).unwrap()
+        };
+
+        let object = build("object_cache1.o");
+        let cached = fs::read_dir(&cache_dir).unwrap().map(|entry| entry.unwrap().path());
+        let cached = cached.collect::<Vec<_>>();
+        assert_eq!(cached.len(), 1, "expected a single cached object: {cached:?}");
+        assert!(fs::read(&cached[0]).unwrap() == object, "cached object differs from the output");
+
+        // Mark the cached object to detect that it is reused rather than recompiled. Trailing data
+        // doesn't affect the object file.
+        let marker = b"reused from the object cache";
+        let mut marked = object;
+        marked.extend_from_slice(marker);
+        fs::write(&cached[0], &marked).unwrap();
+        assert!(build("object_cache2.o").ends_with(marker), "cached object not reused");
+    }),
+    TestCase::custom("aot.single_threaded", &|runner| {
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping single threaded test: threads are inspected through /proc");
+            return;
+        }
+        let mut cmd = runner.rustc_command([
+            "example/std_example.rs",
+            "--crate-name",
+            "single_threaded",
+            "-Ccodegen-units=16",
+        ]);
+        cmd.env("CG_CLIF_SINGLE_THREADED", "1");
+        let mut child = cmd.spawn().unwrap();
+        // Codegen worker threads are named `cgclif-codegen`. Sample the threads of rustc until it
+        // exits and make sure none of them ever shows up.
+        let task_dir = PathBuf::from(format!("/proc/{}/task", child.id()));
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+            for task in fs::read_dir(&task_dir).into_iter().flatten().flatten() {
+                let comm = fs::read_to_string(task.path().join("comm")).unwrap_or_default();
+                if comm.trim_end() == "cgclif-codegen" {
+                    let _ = child.kill();
+                    panic!("codegen thread spawned with CG_CLIF_SINGLE_THREADED=1");
+                }
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert!(status.success(), "{cmd:?} exited with status {status}");
+        runner.run_out_command("single_threaded", &["arg"]);
+    }),
+    TestCase::custom("aot.link_section_fn", &|runner| {
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping link section test: only supported for ELF targets");
+            return;
+        }
+        runner.run_rustc(["example/link_section_fn.rs"]);
+        runner.run_out_command("link_section_fn", &[]);
+        let executable = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("link_section_fn");
+        let mut objdump_cmd = Command::new("objdump");
+        objdump_cmd.arg("-t").arg(executable);
+        let symbols = runner.checked_output(&mut objdump_cmd);
+        for function in ["boot_entry", "boot_helper"] {
+            let symbol = symbols
+                .lines()
+                .find(|line| line.ends_with(&format!(" {function}")))
+                .unwrap_or_else(|| panic!("{function} not found in symbol table:\n{symbols}"));
+            assert!(symbol.contains(" .boot\t"), "{function} not in .boot: {symbol}");
+        }
+
+        // The code of boot_helper must only be emitted into .boot, not also into .text.
+        runner.run_rustc(["example/link_section_fn.rs", "--emit", "obj", "-Ccodegen-units=1"]);
+        let out_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs);
+        let object = out_dir.join("link_section_fn.o");
+        let mut nm_cmd = Command::new("nm");
+        nm_cmd.arg("-S").arg(&object);
+        let symbols = runner.checked_output(&mut nm_cmd);
+        let (offset, size) = symbols
+            .lines()
+            .find_map(|line| match *line.split_whitespace().collect::<Vec<_>>() {
+                [offset, size, _, "boot_helper"] => Some((
+                    usize::from_str_radix(offset, 16).unwrap(),
+                    usize::from_str_radix(size, 16).unwrap(),
+                )),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("boot_helper not found in symbol table:\n{symbols}"));
+        let (boot_file, text_file) =
+            (out_dir.join("link_section_fn.boot"), out_dir.join("link_section_fn.text"));
+        let mut objcopy_cmd = Command::new(get_llvm_objcopy_path(&runner.host_rustc));
+        objcopy_cmd
+            .arg("--dump-section")
+            .arg(format!(".boot={}", boot_file.display()))
+            .arg("--dump-section")
+            .arg(format!(".text={}", text_file.display()))
+            .arg(&object)
+            .arg(out_dir.join("link_section_fn.objcopy.o"));
+        runner.spawn_and_wait(objcopy_cmd);
+        let boot_helper = &fs::read(boot_file).unwrap()[offset..offset + size];
+        let text = fs::read(text_file).unwrap();
+        assert!(
+            !text.windows(size).any(|code| code == boot_helper),
+            "code of boot_helper duplicated in .text",
+        );
+    }),
+    TestCase::custom("aot.dwarf5", &|runner| {
+        if !runner.is_native || !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping DWARF 5 test: binutils may not support the target");
+            return;
+        }
+        runner.run_rustc(["example/dwarf5.rs", "-Zdwarf-version=5"]);
+        let executable = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("dwarf5");
+        let sections = runner.checked_output(Command::new("readelf").arg("-SW").arg(&executable));
+        for section in [".debug_rnglists", ".debug_line_str"] {
+            assert!(sections.contains(section), "{section} not found:\n{sections}");
+        }
+
+        let symbols = runner.checked_output(Command::new("nm").arg(&executable));
+        let address = symbols
+            .lines()
+            .find_map(|line| line.strip_suffix(" T dwarf5_marker"))
+            .unwrap_or_else(|| panic!("dwarf5_marker not found in symbol table:\n{symbols}"));
+        let mut addr2line_cmd = Command::new("addr2line");
+        addr2line_cmd.args(["-f", "-e"]).arg(&executable).arg(format!("0x{address}"));
+        let location = runner.checked_output(&mut addr2line_cmd);
+        assert!(
+            location.starts_with("dwarf5_marker\n") && location.contains("example/dwarf5.rs:"),
+            "dwarf5_marker not resolved to its source location:\n{location}",
+        );
+    }),
+    TestCase::custom("aot.debug_locals", &|runner| {
+        if !runner.target_compiler.triple.contains("linux") {
+            eprintln!("Skipping debug locals test: readelf may not support the target");
+            return;
+        }
+        runner.run_rustc(["example/debug_locals.rs", "--emit", "obj", "-Ccodegen-units=1"]);
+        let object = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("debug_locals.o");
+        let mut readelf_cmd = Command::new("readelf");
+        readelf_cmd.arg("--debug-dump=info").arg(object);
+        let info = runner.checked_output(&mut readelf_cmd);
+
+        // Every entry starts with a line like ` <2><4b>: Abbrev Number: 5 (DW_TAG_variable)`
+        // followed by a line for every attribute.
+        let mut entries = vec![];
+        for line in info.lines() {
+            if line.contains("Abbrev Number:") {
+                entries.push(vec![line]);
+            } else if let Some(entry) = entries.last_mut() {
+                entry.push(line);
+            }
+        }
+        for (tag, name) in [
+            ("DW_TAG_formal_parameter", "debug_locals_param"),
+            ("DW_TAG_variable", "debug_locals_var"),
+        ] {
+            let entry = entries
+                .iter()
+                .find(|entry| {
+                    entry.iter().any(|line| {
+                        line.contains("DW_AT_name") && line.ends_with(&format!(": {name}"))
+                    })
+                })
+                .unwrap_or_else(|| panic!("no debuginfo entry for {name}:\n{info}"));
+            let entry = entry.join("\n");
+            assert!(entry.contains(tag), "{name} is not a {tag}:\n{entry}");
+            for attr in ["DW_AT_location", "DW_AT_type", "DW_AT_decl_line"] {
+                assert!(entry.contains(attr), "{name} has no {attr}:\n{entry}");
+            }
+        }
+
+        runner.run_rustc(["example/debug_locals.rs"]);
+        runner.run_out_command("debug_locals", &[]);
+    }),
+    TestCase::custom("aot.thin_lto", &|runner| {
+        // The upstream std objects have to be linked even though no LTO is performed.
+        let mut cmd = runner.rustc_command([
+            "example/dst-field-align.rs",
+            "--crate-name",
+            "thin_lto",
+            "-Clto=thin",
+        ]);
+        let output = runner.output(&mut cmd);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(output.status.success(), "{cmd:?} exited with status {}:\n{stderr}", output.status);
+        let expected = "ThinLTO is not supported by Cranelift";
+        assert!(stderr.contains(expected), "`{expected}` not found in warnings:\n{stderr}");
+        runner.run_out_command("thin_lto", &[]);
+    }),
     TestCase::custom("aot.gen_block_iterate", &|runner| {
         runner.run_rustc([
             "example/gen_block_iterate.rs",
@@ -151,12 +1472,12 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
 
         RAND.clean(&runner.dirs);
 
-        if runner.is_native {
+        if runner.run_cargo_tests() {
             let mut test_cmd = RAND.test(&runner.target_compiler, &runner.dirs);
             test_cmd.arg("--workspace").arg("--").arg("-q");
             spawn_and_wait(test_cmd);
         } else {
-            eprintln!("Cross-Compiling: Not running tests");
+            eprintln!("Cross-Compiling or check-only: Not running tests");
             let mut build_cmd = RAND.build(&runner.target_compiler, &runner.dirs);
             build_cmd.arg("--workspace").arg("--tests");
             spawn_and_wait(build_cmd);
@@ -176,12 +1497,12 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
 
         LIBCORE_TESTS.clean(&runner.dirs);
 
-        if runner.is_native {
+        if runner.run_cargo_tests() {
             let mut test_cmd = LIBCORE_TESTS.test(&runner.target_compiler, &runner.dirs);
             test_cmd.arg("--").arg("-q");
             spawn_and_wait(test_cmd);
         } else {
-            eprintln!("Cross-Compiling: Not running tests");
+            eprintln!("Cross-Compiling or check-only: Not running tests");
             let mut build_cmd = LIBCORE_TESTS.build(&runner.target_compiler, &runner.dirs);
             build_cmd.arg("--tests");
             spawn_and_wait(build_cmd);
@@ -192,7 +1513,7 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
 
         REGEX.clean(&runner.dirs);
 
-        if runner.is_native {
+        if runner.run_cargo_tests() {
             let mut run_cmd = REGEX.test(&runner.target_compiler, &runner.dirs);
             // regex-capi and regex-debug don't have any tests. Nor do they contain any code
             // that is useful to test with cg_clif. Skip building them to reduce test time.
@@ -214,7 +1535,7 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
             run_cmd.args(["-p", "regex-automata", "--release", "--lib", "--", "-q"]);
             spawn_and_wait(run_cmd);
         } else {
-            eprintln!("Cross-Compiling: Not running tests");
+            eprintln!("Cross-Compiling or check-only: Not running tests");
             let mut build_cmd = REGEX.build(&runner.target_compiler, &runner.dirs);
             build_cmd.arg("--tests");
             spawn_and_wait(build_cmd);
@@ -229,7 +1550,7 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
         build_cmd.arg("--all-targets");
         spawn_and_wait(build_cmd);
 
-        if runner.is_native {
+        if runner.run_cargo_tests() {
             let mut test_cmd = PORTABLE_SIMD.test(&runner.target_compiler, &runner.dirs);
             test_cmd.arg("-q");
             // FIXME remove after portable-simd update
@@ -244,22 +1565,49 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
     }),
 ];
 
+/// Whether `name` is the name of a test or testsuite which can be enabled in config.txt.
+pub(crate) fn is_test_config(name: &str) -> bool {
+    matches!(name, "testsuite.no_sysroot" | "testsuite.base_sysroot" | "testsuite.extended_sysroot")
+        || [NO_SYSROOT_SUITE, BASE_SYSROOT_SUITE, EXTENDED_SYSROOT_SUITE]
+            .iter()
+            .any(|suite| suite.iter().any(|test| test.config == name))
+}
+
 pub(crate) fn run_tests(
     dirs: &Dirs,
     channel: &str,
-    sysroot_kind: SysrootKind,
-    use_unstable_features: bool,
-    skip_tests: &[&str],
+    options: &TestOptions<'_>,
     cg_clif_dylib: &CodegenBackend,
     bootstrap_host_compiler: &Compiler,
     rustup_toolchain_name: Option<&str>,
     target_triple: String,
 ) {
-    let stdlib_source =
-        get_default_sysroot(&bootstrap_host_compiler.rustc).join("lib/rustlib/src/rust");
-    assert!(stdlib_source.exists());
+    let &TestOptions { sysroot_kind, skip_tests, exclude_tests, shard, .. } = options;
+    let run_no_sysroot = is_test_enabled(skip_tests, exclude_tests, "testsuite.no_sysroot");
+    let run_base_sysroot = is_test_enabled(skip_tests, exclude_tests, "testsuite.base_sysroot");
+    let run_extended_sysroot =
+        is_test_enabled(skip_tests, exclude_tests, "testsuite.extended_sysroot");
+
+    // Tests are assigned to shards round-robin in the order in which they are declared. Only
+    // enabled tests are counted, so filtering with --skip-test and --exclude keeps the shards
+    // balanced. Libraries used by other tests are built by every shard.
+    let shard_tests = shard.map(|shard| {
+        [
+            (run_no_sysroot, NO_SYSROOT_SUITE),
+            (run_base_sysroot, BASE_SYSROOT_SUITE),
+            (run_extended_sysroot, EXTENDED_SYSROOT_SUITE),
+        ]
+        .into_iter()
+        .filter(|&(run_suite, _)| run_suite)
+        .flat_map(|(_, suite)| suite)
+        .filter(|test| !test.is_shared() && is_test_enabled(skip_tests, exclude_tests, test.config))
+        .enumerate()
+        .filter(|(i, _)| i % shard.count == shard.index - 1)
+        .map(|(_, test)| test.config)
+        .collect::<Vec<_>>()
+    });
 
-    if config::get_bool("testsuite.no_sysroot") && !skip_tests.contains(&"testsuite.no_sysroot") {
+    if run_no_sysroot {
         let target_compiler = build_sysroot::build_sysroot(
             dirs,
             channel,
@@ -270,14 +1618,14 @@ pub(crate) fn run_tests(
             target_triple.clone(),
         );
 
-        let runner = TestRunner::new(
+        let mut runner = TestRunner::new(
             dirs.clone(),
             target_compiler,
-            use_unstable_features,
-            skip_tests,
+            options,
             bootstrap_host_compiler.triple == target_triple,
-            stdlib_source.clone(),
+            bootstrap_host_compiler.rustc.clone(),
         );
+        runner.shard_tests = shard_tests.as_deref();
 
         BUILD_EXAMPLE_OUT_DIR.ensure_fresh(dirs);
         runner.run_testsuite(NO_SYSROOT_SUITE);
@@ -285,11 +1633,6 @@ pub(crate) fn run_tests(
         eprintln!("[SKIP] no_sysroot tests");
     }
 
-    let run_base_sysroot = config::get_bool("testsuite.base_sysroot")
-        && !skip_tests.contains(&"testsuite.base_sysroot");
-    let run_extended_sysroot = config::get_bool("testsuite.extended_sysroot")
-        && !skip_tests.contains(&"testsuite.extended_sysroot");
-
     if run_base_sysroot || run_extended_sysroot {
         let mut target_compiler = build_sysroot::build_sysroot(
             dirs,
@@ -304,14 +1647,14 @@ pub(crate) fn run_tests(
         // projects. Changing the code to fix them is not worth it, so just silence all lints.
         target_compiler.rustflags.push("--cap-lints=allow".to_owned());
 
-        let runner = TestRunner::new(
+        let mut runner = TestRunner::new(
             dirs.clone(),
             target_compiler,
-            use_unstable_features,
-            skip_tests,
+            options,
             bootstrap_host_compiler.triple == target_triple,
-            stdlib_source,
+            bootstrap_host_compiler.rustc.clone(),
         );
+        runner.shard_tests = shard_tests.as_deref();
 
         if run_base_sysroot {
             runner.run_testsuite(BASE_SYSROOT_SUITE);
@@ -325,6 +1668,29 @@ pub(crate) fn run_tests(
             eprintln!("[SKIP] extended_sysroot tests");
         }
     }
+
+    if let (Some(shard), Some(shard_tests)) = (shard, &shard_tests) {
+        eprintln!(
+            "[SHARD] Ran shard {}/{} with {} of the enabled tests",
+            shard.index,
+            shard.count,
+            shard_tests.len(),
+        );
+    }
+}
+
+/// Returns true if the test or testsuite `config` is enabled in config.txt and not filtered out by
+/// --skip-test or --exclude.
+fn is_test_enabled(skip_tests: &[&str], exclude_tests: &[&str], config: &str) -> bool {
+    config::get_bool(config) && !skip_tests.contains(&config) && !is_excluded(exclude_tests, config)
+}
+
+/// Returns true if `config` is part of any of the excluded test groups. A group matches either
+/// the full test name or any prefix of it ending at a `.`, so `jit` excludes all JIT tests.
+fn is_excluded(exclude_tests: &[&str], config: &str) -> bool {
+    exclude_tests.iter().any(|group| {
+        config == *group || config.strip_prefix(group).is_some_and(|rest| rest.starts_with('.'))
+    })
 }
 
 struct TestRunner<'a> {
@@ -332,20 +1698,30 @@ struct TestRunner<'a> {
     jit_supported: bool,
     use_unstable_features: bool,
     skip_tests: &'a [&'a str],
+    exclude_tests: &'a [&'a str],
+    /// The tests assigned to this shard when running with --shard.
+    shard_tests: Option<&'a [&'a str]>,
+    /// Only compile the test programs without running them.
+    check_only: bool,
+    timeout: Duration,
     dirs: Dirs,
     target_compiler: Compiler,
     stdlib_source: PathBuf,
+    /// The rustc of the toolchain providing the llvm-tools component.
+    host_rustc: PathBuf,
 }
 
 impl<'a> TestRunner<'a> {
     fn new(
         dirs: Dirs,
         mut target_compiler: Compiler,
-        use_unstable_features: bool,
-        skip_tests: &'a [&'a str],
+        options: &TestOptions<'a>,
         is_native: bool,
-        stdlib_source: PathBuf,
+        host_rustc: PathBuf,
     ) -> Self {
+        let stdlib_source = get_default_sysroot(&host_rustc).join("lib/rustlib/src/rust");
+        assert!(stdlib_source.exists());
+
         target_compiler.rustflags.extend(rustflags_from_env("RUSTFLAGS"));
         target_compiler.rustdocflags.extend(rustflags_from_env("RUSTDOCFLAGS"));
 
@@ -357,7 +1733,9 @@ fn new(
             ]);
         }
 
-        let jit_supported = use_unstable_features
+        target_compiler.rustflags.extend_from_slice(options.program_rustflags);
+
+        let jit_supported = options.use_unstable_features
             && is_native
             && target_compiler.triple.contains("x86_64")
             && !target_compiler.triple.contains("windows");
@@ -365,26 +1743,36 @@ fn new(
         Self {
             is_native,
             jit_supported,
-            use_unstable_features,
-            skip_tests,
+            use_unstable_features: options.use_unstable_features,
+            skip_tests: options.skip_tests,
+            exclude_tests: options.exclude_tests,
+            shard_tests: None,
+            check_only: options.check_only,
+            timeout: options.timeout,
             dirs,
             target_compiler,
             stdlib_source,
+            host_rustc,
         }
     }
 
     fn run_testsuite(&self, tests: &[TestCase]) {
-        for TestCase { config, cmd } in tests {
+        for test @ TestCase { config, cmd } in tests {
             let (tag, testname) = config.split_once('.').unwrap();
             let tag = tag.to_uppercase();
             let is_jit_test = tag == "JIT";
 
-            let _guard = if !config::get_bool(config)
-                || (is_jit_test && !self.jit_supported)
-                || self.skip_tests.contains(&config)
+            let _guard = if !is_test_enabled(self.skip_tests, self.exclude_tests, config)
+                // The JIT always runs the program it compiles.
+                || (is_jit_test && (!self.jit_supported || self.check_only))
             {
                 eprintln!("[{tag}] {testname} (skipped)");
                 continue;
+            } else if self.shard_tests.is_some_and(|tests| !tests.contains(config))
+                && !test.is_shared()
+            {
+                eprintln!("[{tag}] {testname} (other shard)");
+                continue;
             } else {
                 let guard = LogGroup::guard(&format!("[{tag}] {testname}"));
                 eprintln!("[{tag}] {testname}");
@@ -436,7 +1824,7 @@ fn run_testsuite(&self, tests: &[TestCase]) {
                     if !args.is_empty() {
                         jit_cmd.env("CG_CLIF_JIT_ARGS", args);
                     }
-                    spawn_and_wait(jit_cmd);
+                    spawn_and_wait_with_timeout(jit_cmd, self.timeout);
 
                     eprintln!("[JIT-lazy] {testname}");
                     let mut jit_cmd = self.rustc_command([
@@ -450,7 +1838,7 @@ fn run_testsuite(&self, tests: &[TestCase]) {
                     if !args.is_empty() {
                         jit_cmd.env("CG_CLIF_JIT_ARGS", args);
                     }
-                    spawn_and_wait(jit_cmd);
+                    spawn_and_wait_with_timeout(jit_cmd, self.timeout);
                 }
             }
         }
@@ -484,10 +1872,106 @@ fn run_rustc<I, S>(&self, args: I)
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        spawn_and_wait(self.rustc_command(args));
+        spawn_and_wait_with_timeout(self.rustc_command(args), self.timeout);
+    }
+
+    /// Run `cmd` like [`spawn_and_wait`], but abort if it takes longer than the test timeout.
+    #[track_caller]
+    fn spawn_and_wait(&self, cmd: Command) {
+        spawn_and_wait_with_timeout(cmd, self.timeout);
+    }
+
+    /// Run `cmd` and collect its output like [`Command::output`], but abort if it takes longer than
+    /// the test timeout.
+    fn output(&self, cmd: &mut Command) -> Output {
+        output_with_timeout(cmd, self.timeout).unwrap_or_else(|TimedOut| {
+            eprintln!("{cmd:?} timed out after {} seconds", self.timeout.as_secs());
+            process::exit(1);
+        })
+    }
+
+    /// Run `cmd` like [`output`](Self::output), assert that it succeeded and return its stdout.
+    #[track_caller]
+    fn checked_output(&self, cmd: &mut Command) -> String {
+        let output = self.output(cmd);
+        assert!(
+            output.status.success(),
+            "{cmd:?} exited with status {}:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr),
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Run rustc with `args`, assert that it fails without an ICE and that its stderr contains all
+    /// of `expected`. Returns the stderr for further checks.
+    #[track_caller]
+    fn expect_compile_error<I, S>(&self, args: I, expected: &[&str]) -> String
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut cmd = self.rustc_command(args);
+        let output = self.output(&mut cmd);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(!output.status.success(), "{cmd:?} unexpectedly succeeded");
+        for expected in expected {
+            assert!(stderr.contains(expected), "`{expected}` not found in error:\n{stderr}");
+        }
+        assert!(!stderr.contains("internal compiler error"), "unexpected ICE:\n{stderr}");
+        stderr
+    }
+
+    /// Whether the sysroot contains the profiler runtime. The sysroot build takes it from the
+    /// bootstrap compiler, so it may only be missing if the bootstrap compiler doesn't ship it for
+    /// the target either. Otherwise the tests using it would silently be skipped.
+    fn has_profiler_runtime(&self) -> bool {
+        let triple = &self.target_compiler.triple;
+        let has_profiler_builtins = |sysroot: PathBuf| {
+            fs::read_dir(sysroot.join("lib").join("rustlib").join(triple).join("lib"))
+                .into_iter()
+                .flatten()
+                .any(|entry| {
+                    entry.unwrap().file_name().to_str().unwrap().contains("profiler_builtins")
+                })
+        };
+        let has_runtime = has_profiler_builtins(RelPath::DIST.to_path(&self.dirs));
+        assert!(
+            has_runtime || !has_profiler_builtins(get_default_sysroot(&self.host_rustc)),
+            "the sysroot is missing the profiler runtime the bootstrap compiler ships for {triple}",
+        );
+        has_runtime
+    }
+
+    /// Compile `example/{name}.rs` as a single codegen unit and return the clif ir written by the
+    /// `CG_CLIF_DUMP_MODULE` option.
+    fn run_rustc_and_dump_clif(&self, name: &str, args: &[&str]) -> String {
+        let dump_dir = BUILD_EXAMPLE_OUT_DIR.to_path(&self.dirs).join(format!("{name}.dump"));
+        let _ = fs::remove_dir_all(&dump_dir);
+        let source = format!("example/{name}.rs");
+        let mut cmd = self.rustc_command([source.as_str(), "-Ccodegen-units=1"]);
+        cmd.args(args);
+        cmd.env("CG_CLIF_DUMP_MODULE", &dump_dir);
+        self.spawn_and_wait(cmd);
+        let clif_file = fs::read_dir(&dump_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension() == Some("clif".as_ref()))
+            .expect("no clif file written");
+        fs::read_to_string(clif_file).unwrap()
+    }
+
+    /// Whether the tests of cargo projects should be run rather than only built.
+    fn run_cargo_tests(&self) -> bool {
+        self.is_native && !self.check_only
     }
 
     fn run_out_command(&self, name: &str, args: &[&str]) {
+        if self.check_only {
+            eprintln!("Check-only: Not running {name}");
+            return;
+        }
+
         let mut full_cmd = vec![];
 
         // Prepend the RUN_WRAPPER's
@@ -509,6 +1993,33 @@ fn run_out_command(&self, name: &str, args: &[&str]) {
         let mut cmd = Command::new(first);
         cmd.args(cmd_iter);
 
-        spawn_and_wait(cmd);
+        spawn_and_wait_with_timeout(cmd, self.timeout);
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn exclude_skips_only_the_excluded_groups() {
+        let enabled = |exclude_tests: &[&str]| {
+            NO_SYSROOT_SUITE
+                .iter()
+                .map(|test| test.config)
+                .filter(|config| !is_excluded(exclude_tests, config))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(enabled(&[]).len(), NO_SYSROOT_SUITE.len());
+        assert_eq!(
+            enabled(&["jit"]),
+            ["build.mini_core", "build.example", "aot.mini_core_hello_world"]
+        );
+        // Multiple --exclude flags accumulate and a group only matches at a `.` boundary.
+        assert_eq!(
+            enabled(&["jit", "build.mini_core", "aot.mini_core"]),
+            ["build.example", "aot.mini_core_hello_world"]
+        );
     }
 }
@@ -1,9 +1,11 @@
 use std::env;
 use std::fs;
-use std::io;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read};
 use std::path::{Path, PathBuf};
-use std::process::{self, Command};
+use std::process::{self, Child, Command, ExitStatus, Output, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::path::{Dirs, RelPath};
 use crate::shared_utils::rustflags_to_cmd_env;
@@ -150,10 +152,12 @@ pub(crate) fn hyperfine_command(
     prepare: Option<&str>,
     cmds: &[(&str, &str)],
     markdown_export: &Path,
+    csv_export: &Path,
 ) -> Command {
     let mut bench = Command::new("hyperfine");
 
     bench.arg("--export-markdown").arg(markdown_export);
+    bench.arg("--export-csv").arg(csv_export);
 
     if warmup != 0 {
         bench.arg("--warmup").arg(warmup.to_string());
@@ -214,6 +218,219 @@ pub(crate) fn spawn_and_wait(mut cmd: Command) {
     }
 }
 
+static PROGRESS_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Report progress in [`spawn_cargo_and_wait_with_progress`] even when not running in a terminal
+/// or when running on CI. Set by `--progress`.
+pub(crate) fn request_progress() {
+    PROGRESS_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Run the cargo command returned by `make_cmd` and wait for it to exit like [`spawn_and_wait`],
+/// while reporting how many of the crates have been compiled, for example `[3/28] compiled core`.
+/// In a terminal the report is a single line that is updated after every crate. Otherwise a line
+/// is printed for every crate. When not running in a terminal or when running on CI, progress is
+/// only reported if requested using `--progress`.
+#[track_caller]
+pub(crate) fn spawn_cargo_and_wait_with_progress(make_cmd: impl Fn() -> Command) {
+    let is_terminal = io::stderr().is_terminal();
+    if !PROGRESS_REQUESTED.load(Ordering::SeqCst) && (!is_terminal || is_ci()) {
+        spawn_and_wait(make_cmd());
+        return;
+    }
+
+    let total =
+        count_cargo_units(make_cmd()).map_or_else(|| "?".to_owned(), |total| total.to_string());
+
+    let mut cmd = make_cmd();
+    cmd.arg("--message-format=json-render-diagnostics").stdout(Stdio::piped());
+    if is_terminal {
+        // Cargo's own progress bar would overwrite ours.
+        cmd.arg("--quiet");
+    }
+    let mut child = cmd.spawn().unwrap();
+    let mut compiled = 0;
+    for message in BufReader::new(child.stdout.take().unwrap()).lines() {
+        let message = message.unwrap();
+        let Some(name) = compiler_artifact_name(&message) else {
+            continue;
+        };
+        let name = if name == "build-script-build" { "build script" } else { name };
+        compiled += 1;
+        if is_terminal {
+            eprint!("\r\x1b[K[{compiled}/{total}] compiled {name}");
+        } else {
+            eprintln!("[{compiled}/{total}] compiled {name}");
+        }
+    }
+    if is_terminal {
+        eprintln!();
+    }
+
+    let status = child.wait().unwrap();
+    if !status.success() {
+        eprintln!("{cmd:?} exited with status {:?}", status);
+        process::exit(1);
+    }
+}
+
+/// The number of compiler invocations necessary for a cargo build. Returns `None` if cargo
+/// doesn't support `--unit-graph`.
+fn count_cargo_units(mut cmd: Command) -> Option<usize> {
+    cmd.args(["--unit-graph", "-Zunstable-options"]).stderr(Stdio::null());
+    let output = cmd.output().ok().filter(|output| output.status.success())?;
+    // Build script executions are separate units which don't invoke the compiler.
+    Some(String::from_utf8(output.stdout).ok()?.matches(r#""mode":"build""#).count())
+}
+
+/// The target name of a `compiler-artifact` message of `cargo --message-format=json`.
+fn compiler_artifact_name(message: &str) -> Option<&str> {
+    if !message.starts_with(r#"{"reason":"compiler-artifact""#) {
+        return None;
+    }
+    let target = &message[message.find(r#""target":{"#)?..];
+    let name = &target[target.find(r#""name":""#)? + r#""name":""#.len()..];
+    Some(&name[..name.find('"')?])
+}
+
+/// Returned by [`spawn_with_timeout`] when the command didn't exit in time.
+#[derive(Debug)]
+pub(crate) struct TimedOut;
+
+/// Run `cmd` and wait for it to exit. If it takes longer than `timeout`, it is killed together with
+/// all processes it spawned.
+pub(crate) fn spawn_with_timeout(
+    cmd: &mut Command,
+    timeout: Duration,
+) -> Result<ExitStatus, TimedOut> {
+    let mut child = process_group::spawn(cmd);
+    wait_with_timeout(&mut child, timeout)
+}
+
+/// Like [`Command::output`], but with a timeout like [`spawn_with_timeout`].
+pub(crate) fn output_with_timeout(
+    cmd: &mut Command,
+    timeout: Duration,
+) -> Result<Output, TimedOut> {
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = process_group::spawn(cmd);
+
+    // Read the output on separate threads to prevent the command from blocking on a full pipe.
+    fn read_to_end(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buf = vec![];
+            pipe.read_to_end(&mut buf).unwrap();
+            buf
+        })
+    }
+    let stdout = read_to_end(child.stdout.take().unwrap());
+    let stderr = read_to_end(child.stderr.take().unwrap());
+
+    let status = wait_with_timeout(&mut child, timeout)?;
+    Ok(Output { status, stdout: stdout.join().unwrap(), stderr: stderr.join().unwrap() })
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<ExitStatus, TimedOut> {
+    let start = Instant::now();
+    let result = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break Ok(status);
+        }
+        if start.elapsed() >= timeout {
+            process_group::kill_all(child);
+            break Err(TimedOut);
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    process_group::exited();
+    result
+}
+
+/// Commands with a timeout are run in a new process group such that processes spawned by them,
+/// like the linker invoked by rustc, can be killed too. As the new process group isn't the
+/// foreground process group of the terminal, Ctrl-C has to be forwarded to it.
+#[cfg(unix)]
+mod process_group {
+    use std::os::raw::c_int;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command};
+    use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+    const SIGINT: c_int = 2;
+    const SIGKILL: c_int = 9;
+
+    extern "C" {
+        fn kill(pid: i32, sig: c_int) -> c_int;
+        fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    /// The process group of the running command.
+    static PROCESS_GROUP: AtomicI32 = AtomicI32::new(0);
+    /// The `SIGINT` handler to restore once the command exited.
+    static PREVIOUS_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+    extern "C" fn forward_sigint(_signum: c_int) {
+        // `kill` is async-signal-safe, so it may be called from a signal handler.
+        unsafe { kill(-PROCESS_GROUP.load(Ordering::SeqCst), SIGINT) };
+    }
+
+    pub(super) fn spawn(cmd: &mut Command) -> Child {
+        cmd.process_group(0);
+        let child = cmd.spawn().unwrap();
+        PROCESS_GROUP.store(child.id() as i32, Ordering::SeqCst);
+        let previous_handler =
+            unsafe { signal(SIGINT, forward_sigint as extern "C" fn(c_int) as usize) };
+        PREVIOUS_HANDLER.store(previous_handler, Ordering::SeqCst);
+        child
+    }
+
+    pub(super) fn exited() {
+        unsafe { signal(SIGINT, PREVIOUS_HANDLER.load(Ordering::SeqCst)) };
+    }
+
+    pub(super) fn kill_all(child: &mut Child) {
+        // Errors are ignored as the processes may have exited in the meantime.
+        unsafe { kill(-(child.id() as i32), SIGKILL) };
+        let _ = child.wait();
+    }
+}
+
+#[cfg(not(unix))]
+mod process_group {
+    use std::process::{Child, Command};
+
+    pub(super) fn spawn(cmd: &mut Command) -> Child {
+        cmd.spawn().unwrap()
+    }
+
+    pub(super) fn exited() {}
+
+    pub(super) fn kill_all(child: &mut Child) {
+        // Errors are ignored as the processes may have exited in the meantime.
+        if cfg!(windows) {
+            let pid = child.id().to_string();
+            let _ = Command::new("taskkill").args(["/F", "/T", "/PID", &pid]).status();
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+#[track_caller]
+pub(crate) fn spawn_and_wait_with_timeout(mut cmd: Command, timeout: Duration) {
+    match spawn_with_timeout(&mut cmd, timeout) {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            eprintln!("{cmd:?} exited with status {:?}", status);
+            process::exit(1);
+        }
+        Err(TimedOut) => {
+            eprintln!("{cmd:?} timed out after {} seconds", timeout.as_secs());
+            process::exit(1);
+        }
+    }
+}
+
 // Based on the retry function in rust's src/ci/shared.sh
 #[track_caller]
 pub(crate) fn retry_spawn_and_wait(tries: u64, mut cmd: Command) {
@@ -290,11 +507,11 @@ fn drop(&mut self) {
 }
 
 pub(crate) fn maybe_incremental(cmd: &mut Command) {
-    if is_ci() || std::env::var("CARGO_BUILD_INCREMENTAL").map_or(false, |val| val == "false") {
-        // Disabling incr comp reduces cache size and incr comp doesn't save as much on CI anyway
+    if std::env::var("CARGO_BUILD_INCREMENTAL").map_or(false, |val| val == "false") {
+        // Set by main on CI unless --incremental is passed
         cmd.env("CARGO_BUILD_INCREMENTAL", "false");
     } else {
-        // Force incr comp even in release mode unless in CI or incremental builds are explicitly disabled
+        // Force incr comp even in release mode unless incremental builds are explicitly disabled
         cmd.env("CARGO_BUILD_INCREMENTAL", "true");
     }
 }
@@ -0,0 +1,3 @@
+// The `aot.abi_tag` test checks the `.note.ABI-tag` note of this executable using readelf.
+
+fn main() {}
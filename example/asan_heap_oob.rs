@@ -0,0 +1,29 @@
+// Compiled with `-Zsanitizer=address`. The test runner checks that the out-of-bounds access
+// selected by the first argument is reported by the ASan runtime.
+
+#![feature(intrinsics)]
+#![allow(internal_features)]
+
+extern "rust-intrinsic" {
+    fn copy_nonoverlapping<T>(src: *const T, dst: *mut T, count: usize);
+}
+
+fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("read") => {
+            let v = std::hint::black_box(vec![0u8; 8]);
+            let oob = unsafe { v.as_ptr().add(8).read() };
+            println!("read {oob} out of bounds without being caught");
+        }
+        Some("copy") => {
+            // A copy with a constant size small enough to be lowered to inline loads and stores.
+            let v = std::hint::black_box(vec![0u64; 1]);
+            let mut buf = [0u64; 2];
+            unsafe {
+                copy_nonoverlapping(v.as_ptr(), buf.as_mut_ptr(), 2);
+            }
+            println!("copied {buf:?} out of bounds without being caught");
+        }
+        arg => panic!("unknown argument {arg:?}"),
+    }
+}
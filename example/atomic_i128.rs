@@ -0,0 +1,47 @@
+// 128bit atomics are lowered to calls into libatomic, as Cranelift has no native support for them.
+// cg_clif links libatomic when these calls are emitted.
+
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+
+use std::intrinsics::*;
+
+const BIG: u128 = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+
+fn main() {
+    let mut value = 5u128;
+    let ptr = &mut value as *mut u128;
+    unsafe {
+        assert_eq!(atomic_load_seqcst(ptr), 5);
+        atomic_store_release(ptr, BIG);
+        assert_eq!(atomic_load_acquire(ptr), BIG);
+
+        assert_eq!(atomic_cxchg_seqcst_seqcst(ptr, 7, 8), (BIG, false));
+        assert_eq!(atomic_cxchg_acqrel_acquire(ptr, BIG, 8), (BIG, true));
+        while !atomic_cxchgweak_relaxed_relaxed(ptr, 8, 9).1 {}
+
+        assert_eq!(atomic_xchg_seqcst(ptr, BIG), 9);
+        assert_eq!(atomic_xadd_relaxed(ptr, 1), BIG);
+        assert_eq!(atomic_xsub_relaxed(ptr, 1 << 64), BIG + 1);
+        assert_eq!(atomic_and_seqcst(ptr, u128::MAX >> 4), BIG + 1 - (1 << 64));
+        assert_eq!(atomic_or_seqcst(ptr, 1 << 127), (BIG + 1 - (1 << 64)) & (u128::MAX >> 4));
+        assert_eq!(atomic_xor_seqcst(ptr, 1 << 127) >> 127, 1);
+        assert_eq!(atomic_nand_seqcst(ptr, 0), (BIG + 1 - (1 << 64)) & (u128::MAX >> 4));
+        assert_eq!(*ptr, u128::MAX);
+
+        *ptr = 1 << 100;
+        assert_eq!(atomic_umax_seqcst(ptr, 1 << 120), 1 << 100);
+        assert_eq!(atomic_umin_seqcst(ptr, 3), 1 << 120);
+        assert_eq!(*ptr, 3);
+    }
+
+    let mut signed = -1i128;
+    let ptr = &mut signed as *mut i128;
+    unsafe {
+        assert_eq!(atomic_max_acqrel(ptr, i128::MIN), -1);
+        assert_eq!(*ptr, -1);
+        assert_eq!(atomic_max_acqrel(ptr, 1 << 90), -1);
+        assert_eq!(atomic_min_release(ptr, i128::MIN), 1 << 90);
+        assert_eq!(*ptr, i128::MIN);
+    }
+}
@@ -0,0 +1,18 @@
+// Compiled with both the legacy and the v0 symbol mangling. Checks that the symbol of a closure
+// demangles to a path containing the function in which it is defined. The expected demangled
+// name is passed as argument.
+
+use std::backtrace::Backtrace;
+use std::hint::black_box;
+
+#[inline(never)]
+fn parent_function() -> String {
+    let closure = black_box(|| Backtrace::force_capture().to_string());
+    closure()
+}
+
+fn main() {
+    let expected = std::env::args().nth(1).unwrap();
+    let backtrace = parent_function();
+    assert!(backtrace.contains(&expected), "`{}` not found in backtrace:\n{}", expected, backtrace);
+}
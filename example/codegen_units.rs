@@ -0,0 +1,32 @@
+// Compiled with `-Ccodegen-units=4 -Csave-temps`. Every module ends up in a separate codegen unit,
+// which the test runner checks produces a separate object file.
+
+#![crate_type = "lib"]
+
+pub mod a {
+    #[inline(never)]
+    pub fn a(x: u32) -> u32 {
+        x + 1
+    }
+}
+
+pub mod b {
+    #[inline(never)]
+    pub fn b(x: u32) -> u32 {
+        x * 2
+    }
+}
+
+pub mod c {
+    #[inline(never)]
+    pub fn c(x: u32) -> u32 {
+        x - 3
+    }
+}
+
+pub mod d {
+    #[inline(never)]
+    pub fn d(x: u32) -> u32 {
+        x / 4
+    }
+}
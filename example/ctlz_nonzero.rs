@@ -0,0 +1,11 @@
+// Compiled with `-O` and with `-Cdebug-assertions=on` using `CG_CLIF_DUMP_MODULE`. The test runner
+// checks that a zero check is only emitted for `ctlz_nonzero` when UB checks are enabled.
+
+#![crate_type = "lib"]
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+
+#[no_mangle]
+pub unsafe fn ctlz_nonzero(x: u32) -> u32 {
+    std::intrinsics::ctlz_nonzero(x)
+}
@@ -0,0 +1,12 @@
+// Compiled with `-Copt-level=0`. The test runner checks that the debuginfo describes the location of
+// the parameter and the local variable of `debug_locals_double`.
+
+fn debug_locals_double(debug_locals_param: u32) -> u32 {
+    let debug_locals_var = debug_locals_param * 2;
+    std::hint::black_box(&debug_locals_var);
+    debug_locals_var
+}
+
+fn main() {
+    assert_eq!(debug_locals_double(std::hint::black_box(3)), 6);
+}
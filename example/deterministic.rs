@@ -0,0 +1,18 @@
+// Compiled twice with debuginfo and `--remap-path-prefix`. The test runner checks that both object
+// files are byte-identical and don't contain the working directory.
+
+#![no_std]
+#![crate_type = "lib"]
+
+pub static TABLE: [u32; 4] = [1, 2, 3, 4];
+
+pub struct Point {
+    pub x: u32,
+    pub y: u32,
+}
+
+#[inline(never)]
+pub fn mix(point: &Point, index: usize) -> u32 {
+    let lookup = |i: usize| TABLE[i & 3];
+    (point.x ^ lookup(index)) | (point.y ^ lookup(index >> 2))
+}
@@ -0,0 +1,13 @@
+// Compiled with `-Zdwarf-version=5`. The test runner resolves the address of `dwarf5_marker` to its
+// source location, which requires following the range list of the compilation unit stored in
+// `.debug_rnglists`.
+
+#[no_mangle]
+#[inline(never)]
+pub fn dwarf5_marker(a: u32) -> u32 {
+    a.wrapping_mul(3)
+}
+
+fn main() {
+    std::process::exit(std::hint::black_box(dwarf5_marker as fn(u32) -> u32)(0) as i32);
+}
@@ -0,0 +1,17 @@
+// Built as a dylib with multiple codegen units. The test runner checks that `exported_entry` is in
+// the dynamic symbol table, but `internal_helper` isn't even though it is referenced from another
+// codegen unit.
+
+#![crate_type = "dylib"]
+
+mod internal {
+    #[inline(never)]
+    pub(crate) fn internal_helper(x: u32) -> u32 {
+        x.wrapping_mul(3)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn exported_entry(x: u32) -> u32 {
+    internal::internal_helper(x) + 1
+}
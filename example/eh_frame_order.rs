@@ -0,0 +1,26 @@
+// The `aot.eh_frame_order` test checks using readelf that the FDEs in `.eh_frame` of this
+// executable are in the same order as the functions they describe and that the linker created a
+// sorted `.eh_frame_hdr` search table covering them.
+
+#[no_mangle]
+#[inline(never)]
+extern "C" fn eh_frame_order_first(x: u64) -> u64 {
+    x.wrapping_mul(3)
+}
+
+#[no_mangle]
+#[inline(never)]
+extern "C" fn eh_frame_order_second(x: u64) -> u64 {
+    x.rotate_left(7)
+}
+
+#[no_mangle]
+#[inline(never)]
+extern "C" fn eh_frame_order_third(x: u64) -> u64 {
+    x ^ 0x5555
+}
+
+fn main() {
+    let x = std::hint::black_box(1);
+    std::hint::black_box(eh_frame_order_third(eh_frame_order_second(eh_frame_order_first(x))));
+}
@@ -0,0 +1,12 @@
+// Built as an rlib with `-Cllvm-args=embed_metadata=true`. The test runner checks that one of the
+// object files in the rlib contains the crate metadata in a `.rustc` section.
+
+#![crate_type = "lib"]
+
+pub struct EmbeddedMetadata {
+    pub value: u32,
+}
+
+pub fn embedded_metadata(value: u32) -> EmbeddedMetadata {
+    EmbeddedMetadata { value }
+}
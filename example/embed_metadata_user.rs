@@ -0,0 +1,8 @@
+// Built against the dylib version of `embed_metadata`, which requires rustc to be able to read the
+// metadata of the dylib.
+
+#![crate_type = "lib"]
+
+pub fn use_embedded_metadata() -> u32 {
+    embed_metadata::embedded_metadata(42).value
+}
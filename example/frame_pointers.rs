@@ -0,0 +1,20 @@
+// Compiled with `-Cforce-frame-pointers=yes`. Checks that even leaf functions set up a frame
+// pointer, which frame pointer based unwinders used by profilers depend on.
+
+#[cfg(target_arch = "x86_64")]
+#[inline(never)]
+fn leaf(a: u32, b: u32) -> u32 {
+    a.wrapping_add(b)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn main() {
+    assert_eq!(std::hint::black_box(leaf)(1, 2), 3);
+
+    // push rbp; mov rbp, rsp
+    let prologue = unsafe { std::slice::from_raw_parts(leaf as *const u8, 4) };
+    assert_eq!(prologue, [0x55, 0x48, 0x89, 0xe5], "leaf function doesn't set up a frame pointer");
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn main() {}
@@ -0,0 +1,29 @@
+// Compiled twice with `-Cincremental`, first with `--cfg rpass1` and then with `--cfg rpass2`.
+// Only the `changed` module differs between both compilations, so the codegen unit of the
+// `unchanged` module has to be reused from the incremental cache.
+
+#![feature(rustc_attrs)]
+#![crate_type = "lib"]
+#![rustc_partition_reused(module = "incremental_reuse-unchanged", cfg = "rpass2")]
+#![rustc_partition_codegened(module = "incremental_reuse-changed", cfg = "rpass2")]
+
+pub mod unchanged {
+    #[inline(never)]
+    pub fn answer() -> u32 {
+        42
+    }
+}
+
+pub mod changed {
+    #[cfg(rpass1)]
+    #[inline(never)]
+    pub fn value() -> u32 {
+        1
+    }
+
+    #[cfg(rpass2)]
+    #[inline(never)]
+    pub fn value() -> u32 {
+        2
+    }
+}
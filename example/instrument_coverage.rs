@@ -0,0 +1,16 @@
+// Compiled with `-Cinstrument-coverage`. The test runner checks the line counts reported by
+// `llvm-cov` for the profile written when running it.
+
+fn covered(x: u32) -> u32 {
+    if x > 1 {
+        x * 2
+    } else {
+        x + 1
+    }
+}
+
+fn main() {
+    for x in [2, 3, 4] {
+        assert_eq!(std::hint::black_box(covered)(x), x * 2);
+    }
+}
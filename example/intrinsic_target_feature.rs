@@ -0,0 +1,34 @@
+// Calling an AVX2 LLVM intrinsic requires the avx2 target feature. The LLVM backend fails to compile
+// such calls too.
+
+#![feature(abi_unadjusted, link_llvm_intrinsics, repr_simd, simd_ffi)]
+#![allow(internal_features)]
+
+#[repr(simd)]
+#[derive(Copy, Clone)]
+struct I16x16([i16; 16]);
+
+#[allow(improper_ctypes)]
+extern "unadjusted" {
+    #[link_name = "llvm.x86.avx2.pmul.hr.sw"]
+    fn pmulhrsw(a: I16x16, b: I16x16) -> I16x16;
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn with_avx2(a: I16x16) -> I16x16 {
+    pmulhrsw(a, a)
+}
+
+// Only allowed when avx2 is enabled for the whole crate.
+unsafe fn without_avx2(a: I16x16) -> I16x16 {
+    pmulhrsw(a, a)
+}
+
+fn main() {
+    let a = I16x16([0x4000; 16]);
+    if is_x86_feature_detected!("avx2") {
+        let (b, c) = unsafe { (with_avx2(a), without_avx2(a)) };
+        assert_eq!(unsafe { std::mem::transmute::<_, [i16; 16]>(b) }, [0x2000; 16]);
+        assert_eq!(unsafe { std::mem::transmute::<_, [i16; 16]>(c) }, [0x2000; 16]);
+    }
+}
@@ -0,0 +1,31 @@
+// The test runner checks with `objdump` that `boot_entry` and `boot_helper` are placed in the
+// `.boot` section without leaving a copy of their code in `.text` and that calls between the
+// sections still work.
+
+use std::hint::black_box;
+
+static TABLE: [u32; 4] = [10, 20, 30, 40];
+
+#[no_mangle]
+#[inline(never)]
+#[link_section = ".boot"]
+pub fn boot_entry(x: u32) -> u32 {
+    boot_helper(x) + helper_in_text(x) + black_box(&TABLE)[x as usize % 4]
+}
+
+#[no_mangle]
+#[inline(never)]
+#[link_section = ".boot"]
+pub fn boot_helper(x: u32) -> u32 {
+    x.wrapping_mul(3)
+}
+
+#[inline(never)]
+fn helper_in_text(x: u32) -> u32 {
+    x + 1
+}
+
+fn main() {
+    assert_eq!(black_box(boot_entry as fn(u32) -> u32)(5), 15 + 6 + 20);
+    assert_eq!(boot_entry(black_box(2)), 6 + 3 + 30);
+}
@@ -0,0 +1,15 @@
+// Linked with `-Clink-arg=example/linker_script.ld`. `linker_script_alias` is only defined by
+// this linker script, so linking fails if the link arg doesn't reach the linker.
+
+extern "C" {
+    fn linker_script_alias() -> u32;
+}
+
+#[no_mangle]
+pub extern "C" fn linker_script_target() -> u32 {
+    42
+}
+
+fn main() {
+    assert_eq!(unsafe { linker_script_alias() }, 42);
+}
@@ -0,0 +1,12 @@
+// Compiled with and without `-Zlocation-detail=none`. The test runner checks that the name of this
+// file is only part of the object file in the latter case.
+
+#![crate_type = "lib"]
+
+#[inline(never)]
+pub fn checked_div(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        panic!("division by zero");
+    }
+    a / b
+}
@@ -1,4 +1,13 @@
-#![feature(no_core, lang_items, never_type, linkage, extern_types, thread_local, repr_simd)]
+#![feature(
+    no_core,
+    lang_items,
+    never_type,
+    linkage,
+    extern_types,
+    thread_local,
+    repr_simd,
+    asm_const
+)]
 #![no_core]
 #![allow(dead_code, non_camel_case_types, internal_features)]
 
@@ -333,6 +342,8 @@ struct ExternTypeWrapper {
     ))]
     unsafe {
         global_asm_test();
+        assert_eq!(global_asm_sym_alias(), 42);
+        assert_eq!(global_asm_const(), 7);
     }
 
     // Both statics have a reference that points to the same anonymous allocation.
@@ -366,6 +377,50 @@ fn stack_val_align() {
 ))]
 extern "C" {
     fn global_asm_test();
+    fn global_asm_sym_alias() -> u32;
+    fn global_asm_const() -> u32;
+}
+
+#[cfg(all(
+    not(jit),
+    not(no_unstable_features),
+    target_arch = "x86_64",
+    any(target_os = "linux", target_os = "macos")
+))]
+extern "C" fn global_asm_sym_target() -> u32 {
+    42
+}
+
+#[cfg(all(not(jit), not(no_unstable_features), target_arch = "x86_64", target_os = "linux"))]
+global_asm! {
+    "
+    .global global_asm_sym_alias
+    global_asm_sym_alias:
+    jmp {target}
+
+    .global global_asm_const
+    global_asm_const:
+    mov eax, {value}
+    ret
+    ",
+    target = sym global_asm_sym_target,
+    value = const 7,
+}
+
+#[cfg(all(not(jit), not(no_unstable_features), target_arch = "x86_64", target_os = "macos"))]
+global_asm! {
+    "
+    .global _global_asm_sym_alias
+    _global_asm_sym_alias:
+    jmp {target}
+
+    .global _global_asm_const
+    _global_asm_const:
+    mov eax, {value}
+    ret
+    ",
+    target = sym global_asm_sym_target,
+    value = const 7,
 }
 
 #[cfg(all(not(jit), not(no_unstable_features), target_arch = "x86_64", target_os = "linux"))]
@@ -500,6 +555,9 @@ unsafe fn join(self) {
 #[cfg(not(jit))]
 extern "C" fn mutate_tls(_: *mut c_void) -> *mut c_void {
     unsafe {
+        // The new thread must see the initial value rather than the value written by the main
+        // thread.
+        assert_eq!(TLS, 42);
         TLS = 0;
     }
     0 as *mut c_void
@@ -509,12 +567,14 @@ extern "C" fn mutate_tls(_: *mut c_void) -> *mut c_void {
 fn test_tls() {
     unsafe {
         assert_eq!(TLS, 42);
+        TLS = 1;
 
         let thread = Thread::create(mutate_tls);
         thread.join();
 
         // TLS of main thread must not have been changed by the other thread.
-        assert_eq!(TLS, 42);
+        assert_eq!(TLS, 1);
+        TLS = 42;
 
         puts("TLS works!\n\0" as *const str as *const i8);
     }
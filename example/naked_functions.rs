@@ -0,0 +1,27 @@
+#![feature(naked_functions)]
+
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+#[naked]
+extern "C" fn add(a: u32, b: u32) -> u32 {
+    unsafe {
+        std::arch::asm!("lea eax, [rdi + rsi]", "ret", options(noreturn));
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+#[naked]
+extern "C" fn return_const() -> u64 {
+    unsafe {
+        std::arch::asm!("mov rax, {}", "ret", const 42, options(noreturn));
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+fn main() {
+    assert_eq!(add(1, 2), 3);
+    assert_eq!(std::hint::black_box(add)(40, 2), 42);
+    assert_eq!(return_const(), 42);
+}
+
+#[cfg(not(all(target_arch = "x86_64", not(windows))))]
+fn main() {}
@@ -0,0 +1,14 @@
+// Compiled with `--print=native-static-libs`. The test runner checks that `-lm` is part of the
+// printed native libraries.
+
+#![crate_type = "staticlib"]
+
+#[link(name = "m")]
+extern "C" {
+    fn cbrt(x: f64) -> f64;
+}
+
+#[no_mangle]
+pub extern "C" fn cube_root(x: f64) -> f64 {
+    unsafe { cbrt(x) }
+}
@@ -200,6 +200,23 @@ unsafe fn test_vqadd_u8() {
     assert_eq!(r, e);
 }
 
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vcntq_u8() {
+    let a =
+        u8x16::from([0, 1, 2, 3, 0x0f, 0x10, 0x55, 0x7f, 0x80, 0xaa, 0xf0, 0xfe, 0xff, 4, 7, 9]);
+    let r: u8x16 = transmute(vcntq_u8(transmute(a)));
+    let e = u8x16::from([0, 1, 1, 2, 4, 1, 4, 7, 1, 4, 4, 7, 8, 1, 3, 2]);
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vcnt_u8() {
+    let a = u8x8::from([0, 1, 3, 0x0f, 0x7f, 0x80, 0xfe, 0xff]);
+    let r: u8x8 = transmute(vcnt_u8(transmute(a)));
+    let e = u8x8::from([0, 1, 2, 4, 7, 1, 7, 8]);
+    assert_eq!(r, e);
+}
+
 #[cfg(target_arch = "aarch64")]
 fn main() {
     unsafe {
@@ -227,6 +244,9 @@ fn main() {
 
         test_vqsub_u8();
         test_vqadd_u8();
+
+        test_vcntq_u8();
+        test_vcnt_u8();
     }
 }
 
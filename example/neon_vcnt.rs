@@ -0,0 +1,10 @@
+// Compiled with `-O` and `CG_CLIF_DUMP_MODULE` when targeting AArch64. The test runner checks that
+// `vcntq_u8` is lowered to a single vector popcnt rather than a popcnt per lane.
+
+#![crate_type = "lib"]
+
+#[cfg(target_arch = "aarch64")]
+#[no_mangle]
+pub unsafe fn count_bytes(a: std::arch::aarch64::uint8x16_t) -> std::arch::aarch64::uint8x16_t {
+    std::arch::aarch64::vcntq_u8(a)
+}
@@ -0,0 +1,36 @@
+// Compiled with `CG_CLIF_DUMP_MODULE`. The test runner checks that every `offset_of!` is lowered to
+// a constant without computing any field address.
+
+#![crate_type = "lib"]
+#![feature(offset_of_enum, offset_of_nested)]
+
+use std::mem::offset_of;
+
+#[repr(C)]
+pub struct Inner {
+    a: u8,
+    b: u32,
+}
+
+#[repr(C)]
+pub struct Outer {
+    x: u16,
+    inner: Inner,
+    y: u64,
+}
+
+#[repr(C, u8)]
+pub enum Enum {
+    A(u8),
+    B { c: u8, d: u64 },
+}
+
+#[no_mangle]
+pub fn offset_of_nested_field() -> usize {
+    offset_of!(Outer, inner.b)
+}
+
+#[no_mangle]
+pub fn offset_of_enum_field() -> usize {
+    offset_of!(Enum, B.d)
+}
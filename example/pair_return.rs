@@ -0,0 +1,9 @@
+// Compiled with `CG_CLIF_DUMP_MODULE`. The test runner checks that the tuple is returned in two
+// registers rather than through a return area pointer.
+
+#![crate_type = "lib"]
+
+#[no_mangle]
+pub fn pair_return(a: u64, b: u64) -> (u64, u64) {
+    (b, a)
+}
@@ -0,0 +1,29 @@
+// Compiled with `-Cpanic=abort -Cforce-unwind-tables=no`. Nothing can unwind, so no cleanup
+// blocks or unwind tables should be necessary for the code below to work.
+
+use std::hint::black_box;
+
+struct DropGuard<'a>(&'a mut u32);
+
+impl Drop for DropGuard<'_> {
+    fn drop(&mut self) {
+        *self.0 += 1;
+    }
+}
+
+fn parse(s: &str) -> Result<u32, std::num::ParseIntError> {
+    s.parse()
+}
+
+fn fallible_with_drop(s: &str, drop_count: &mut u32) -> Result<u32, std::num::ParseIntError> {
+    let _guard = DropGuard(drop_count);
+    let n = parse(black_box(s))?;
+    Ok(n * 2)
+}
+
+fn main() {
+    let mut drop_count = 0;
+    assert_eq!(fallible_with_drop("21", &mut drop_count), Ok(42));
+    assert!(fallible_with_drop("not a number", &mut drop_count).is_err());
+    assert_eq!(drop_count, 2);
+}
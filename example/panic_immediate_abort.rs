@@ -0,0 +1,37 @@
+// Compiled with `-O -Cllvm-args=panic_immediate_abort=true -Coverflow-checks=yes` using
+// `CG_CLIF_DUMP_MODULE`. All panics should be lowered to a bare trap without codegening any panic
+// messages or locations. `-O` inlines `Option::unwrap`, which otherwise gets passed the location.
+
+#![crate_type = "lib"]
+
+pub fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+pub fn index(slice: &[u8], idx: usize) -> u8 {
+    slice[idx]
+}
+
+pub fn div(a: i32, b: i32) -> i32 {
+    a / b
+}
+
+pub fn user_panic() {
+    panic!("user panic message");
+}
+
+pub fn user_panic_fmt(a: u32) {
+    panic!("user panic message with argument {a}");
+}
+
+pub fn check_eq(a: u32, b: u32) {
+    assert_eq!(a, b, "assert_eq message");
+}
+
+pub fn unwrap(a: Option<u32>) -> u32 {
+    a.unwrap()
+}
+
+pub fn expect(a: Option<u32>) -> u32 {
+    a.expect("expect message")
+}
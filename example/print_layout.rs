@@ -0,0 +1,16 @@
+// Compiled with `CG_CLIF_PRINT_LAYOUT=PrintLayoutStruct` and with `-Zprint-type-sizes`. The test
+// runner checks that the layout of `PrintLayoutStruct` is printed in both cases.
+
+#![crate_type = "lib"]
+
+pub struct PrintLayoutStruct {
+    pub a: u8,
+    pub b: u32,
+    pub c: bool,
+}
+
+#[inline(never)]
+pub fn make(a: u8, b: u32) -> PrintLayoutStruct {
+    let s = PrintLayoutStruct { a, b, c: a == 0 };
+    s
+}
@@ -0,0 +1,24 @@
+// Compiled with `-Cprofile-generate`. The test runner checks the call counts in the profile written
+// when running it and then compiles it again using the profile with `-Cprofile-use`.
+
+#[no_mangle]
+#[inline(never)]
+fn profile_generate_called(x: u32) -> u32 {
+    x * 2
+}
+
+#[no_mangle]
+#[inline(never)]
+fn profile_generate_not_called() {
+    std::process::abort();
+}
+
+fn main() {
+    let mut sum = 0;
+    for i in 0..3 {
+        sum += profile_generate_called(std::hint::black_box(i));
+    }
+    if std::hint::black_box(sum) != 6 {
+        profile_generate_not_called();
+    }
+}
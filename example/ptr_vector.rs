@@ -0,0 +1,33 @@
+// Per-lane arithmetic on vectors of pointers using `simd_arith_offset` and scattering and gathering
+// through the resulting pointers.
+
+#![feature(portable_simd)]
+
+use std::hint::black_box;
+use std::simd::prelude::*;
+
+fn main() {
+    let mut buf = [0u32; 16];
+    let base = Simd::<*mut u32, 4>::splat(buf.as_mut_ptr());
+
+    // The indices are scaled by the size of the pointee.
+    let ptrs = base.wrapping_add(black_box(Simd::from_array([0, 3, 7, 15])));
+    assert_eq!(ptrs.addr() - base.addr(), Simd::from_array([0, 12, 28, 60]));
+    unsafe {
+        Simd::from_array([1, 2, 3, 4]).scatter_ptr(ptrs);
+    }
+    assert_eq!(buf, [1, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 4]);
+
+    // Negative offsets
+    let end = Simd::<*const u32, 4>::splat(buf.as_ptr().wrapping_add(15));
+    let ptrs = end.wrapping_offset(black_box(Simd::from_array([0, -8, -12, -15])));
+    assert_eq!(unsafe { Simd::gather_ptr(ptrs) }, Simd::from_array([4, 3, 2, 1]));
+    let ptrs = end.wrapping_sub(black_box(Simd::from_array([15, 12, 8, 0])));
+    assert_eq!(unsafe { Simd::gather_ptr(ptrs) }, Simd::from_array([1, 2, 3, 4]));
+
+    // Byte sized pointees need no scaling.
+    let bytes = [10u8, 11, 12, 13];
+    let ptrs = Simd::<*const u8, 2>::splat(bytes.as_ptr());
+    let ptrs = ptrs.wrapping_add(black_box(Simd::from_array([3, 1])));
+    assert_eq!(unsafe { Simd::gather_ptr(ptrs) }, Simd::from_array([13, 11]));
+}
@@ -0,0 +1,63 @@
+// Checks that reallocating an over-aligned allocation passes the alignment to the
+// `#[global_allocator]` and that the data and alignment are preserved. The default allocator
+// shim is covered by `test_realloc_align` in std_example.rs.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct RecordAlign;
+
+static LAST_REALLOC_ALIGN: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for RecordAlign {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LAST_REALLOC_ALIGN.store(layout.align(), Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: RecordAlign = RecordAlign;
+
+#[repr(align(256))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Page(u8);
+
+fn main() {
+    unsafe {
+        let layout = Layout::from_size_align(64, 4096).unwrap();
+        let ptr = std::alloc::alloc(layout);
+        assert!(!ptr.is_null());
+        ptr.write_bytes(0xab, 64);
+
+        // Grow and then shrink the allocation.
+        let ptr = std::alloc::realloc(ptr, layout, 10000);
+        assert_eq!(LAST_REALLOC_ALIGN.load(Ordering::SeqCst), 4096);
+        assert_eq!(ptr as usize % 4096, 0);
+        assert!(std::slice::from_raw_parts(ptr, 64).iter().all(|&byte| byte == 0xab));
+
+        let layout = Layout::from_size_align(10000, 4096).unwrap();
+        let ptr = std::alloc::realloc(ptr, layout, 32);
+        assert_eq!(LAST_REALLOC_ALIGN.load(Ordering::SeqCst), 4096);
+        assert_eq!(ptr as usize % 4096, 0);
+        assert!(std::slice::from_raw_parts(ptr, 32).iter().all(|&byte| byte == 0xab));
+
+        std::alloc::dealloc(ptr, Layout::from_size_align(32, 4096).unwrap());
+    }
+
+    let mut pages = vec![Page(1)];
+    for i in 2..100 {
+        pages.push(Page(i));
+        assert_eq!(pages.as_ptr() as usize % 256, 0);
+    }
+    assert_eq!(LAST_REALLOC_ALIGN.load(Ordering::SeqCst), 256);
+    assert!(pages.iter().enumerate().all(|(i, page)| page.0 as usize == i + 1));
+}
@@ -0,0 +1,15 @@
+// Compiled with `-Crelocation-model=static` by the test runner, which checks that the object file
+// uses absolute relocations instead of GOT and PLT relocations and that `.eh_frame` uses absolute
+// addresses.
+
+#![crate_type = "lib"]
+
+extern "C" {
+    static EXTERNAL_COUNTER: u32;
+    fn external_function(value: u32) -> u32;
+}
+
+#[no_mangle]
+pub unsafe fn call_with_external_counter() -> u32 {
+    external_function(EXTERNAL_COUNTER) + 1
+}
@@ -0,0 +1,59 @@
+// Passes `#[repr(simd)]` vectors to and from `extern "C"` functions written in assembly. The SysV
+// x86_64 calling convention passes and returns `__m128` in xmm registers.
+
+#![feature(naked_functions, repr_simd)]
+#![allow(improper_ctypes_definitions)]
+
+#[repr(simd)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct F32x4(f32, f32, f32, f32);
+
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+#[naked]
+extern "C" fn make_f32x4(a: f32, b: f32, c: f32, d: f32) -> F32x4 {
+    unsafe {
+        std::arch::asm!(
+            "unpcklps xmm0, xmm1",
+            "unpcklps xmm2, xmm3",
+            "movlhps xmm0, xmm2",
+            "ret",
+            options(noreturn)
+        );
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+#[naked]
+extern "C" fn swap_halves(v: F32x4) -> F32x4 {
+    unsafe {
+        std::arch::asm!("shufps xmm0, xmm0, 0x4e", "ret", options(noreturn));
+    }
+}
+
+/// Calls `f` with `v` still in xmm0 to check how cg_clif receives and returns vectors.
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+#[naked]
+extern "C" fn call_with_vector(v: F32x4, f: extern "C" fn(F32x4) -> F32x4) -> F32x4 {
+    unsafe {
+        std::arch::asm!("jmp rdi", options(noreturn));
+    }
+}
+
+extern "C" fn negate(v: F32x4) -> F32x4 {
+    F32x4(-v.0, -v.1, -v.2, -v.3)
+}
+
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+fn main() {
+    use std::hint::black_box;
+
+    let v = make_f32x4(black_box(1.0), 2.0, 3.0, 4.0);
+    assert_eq!(v, F32x4(1.0, 2.0, 3.0, 4.0));
+    assert_eq!(swap_halves(black_box(v)), F32x4(3.0, 4.0, 1.0, 2.0));
+    assert_eq!(call_with_vector(black_box(v), negate), F32x4(-1.0, -2.0, -3.0, -4.0));
+}
+
+#[cfg(not(all(target_arch = "x86_64", not(windows))))]
+fn main() {
+    let _ = negate;
+}
@@ -0,0 +1,83 @@
+// Test that the transcendental simd float intrinsics, which are lowered to a libcall per lane,
+// produce the same results as the respective scalar functions.
+
+#![feature(repr_simd, platform_intrinsics)]
+#![allow(non_camel_case_types)]
+
+#[repr(simd)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct f32x4(pub f32, pub f32, pub f32, pub f32);
+
+#[repr(simd)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct f64x2(pub f64, pub f64);
+
+extern "platform-intrinsic" {
+    fn simd_fsin<T>(x: T) -> T;
+    fn simd_fcos<T>(x: T) -> T;
+    fn simd_fexp<T>(x: T) -> T;
+    fn simd_fexp2<T>(x: T) -> T;
+    fn simd_flog<T>(x: T) -> T;
+    fn simd_flog10<T>(x: T) -> T;
+    fn simd_flog2<T>(x: T) -> T;
+    fn simd_fpow<T>(x: T, y: T) -> T;
+}
+
+fn check_f32x4(name: &str, res: f32x4, x: f32x4, f: impl Fn(f32, usize) -> f32) {
+    let res = [res.0, res.1, res.2, res.3];
+    let x = [x.0, x.1, x.2, x.3];
+    for i in 0..4 {
+        let expected = f(x[i], i);
+        assert_eq!(
+            res[i].to_bits(),
+            expected.to_bits(),
+            "{name}({}): {} != {expected}",
+            x[i],
+            res[i]
+        );
+    }
+}
+
+fn check_f64x2(name: &str, res: f64x2, x: f64x2, f: impl Fn(f64, usize) -> f64) {
+    let res = [res.0, res.1];
+    let x = [x.0, x.1];
+    for i in 0..2 {
+        let expected = f(x[i], i);
+        assert_eq!(
+            res[i].to_bits(),
+            expected.to_bits(),
+            "{name}({}): {} != {expected}",
+            x[i],
+            res[i]
+        );
+    }
+}
+
+fn main() {
+    let x = f32x4(0.5, 1.0, 2.25, 100.0);
+    let y = f32x4(3.0, -1.5, 0.5, 2.0);
+    let y_lanes = [y.0, y.1, y.2, y.3];
+    let a = f64x2(0.75, 42.0);
+    let b = f64x2(-2.0, 0.25);
+    let b_lanes = [b.0, b.1];
+
+    unsafe {
+        check_f32x4("sin", simd_fsin(x), x, |x, _| x.sin());
+        check_f32x4("cos", simd_fcos(x), x, |x, _| x.cos());
+        check_f32x4("exp", simd_fexp(x), x, |x, _| x.exp());
+        check_f32x4("exp2", simd_fexp2(x), x, |x, _| x.exp2());
+        check_f32x4("ln", simd_flog(x), x, |x, _| x.ln());
+        check_f32x4("log10", simd_flog10(x), x, |x, _| x.log10());
+        check_f32x4("log2", simd_flog2(x), x, |x, _| x.log2());
+        check_f32x4("powf", simd_fpow(x, y), x, |x, i| x.powf(y_lanes[i]));
+
+        check_f64x2("sin", simd_fsin(a), a, |x, _| x.sin());
+        check_f64x2("cos", simd_fcos(a), a, |x, _| x.cos());
+        check_f64x2("exp", simd_fexp(a), a, |x, _| x.exp());
+        check_f64x2("exp2", simd_fexp2(a), a, |x, _| x.exp2());
+        check_f64x2("ln", simd_flog(a), a, |x, _| x.ln());
+        check_f64x2("log10", simd_flog10(a), a, |x, _| x.log10());
+        check_f64x2("log2", simd_flog2(a), a, |x, _| x.log2());
+        check_f64x2("powf", simd_fpow(a, b), a, |x, i| x.powf(b_lanes[i]));
+    }
+}
@@ -0,0 +1,19 @@
+// The test runner checks that an out of bounds `simd_shuffle_generic` index is reported as an error
+// rather than producing a function the Cranelift verifier rejects.
+
+#![feature(repr_simd, platform_intrinsics, adt_const_params)]
+#![allow(incomplete_features, internal_features)]
+#![crate_type = "lib"]
+
+#[repr(simd)]
+#[derive(Copy, Clone)]
+pub struct U8x2(u8, u8);
+
+extern "platform-intrinsic" {
+    fn simd_shuffle_generic<T, U, const IDX: &'static [u32]>(x: T, y: T) -> U;
+}
+
+pub fn shuffle(x: U8x2, y: U8x2) -> U8x2 {
+    const IDX: &[u32] = &[0, 4];
+    unsafe { simd_shuffle_generic::<_, _, IDX>(x, y) }
+}
@@ -0,0 +1,12 @@
+// Compiled with `CG_CLIF_SIZE_REPORT=1`. The test runner checks that the code and unwind tables of
+// `checked_index` show up in the size report.
+
+#![crate_type = "lib"]
+
+#[inline(never)]
+pub fn checked_index(values: &[u32], index: usize) -> u32 {
+    if index >= values.len() {
+        panic!("index {index} out of bounds");
+    }
+    values[index]
+}
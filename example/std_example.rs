@@ -1,12 +1,19 @@
 #![feature(
+    adt_const_params,
+    c_variadic,
     core_intrinsics,
     coroutines,
     coroutine_trait,
     is_sorted,
+    platform_intrinsics,
+    ptr_metadata,
     repr_simd,
+    specialization,
     tuple_trait,
-    unboxed_closures
+    unboxed_closures,
+    unsized_fn_params
 )]
+#![allow(incomplete_features, internal_features)]
 
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
@@ -109,6 +116,19 @@ fn main() {
     }
 
     test_checked_mul();
+    test_float_rounding();
+    test_overlapping_copy();
+    test_unsized_fn_params();
+
+    unsafe {
+        assert_eq!(std::intrinsics::unchecked_div(black_box(-7i32), black_box(2)), -3);
+        assert_eq!(std::intrinsics::unchecked_rem(black_box(-7i32), black_box(2)), -1);
+        assert_eq!(
+            std::intrinsics::unchecked_div(black_box(u128::MAX), black_box(1 << 64)),
+            u64::MAX as u128
+        );
+        assert_eq!(std::intrinsics::unchecked_rem(black_box(i128::MIN), black_box(3)), -2);
+    }
 
     let _a = 1u32 << 2u8;
 
@@ -117,6 +137,10 @@ fn main() {
 
     println!("{:?}", std::intrinsics::caller_location());
 
+    // x86_64 mandates SSE2 support, so it must be reported for `#[cfg(target_feature)]`.
+    #[cfg(target_arch = "x86_64")]
+    assert!(cfg!(target_feature = "sse2"));
+
     #[cfg(target_arch = "x86_64")]
     unsafe {
         test_simd();
@@ -164,9 +188,45 @@ enum Never {}
 
     foo(I64X2(0, 0));
 
+    test_simd_reduce_bitwise();
+    test_simd_reduce_4_lanes();
+    test_simd_shuffle_generic();
+    test_simd_bitmask();
+
+    test_ptr_offset();
+    test_exact_div();
+    test_swap_bytes();
+    test_ptr_metadata();
+    test_specialization();
+    test_swap();
+    test_realloc_align();
+    test_dyn_size_align();
+    test_drop_order();
+    test_overaligned_enum();
+    test_is_val_statically_known();
+
+    #[cfg(all(any(target_arch = "x86_64", target_arch = "aarch64"), not(windows)))]
+    test_c_variadic();
+
     transmute_fat_pointer();
 
     rust_call_abi();
+
+    // Reading the discriminant of an uninhabited enum must codegen to a trap rather than a load.
+    std::hint::black_box(match_uninhabited_enum as fn(&UninhabitedEnum) -> u8);
+}
+
+#[allow(dead_code)]
+enum UninhabitedEnum {
+    A(std::convert::Infallible),
+    B(std::convert::Infallible, u8),
+}
+
+fn match_uninhabited_enum(x: &UninhabitedEnum) -> u8 {
+    match *x {
+        UninhabitedEnum::A(_) => 1,
+        UninhabitedEnum::B(_, b) => b,
+    }
 }
 
 fn panic(_: u128) {
@@ -197,6 +257,172 @@ fn rust_call_abi() {
 #[allow(improper_ctypes_definitions)]
 extern "C" fn foo(_a: I64X2) {}
 
+#[repr(simd)]
+#[derive(Copy, Clone)]
+struct I32X3(i32, i32, i32);
+
+#[repr(simd)]
+#[derive(Copy, Clone)]
+struct U8X8(u8, u8, u8, u8, u8, u8, u8, u8);
+
+#[repr(simd)]
+#[derive(Copy, Clone)]
+struct I8X8(i8, i8, i8, i8, i8, i8, i8, i8);
+
+extern "platform-intrinsic" {
+    fn simd_reduce_and<T, U>(x: T) -> U;
+    fn simd_reduce_or<T, U>(x: T) -> U;
+    fn simd_reduce_xor<T, U>(x: T) -> U;
+    fn simd_reduce_mul_ordered<T, U>(x: T, y: U) -> U;
+    fn simd_reduce_mul_unordered<T, U>(x: T) -> U;
+    fn simd_reduce_min<T, U>(x: T) -> U;
+    fn simd_reduce_max<T, U>(x: T) -> U;
+    fn simd_shuffle_generic<T, U, const IDX: &'static [u32]>(x: T, y: T) -> U;
+    fn simd_bitmask<T, U>(x: T) -> U;
+}
+
+#[repr(simd)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct I32X4(i32, i32, i32, i32);
+
+#[repr(simd)]
+#[derive(Copy, Clone)]
+struct F32X4(f32, f32, f32, f32);
+
+fn shuffle_generic<const IDX: &'static [u32]>(x: I32X4, y: I32X4) -> I32X4 {
+    unsafe { simd_shuffle_generic::<_, _, IDX>(x, y) }
+}
+
+fn test_float_rounding() {
+    use std::intrinsics::*;
+
+    macro_rules! check_rounding {
+        ($($intrinsic:ident: $expected:expr;)*) => {$(
+            let actual = [2.5, 3.5, -2.5].map(|x| unsafe { $intrinsic(black_box(x)) });
+            assert_eq!(actual, $expected, stringify!($intrinsic));
+        )*};
+    }
+
+    check_rounding! {
+        roundf32: [3.0, 4.0, -3.0];
+        roundf64: [3.0, 4.0, -3.0];
+        rintf32: [2.0, 4.0, -2.0];
+        rintf64: [2.0, 4.0, -2.0];
+        nearbyintf32: [2.0, 4.0, -2.0];
+        nearbyintf64: [2.0, 4.0, -2.0];
+        truncf32: [2.0, 3.0, -2.0];
+        truncf64: [2.0, 3.0, -2.0];
+        floorf32: [2.0, 3.0, -3.0];
+        floorf64: [2.0, 3.0, -3.0];
+        ceilf32: [3.0, 4.0, -2.0];
+        ceilf64: [3.0, 4.0, -2.0];
+    }
+}
+
+fn test_overlapping_copy() {
+    // A forward copy would overwrite the source before it is read.
+    let mut bytes = [1u8, 2, 3, 4, 5, 6, 7, 8];
+    unsafe { std::ptr::copy(bytes.as_ptr(), bytes.as_mut_ptr().add(2), 4) };
+    assert_eq!(bytes, [1, 2, 1, 2, 3, 4, 7, 8]);
+
+    let mut words = [1u16, 2, 3, 4, 5, 6, 7, 8];
+    unsafe { std::ptr::copy(words.as_ptr(), words.as_mut_ptr().add(1), black_box(6)) };
+    assert_eq!(words, [1, 1, 2, 3, 4, 5, 6, 8]);
+
+    let mut words = [1u16, 2, 3, 4, 5, 6, 7, 8];
+    unsafe { std::ptr::copy(words.as_ptr().add(2), words.as_mut_ptr(), black_box(5)) };
+    assert_eq!(words, [3, 4, 5, 6, 7, 6, 7, 8]);
+}
+
+#[repr(align(16))]
+#[derive(Copy, Clone)]
+struct Aligned16(u8);
+
+// `values` is passed by reference to the backing storage of the caller.
+fn sum_unsized_param(values: [Aligned16]) -> (usize, usize, u32) {
+    let addr = &values as *const [Aligned16] as *const u8 as usize;
+    (values.len(), addr % 16, values.iter().map(|value| u32::from(value.0)).sum())
+}
+
+fn test_unsized_fn_params() {
+    let boxed = vec![Aligned16(7); black_box(5)].into_boxed_slice();
+    let (len, misalignment, sum) = sum_unsized_param(*boxed);
+    assert_eq!(len, 5);
+    assert_eq!(misalignment, 0);
+    assert_eq!(sum, 35);
+}
+
+fn test_simd_shuffle_generic() {
+    let x = black_box(I32X4(0, 1, 2, 3));
+    let y = black_box(I32X4(4, 5, 6, 7));
+    assert_eq!(shuffle_generic::<{ &[3, 2, 1, 0] }>(x, y), I32X4(3, 2, 1, 0));
+    assert_eq!(shuffle_generic::<{ &[0, 4, 7, 3] }>(x, y), I32X4(0, 4, 7, 3));
+}
+
+// Lane `i` of the mask maps to bit `i` of the bitmask on little endian targets. Big endian targets
+// use the reverse order.
+fn test_simd_bitmask() {
+    unsafe {
+        let mask = black_box(I8X8(-1, 0, 0, -1, 0, -1, -1, 0));
+        let expected: u8 = if cfg!(target_endian = "little") { 0b0110_1001 } else { 0b1001_0110 };
+        assert_eq!(simd_bitmask::<_, u8>(mask), expected);
+        assert_eq!(simd_bitmask::<_, [u8; 1]>(mask), [expected]);
+
+        // The bits beyond the lane count are zero.
+        let mask = black_box(I32X4(-1, 0, -1, -1));
+        let expected: u8 = if cfg!(target_endian = "little") { 0b1101 } else { 0b1011 };
+        assert_eq!(simd_bitmask::<_, u8>(mask), expected);
+    }
+}
+
+fn test_simd_reduce_bitwise() {
+    unsafe {
+        let all_true = black_box(I32X3(-1, -1, -1));
+        assert_eq!(simd_reduce_and::<_, i32>(all_true), -1);
+        assert_eq!(simd_reduce_or::<_, i32>(all_true), -1);
+
+        let mixed = black_box(I32X3(-1, 0, -1));
+        assert_eq!(simd_reduce_and::<_, i32>(mixed), 0);
+        assert_eq!(simd_reduce_or::<_, i32>(mixed), -1);
+
+        let bytes = black_box(U8X8(0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x81));
+        assert_eq!(simd_reduce_xor::<_, u8>(bytes), 0xfe);
+        assert_eq!(simd_reduce_and::<_, u8>(bytes), 0);
+        assert_eq!(simd_reduce_or::<_, u8>(bytes), 0xff);
+        assert_eq!(simd_reduce_xor::<_, i32>(black_box(I32X3(0b110, 0b011, 0b101))), 0);
+    }
+}
+
+fn test_simd_reduce_4_lanes() {
+    unsafe {
+        let ints = black_box(I32X4(3, -7, 5, 2));
+        assert_eq!(simd_reduce_min::<_, i32>(ints), -7);
+        assert_eq!(simd_reduce_max::<_, i32>(ints), 5);
+        assert_eq!(simd_reduce_mul_unordered::<_, i32>(ints), -210);
+        assert_eq!(simd_reduce_mul_ordered::<_, i32>(ints, 2), -420);
+        assert_eq!(simd_reduce_and::<_, i32>(ints), 0);
+        assert_eq!(simd_reduce_or::<_, i32>(ints), -1);
+        assert_eq!(simd_reduce_xor::<_, i32>(ints), -3);
+
+        let floats = black_box(F32X4(1.5, 2.0, -4.0, 0.5));
+        assert_eq!(simd_reduce_min::<_, f32>(floats), -4.0);
+        assert_eq!(simd_reduce_max::<_, f32>(floats), 2.0);
+        assert_eq!(simd_reduce_mul_unordered::<_, f32>(floats), -6.0);
+        assert_eq!(simd_reduce_mul_ordered::<_, f32>(floats, -1.0), 6.0);
+
+        // NaN lanes are ignored unless all lanes are NaN.
+        let with_nan = black_box(F32X4(2.0, f32::NAN, -1.5, 4.0));
+        assert_eq!(simd_reduce_min::<_, f32>(with_nan), -1.5);
+        assert_eq!(simd_reduce_max::<_, f32>(with_nan), 4.0);
+        let first_nan = black_box(F32X4(f32::NAN, 1.0, 3.0, 2.0));
+        assert_eq!(simd_reduce_min::<_, f32>(first_nan), 1.0);
+        assert_eq!(simd_reduce_max::<_, f32>(first_nan), 3.0);
+        let all_nan = black_box(F32X4(f32::NAN, f32::NAN, f32::NAN, f32::NAN));
+        assert!(simd_reduce_min::<_, f32>(all_nan).is_nan());
+        assert!(simd_reduce_max::<_, f32>(all_nan).is_nan());
+    }
+}
+
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "sse2")]
 unsafe fn test_simd() {
@@ -224,13 +450,21 @@ unsafe fn test_simd() {
     test_mm_add_pd();
     test_mm_cvtepi8_epi16();
     test_mm_cvtsi128_si64();
+    test_mm_sse2_integer_arith();
+    test_mm_mulhi();
+    test_mm_sse2_shifts();
+    test_mm_max_min();
+    test_mm_sqrt();
+    test_mm_comi();
 
     test_mm_extract_epi8();
     test_mm_insert_epi16();
     test_mm_shuffle_epi8();
+    test_mm_legacy_const_generics();
 
     test_mm256_shuffle_epi8();
     test_mm256_permute2x128_si256();
+    test_mm256_mulhi();
 
     #[rustfmt::skip]
     let mask1 = _mm_movemask_epi8(dbg!(_mm_setr_epi8(255u8 as i8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0)));
@@ -312,6 +546,122 @@ unsafe fn test_mm_add_pd() {
     assert_eq_m128d(r, _mm_setr_pd(6.0, 12.0));
 }
 
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn test_mm_sse2_integer_arith() {
+    // paddd, psubb and pmullw
+    let a = _mm_setr_epi32(1, -2, i32::MAX, 4);
+    let b = _mm_setr_epi32(10, 20, 1, -4);
+    assert_eq_m128i(_mm_add_epi32(a, b), _mm_setr_epi32(11, 18, i32::MIN, 0));
+
+    let a = _mm_set1_epi8(5);
+    let b = _mm_setr_epi8(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, i8::MIN);
+    let e = _mm_setr_epi8(5, 4, 3, 2, 1, 0, -1, -2, -3, -4, -5, -6, -7, -8, -9, -123);
+    assert_eq_m128i(_mm_sub_epi8(a, b), e);
+
+    let a = _mm_setr_epi16(1, 2, 3, 4, 300, -5, 0, i16::MAX);
+    let b = _mm_setr_epi16(7, 7, 7, 7, 300, 5, 9, 2);
+    let e = _mm_setr_epi16(7, 14, 21, 28, 24464, -25, 0, -2);
+    assert_eq_m128i(_mm_mullo_epi16(a, b), e);
+
+    let a = _mm_setr_epi32(1, 5, -3, 0);
+    let b = _mm_setr_epi32(1, 4, -2, 0);
+    assert_eq_m128i(_mm_cmpgt_epi32(a, b), _mm_setr_epi32(0, -1, 0, 0));
+    assert_eq_m128i(_mm_cmpeq_epi32(a, b), _mm_setr_epi32(-1, 0, 0, -1));
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn test_mm_mulhi() {
+    // pmulhw, pmulhuw and pmulhrsw
+    let a = _mm_setr_epi16(1000, -1000, i16::MAX, i16::MIN, -1, 16384, 12345, i16::MIN);
+    let b = _mm_setr_epi16(1000, 1000, i16::MAX, i16::MIN, -1, 16384, -23456, 1);
+    let e = _mm_setr_epi16(15, -16, 16383, 16384, 0, 4096, -4419, -1);
+    assert_eq_m128i(_mm_mulhi_epi16(a, b), e);
+    let e = _mm_setr_epi16(15, 984, 16383, 16384, -2, 4096, 7926, 0);
+    assert_eq_m128i(_mm_mulhi_epu16(a, b), e);
+    let e = _mm_setr_epi16(31, -31, 32766, i16::MIN, 0, 8192, -8837, -1);
+    assert_eq_m128i(_mm_mulhrs_epi16(a, b), e);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn test_mm_sse2_shifts() {
+    let a = _mm_setr_epi32(1, -8, i32::MIN, 0x1234);
+    assert_eq_m128i(_mm_sll_epi32(a, _mm_set_epi64x(0, 4)), _mm_setr_epi32(16, -128, 0, 0x12340));
+    assert_eq_m128i(
+        _mm_srl_epi32(a, _mm_set_epi64x(0, 4)),
+        _mm_setr_epi32(0, 0x0fff_ffff, 0x0800_0000, 0x123),
+    );
+    assert_eq_m128i(
+        _mm_sra_epi32(a, _mm_set_epi64x(0, 4)),
+        _mm_setr_epi32(0, -1, -(1 << 27), 0x123),
+    );
+    // Only the low 64 bits of the count are used. Too large counts shift out all bits.
+    assert_eq_m128i(_mm_sll_epi32(a, _mm_set_epi64x(-1, 32)), _mm_setzero_si128());
+    assert_eq_m128i(_mm_srl_epi16(_mm_set1_epi16(-1), _mm_set_epi64x(0, 16)), _mm_setzero_si128());
+    assert_eq_m128i(_mm_sra_epi32(a, _mm_set_epi64x(0, 40)), _mm_setr_epi32(0, -1, -1, 0));
+    assert_eq_m128i(
+        _mm_sll_epi64(_mm_set_epi64x(1, 3), _mm_set_epi64x(0, 63)),
+        _mm_set_epi64x(i64::MIN, i64::MIN),
+    );
+    assert_eq_m128i(_mm_sll_epi16(_mm_set1_epi16(3), _mm_set_epi64x(0, 1)), _mm_set1_epi16(6));
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn test_mm_max_min() {
+    let a = _mm_setr_pd(1.0, f64::NAN);
+    let b = _mm_setr_pd(2.0, 3.0);
+    assert_eq_m128d(_mm_max_pd(a, b), _mm_setr_pd(2.0, 3.0));
+    assert_eq_m128d(_mm_min_pd(a, b), _mm_setr_pd(1.0, 3.0));
+    // If either operand is NaN the second operand is returned.
+    let r: [f64; 2] = std::mem::transmute(_mm_max_pd(b, a));
+    assert_eq!(r[0], 2.0);
+    assert!(r[1].is_nan());
+
+    let a = _mm_setr_ps(1.0, -0.0, 5.0, f32::NAN);
+    let b = _mm_setr_ps(2.0, 0.0, -5.0, 1.0);
+    let r: [f32; 4] = std::mem::transmute(_mm_max_ps(a, b));
+    assert_eq!(r, [2.0, 0.0, 5.0, 1.0]);
+    assert!(r[1].is_sign_positive());
+    let r: [f32; 4] = std::mem::transmute(_mm_min_ps(a, b));
+    assert_eq!(r, [1.0, 0.0, -5.0, 1.0]);
+    assert!(r[1].is_sign_positive());
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn test_mm_sqrt() {
+    assert_eq_m128d(_mm_sqrt_pd(_mm_setr_pd(4.0, 2.25)), _mm_setr_pd(2.0, 1.5));
+    let r: [f32; 4] = std::mem::transmute(_mm_sqrt_ps(_mm_setr_ps(1.0, 9.0, 0.25, 0.0)));
+    assert_eq!(r, [1.0, 3.0, 0.5, 0.0]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn test_mm_comi() {
+    let one = _mm_setr_pd(1.0, f64::NAN);
+    let two = _mm_setr_pd(2.0, 0.0);
+    let nan = _mm_set1_pd(f64::NAN);
+    assert_eq!(_mm_comilt_sd(one, two), 1);
+    assert_eq!(_mm_comigt_sd(one, two), 0);
+    assert_eq!(_mm_comieq_sd(one, one), 1);
+    assert_eq!(_mm_ucomile_sd(two, two), 1);
+    assert_eq!(_mm_ucomineq_sd(one, two), 1);
+    // Only the not equal comparison is true for unordered operands.
+    assert_eq!(_mm_comieq_sd(nan, one), 0);
+    assert_eq!(_mm_comilt_sd(nan, one), 0);
+    assert_eq!(_mm_ucomige_sd(one, nan), 0);
+    assert_eq!(_mm_comineq_sd(nan, one), 1);
+
+    let one = _mm_set1_ps(1.0);
+    let two = _mm_set1_ps(2.0);
+    assert_eq!(_mm_comile_ss(one, two), 1);
+    assert_eq!(_mm_ucomige_ss(one, two), 0);
+    assert_eq!(_mm_ucomineq_ss(_mm_set1_ps(f32::NAN), one), 1);
+}
+
 #[cfg(target_arch = "x86_64")]
 fn assert_eq_m128i(x: std::arch::x86_64::__m128i, y: std::arch::x86_64::__m128i) {
     unsafe {
@@ -396,6 +746,35 @@ unsafe fn test_mm_shuffle_epi8() {
     assert_eq_m128i(r, expected);
 }
 
+// The immediates of these intrinsics are passed as regular arguments, but rustc rewrites them into
+// const generic arguments because of `#[rustc_legacy_const_generics]`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn test_mm_legacy_const_generics() {
+    let a = _mm_setr_epi32(0, 1, 2, 3);
+    assert_eq_m128i(_mm_shuffle_epi32(a, 0b00_01_10_11), _mm_setr_epi32(3, 2, 1, 0));
+
+    let a = _mm_setr_epi16(0, 1, 2, 3, 4, 5, 6, 7);
+    let b = _mm_setr_epi16(10, 11, 12, 13, 14, 15, 16, 17);
+    let r = _mm_shufflelo_epi16(a, 0b00_01_10_11);
+    assert_eq_m128i(r, _mm_setr_epi16(3, 2, 1, 0, 4, 5, 6, 7));
+    let r = _mm_shufflehi_epi16(a, 0b00_01_10_11);
+    assert_eq_m128i(r, _mm_setr_epi16(0, 1, 2, 3, 7, 6, 5, 4));
+    let r = _mm_blend_epi16(a, b, 0b1010_0101);
+    assert_eq_m128i(r, _mm_setr_epi16(10, 1, 12, 3, 4, 15, 6, 17));
+
+    let a = _mm_setr_epi8(16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31);
+    let b = _mm_setr_epi8(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+    let r = _mm_alignr_epi8(a, b, 4);
+    let e = _mm_setr_epi8(4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19);
+    assert_eq_m128i(r, e);
+
+    let a = _mm_setr_ps(1.0, 2.0, 3.0, 4.0);
+    let b = _mm_setr_ps(5.0, 6.0, 7.0, 8.0);
+    let r = _mm_shuffle_ps(a, b, 0b01_00_11_10);
+    assert_eq!(std::mem::transmute::<_, [f32; 4]>(r), [3.0, 4.0, 5.0, 6.0]);
+}
+
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2")]
 unsafe fn test_mm256_shuffle_epi8() {
@@ -434,6 +813,39 @@ unsafe fn test_mm256_permute2x128_si256() {
     assert_eq_m256i(r, e);
 }
 
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn test_mm256_mulhi() {
+    #[rustfmt::skip]
+    let a = _mm256_setr_epi16(
+        1000, -1000, i16::MAX, i16::MIN, -1, 16384, 12345, i16::MIN,
+        1000, 1000, i16::MAX, i16::MIN, -1, 16384, -23456, 1,
+    );
+    #[rustfmt::skip]
+    let b = _mm256_setr_epi16(
+        1000, 1000, i16::MAX, i16::MIN, -1, 16384, -23456, 1,
+        1000, -1000, i16::MAX, i16::MIN, -1, 16384, 12345, i16::MIN,
+    );
+    #[rustfmt::skip]
+    let e = _mm256_setr_epi16(
+        15, -16, 16383, 16384, 0, 4096, -4419, -1,
+        15, -16, 16383, 16384, 0, 4096, -4419, -1,
+    );
+    assert_eq_m256i(_mm256_mulhi_epi16(a, b), e);
+    #[rustfmt::skip]
+    let e = _mm256_setr_epi16(
+        15, 984, 16383, 16384, -2, 4096, 7926, 0,
+        15, 984, 16383, 16384, -2, 4096, 7926, 0,
+    );
+    assert_eq_m256i(_mm256_mulhi_epu16(a, b), e);
+    #[rustfmt::skip]
+    let e = _mm256_setr_epi16(
+        31, -31, 32766, i16::MIN, 0, 8192, -8837, -1,
+        31, -31, 32766, i16::MIN, 0, 8192, -8837, -1,
+    );
+    assert_eq_m256i(_mm256_mulhrs_epi16(a, b), e);
+}
+
 fn test_checked_mul() {
     let u: Option<u8> = u8::from_str_radix("1000", 10).ok();
     assert_eq!(u, None);
@@ -453,6 +865,348 @@ fn test_checked_mul() {
     assert_eq!((-1i64).checked_mul(i64::MIN + 1), Some(i64::MAX));
     assert_eq!(1i64.checked_mul(i64::MIN), Some(i64::MIN));
     assert_eq!(i64::MIN.checked_mul(i64::MIN), None);
+
+    assert_eq!(black_box(i128::MAX).checked_mul(black_box(2)), None);
+    assert_eq!(black_box(i128::MIN).checked_mul(black_box(-1)), None);
+    assert_eq!(black_box(-1i128).checked_mul(black_box(i128::MIN + 1)), Some(i128::MAX));
+    assert_eq!(black_box(1i128 << 126).checked_mul(black_box(-2)), Some(i128::MIN));
+    assert_eq!(black_box(1i128 << 126).checked_mul(black_box(2)), None);
+    assert_eq!(black_box(u128::MAX).checked_mul(black_box(2)), None);
+    assert_eq!(black_box(1u128 << 64).checked_mul(black_box(1u128 << 64)), None);
+    assert_eq!(
+        black_box(u64::MAX as u128).checked_mul(black_box(u64::MAX as u128 + 2)),
+        Some(u128::MAX)
+    );
+    assert_eq!(
+        black_box(0x1234_5678_9abc_def0u128).checked_mul(black_box(0xfedc_ba98_7654_3210u128)),
+        Some(0x121f_a00a_d77d_7422_236d_88fe_5618_cf00u128)
+    );
+    assert_eq!(
+        black_box(-0x1234_5678_9abc_def0i128).checked_mul(black_box(0x7654_3210_fedc_ba98i128)),
+        Some(-0x086a_1c97_652e_6a89_252c_268a_d05e_be80i128)
+    );
+}
+
+fn test_ptr_offset() {
+    let array = [1u32, 2, 3, 4];
+    let first = black_box(array.as_ptr());
+    unsafe {
+        assert_eq!(*first.offset(black_box(3)), 4);
+        assert_eq!(*first.add(black_box(3)).offset(black_box(-2)), 2);
+    }
+
+    // Unlike `offset`, `wrapping_offset` may wrap around the address space.
+    let last_byte = black_box(usize::MAX as *const u8);
+    assert_eq!(last_byte.wrapping_offset(black_box(1)), std::ptr::null());
+    assert_eq!(std::ptr::null::<u16>().wrapping_offset(black_box(-1)) as usize, usize::MAX - 1);
+}
+
+fn test_exact_div() {
+    use std::intrinsics::exact_div;
+
+    unsafe {
+        // Constant divisors are lowered to a shift and a multiplication by the inverse.
+        for x in [0u32, 12, 24, 1200, 12 * 357_913_941] {
+            let x = black_box(x);
+            assert_eq!(exact_div(x, 12), x / 12);
+            assert_eq!(exact_div(x, 4), x / 4);
+            assert_eq!(exact_div(x, 3), x / 3);
+            assert_eq!(exact_div(x, 1), x);
+        }
+        for x in [0i16, 6, -6, -30, 6 * 5461, i16::MIN + 2] {
+            let x = black_box(x);
+            assert_eq!(exact_div(x, 6), x / 6);
+            assert_eq!(exact_div(x, -6), x / -6);
+            assert_eq!(exact_div(x, -3), x / -3);
+            assert_eq!(exact_div(x, 2), x / 2);
+        }
+        assert_eq!(exact_div(black_box(255u8), 15), 17);
+        assert_eq!(exact_div(black_box(-128i8), 64), -2);
+        assert_eq!(exact_div(black_box(-128i8), -128), 1);
+        assert_eq!(exact_div(black_box(-126i8), -7), 18);
+        assert_eq!(exact_div(black_box(u64::MAX), 5), u64::MAX / 5);
+        assert_eq!(exact_div(black_box(u64::MAX - 1), 2), u64::MAX / 2);
+        assert_eq!(exact_div(black_box(i64::MIN), 1 << 32), -(1 << 31));
+        assert_eq!(exact_div(black_box(i64::MIN), -2), 1 << 62);
+        assert_eq!(exact_div(black_box(-7_000_000_049i64), -7), 1_000_000_007);
+        assert_eq!(exact_div(black_box(-84isize), -12), 7);
+
+        // Runtime divisors
+        assert_eq!(exact_div(black_box(84u32), black_box(12)), 7);
+        assert_eq!(exact_div(black_box(-84i64), black_box(12)), -7);
+        assert_eq!(exact_div(black_box(3i128 << 100), 3), 1 << 100);
+        assert_eq!(exact_div(black_box(-(3i128 << 100)), black_box(-(1 << 100))), 3);
+        assert_eq!(exact_div(black_box(u128::MAX), black_box(u64::MAX as u128)), (1 << 64) + 1);
+    }
+
+    // `offset_from` divides the distance in bytes exactly by the size of the pointee. So does the
+    // length of a slice iterator.
+    let array = [[0u8; 3]; 5];
+    let first = black_box(array.as_ptr());
+    unsafe {
+        assert_eq!(first.add(4).offset_from(first), 4);
+        assert_eq!(first.offset_from(first.add(4)), -4);
+    }
+    let mut iter = black_box(&array).iter();
+    iter.next();
+    assert_eq!(iter.len(), 4);
+}
+
+fn test_swap_bytes() {
+    let x = black_box(0x0011_2233_4455_6677_8899_aabb_ccdd_eeffu128);
+    assert_eq!(x.swap_bytes(), 0xffee_ddcc_bbaa_9988_7766_5544_3322_1100);
+    assert_eq!(x.swap_bytes().swap_bytes(), x);
+    assert_eq!(x.to_be_bytes(), x.swap_bytes().to_le_bytes());
+    assert_eq!(black_box(0x80i128).swap_bytes(), i128::MIN);
+    assert_eq!(black_box(1u128).swap_bytes(), 1 << 120);
+    assert_eq!(black_box(u128::MAX - 1).swap_bytes(), !(1 << 120));
+
+    assert_eq!(black_box(0x0011_2233_4455_6677u64).swap_bytes(), 0x7766_5544_3322_1100);
+    assert_eq!(black_box(0x0011u16).swap_bytes(), 0x1100);
+    assert_eq!(black_box(0x12u8).swap_bytes(), 0x12);
+}
+
+fn test_ptr_metadata() {
+    use std::fmt::Debug;
+    use std::ptr::{self, DynMetadata};
+
+    let array = [1u16, 2, 3, 4, 5];
+    let slice: *const [u16] = black_box(&array[1..4]);
+    assert_eq!(ptr::metadata(slice), 3);
+    let data = slice as *const ();
+    let slice2: *const [u16] = ptr::from_raw_parts(data, ptr::metadata(slice));
+    assert_eq!(slice2, slice);
+    assert_eq!(unsafe { &*slice2 }, &[2, 3, 4]);
+    let longer: *mut [u16] = ptr::from_raw_parts_mut(data as *mut (), 4);
+    assert_eq!(unsafe { &*longer }, &[2, 3, 4, 5]);
+
+    let value = 0x1234_5678u32;
+    let object: *const dyn Debug = black_box(&value);
+    let vtable: DynMetadata<dyn Debug> = ptr::metadata(object);
+    assert_eq!(vtable.size_of(), 4);
+    assert_eq!(vtable.align_of(), 4);
+    let object2: *const dyn Debug = ptr::from_raw_parts(object as *const (), vtable);
+    assert!(ptr::eq(object2, object));
+    assert_eq!(format!("{:?}", unsafe { &*object2 }), "305419896");
+
+    // Metadata of a sized pointee is `()`.
+    let thin: *const u32 = ptr::from_raw_parts(&value as *const u32 as *const (), ());
+    assert_eq!(unsafe { *thin }, value);
+}
+
+trait Describe {
+    fn describe(&self) -> &'static str;
+}
+
+impl<T> Describe for T {
+    default fn describe(&self) -> &'static str {
+        "default"
+    }
+}
+
+impl Describe for u32 {
+    fn describe(&self) -> &'static str {
+        "u32"
+    }
+}
+
+impl<T> Describe for Vec<T> {
+    fn describe(&self) -> &'static str {
+        "vec"
+    }
+}
+
+#[inline(never)]
+fn describe_generic<T>(value: T) -> &'static str {
+    value.describe()
+}
+
+fn test_specialization() {
+    assert_eq!(describe_generic(1u8), "default");
+    assert_eq!(describe_generic(1u32), "u32");
+    assert_eq!(describe_generic(vec![()]), "vec");
+
+    let objects: [&dyn Describe; 3] = [&1u8, &1u32, &Vec::<u8>::new()];
+    let described = objects.map(|object| black_box(object).describe());
+    assert_eq!(described, ["default", "u32", "vec"]);
+
+    let describe_u32: fn(&u32) -> &'static str = <u32 as Describe>::describe;
+    assert_eq!(black_box(describe_u32)(&1), "u32");
+}
+
+fn test_swap() {
+    let mut a = 1u8;
+    let mut b = 2u8;
+    std::mem::swap(black_box(&mut a), black_box(&mut b));
+    assert_eq!((a, b), (2, 1));
+
+    let mut a = (1u64, 2u32);
+    let mut b = (3u64, 4u32);
+    std::mem::swap(black_box(&mut a), black_box(&mut b));
+    assert_eq!((a, b), ((3, 4), (1, 2)));
+
+    let mut a = [1u16, 2, 3, 4, 5];
+    let mut b = [6u16, 7, 8, 9, 10];
+    std::mem::swap(black_box(&mut a), black_box(&mut b));
+    assert_eq!((a, b), ([6, 7, 8, 9, 10], [1, 2, 3, 4, 5]));
+
+    let mut a = [[1u64; 4]; 32];
+    let mut b = [[2u64; 4]; 32];
+    a[31][3] = 3;
+    std::mem::swap(black_box(&mut a), black_box(&mut b));
+    assert_eq!((a[0][0], a[31][3], b[0][0], b[31][3]), (2, 2, 1, 3));
+
+    // Swapping a value with itself leaves it unchanged.
+    let ptr = black_box(&mut b as *mut [[u64; 4]; 32]);
+    unsafe { std::ptr::swap(ptr, ptr) };
+    assert_eq!((b[0][0], b[31][3]), (1, 3));
+    let mut small = [1u8, 2, 3];
+    let ptr = black_box(&mut small as *mut [u8; 3]);
+    unsafe { std::ptr::swap(ptr, ptr) };
+    assert_eq!(small, [1, 2, 3]);
+}
+
+// `size_of_val`/`align_of_val` and the `vtable_size`/`vtable_align` intrinsics read the size and
+// alignment stored in the vtable.
+fn test_dyn_size_align() {
+    use std::fmt::Debug;
+
+    #[derive(Debug)]
+    #[repr(align(32))]
+    struct Aligned(u8);
+
+    let values: [Box<dyn Debug>; 3] =
+        [Box::new(0u16), Box::new((1u64, 2u32, 3u8)), Box::new(Aligned(4))];
+    for (value, (size, align)) in values.iter().zip([(2, 2), (16, 8), (32, 32)]) {
+        let value: &dyn Debug = black_box(&**value);
+        assert_eq!((std::mem::size_of_val(value), std::mem::align_of_val(value)), (size, align));
+
+        let (_, vtable) = unsafe { std::mem::transmute::<_, (*const (), *const ())>(value) };
+        let intrinsic_size_align = unsafe {
+            (std::intrinsics::vtable_size(vtable), std::intrinsics::vtable_align(vtable))
+        };
+        assert_eq!(intrinsic_size_align, (size, align));
+    }
+}
+
+// Unwinding isn't supported, so this only covers the drops on the regular control flow, including
+// early returns through nested scopes and locals which are only conditionally moved out.
+fn test_drop_order() {
+    use std::cell::RefCell;
+
+    struct Logged<'a>(&'a RefCell<Vec<&'static str>>, &'static str);
+
+    impl Drop for Logged<'_> {
+        fn drop(&mut self) {
+            self.0.borrow_mut().push(self.1);
+        }
+    }
+
+    fn scopes(log: &RefCell<Vec<&'static str>>, fail: bool, keep: bool) -> Option<()> {
+        let _a = Logged(log, "a");
+        let b = Logged(log, "b");
+        {
+            let _c = Logged(log, "c");
+            if black_box(fail) {
+                None?;
+            }
+        }
+        let moved = if black_box(keep) { Some(b) } else { None };
+        let _d = Logged(log, "d");
+        drop(moved);
+        Some(())
+    }
+
+    for (fail, keep, expected) in [
+        (true, false, &["c", "b", "a"][..]),
+        (false, false, &["c", "d", "b", "a"]),
+        (false, true, &["c", "b", "d", "a"]),
+    ] {
+        let log = RefCell::new(vec![]);
+        assert_eq!(scopes(&log, fail, keep).is_some(), !fail);
+        assert_eq!(*log.borrow(), expected, "fail={fail} keep={keep}");
+    }
+}
+
+// Locals of over-aligned enums need a stack slot with the alignment of the enum. An alignment
+// larger than the stack alignment requires dynamically realigning the stack slot.
+fn test_overaligned_enum() {
+    #[allow(dead_code)]
+    #[derive(Clone, Copy)]
+    #[repr(align(16))]
+    enum Align16 {
+        A(u8),
+        B(u64),
+    }
+
+    #[allow(dead_code)]
+    #[derive(Clone, Copy)]
+    #[repr(align(4096))]
+    enum Align4096 {
+        A(u8),
+        B,
+    }
+
+    #[inline(never)]
+    fn addr_of<T>(value: &T) -> usize {
+        value as *const T as usize
+    }
+
+    #[inline(never)]
+    fn addr_of_arg(value: Align4096) -> usize {
+        addr_of(&value)
+    }
+
+    assert_eq!(std::mem::align_of::<Align16>(), 16);
+    assert_eq!(std::mem::align_of::<Align4096>(), 4096);
+
+    let a = Align16::B(std::hint::black_box(1));
+    let b = [Align4096::A(std::hint::black_box(2)), Align4096::B];
+    assert_eq!(addr_of(&a) % 16, 0);
+    assert_eq!(addr_of(&b[0]) % 4096, 0);
+    assert_eq!(addr_of(&b[1]) % 4096, 0);
+    assert_eq!(addr_of_arg(b[0]) % 4096, 0);
+}
+
+// Library code like `str::repeat` uses this to pick a faster path for constant arguments.
+fn test_is_val_statically_known() {
+    use std::intrinsics::is_val_statically_known;
+
+    #[inline(never)]
+    fn is_known(x: u32) -> bool {
+        is_val_statically_known(x)
+    }
+
+    #[inline(never)]
+    fn pick_branch(x: u32) -> &'static str {
+        if is_val_statically_known(x) { "known" } else { "unknown" }
+    }
+
+    assert!(is_val_statically_known(42u32));
+    assert!(is_val_statically_known(true));
+    assert!(is_val_statically_known(u128::MAX));
+    assert_eq!(if is_val_statically_known(7u8) { "known" } else { "unknown" }, "known");
+    assert!(!is_known(42));
+    assert_eq!(pick_branch(black_box(7)), "unknown");
+}
+
+// Goes through `__rust_realloc` of the allocator shim, which forwards to `__rdl_realloc`.
+fn test_realloc_align() {
+    use std::alloc::{alloc, dealloc, realloc, Layout};
+
+    unsafe {
+        let layout = Layout::from_size_align(64, 4096).unwrap();
+        let ptr = alloc(layout);
+        assert!(!ptr.is_null());
+        ptr.write_bytes(0xcd, 64);
+
+        let ptr = realloc(ptr, layout, 20000);
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % 4096, 0);
+        assert!(std::slice::from_raw_parts(ptr, 64).iter().all(|&byte| byte == 0xcd));
+
+        dealloc(ptr, Layout::from_size_align(20000, 4096).unwrap());
+    }
 }
 
 #[derive(PartialEq)]
@@ -472,3 +1226,45 @@ fn map(a: Option<(u8, Box<Instruction>)>) -> Option<Box<Instruction>> {
         Some((_, instr)) => Some(instr),
     }
 }
+
+#[cfg(all(any(target_arch = "x86_64", target_arch = "aarch64"), not(windows)))]
+unsafe extern "C" fn sum_variadic(count: usize, mut args: ...) -> i32 {
+    let mut copy = args.clone();
+    let mut sum = 0;
+    for _ in 0..count {
+        sum += args.arg::<i32>();
+    }
+    // The copy is independent of the original argument list.
+    assert_eq!(copy.arg::<i32>(), 1);
+    sum
+}
+
+#[cfg(all(any(target_arch = "x86_64", target_arch = "aarch64"), not(windows)))]
+fn test_c_variadic() {
+    unsafe {
+        assert_eq!(sum_variadic(3, 1i32, 2i32, 3i32), 6);
+        // Some of the arguments are passed on the stack.
+        assert_eq!(sum_variadic(8, 1i32, 2i32, 3i32, 4i32, 5i32, 6i32, 7i32, 8i32), 36);
+
+        // Passing floats to variadic functions is only supported on AArch64.
+        #[cfg(target_arch = "aarch64")]
+        {
+            assert_eq!(sum_f64_variadic(3, 1.0f64, 2.5f64, 4.0f64), 7.5);
+            assert_eq!(
+                sum_f64_variadic(
+                    9, 1.0f64, 2.0f64, 3.0f64, 4.0f64, 5.0f64, 6.0f64, 7.0f64, 8.0f64, 9.0f64
+                ),
+                45.0,
+            );
+        }
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", not(windows)))]
+unsafe extern "C" fn sum_f64_variadic(count: usize, mut args: ...) -> f64 {
+    let mut sum = 0.0;
+    for _ in 0..count {
+        sum += args.arg::<f64>();
+    }
+    sum
+}
@@ -0,0 +1,15 @@
+// Compiled with `-Ztls-model=local-exec`. The test runner checks that a warning is emitted about the
+// requested TLS model not being supported rather than the flag being silently ignored.
+
+#![feature(thread_local)]
+#![crate_type = "lib"]
+
+#[thread_local]
+static mut COUNTER: u32 = 0;
+
+pub fn increment() -> u32 {
+    unsafe {
+        COUNTER += 1;
+        COUNTER
+    }
+}
@@ -0,0 +1,44 @@
+// Compiled with `-Cllvm-args=ub_checks=true` and debug assertions disabled. The test runner checks
+// that the UB selected by the first argument aborts with the message of the check of cg_clif. The
+// intrinsics are called directly to bypass the precondition checks of the standard library.
+
+#![feature(core_intrinsics, intrinsics)]
+#![allow(internal_features)]
+
+use std::hint::black_box;
+
+extern "rust-intrinsic" {
+    fn copy_nonoverlapping<T>(src: *const T, dst: *mut T, count: usize);
+    fn offset<Ptr, Delta>(dst: Ptr, offset: Delta) -> Ptr;
+}
+
+fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("copy_nonoverlapping") => {
+            let mut buf = [0u8; 16];
+            let ptr = buf.as_mut_ptr();
+            let len = black_box(8);
+            unsafe {
+                copy_nonoverlapping(ptr, ptr.add(4), len);
+            }
+            println!("overlapping copy not detected: {:?}", buf);
+        }
+        Some("exact_div") => {
+            let quotient = unsafe { std::intrinsics::exact_div(black_box(13u32), 4) };
+            println!("inexact exact_div not detected: {quotient}");
+        }
+        Some("unchecked_div") => {
+            let quotient = unsafe { std::intrinsics::unchecked_div(black_box(1u32), black_box(0)) };
+            println!("unchecked_div by zero not detected: {quotient}");
+        }
+        Some("ctlz_nonzero") => {
+            let zeros = unsafe { std::intrinsics::ctlz_nonzero(black_box(0u32)) };
+            println!("ctlz_nonzero of zero not detected: {zeros}");
+        }
+        Some("offset") => {
+            let ptr = unsafe { offset(black_box(&0u32 as *const u32), black_box(isize::MAX)) };
+            println!("overflowing offset not detected: {ptr:?}");
+        }
+        arg => panic!("unknown UB check {arg:?}"),
+    }
+}
@@ -0,0 +1,17 @@
+// Compiled with and without `--cfg checked`. The test runner checks that `/` and `%` are guarded
+// exactly once against a zero divisor and overflow, while `unchecked_div` and `unchecked_rem` are
+// only guarded when UB checks are enabled.
+
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+#![crate_type = "lib"]
+
+#[cfg(checked)]
+pub fn div_rem(a: i32, b: i32) -> (i32, i32) {
+    (a / b, a % b)
+}
+
+#[cfg(not(checked))]
+pub fn div_rem(a: i32, b: i32) -> (i32, i32) {
+    unsafe { (std::intrinsics::unchecked_div(a, b), std::intrinsics::unchecked_rem(a, b)) }
+}
@@ -0,0 +1,17 @@
+// Compiled with `CG_CLIF_DUMP_MODULE`. The test runner checks that matching on an uninhabited enum
+// traps instead of loading the discriminant.
+
+#![crate_type = "lib"]
+
+pub enum UninhabitedEnum {
+    A(std::convert::Infallible),
+    B(std::convert::Infallible, u8),
+}
+
+#[no_mangle]
+pub fn match_uninhabited_enum(x: &UninhabitedEnum) -> u8 {
+    match *x {
+        UninhabitedEnum::A(_) => 1,
+        UninhabitedEnum::B(_, b) => b,
+    }
+}
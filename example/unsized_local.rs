@@ -0,0 +1,11 @@
+// Cranelift can't allocate stack space of a size only known at runtime. The test runner checks that
+// unsized locals are rejected with an error pointing at the local rather than an ICE.
+
+#![feature(unsized_locals)]
+#![allow(incomplete_features)]
+#![crate_type = "lib"]
+
+pub fn first(boxed: Box<[u8]>) -> u8 {
+    let values: [u8] = *boxed;
+    values[0]
+}
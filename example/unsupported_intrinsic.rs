@@ -0,0 +1,11 @@
+// Compiled by the test runner, which checks that an intrinsic cg_clif doesn't support yet results
+// in an ICE pointing at the source of the call rather than a bare panic.
+
+#![crate_type = "lib"]
+
+use std::arch::x86_64::*;
+
+#[target_feature(enable = "avx")]
+pub unsafe fn cmp_always_true(a: __m128, b: __m128) -> __m128 {
+    _mm_cmp_ps::<_CMP_TRUE_UQ>(a, b)
+}
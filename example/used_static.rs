@@ -0,0 +1,15 @@
+// Linked with `--gc-sections`. The test runner checks that the unreferenced `#[used(linker)]`
+// static is kept in the executable while the `#[used(compiler)]` one is garbage collected. Each
+// static is placed in its own section as ELF linkers garbage collect whole sections.
+
+#![feature(used_with_arg)]
+
+#[used(linker)]
+#[link_section = ".data.cg_clif_used_linker"]
+static USED_LINKER: [u8; 20] = *b"cg_clif_used_linker!";
+
+#[used(compiler)]
+#[link_section = ".data.cg_clif_used_compiler"]
+static USED_COMPILER: [u8; 22] = *b"cg_clif_used_compiler!";
+
+fn main() {}
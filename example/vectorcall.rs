@@ -0,0 +1,60 @@
+// Calls between Rust and hand written assembly using the x86_64 Windows vectorcall calling
+// convention.
+
+#![feature(abi_vectorcall)]
+
+use std::arch::global_asm;
+
+// a: i32 in ecx, b: f64 in xmm1, c: i64 in r8, d: f32 in xmm3, e: i64 on the stack after the
+// 32 byte shadow space and the return address. Returns a + b + c + d + e in xmm0.
+global_asm!(
+    ".globl asm_vectorcall_sum",
+    "asm_vectorcall_sum:",
+    "cvtsi2sd xmm0, ecx",
+    "addsd xmm0, xmm1",
+    "cvtsi2sd xmm2, r8",
+    "addsd xmm0, xmm2",
+    "cvtss2sd xmm3, xmm3",
+    "addsd xmm0, xmm3",
+    "cvtsi2sd xmm2, qword ptr [rsp + 40]",
+    "addsd xmm0, xmm2",
+    "ret",
+);
+
+// Calls rust_vectorcall_callee(7, 2.5, 3, 4.0, 100) using the vectorcall register assignment.
+global_asm!(
+    ".globl asm_call_rust_vectorcall",
+    "asm_call_rust_vectorcall:",
+    "sub rsp, 56",
+    "mov qword ptr [rsp + 32], 100",
+    "mov rcx, 7",
+    "mov eax, 0x40200000",
+    "movd xmm1, eax",
+    "mov r8d, 3",
+    "mov rax, 0x4010000000000000",
+    "movq xmm3, rax",
+    "call rust_vectorcall_callee",
+    "add rsp, 56",
+    "ret",
+);
+
+extern "vectorcall" {
+    fn asm_vectorcall_sum(a: i32, b: f64, c: i64, d: f32, e: i64) -> f64;
+}
+
+extern "C" {
+    fn asm_call_rust_vectorcall() -> f64;
+}
+
+#[no_mangle]
+extern "vectorcall" fn rust_vectorcall_callee(a: i64, b: f32, c: u8, d: f64, e: i64) -> f64 {
+    assert_eq!((a, b, c, d, e), (7, 2.5, 3, 4.0, 100));
+    a as f64 * b as f64 + c as f64 * d + e as f64
+}
+
+fn main() {
+    let sum = unsafe { asm_vectorcall_sum(-1, 0.5, 20, 0.25, 300) };
+    assert_eq!(sum, 319.75);
+
+    assert_eq!(unsafe { asm_call_rust_vectorcall() }, 129.5);
+}
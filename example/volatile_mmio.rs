@@ -0,0 +1,19 @@
+// Compiled with and without `-Copt-level=3` using `CG_CLIF_DUMP_MODULE`. The test runner checks
+// that every volatile load is kept, even when the same address is read twice or was just written.
+
+#![crate_type = "lib"]
+#![feature(core_intrinsics)]
+#![allow(internal_features)]
+
+use std::intrinsics::{volatile_load, volatile_store};
+
+#[no_mangle]
+pub unsafe fn read_status_twice(reg: *const u32) -> u32 {
+    volatile_load(reg) + volatile_load(reg)
+}
+
+#[no_mangle]
+pub unsafe fn write_then_read(reg: *mut u32) -> u32 {
+    volatile_store(reg, 1);
+    volatile_load(reg)
+}
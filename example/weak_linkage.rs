@@ -0,0 +1,23 @@
+// Both this crate and weak_linkage_lib.rs define the same weak symbols.
+
+#![feature(linkage)]
+
+extern crate weak_linkage_lib;
+
+#[no_mangle]
+#[linkage = "weak"]
+pub extern "C" fn weak_linkage_fn() -> u32 {
+    2
+}
+
+#[no_mangle]
+#[linkage = "weak"]
+pub static WEAK_LINKAGE_STATIC: u32 = 2;
+
+fn main() {
+    // The linker picks a single definition, which both crates refer to.
+    let (lib_fn, lib_static) = weak_linkage_lib::weak_linkage_addrs();
+    assert_eq!(lib_fn, weak_linkage_fn as extern "C" fn() -> u32 as usize);
+    assert_eq!(lib_static, &WEAK_LINKAGE_STATIC as *const u32 as usize);
+    assert_eq!(weak_linkage_lib::weak_linkage_value(), weak_linkage_fn());
+}
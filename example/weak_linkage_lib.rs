@@ -0,0 +1,29 @@
+// Defines the same weak symbols as weak_linkage.rs. Linking both crates into a single executable
+// must pick one definition of each rather than failing with duplicate symbol errors.
+
+#![feature(linkage)]
+
+#[no_mangle]
+#[linkage = "weak"]
+pub extern "C" fn weak_linkage_fn() -> u32 {
+    1
+}
+
+#[no_mangle]
+#[linkage = "weak"]
+pub static WEAK_LINKAGE_STATIC: u32 = 1;
+
+// Referencing the weak symbols from a function used by the executable ensures that the object
+// file containing the definitions of this crate gets linked.
+#[inline(never)]
+pub fn weak_linkage_addrs() -> (usize, usize) {
+    (
+        weak_linkage_fn as extern "C" fn() -> u32 as usize,
+        &WEAK_LINKAGE_STATIC as *const u32 as usize,
+    )
+}
+
+#[inline(never)]
+pub fn weak_linkage_value() -> u32 {
+    weak_linkage_fn()
+}
@@ -0,0 +1,89 @@
+//! Compile a trivial function using the API of the `testing` feature and check the disassembly of
+//! the resulting machine code.
+//!
+//! Run using `cargo run --example compile_function --features testing`. Requires a GNU objdump
+//! supporting x86_64.
+
+use std::process::Command;
+use std::str::FromStr;
+
+use cranelift_codegen::binemit::Reloc;
+use cranelift_codegen::ir::types;
+use cranelift_codegen::ir::{
+    AbiParam, ExtFuncData, ExternalName, Function, InstBuilder, Signature, UserExternalName,
+    UserFuncName,
+};
+use cranelift_codegen::isa::{self, CallConv};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use rustc_codegen_cranelift::testing::compile_clif_function;
+use target_lexicon::Triple;
+
+fn main() {
+    let mut flag_builder = settings::builder();
+    flag_builder.set("opt_level", "speed").unwrap();
+    let isa = isa::lookup(Triple::from_str("x86_64-unknown-linux-gnu").unwrap())
+        .unwrap()
+        .finish(settings::Flags::new(flag_builder))
+        .unwrap();
+
+    // fn add_and_call(a: i64, b: i64) -> i64 { callee(a + b) }
+    let mut sig = Signature::new(CallConv::SystemV);
+    sig.params.push(AbiParam::new(types::I64));
+    sig.params.push(AbiParam::new(types::I64));
+    sig.returns.push(AbiParam::new(types::I64));
+    let mut callee_sig = Signature::new(CallConv::SystemV);
+    callee_sig.params.push(AbiParam::new(types::I64));
+    callee_sig.returns.push(AbiParam::new(types::I64));
+
+    let mut func = Function::with_name_signature(UserFuncName::user(0, 0), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    let mut bcx = FunctionBuilder::new(&mut func, &mut func_ctx);
+    let callee_name = bcx.func.declare_imported_user_function(UserExternalName::new(0, 1));
+    let callee_sig = bcx.import_signature(callee_sig);
+    let callee = bcx.import_function(ExtFuncData {
+        name: ExternalName::User(callee_name),
+        signature: callee_sig,
+        colocated: true,
+    });
+    let block = bcx.create_block();
+    bcx.append_block_params_for_function_params(block);
+    bcx.switch_to_block(block);
+    let (a, b) = (bcx.block_params(block)[0], bcx.block_params(block)[1]);
+    let sum = bcx.ins().iadd(a, b);
+    let call = bcx.ins().call(callee, &[sum]);
+    let res = bcx.inst_results(call)[0];
+    bcx.ins().return_(&[res]);
+    bcx.seal_all_blocks();
+    bcx.finalize();
+
+    let compiled = compile_clif_function(&*isa, func).unwrap();
+
+    assert_eq!(compiled.relocs.len(), 1, "{:?}", compiled.relocs);
+    let reloc = &compiled.relocs[0];
+    assert_eq!(reloc.kind, Reloc::X86CallPCRel4);
+    assert_eq!(reloc.target, "u0:1");
+    assert_eq!(reloc.addend, -4);
+
+    let code_path =
+        std::env::temp_dir().join(format!("cg_clif_compile_function_{}", std::process::id()));
+    std::fs::write(&code_path, &compiled.code).unwrap();
+    let output = Command::new("objdump")
+        .args(["-D", "-b", "binary", "-m", "i386:x86-64", "-M", "intel"])
+        .arg(&code_path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&code_path).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let disasm = String::from_utf8(output.stdout).unwrap();
+    println!("{disasm}");
+    let insns = disasm.lines().filter_map(|line| line.split('\t').nth(2)).collect::<Vec<_>>();
+    // Depending on register allocation the addition is lowered to either `add` or `lea`.
+    for expected in [&["add", "lea"][..], &["call"], &["ret"]] {
+        assert!(
+            insns.iter().any(|insn| expected.iter().any(|mnemonic| insn.starts_with(mnemonic))),
+            "missing {expected:?} instruction",
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Compile a function of a small crate using the `compile_instance` API of the `testing` feature
+//! and check that the call to another function is resolved to the symbol name of the callee.
+//!
+//! Run using `cargo run --example compile_instance --features testing`.
+
+#![feature(rustc_private)]
+
+extern crate rustc_driver;
+extern crate rustc_interface;
+extern crate rustc_middle;
+
+use rustc_codegen_cranelift::testing::compile_instance;
+use rustc_driver::{Callbacks, Compilation, RunCompiler};
+use rustc_interface::Queries;
+use rustc_interface::interface::Compiler;
+use rustc_middle::ty::Instance;
+
+const SOURCE: &str = r#"
+#[no_mangle]
+pub fn callee(x: u64) -> u64 {
+    x / 3
+}
+
+#[no_mangle]
+pub fn caller(x: u64) -> u64 {
+    callee(x) + 1
+}
+"#;
+
+struct CompileCaller;
+
+impl Callbacks for CompileCaller {
+    fn after_analysis<'tcx>(
+        &mut self,
+        _compiler: &Compiler,
+        queries: &'tcx Queries<'tcx>,
+    ) -> Compilation {
+        queries.global_ctxt().unwrap().enter(|tcx| {
+            let caller = tcx
+                .hir()
+                .body_owners()
+                .find(|&def_id| tcx.item_name(def_id.to_def_id()).as_str() == "caller")
+                .expect("no function named caller");
+            let compiled = compile_instance(tcx, Instance::mono(tcx, caller.to_def_id()));
+
+            assert!(!compiled.code.is_empty());
+            assert!(
+                compiled.relocs.iter().any(|reloc| reloc.target == "callee"),
+                "call to callee not found: {:?}",
+                compiled.relocs,
+            );
+            for reloc in &compiled.relocs {
+                assert!(
+                    (reloc.offset as usize) < compiled.code.len(),
+                    "relocation outside of the function: {reloc:?}",
+                );
+            }
+        });
+        Compilation::Stop
+    }
+}
+
+fn main() {
+    let source_path =
+        std::env::temp_dir().join(format!("cg_clif_compile_instance_{}.rs", std::process::id()));
+    std::fs::write(&source_path, SOURCE).unwrap();
+    let args = [
+        "rustc".to_owned(),
+        "--crate-type=lib".to_owned(),
+        source_path.to_str().unwrap().to_owned(),
+    ];
+    let res = RunCompiler::new(&args, &mut CompileCaller).run();
+    std::fs::remove_file(&source_path).unwrap();
+    res.unwrap();
+}
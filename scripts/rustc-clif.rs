@@ -1,8 +1,9 @@
 use std::env;
 use std::ffi::OsString;
+use std::io::Write;
 #[cfg(unix)]
 use std::os::unix::process::CommandExt;
-use std::process::Command;
+use std::process::{self, Command, Stdio};
 
 fn main() {
     let current_exe = env::current_exe().unwrap();
@@ -15,7 +16,9 @@ fn main() {
         env::consts::DLL_PREFIX.to_string() + "rustc_codegen_cranelift" + env::consts::DLL_SUFFIX,
     );
 
-    let passed_args = std::env::args_os().skip(1).collect::<Vec<_>>();
+    let mut passed_args = std::env::args_os().skip(1).collect::<Vec<_>>();
+    let llvm_backend_fallback = passed_args.iter().any(|arg| arg == "--with-llvm-backend-fallback");
+    passed_args.retain(|arg| arg != "--with-llvm-backend-fallback");
     let mut args = vec![];
     args.push(OsString::from("-Cpanic=abort"));
     args.push(OsString::from("-Zpanic-abort-tests"));
@@ -42,11 +45,45 @@ fn main() {
         "rustc"
     };
 
+    if llvm_backend_fallback {
+        // Compile the whole crate with the default LLVM backend if cg_clif fails to compile it,
+        // for example because it uses a feature cg_clif doesn't support yet. cg_clif creates the
+        // marker file once the frontend succeeded, so errors in the crate itself are reported
+        // without compiling it a second time. The errors of cg_clif are only shown if the LLVM
+        // backend fails too.
+        let marker = env::temp_dir().join(format!("cg_clif_codegen_started_{}", process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let output = Command::new(rustc)
+            .args(&args)
+            .env("__CG_CLIF_CODEGEN_STARTED_MARKER", &marker)
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+            .wait_with_output()
+            .unwrap();
+        let codegen_started = std::fs::remove_file(&marker).is_ok();
+        if output.status.success() || !codegen_started {
+            std::io::stderr().write_all(&output.stderr).unwrap();
+            process::exit(output.status.code().unwrap_or(1));
+        }
+        eprintln!(
+            "warning: cg_clif failed to compile this crate; compiling it with the LLVM backend \
+            instead"
+        );
+        args.retain(|arg| !arg.to_str().is_some_and(|arg| arg.starts_with("-Zcodegen-backend=")));
+        let status = Command::new(rustc).args(&args).status().unwrap();
+        if !status.success() {
+            eprintln!("note: errors of cg_clif before falling back to the LLVM backend:");
+            std::io::stderr().write_all(&output.stderr).unwrap();
+        }
+        process::exit(status.code().unwrap_or(1));
+    }
+
     #[cfg(unix)]
     panic!("Failed to spawn rustc: {}", Command::new(rustc).args(args).exec());
 
     #[cfg(not(unix))]
-    std::process::exit(
+    process::exit(
         Command::new(rustc).args(args).spawn().unwrap().wait().unwrap().code().unwrap_or(1),
     );
 }
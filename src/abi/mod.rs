@@ -3,6 +3,7 @@
 mod comments;
 mod pass_mode;
 mod returning;
+mod varargs;
 
 use std::borrow::Cow;
 
@@ -17,6 +18,7 @@
 
 use self::pass_mode::*;
 pub(crate) use self::returning::codegen_return;
+pub(crate) use self::varargs::codegen_va_arg;
 use crate::prelude::*;
 
 fn clif_sig_from_fn_abi<'tcx>(
@@ -30,11 +32,34 @@ fn clif_sig_from_fn_abi<'tcx>(
 
     let (return_ptr, returns) = fn_abi.ret.get_abi_return(tcx);
     // Sometimes the first param is a pointer to the place where the return value needs to be stored.
-    let params: Vec<_> = return_ptr.into_iter().chain(inputs).collect();
+    let mut params: Vec<_> = return_ptr.into_iter().chain(inputs).collect();
+    if fn_abi.c_variadic {
+        params.extend(self::varargs::va_register_params(tcx, &params));
+    }
+    if call_conv == CallConv::WindowsFastcall && fn_abi.conv == Conv::X86VectorCall {
+        check_vectorcall_params(tcx, &params);
+    }
 
     Signature { params, returns, call_conv }
 }
 
+/// On x86_64 Windows `extern "vectorcall"` assigns argument registers like the Windows x64 calling
+/// convention, except that float and vector arguments in the fifth and sixth position are passed
+/// in XMM4 and XMM5 and that vector arguments after the sixth position are passed by reference.
+/// Aggregates including HVAs are already passed by reference by the rustc ABI classification, just
+/// like with the LLVM backend. Cranelift can't express the extra argument registers, so signatures
+/// which would need them are rejected rather than miscompiled.
+fn check_vectorcall_params(tcx: TyCtxt<'_>, params: &[AbiParam]) {
+    for (i, param) in params.iter().enumerate().skip(4) {
+        if param.value_type.is_vector() || (param.value_type.is_float() && i < 6) {
+            tcx.dcx().fatal(
+                "extern \"vectorcall\" functions with float or vector arguments after the fourth \
+                argument are not yet supported by Cranelift",
+            );
+        }
+    }
+}
+
 pub(crate) fn conv_to_call_conv(sess: &Session, c: Conv, default_call_conv: CallConv) -> CallConv {
     match c {
         Conv::Rust | Conv::C => default_call_conv,
@@ -42,7 +67,8 @@ pub(crate) fn conv_to_call_conv(sess: &Session, c: Conv, default_call_conv: Call
         Conv::X86_64SysV => CallConv::SystemV,
         Conv::X86_64Win64 => CallConv::WindowsFastcall,
 
-        // Should already get a back compat warning
+        // Should already get a back compat warning. On x86_64 Windows the default call conv
+        // matches vectorcall for all signatures accepted by `check_vectorcall_params`.
         Conv::X86Fastcall | Conv::X86Stdcall | Conv::X86ThisCall | Conv::X86VectorCall => {
             default_call_conv
         }
@@ -183,6 +209,7 @@ fn make_local_place<'tcx>(
             "unsized locals are not yet supported",
         );
     }
+    crate::value_and_place::maybe_print_layout(fx, layout);
     let place = if is_ssa {
         if let rustc_target::abi::Abi::ScalarPair(_, _) = layout.abi {
             CPlace::new_var_pair(fx, local, layout)
@@ -217,26 +244,25 @@ pub(crate) fn codegen_fn_prelude<'tcx>(fx: &mut FunctionCx<'_, '_, 'tcx>, start_
     enum ArgKind<'tcx> {
         Normal(Option<CValue<'tcx>>),
         Spread(Vec<Option<CValue<'tcx>>>),
+        // The `...` argument of a C-variadic function
+        VaList,
     }
 
     let fn_abi = fx.fn_abi.take().unwrap();
 
-    // FIXME implement variadics in cranelift
-    if fn_abi.c_variadic {
-        fx.tcx.dcx().span_fatal(
-            fx.mir.span,
-            "Defining variadic functions is not yet supported by Cranelift",
-        );
-    }
-
     let mut arg_abis_iter = fn_abi.args.iter();
 
     let func_params = fx
         .mir
         .args_iter()
-        .map(|local| {
+        .enumerate()
+        .map(|(arg_index, local)| {
             let arg_ty = fx.monomorphize(fx.mir.local_decls[local].ty);
 
+            if fn_abi.c_variadic && arg_index == fn_abi.args.len() {
+                return (local, ArgKind::VaList, arg_ty);
+            }
+
             // Adapted from https://github.com/rust-lang/rust/blob/145155dc96757002c7b2e9de8489416e2fdbbd57/src/librustc_codegen_llvm/mir/mod.rs#L442-L482
             if Some(local) == fx.mir.spread_arg {
                 // This argument (e.g. the last argument in the "rust-call" ABI)
@@ -277,7 +303,8 @@ enum ArgKind<'tcx> {
 
     assert!(arg_abis_iter.next().is_none(), "ArgAbi left behind");
     fx.fn_abi = Some(fn_abi);
-    assert!(block_params_iter.next().is_none(), "arg_value left behind");
+    // The argument registers which may contain variadic arguments
+    let va_register_values = block_params_iter.collect::<Vec<_>>();
 
     self::comments::add_locals_header_comment(fx);
 
@@ -321,8 +348,14 @@ enum ArgKind<'tcx> {
                     }
                 }
             }
+            ArgKind::VaList => {
+                self::varargs::codegen_va_start(fx, place, &va_register_values);
+            }
         }
     }
+    if !fx.fn_abi.as_ref().unwrap().c_variadic {
+        assert!(va_register_values.is_empty(), "arg_value left behind");
+    }
 
     for local in fx.mir.vars_and_temps_iter() {
         let ty = fx.monomorphize(fx.mir.local_decls[local].ty);
@@ -334,6 +367,8 @@ enum ArgKind<'tcx> {
         assert_eq!(fx.local_map.push(place), local);
     }
 
+    crate::coverage::codegen_call_counter(fx);
+
     fx.bcx.ins().jump(*fx.block_map.get(START_BLOCK).unwrap(), &[]);
 }
 
@@ -427,6 +462,9 @@ pub(crate) fn codegen_terminator_call<'tcx>(
     } else {
         instance.is_some_and(|inst| {
             fx.tcx.codegen_fn_attrs(inst.def_id()).flags.contains(CodegenFnAttrFlags::COLD)
+                || fx.cx.profile_use.as_ref().is_some_and(|profile_use| {
+                    profile_use.is_never_called(fx.tcx.symbol_name(inst).name)
+                })
         })
     };
     if is_cold {
@@ -519,18 +557,20 @@ enum CallTarget {
     };
 
     self::returning::codegen_with_call_return_arg(fx, &fn_abi.ret, ret_place, |fx, return_ptr| {
-        let call_args = return_ptr
-            .into_iter()
-            .chain(first_arg_override.into_iter())
-            .chain(
-                args.into_iter()
-                    .enumerate()
-                    .skip(if first_arg_override.is_some() { 1 } else { 0 })
-                    .flat_map(|(i, arg)| {
-                        adjust_arg_for_abi(fx, arg.value, &fn_abi.args[i], arg.is_owned).into_iter()
-                    }),
-            )
-            .collect::<Vec<Value>>();
+        let mut call_args =
+            return_ptr.into_iter().chain(first_arg_override.into_iter()).collect::<Vec<Value>>();
+        let mut fixed_arg_count = call_args.len();
+        for (i, arg) in
+            args.into_iter().enumerate().skip(if first_arg_override.is_some() { 1 } else { 0 })
+        {
+            call_args.extend(adjust_arg_for_abi(fx, arg.value, &fn_abi.args[i], arg.is_owned));
+            if i < fn_abi.fixed_count as usize {
+                fixed_arg_count = call_args.len();
+            }
+        }
+        if fn_sig.c_variadic() {
+            call_args = self::varargs::adjust_variadic_call_args(fx, call_args, fixed_arg_count);
+        }
 
         let call_inst = match func_ref {
             CallTarget::Direct(func_ref) => fx.bcx.ins().call(func_ref, &call_args),
@@ -552,8 +592,8 @@ enum CallTarget {
                 .into_iter()
                 .map(|arg| {
                     let ty = fx.bcx.func.dfg.value_type(arg);
-                    if !ty.is_int() {
-                        // FIXME set %al to upperbound on float args once floats are supported
+                    if !ty.is_int() && !self::varargs::variadic_float_args_supported(fx.tcx) {
+                        // FIXME set %al to upperbound on float args on x86_64 SysV
                         fx.tcx.dcx().span_fatal(
                             source_info.span,
                             format!("Non int ty {:?} for variadic call", ty),
@@ -0,0 +1,330 @@
+//! Support for defining C-variadic functions and reading their arguments using `va_arg`.
+//!
+//! Cranelift doesn't know about variadic functions. For the SysV x86_64 ABI and AAPCS64 all
+//! argument registers not used by the fixed arguments are added as extra params to the signature
+//! of variadic functions and spilled to the register save area in the prologue. Arguments passed on
+//! the stack are found relative to the frame pointer. On Apple AArch64 all variadic arguments are
+//! passed on the stack.
+
+use cranelift_codegen::ir::ArgumentPurpose;
+use rustc_target::abi::Endian;
+
+use crate::prelude::*;
+
+/// Size of the general purpose register part of the SysV x86_64 register save area.
+const SYSV64_GP_SAVE_AREA_SIZE: i64 = 6 * 8;
+/// Size of the register save area for all general purpose and vector argument registers.
+const SYSV64_SAVE_AREA_SIZE: i64 = SYSV64_GP_SAVE_AREA_SIZE + 8 * 16;
+
+/// Size of the general purpose and vector register save areas of AAPCS64.
+const AAPCS64_GR_SAVE_AREA_SIZE: i64 = 8 * 8;
+const AAPCS64_VR_SAVE_AREA_SIZE: i64 = 8 * 16;
+
+/// Offset of the incoming stack arguments from the frame pointer, skipping the saved frame pointer
+/// and the return address.
+const STACK_ARGS_FP_OFFSET: i64 = 16;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum VaListKind {
+    SysV64,
+    Aapcs64,
+    AppleAarch64,
+}
+
+fn va_list_kind(tcx: TyCtxt<'_>) -> Option<VaListKind> {
+    let target = &tcx.sess.target;
+    match &*target.arch {
+        "x86_64" if !target.is_like_windows => Some(VaListKind::SysV64),
+        "aarch64" if target.is_like_osx => Some(VaListKind::AppleAarch64),
+        "aarch64" if !target.is_like_windows && target.endian == Endian::Little => {
+            Some(VaListKind::Aapcs64)
+        }
+        _ => None,
+    }
+}
+
+/// Whether float arguments can be passed to variadic functions. On x86_64 SysV the number of vector
+/// registers used would need to be passed in `%al`.
+pub(super) fn variadic_float_args_supported(tcx: TyCtxt<'_>) -> bool {
+    matches!(va_list_kind(tcx), Some(VaListKind::Aapcs64 | VaListKind::AppleAarch64))
+}
+
+/// The number of general purpose and vector registers used by `params`. Returns `None` if any of
+/// the params is passed on the stack.
+fn register_usage(kind: VaListKind, params: &[AbiParam]) -> Option<(i64, i64)> {
+    let (max_gp, max_fp) = match kind {
+        VaListKind::SysV64 => (6, 8),
+        VaListKind::Aapcs64 | VaListKind::AppleAarch64 => (8, 8),
+    };
+    let mut gp = 0;
+    let mut fp = 0;
+    for param in params {
+        if let ArgumentPurpose::StructArgument(_) = param.purpose {
+            return None;
+        }
+        match param.value_type {
+            // AAPCS64 passes 128bit integers in an even numbered register pair.
+            types::I128 if kind != VaListKind::SysV64 => gp = (gp + 1) / 2 * 2 + 2,
+            types::I128 => gp += 2,
+            ty if ty.is_int() => gp += 1,
+            _ => fp += 1,
+        }
+    }
+    if gp <= max_gp && fp <= max_fp { Some((gp, fp)) } else { None }
+}
+
+/// Extra params appended to the signature of variadic functions to receive all argument registers
+/// which may hold variadic arguments.
+pub(super) fn va_register_params(tcx: TyCtxt<'_>, fixed_params: &[AbiParam]) -> Vec<AbiParam> {
+    match va_list_kind(tcx) {
+        Some(kind @ (VaListKind::SysV64 | VaListKind::Aapcs64)) => {
+            let Some((gp, fp)) = register_usage(kind, fixed_params) else {
+                return vec![];
+            };
+            let max_gp = if kind == VaListKind::SysV64 { 6 } else { 8 };
+            std::iter::repeat(AbiParam::new(types::I64))
+                .take((max_gp - gp) as usize)
+                .chain(std::iter::repeat(AbiParam::new(types::F64)).take((8 - fp) as usize))
+                .collect()
+        }
+        Some(VaListKind::AppleAarch64) | None => vec![],
+    }
+}
+
+/// Initialize the `VaListImpl` backing the `...` argument of a variadic function.
+pub(super) fn codegen_va_start<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    va_list: CPlace<'tcx>,
+    va_register_values: &[Value],
+) {
+    let Some(kind) = va_list_kind(fx.tcx) else {
+        fx.tcx.dcx().span_fatal(
+            fx.mir.span,
+            "Defining variadic functions is not yet supported by Cranelift on this target",
+        );
+    };
+
+    let params = &fx.bcx.func.signature.params;
+    let fixed_params = &params[..params.len() - va_register_values.len()];
+    let Some((gp, fp)) = register_usage(kind, fixed_params) else {
+        fx.tcx.dcx().span_fatal(
+            fx.mir.span,
+            "Variadic functions with fixed arguments passed on the stack are not yet supported by \
+            Cranelift",
+        );
+    };
+
+    let stack_args = fx.bcx.ins().get_frame_pointer(fx.pointer_type);
+    let stack_args = fx.bcx.ins().iadd_imm(stack_args, STACK_ARGS_FP_OFFSET);
+
+    match kind {
+        VaListKind::SysV64 => {
+            let save_area = fx.create_stack_slot(SYSV64_SAVE_AREA_SIZE as u32, 16);
+            let (mut gp_offset, mut fp_offset) = (gp * 8, SYSV64_GP_SAVE_AREA_SIZE + fp * 16);
+            for &val in va_register_values {
+                let offset = if fx.bcx.func.dfg.value_type(val).is_int() {
+                    gp_offset += 8;
+                    gp_offset - 8
+                } else {
+                    fp_offset += 16;
+                    fp_offset - 16
+                };
+                save_area.offset_i64(fx, offset).store(fx, val, MemFlags::trusted());
+            }
+
+            let gp_offset = fx.bcx.ins().iconst(types::I32, gp * 8);
+            let fp_offset = fx.bcx.ins().iconst(types::I32, SYSV64_GP_SAVE_AREA_SIZE + fp * 16);
+            let reg_save_area = save_area.get_addr(fx);
+            for (i, val) in
+                [gp_offset, fp_offset, stack_args, reg_save_area].into_iter().enumerate()
+            {
+                let field = va_list.place_field(fx, FieldIdx::new(i));
+                field.write_cvalue(fx, CValue::by_val(val, field.layout()));
+            }
+        }
+        VaListKind::Aapcs64 => {
+            // The registers are saved at the end of the save areas, with `gr_offs` and `vr_offs`
+            // holding the negative offset of the next unread register from the end.
+            let gr_save_area = fx.create_stack_slot(AAPCS64_GR_SAVE_AREA_SIZE as u32, 16);
+            let vr_save_area = fx.create_stack_slot(AAPCS64_VR_SAVE_AREA_SIZE as u32, 16);
+            let (mut gr_offset, mut vr_offset) = (gp * 8, fp * 16);
+            for &val in va_register_values {
+                let addr = if fx.bcx.func.dfg.value_type(val).is_int() {
+                    gr_offset += 8;
+                    gr_save_area.offset_i64(fx, gr_offset - 8)
+                } else {
+                    vr_offset += 16;
+                    vr_save_area.offset_i64(fx, vr_offset - 16)
+                };
+                addr.store(fx, val, MemFlags::trusted());
+            }
+
+            let gr_top = gr_save_area.offset_i64(fx, AAPCS64_GR_SAVE_AREA_SIZE).get_addr(fx);
+            let vr_top = vr_save_area.offset_i64(fx, AAPCS64_VR_SAVE_AREA_SIZE).get_addr(fx);
+            let gr_offs = fx.bcx.ins().iconst(types::I32, gp * 8 - AAPCS64_GR_SAVE_AREA_SIZE);
+            let vr_offs = fx.bcx.ins().iconst(types::I32, fp * 16 - AAPCS64_VR_SAVE_AREA_SIZE);
+            for (i, val) in [stack_args, gr_top, vr_top, gr_offs, vr_offs].into_iter().enumerate() {
+                let field = va_list.place_field(fx, FieldIdx::new(i));
+                field.write_cvalue(fx, CValue::by_val(val, field.layout()));
+            }
+        }
+        VaListKind::AppleAarch64 => {
+            va_list.write_cvalue(fx, CValue::by_val(stack_args, va_list.layout()));
+        }
+    }
+}
+
+/// Insert padding arguments for calls to variadic functions to ensure the variadic arguments are
+/// passed on the stack on targets where this is required.
+pub(super) fn adjust_variadic_call_args(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    mut call_args: Vec<Value>,
+    fixed_arg_count: usize,
+) -> Vec<Value> {
+    if va_list_kind(fx.tcx) != Some(VaListKind::AppleAarch64) {
+        return call_args;
+    }
+
+    let (fixed_args, variadic_args) = call_args.split_at(fixed_arg_count);
+    let used_gp = fixed_args.iter().filter(|&&arg| fx.bcx.func.dfg.value_type(arg).is_int());
+    let used_gp = used_gp.count();
+    let used_fp = fixed_args.len() - used_gp;
+    let variadic_args = variadic_args.to_vec();
+    call_args.truncate(fixed_arg_count);
+
+    // Fill all remaining argument registers. Every variadic argument occupies a full 8 byte stack
+    // slot.
+    for _ in used_gp..8 {
+        call_args.push(fx.bcx.ins().iconst(types::I64, 0));
+    }
+    for _ in used_fp..8 {
+        call_args.push(fx.bcx.ins().f64const(0.0));
+    }
+    for arg in variadic_args {
+        let arg = match fx.bcx.func.dfg.value_type(arg) {
+            ty if ty.is_int() && ty.bits() < 64 => fx.bcx.ins().uextend(types::I64, arg),
+            types::F32 => fx.bcx.ins().fpromote(types::F64, arg),
+            _ => arg,
+        };
+        call_args.push(arg);
+    }
+
+    call_args
+}
+
+/// Branch on `in_regs` between the argument at `reg_addr` in the register save area and the next
+/// argument on the stack. `advance_reg_offset` is called in the register branch to mark the
+/// register as read.
+fn reg_or_stack_arg_addr<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    in_regs: Value,
+    reg_addr: impl FnOnce(&mut FunctionCx<'_, '_, 'tcx>) -> Value,
+    stack_ptr_place: CPlace<'tcx>,
+) -> Value {
+    let reg_block = fx.bcx.create_block();
+    let stack_block = fx.bcx.create_block();
+    let next_block = fx.bcx.create_block();
+    let arg_addr = fx.bcx.append_block_param(next_block, fx.pointer_type);
+    fx.bcx.ins().brif(in_regs, reg_block, &[], stack_block, &[]);
+    fx.bcx.seal_block(reg_block);
+    fx.bcx.seal_block(stack_block);
+
+    fx.bcx.switch_to_block(reg_block);
+    let reg_addr = reg_addr(fx);
+    fx.bcx.ins().jump(next_block, &[reg_addr]);
+
+    fx.bcx.switch_to_block(stack_block);
+    // Every variadic argument occupies a full 8 byte stack slot.
+    let stack_ptr = stack_ptr_place.to_cvalue(fx).load_scalar(fx);
+    let new_stack_ptr = fx.bcx.ins().iadd_imm(stack_ptr, 8);
+    stack_ptr_place.write_cvalue(fx, CValue::by_val(new_stack_ptr, stack_ptr_place.layout()));
+    fx.bcx.ins().jump(next_block, &[stack_ptr]);
+
+    fx.bcx.seal_block(next_block);
+    fx.bcx.switch_to_block(next_block);
+    arg_addr
+}
+
+pub(crate) fn codegen_va_arg<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    va_list_ref: CValue<'tcx>,
+    ret: CPlace<'tcx>,
+    span: Span,
+) {
+    let Some(kind) = va_list_kind(fx.tcx) else {
+        fx.tcx.dcx().span_fatal(span, "va_arg is not yet supported by Cranelift on this target");
+    };
+
+    let va_list_ty = va_list_ref.layout().ty.builtin_deref(true).unwrap().ty;
+    let va_list_layout = fx.layout_of(va_list_ty);
+    let va_list = CPlace::for_ptr(Pointer::new(va_list_ref.load_scalar(fx)), va_list_layout);
+    // All types implementing `VaArgSafe` fit in a single register or stack slot.
+    let arg_ty = match fx.clif_type(ret.layout().ty) {
+        Some(arg_ty) if arg_ty.bytes() <= 8 && !arg_ty.is_vector() => arg_ty,
+        _ => fx.tcx.dcx().span_fatal(span, format!("Unsupported va_arg type {}", ret.layout().ty)),
+    };
+
+    let arg_addr = match kind {
+        VaListKind::SysV64 => {
+            // gp_offset or fp_offset
+            let (offset_field, offset_limit, offset_step) = if arg_ty.is_int() {
+                (0, SYSV64_GP_SAVE_AREA_SIZE, 8)
+            } else {
+                (1, SYSV64_SAVE_AREA_SIZE, 16)
+            };
+            let offset_place = va_list.place_field(fx, FieldIdx::new(offset_field));
+            let overflow_arg_area_place = va_list.place_field(fx, FieldIdx::new(2));
+            let reg_save_area_place = va_list.place_field(fx, FieldIdx::new(3));
+
+            let offset = offset_place.to_cvalue(fx).load_scalar(fx);
+            let in_regs = fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, offset, offset_limit);
+            reg_or_stack_arg_addr(
+                fx,
+                in_regs,
+                |fx| {
+                    let reg_save_area = reg_save_area_place.to_cvalue(fx).load_scalar(fx);
+                    let offset_ext = fx.bcx.ins().uextend(fx.pointer_type, offset);
+                    let new_offset = fx.bcx.ins().iadd_imm(offset, offset_step);
+                    offset_place
+                        .write_cvalue(fx, CValue::by_val(new_offset, offset_place.layout()));
+                    fx.bcx.ins().iadd(reg_save_area, offset_ext)
+                },
+                overflow_arg_area_place,
+            )
+        }
+        VaListKind::Aapcs64 => {
+            // gr_top and gr_offs or vr_top and vr_offs
+            let (top_field, offs_field, offs_step) =
+                if arg_ty.is_int() { (1, 3, 8) } else { (2, 4, 16) };
+            let stack_place = va_list.place_field(fx, FieldIdx::new(0));
+            let top_place = va_list.place_field(fx, FieldIdx::new(top_field));
+            let offs_place = va_list.place_field(fx, FieldIdx::new(offs_field));
+
+            // The offsets count up from a negative value to zero.
+            let offs = offs_place.to_cvalue(fx).load_scalar(fx);
+            let in_regs = fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, offs, 0);
+            reg_or_stack_arg_addr(
+                fx,
+                in_regs,
+                |fx| {
+                    let top = top_place.to_cvalue(fx).load_scalar(fx);
+                    let offs_ext = fx.bcx.ins().sextend(fx.pointer_type, offs);
+                    let new_offs = fx.bcx.ins().iadd_imm(offs, offs_step);
+                    offs_place.write_cvalue(fx, CValue::by_val(new_offs, offs_place.layout()));
+                    fx.bcx.ins().iadd(top, offs_ext)
+                },
+                stack_place,
+            )
+        }
+        VaListKind::AppleAarch64 => {
+            let ptr_place = va_list.place_field(fx, FieldIdx::new(0));
+            let ptr = ptr_place.to_cvalue(fx).load_scalar(fx);
+            let new_ptr = fx.bcx.ins().iadd_imm(ptr, 8);
+            ptr_place.write_cvalue(fx, CValue::by_val(new_ptr, ptr_place.layout()));
+            ptr
+        }
+    };
+
+    let val = Pointer::new(arg_addr).load(fx, arg_ty, MemFlags::trusted());
+    ret.write_cvalue(fx, CValue::by_val(val, ret.layout()));
+}
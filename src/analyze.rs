@@ -2,6 +2,7 @@
 
 use rustc_index::IndexVec;
 use rustc_middle::mir::StatementKind::*;
+use rustc_session::config::{DebugInfo, OptLevel};
 
 use crate::prelude::*;
 
@@ -21,6 +22,19 @@ pub(crate) fn analyze(fx: &FunctionCx<'_, '_, '_>) -> IndexVec<Local, SsaKind> {
     let mut flag_map =
         fx.mir.local_decls.iter().map(|_| SsaKind::MaybeSsa).collect::<IndexVec<Local, SsaKind>>();
 
+    // Keep user variables in memory when compiling without optimizations so that debuginfo can
+    // describe their location.
+    if fx.func_debug_cx.is_some()
+        && fx.tcx.sess.opts.debuginfo == DebugInfo::Full
+        && fx.tcx.sess.opts.optimize == OptLevel::No
+    {
+        for var in &fx.mir.var_debug_info {
+            if let VarDebugInfoContents::Place(place) = var.value {
+                flag_map[place.local] = SsaKind::NotSsa;
+            }
+        }
+    }
+
     for bb in fx.mir.basic_blocks.iter() {
         for stmt in bb.statements.iter() {
             match &stmt.kind {
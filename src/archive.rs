@@ -5,6 +5,10 @@
 };
 use rustc_session::Session;
 
+// Thin archives are deliberately not emitted for rlibs. rustc reads the crate metadata from the
+// `lib.rmeta` member of the rlib, which doesn't have its contents embedded in a thin archive. In
+// addition the metadata and object files are written to temporary directories which are removed
+// once linking finishes, so the member paths would dangle.
 pub(crate) struct ArArchiveBuilderBuilder;
 
 impl ArchiveBuilderBuilder for ArArchiveBuilderBuilder {
@@ -1,16 +1,23 @@
 //! Codegen of a single function
 
-use cranelift_codegen::ir::UserFuncName;
-use cranelift_codegen::CodegenError;
-use cranelift_module::ModuleError;
+use std::hash::Hash;
+
+use cranelift_codegen::binemit::{CodeOffset, Reloc};
+use cranelift_codegen::ir::{ExternalName, LibCall, UserFuncName};
+use cranelift_codegen::{CodegenError, FinalizedRelocTarget};
+use cranelift_module::{DataId, ModuleError};
 use rustc_ast::InlineAsmOptions;
+use rustc_data_structures::stable_hasher::StableHasher;
+use rustc_index::bit_set::BitSet;
 use rustc_index::IndexVec;
 use rustc_middle::ty::adjustment::PointerCoercion;
 use rustc_middle::ty::layout::FnAbiOf;
 use rustc_middle::ty::print::with_no_trimmed_paths;
+use rustc_session::config::DebugInfo;
+use rustc_span::sym;
 
 use crate::constant::ConstantCx;
-use crate::debuginfo::FunctionDebugContext;
+use crate::debuginfo::{FunctionDebugContext, TypeDebugContext};
 use crate::prelude::*;
 use crate::pretty_clif::CommentWriter;
 
@@ -20,11 +27,82 @@ pub(crate) struct CodegenedFunction {
     func: Function,
     clif_comments: CommentWriter,
     func_debug_cx: Option<FunctionDebugContext>,
+    link_section: Option<Symbol>,
+}
+
+impl CodegenedFunction {
+    /// Hash everything that determines the code and symbol of this function in the object file.
+    pub(crate) fn hash_for_object_cache(&self, hasher: &mut StableHasher) {
+        self.symbol_name.hash(hasher);
+        self.func_id.as_u32().hash(hasher);
+        self.func.display().to_string().hash(hasher);
+        self.link_section.map(|section| section.to_string()).hash(hasher);
+    }
+}
+
+/// The code of a function with `#[link_section]`. Cranelift always defines functions in the text
+/// section, so these functions are only compiled and their code is defined in the requested section
+/// once the object file is finished.
+pub(crate) struct FunctionInSection {
+    pub(crate) func_id: FuncId,
+    pub(crate) section: String,
+    pub(crate) alignment: u64,
+    pub(crate) code: Vec<u8>,
+    pub(crate) relocs: Vec<FunctionReloc>,
+}
+
+/// A relocation in the machine code of a function.
+pub(crate) struct FunctionReloc {
+    pub(crate) offset: CodeOffset,
+    pub(crate) kind: Reloc,
+    pub(crate) target: FunctionRelocTarget,
+    pub(crate) addend: i64,
+}
+
+pub(crate) enum FunctionRelocTarget {
+    Func(FuncId),
+    Data(DataId),
+    LibCall(LibCall),
+    /// An offset into the function itself.
+    FunctionOffset(CodeOffset),
+}
+
+/// Resolve the relocations of the function compiled in `context`. User external names in
+/// namespace 0 are functions and in namespace 1 data objects, like for the Cranelift module.
+pub(crate) fn function_relocs(context: &Context, symbol_name: &str) -> Vec<FunctionReloc> {
+    context
+        .compiled_code()
+        .unwrap()
+        .buffer
+        .relocs()
+        .iter()
+        .map(|reloc| {
+            let target = match &reloc.target {
+                FinalizedRelocTarget::ExternalName(ExternalName::User(name)) => {
+                    let name = &context.func.params.user_named_funcs()[*name];
+                    match name.namespace {
+                        0 => FunctionRelocTarget::Func(FuncId::from_u32(name.index)),
+                        1 => FunctionRelocTarget::Data(DataId::from_u32(name.index)),
+                        _ => unreachable!(),
+                    }
+                }
+                FinalizedRelocTarget::ExternalName(ExternalName::LibCall(libcall)) => {
+                    FunctionRelocTarget::LibCall(*libcall)
+                }
+                FinalizedRelocTarget::Func(offset) => FunctionRelocTarget::FunctionOffset(*offset),
+                FinalizedRelocTarget::ExternalName(name) => {
+                    panic!("unexpected relocation target {name:?} in {symbol_name}")
+                }
+            };
+            FunctionReloc { offset: reloc.offset, kind: reloc.kind, target, addend: reloc.addend }
+        })
+        .collect()
 }
 
 pub(crate) fn codegen_fn<'tcx>(
     tcx: TyCtxt<'tcx>,
     cx: &mut crate::CodegenCx,
+    type_dbg: &mut TypeDebugContext<'tcx>,
     cached_func: Function,
     module: &mut dyn Module,
     instance: Instance<'tcx>,
@@ -74,6 +152,12 @@ pub(crate) fn codegen_fn<'tcx>(
         None
     };
 
+    let coverage_counters = if let Some(coverage_context) = &mut cx.coverage_context {
+        coverage_context.define_function(module, &symbol_name, mir)
+    } else {
+        None
+    };
+
     let mut fx = FunctionCx {
         cx,
         module,
@@ -82,6 +166,7 @@ pub(crate) fn codegen_fn<'tcx>(
         pointer_type,
         constants_cx: ConstantCx::new(),
         func_debug_cx,
+        coverage_counters,
 
         instance,
         symbol_name,
@@ -92,6 +177,8 @@ pub(crate) fn codegen_fn<'tcx>(
         block_map,
         local_map: IndexVec::with_capacity(mir.local_decls.len()),
         caller_location: None, // set by `codegen_fn_prelude`
+        current_span: mir.span,
+        target_features: None,
 
         clif_comments,
         last_source_file: None,
@@ -99,6 +186,7 @@ pub(crate) fn codegen_fn<'tcx>(
     };
 
     tcx.prof.generic_activity("codegen clif ir").run(|| codegen_fn_body(&mut fx, start_block));
+    define_debug_locals(&mut fx, type_dbg);
     fx.bcx.seal_all_blocks();
     fx.bcx.finalize();
 
@@ -123,7 +211,9 @@ pub(crate) fn codegen_fn<'tcx>(
     // Verify function
     verify_func(tcx, &clif_comments, &func);
 
-    CodegenedFunction { symbol_name, func_id, func, clif_comments, func_debug_cx }
+    let link_section = tcx.codegen_fn_attrs(instance.def_id()).link_section;
+
+    CodegenedFunction { symbol_name, func_id, func, clif_comments, func_debug_cx, link_section }
 }
 
 pub(crate) fn compile_fn(
@@ -171,9 +261,19 @@ pub(crate) fn compile_fn(
     };
 
     // Define function
+    let in_section = codegened_func.link_section.filter(|_| cx.functions_in_sections.is_some());
     cx.profiler.generic_activity("define function").run(|| {
-        context.want_disasm = cx.should_write_ir;
-        match module.define_function(codegened_func.func_id, context) {
+        context.want_disasm = cx.should_write_ir || cx.module_asm.is_some();
+        let res = if in_section.is_some() {
+            // Defined in the requested section once the object file is finished.
+            context
+                .compile(module.isa(), &mut Default::default())
+                .map(|_| ())
+                .map_err(|err| ModuleError::Compilation(err.inner))
+        } else {
+            module.define_function(codegened_func.func_id, context)
+        };
+        match res {
             Ok(()) => {}
             Err(ModuleError::Compilation(CodegenError::ImplLimitExceeded)) => {
                 let early_dcx = rustc_session::EarlyDiagCtxt::new(
@@ -190,6 +290,17 @@ pub(crate) fn compile_fn(
         }
     });
 
+    if let Some(section) = in_section {
+        let compiled_code = context.compiled_code().unwrap();
+        cx.functions_in_sections.as_mut().unwrap().push(FunctionInSection {
+            func_id: codegened_func.func_id,
+            section: section.to_string(),
+            alignment: u64::from(compiled_code.buffer.alignment),
+            code: compiled_code.code_buffer().to_vec(),
+            relocs: function_relocs(context, &codegened_func.symbol_name),
+        });
+    }
+
     if cx.should_write_ir {
         // Write optimized function to file for debugging
         crate::pretty_clif::write_clif_file(
@@ -210,6 +321,28 @@ pub(crate) fn compile_fn(
         }
     }
 
+    if let (Some(module_asm), Some(disasm)) =
+        (&mut cx.module_asm, &context.compiled_code().unwrap().vcode)
+    {
+        write_function_asm(
+            module_asm,
+            &codegened_func.symbol_name,
+            disasm,
+            context.compiled_code().unwrap().buffer.unwind_info.iter().map(|&(offset, _)| offset),
+            cx.unwind_context.cfi_directives(context, module.isa()),
+        );
+    }
+
+    if let Some(module_clif) = &mut cx.module_clif {
+        cranelift_codegen::write::decorate_function(
+            &mut &clif_comments,
+            module_clif,
+            &context.func,
+        )
+        .unwrap();
+        module_clif.push('\n');
+    }
+
     // Define debuginfo for function
     let isa = module.isa();
     let debug_context = &mut cx.debug_context;
@@ -226,6 +359,41 @@ pub(crate) fn compile_fn(
     });
 }
 
+/// Append the disassembly of a single function to the `--emit asm` output. If the function has
+/// unwind info, it is wrapped in `.cfi_startproc` and `.cfi_endproc`. The `.cfi_*` directives
+/// replace the `unwind` pseudo-instructions of the disassembly. `unwind_offsets` are the code offsets
+/// of these pseudo-instructions in the order they appear in the disassembly.
+fn write_function_asm(
+    module_asm: &mut String,
+    symbol_name: &str,
+    disasm: &str,
+    mut unwind_offsets: impl Iterator<Item = u32>,
+    cfi_directives: Option<Vec<(u32, String)>>,
+) {
+    module_asm.push_str(&format!("{symbol_name}:\n"));
+    let Some(cfi_directives) = cfi_directives else {
+        module_asm.push_str(&format!("{disasm}\n"));
+        return;
+    };
+
+    module_asm.push_str("  .cfi_startproc\n");
+    let mut cfi_directives = cfi_directives.into_iter().peekable();
+    for line in disasm.lines() {
+        if !line.trim_start().starts_with("unwind ") {
+            module_asm.push_str(&format!("{line}\n"));
+            continue;
+        }
+        let offset = unwind_offsets.next().unwrap_or(u32::MAX);
+        while let Some((_, directive)) = cfi_directives.next_if(|&(at, _)| at <= offset) {
+            module_asm.push_str(&format!("  {directive}\n"));
+        }
+    }
+    for (_, directive) in cfi_directives {
+        module_asm.push_str(&format!("  {directive}\n"));
+    }
+    module_asm.push_str("  .cfi_endproc\n\n");
+}
+
 pub(crate) fn verify_func(
     tcx: TyCtxt<'_>,
     writer: &crate::pretty_clif::CommentWriter,
@@ -248,6 +416,43 @@ pub(crate) fn verify_func(
     });
 }
 
+/// Emit debuginfo for all user variables that live directly in a stack slot.
+fn define_debug_locals<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    type_dbg: &mut TypeDebugContext<'tcx>,
+) {
+    if fx.tcx.sess.opts.debuginfo != DebugInfo::Full {
+        return;
+    }
+    let (Some(debug_context), Some(func_debug_cx)) =
+        (&mut fx.cx.debug_context, &mut fx.func_debug_cx)
+    else {
+        return;
+    };
+
+    for var in &fx.mir.var_debug_info {
+        // FIXME support variables with projections, composite variables and constants
+        if var.composite.is_some() {
+            continue;
+        }
+        let VarDebugInfoContents::Place(place) = var.value else {
+            continue;
+        };
+        if !place.projection.is_empty() {
+            continue;
+        }
+
+        let place = fx.local_map[place.local];
+        if place.layout().is_unsized() {
+            continue;
+        }
+        let Some(ptr) = place.try_to_ptr() else {
+            continue;
+        };
+        debug_context.define_local(fx.tcx, type_dbg, func_debug_cx, var, place.layout().ty, ptr);
+    }
+}
+
 fn codegen_fn_body(fx: &mut FunctionCx<'_, '_, '_>, start_block: Block) {
     let arg_uninhabited = fx
         .mir
@@ -264,22 +469,33 @@ fn codegen_fn_body(fx: &mut FunctionCx<'_, '_, '_>, start_block: Block) {
         .generic_activity("codegen prelude")
         .run(|| crate::abi::codegen_fn_prelude(fx, start_block));
 
+    let panicking_blocks = fx.cx.panic_immediate_abort.then(|| panicking_blocks(fx));
+
     for (bb, bb_data) in fx.mir.basic_blocks.iter_enumerated() {
         let block = fx.get_block(bb);
         fx.bcx.switch_to_block(block);
 
         if bb_data.is_cleanup {
-            // Unwinding after panicking is not supported
+            // Unwinding after panicking is not supported. Calls and drops are lowered without an
+            // unwind edge, so cleanup blocks are never reachable. With `-Cpanic=abort` this is
+            // also the correct behavior.
             continue;
 
             // FIXME Once unwinding is supported and Cranelift supports marking blocks as cold, do
             // so for cleanup blocks.
         }
 
+        if panicking_blocks.as_ref().is_some_and(|blocks| blocks.contains(bb)) {
+            // Skip the statements too. They only compute the panic message and location.
+            fx.set_debug_loc(bb_data.terminator().source_info);
+            codegen_panic_immediate_abort(fx);
+            continue;
+        }
+
         fx.bcx.ins().nop();
         for stmt in &bb_data.statements {
             fx.set_debug_loc(stmt.source_info);
-            codegen_stmt(fx, block, stmt);
+            codegen_stmt(fx, bb, block, stmt);
         }
 
         if fx.clif_comments.enabled() {
@@ -343,6 +559,10 @@ fn codegen_fn_body(fx: &mut FunctionCx<'_, '_, '_>, start_block: Block) {
                 fx.bcx.switch_to_block(failure);
                 fx.bcx.ins().nop();
 
+                if codegen_panic_immediate_abort(fx) {
+                    continue;
+                }
+
                 match &**msg {
                     AssertKind::BoundsCheck { ref len, ref index } => {
                         let len = codegen_operand(fx, len).load_scalar(fx);
@@ -494,6 +714,7 @@ fn codegen_fn_body(fx: &mut FunctionCx<'_, '_, '_>, start_block: Block) {
 
 fn codegen_stmt<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
+    bb: BasicBlock,
     #[allow(unused_variables)] cur_block: Block,
     stmt: &Statement<'tcx>,
 ) {
@@ -544,6 +765,14 @@ fn codegen_stmt<'tcx>(
                     let lhs = codegen_operand(fx, &lhs_rhs.0);
                     let rhs = codegen_operand(fx, &lhs_rhs.1);
 
+                    if matches!(bin_op, BinOp::Div | BinOp::Rem)
+                        && lhs.layout().ty.is_integral()
+                        && fx.cx.ub_checks
+                        && is_lowered_unchecked_div(fx, bb)
+                    {
+                        crate::num::codegen_unchecked_div_ub_check(fx, lhs, rhs);
+                    }
+
                     let res = crate::num::codegen_binop(fx, bin_op, lhs, rhs);
                     lval.write_cvalue(fx, res);
                 }
@@ -768,7 +997,9 @@ fn is_fat_ptr<'tcx>(fx: &FunctionCx<'_, '_, 'tcx>, ty: Ty<'tcx>) -> bool {
                             layout.offset_of_subfield(fx, fields.iter()).bytes()
                         }
                         NullOp::DebugAssertions => {
-                            let val = fx.tcx.sess.opts.debug_assertions;
+                            // Used by the standard library to decide whether to check the
+                            // preconditions of unsafe functions.
+                            let val = fx.cx.ub_checks;
                             let val = CValue::by_val(
                                 fx.bcx.ins().iconst(types::I8, i64::try_from(val).unwrap()),
                                 fx.layout_of(fx.tcx.types.bool),
@@ -820,7 +1051,7 @@ fn is_fat_ptr<'tcx>(fx: &FunctionCx<'_, '_, 'tcx>, ty: Ty<'tcx>) -> bool {
         | StatementKind::PlaceMention(..)
         | StatementKind::AscribeUserType(..) => {}
 
-        StatementKind::Coverage { .. } => fx.tcx.dcx().fatal("-Zcoverage is unimplemented"),
+        StatementKind::Coverage(coverage) => crate::coverage::codegen_coverage(fx, &coverage.kind),
         StatementKind::Intrinsic(ref intrinsic) => match &**intrinsic {
             // We ignore `assume` intrinsics, they are only useful for optimizations
             NonDivergingIntrinsic::Assume(_) => {}
@@ -830,25 +1061,35 @@ fn is_fat_ptr<'tcx>(fx: &FunctionCx<'_, '_, 'tcx>, ty: Ty<'tcx>) -> bool {
                 count,
             }) => {
                 let dst = codegen_operand(fx, dst);
-                let pointee = dst
-                    .layout()
-                    .pointee_info_at(fx, rustc_target::abi::Size::ZERO)
-                    .expect("Expected pointer");
+                let elem_ty = dst.layout().ty.builtin_deref(true).expect("Expected pointer").ty;
+                let elem_layout = fx.layout_of(elem_ty);
                 let dst = dst.load_scalar(fx);
                 let src = codegen_operand(fx, src).load_scalar(fx);
-                let count = codegen_operand(fx, count).load_scalar(fx);
-                let elem_size: u64 = pointee.size.bytes();
-                let bytes = if elem_size != 1 {
-                    fx.bcx.ins().imul_imm(count, elem_size as i64)
-                } else {
-                    count
-                };
-                fx.bcx.call_memcpy(fx.target_config, dst, src, bytes);
+                crate::intrinsics::codegen_copy(fx, dst, src, count, elem_layout, true);
             }
         },
     }
 }
 
+/// The `lower_intrinsics` MIR pass turns `unchecked_div` and `unchecked_rem` into a plain
+/// `BinOp::Div` and `BinOp::Rem`, so their lowering is only reached through these. They differ
+/// from `/` and `%` in not being guarded by the division assertions MIR building emits for the
+/// latter, which are the terminator of a predecessor of the block containing the division.
+fn is_lowered_unchecked_div(fx: &FunctionCx<'_, '_, '_>, bb: BasicBlock) -> bool {
+    !fx.mir.basic_blocks.predecessors()[bb].iter().any(|&pred| {
+        let TerminatorKind::Assert { msg, .. } = &fx.mir.basic_blocks[pred].terminator().kind
+        else {
+            return false;
+        };
+        matches!(
+            **msg,
+            AssertKind::DivisionByZero(_)
+                | AssertKind::RemainderByZero(_)
+                | AssertKind::Overflow(BinOp::Div | BinOp::Rem, _, _)
+        )
+    })
+}
+
 fn codegen_array_len<'tcx>(fx: &mut FunctionCx<'_, '_, 'tcx>, place: CPlace<'tcx>) -> Value {
     match *place.layout().ty.kind() {
         ty::Array(_elem_ty, len) => {
@@ -942,11 +1183,103 @@ pub(crate) fn codegen_operand<'tcx>(
     }
 }
 
+/// Whether `def_id` is one of the functions through which the standard library panics. They are
+/// all diverging.
+fn is_panic_fn(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    if tcx.lang_items().begin_panic_fn() == Some(def_id) {
+        return true;
+    }
+    if tcx.crate_name(def_id.krate) != sym::core {
+        return false;
+    }
+    let path = tcx.def_path(def_id).to_string_no_crate_verbose();
+    path.starts_with("::panicking::")
+        || matches!(
+            &*path,
+            "::option::unwrap_failed" | "::option::expect_failed" | "::result::unwrap_failed"
+        )
+}
+
+/// Whether `def_id` is a method of one of the types `format_args!()` expands to. These only
+/// construct a value without any side effects.
+fn is_format_args_fn(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    let Some(impl_def_id) = tcx.impl_of_method(def_id) else {
+        return false;
+    };
+    let Some(adt_def) = tcx.type_of(impl_def_id).instantiate_identity().ty_adt_def() else {
+        return false;
+    };
+    let lang_items = tcx.lang_items();
+    [
+        lang_items.format_arguments(),
+        lang_items.format_argument(),
+        lang_items.format_placeholder(),
+        lang_items.format_unsafe_arg(),
+    ]
+    .contains(&Some(adt_def.did()))
+}
+
+/// Find all blocks which unconditionally end up calling a panic function. With
+/// `panic_immediate_abort` these are replaced by a trap, which also skips building the panic
+/// message and location, including for panics that don't go through a panic lang item like
+/// `assert_eq!()` and `Option::unwrap()`.
+fn panicking_blocks(fx: &FunctionCx<'_, '_, '_>) -> BitSet<BasicBlock> {
+    let mut panicking = BitSet::new_empty(fx.mir.basic_blocks.len());
+    loop {
+        let mut changed = false;
+        // Successors usually come after their predecessors, so walking backwards converges faster.
+        for (bb, bb_data) in fx.mir.basic_blocks.iter_enumerated().rev() {
+            if bb_data.is_cleanup || panicking.contains(bb) {
+                continue;
+            }
+            let is_panicking = match &bb_data.terminator().kind {
+                TerminatorKind::Goto { target } | TerminatorKind::Assert { target, .. } => {
+                    panicking.contains(*target)
+                }
+                TerminatorKind::SwitchInt { targets, .. } => {
+                    targets.all_targets().iter().all(|&target| panicking.contains(target))
+                }
+                TerminatorKind::Call { func, target, .. } => match func.const_fn_def() {
+                    Some((def_id, _)) => {
+                        is_panic_fn(fx.tcx, def_id)
+                            || is_format_args_fn(fx.tcx, def_id)
+                                && target.is_some_and(|target| panicking.contains(target))
+                    }
+                    None => false,
+                },
+                _ => false,
+            };
+            if is_panicking {
+                panicking.insert(bb);
+                changed = true;
+            }
+        }
+        if !changed {
+            return panicking;
+        }
+    }
+}
+
+/// If panics should immediately abort, emit a trap and return true. The caller must not codegen
+/// the panic message and location in that case.
+pub(crate) fn codegen_panic_immediate_abort(fx: &mut FunctionCx<'_, '_, '_>) -> bool {
+    if !fx.cx.panic_immediate_abort {
+        return false;
+    }
+
+    fx.bcx.ins().trap(TrapCode::User(0));
+    true
+}
+
 pub(crate) fn codegen_panic<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     msg_str: &str,
     source_info: mir::SourceInfo,
 ) {
+    if codegen_panic_immediate_abort(fx) {
+        return;
+    }
+
     let location = fx.get_caller_location(source_info).load_scalar(fx);
 
     let msg_ptr = fx.anonymous_str(msg_str);
@@ -961,6 +1294,10 @@ pub(crate) fn codegen_panic_nounwind<'tcx>(
     msg_str: &str,
     span: Option<Span>,
 ) {
+    if codegen_panic_immediate_abort(fx) {
+        return;
+    }
+
     let msg_ptr = fx.anonymous_str(msg_str);
     let msg_len = fx.bcx.ins().iconst(fx.pointer_type, i64::try_from(msg_str.len()).unwrap());
     let args = [msg_ptr, msg_len];
@@ -973,6 +1310,10 @@ pub(crate) fn codegen_unwind_terminate<'tcx>(
     source_info: mir::SourceInfo,
     reason: UnwindTerminateReason,
 ) {
+    if codegen_panic_immediate_abort(fx) {
+        return;
+    }
+
     let args = [];
 
     codegen_panic_inner(fx, reason.lang_item(), &args, Some(source_info.span));
@@ -91,6 +91,18 @@ pub(crate) fn maybe_codegen_checked<'tcx>(
 
     match bin_op {
         BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => unreachable!(),
+        BinOp::Mul if has_native_i128_mul(fx) => {
+            let out_ty = Ty::new_tup(fx.tcx, &[lhs.layout().ty, fx.tcx.types.bool]);
+            let lhs = lhs.load_scalar(fx);
+            let rhs = rhs.load_scalar(fx);
+            let res = fx.bcx.ins().imul(lhs, rhs);
+            let oflow = if is_signed {
+                codegen_smul_overflow(fx, lhs, rhs)
+            } else {
+                codegen_umul_overflow(fx, lhs, rhs)
+            };
+            Some(CValue::by_val_pair(res, oflow, fx.layout_of(out_ty)))
+        }
         BinOp::Mul if is_signed => {
             let out_ty = Ty::new_tup(fx.tcx, &[lhs.layout().ty, fx.tcx.types.bool]);
             let oflow = CPlace::new_stack_slot(fx, fx.layout_of(fx.tcx.types.i32));
@@ -138,3 +150,72 @@ pub(crate) fn maybe_codegen_checked<'tcx>(
         BinOp::Shl | BinOp::ShlUnchecked | BinOp::Shr | BinOp::ShrUnchecked => unreachable!(),
     }
 }
+
+/// Reverses the bytes of a 128bit value by byte swapping both 64bit halves and swapping the halves.
+pub(crate) fn codegen_bswap(fx: &mut FunctionCx<'_, '_, '_>, val: Value) -> Value {
+    let (lo, hi) = fx.bcx.ins().isplit(val);
+    let lo = fx.bcx.ins().bswap(lo);
+    let hi = fx.bcx.ins().bswap(hi);
+    fx.bcx.ins().iconcat(hi, lo)
+}
+
+/// Whether Cranelift can lower `imul.i128` for the current target without a libcall.
+fn has_native_i128_mul(fx: &FunctionCx<'_, '_, '_>) -> bool {
+    matches!(&*fx.tcx.sess.target.arch, "x86_64" | "aarch64" | "riscv64" | "s390x")
+}
+
+/// Computes whether the unsigned 128bit multiplication of `lhs` and `rhs` overflows using 64bit
+/// multiplies of the halves.
+fn codegen_umul_overflow(fx: &mut FunctionCx<'_, '_, '_>, lhs: Value, rhs: Value) -> Value {
+    let (lhs_lo, lhs_hi) = fx.bcx.ins().isplit(lhs);
+    let (rhs_lo, rhs_hi) = fx.bcx.ins().isplit(rhs);
+
+    // lhs_hi * rhs_hi is shifted left by 128 bits, so it overflows if both are non-zero.
+    let lhs_hi_nonzero = fx.bcx.ins().icmp_imm(IntCC::NotEqual, lhs_hi, 0);
+    let rhs_hi_nonzero = fx.bcx.ins().icmp_imm(IntCC::NotEqual, rhs_hi, 0);
+    let hi_hi_oflow = fx.bcx.ins().band(lhs_hi_nonzero, rhs_hi_nonzero);
+
+    // The cross products are shifted left by 64 bits, so they must each fit in 64 bits.
+    let cross_a_hi = fx.bcx.ins().umulhi(lhs_hi, rhs_lo);
+    let cross_a_oflow = fx.bcx.ins().icmp_imm(IntCC::NotEqual, cross_a_hi, 0);
+    let cross_b_hi = fx.bcx.ins().umulhi(lhs_lo, rhs_hi);
+    let cross_b_oflow = fx.bcx.ins().icmp_imm(IntCC::NotEqual, cross_b_hi, 0);
+
+    // If there was no overflow so far at most one of the cross products is non-zero. Adding it to
+    // the high half of lhs_lo * rhs_lo must not carry.
+    let cross_a = fx.bcx.ins().imul(lhs_hi, rhs_lo);
+    let cross_b = fx.bcx.ins().imul(lhs_lo, rhs_hi);
+    let cross = fx.bcx.ins().iadd(cross_a, cross_b);
+    let lo_lo_hi = fx.bcx.ins().umulhi(lhs_lo, rhs_lo);
+    let res_hi = fx.bcx.ins().iadd(lo_lo_hi, cross);
+    let carry_oflow = fx.bcx.ins().icmp(IntCC::UnsignedLessThan, res_hi, lo_lo_hi);
+
+    let oflow = fx.bcx.ins().bor(hi_hi_oflow, cross_a_oflow);
+    let oflow = fx.bcx.ins().bor(oflow, cross_b_oflow);
+    fx.bcx.ins().bor(oflow, carry_oflow)
+}
+
+/// Computes whether the signed 128bit multiplication of `lhs` and `rhs` overflows by checking the
+/// unsigned product of the absolute values against the range of the signed result.
+fn codegen_smul_overflow(fx: &mut FunctionCx<'_, '_, '_>, lhs: Value, rhs: Value) -> Value {
+    let lhs_neg = fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, lhs, 0);
+    let rhs_neg = fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, rhs, 0);
+    let neg_lhs = fx.bcx.ins().ineg(lhs);
+    let neg_rhs = fx.bcx.ins().ineg(rhs);
+    let lhs_abs = fx.bcx.ins().select(lhs_neg, neg_lhs, lhs);
+    let rhs_abs = fx.bcx.ins().select(rhs_neg, neg_rhs, rhs);
+
+    let abs_oflow = codegen_umul_overflow(fx, lhs_abs, rhs_abs);
+    let abs_res = fx.bcx.ins().imul(lhs_abs, rhs_abs);
+
+    // A negative result may have a magnitude of up to 2^127, a positive one up to 2^127 - 1.
+    let res_neg = fx.bcx.ins().bxor(lhs_neg, rhs_neg);
+    let max_lo = fx.bcx.ins().iconst(types::I64, -1);
+    let max_hi = fx.bcx.ins().iconst(types::I64, i64::MAX);
+    let max = fx.bcx.ins().iconcat(max_lo, max_hi);
+    let res_neg_ext = fx.bcx.ins().uextend(types::I128, res_neg);
+    let limit = fx.bcx.ins().iadd(max, res_neg_ext);
+    let range_oflow = fx.bcx.ins().icmp(IntCC::UnsignedGreaterThan, abs_res, limit);
+
+    fx.bcx.ins().bor(abs_oflow, range_oflow)
+}
@@ -1,12 +1,14 @@
 use cranelift_codegen::isa::TargetFrontendConfig;
+use cranelift_module::DataId;
 use gimli::write::FileId;
+use rustc_data_structures::fx::FxHashSet;
 use rustc_data_structures::sync::Lrc;
 use rustc_index::IndexVec;
 use rustc_middle::ty::layout::{
     FnAbiError, FnAbiOfHelpers, FnAbiRequest, LayoutError, LayoutOfHelpers,
 };
 use rustc_span::source_map::Spanned;
-use rustc_span::SourceFile;
+use rustc_span::{SourceFile, Symbol};
 use rustc_target::abi::call::FnAbi;
 use rustc_target::abi::{Integer, Primitive};
 use rustc_target::spec::{HasTargetSpec, Target};
@@ -60,6 +62,9 @@ fn clif_type_from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<types::Typ
             IntTy::Isize => pointer_ty(tcx),
         },
         ty::Char => types::I32,
+        // f16 and f128 exist neither in the pinned rustc nor in Cranelift yet. This match is
+        // exhaustive on purpose, so adding them to `FloatTy` fails to compile here rather than
+        // miscompiling. They will need `types::F16`/`types::F128` and libcalls like `__addtf3`.
         ty::Float(size) => match size {
             FloatTy::F32 => types::F32,
             FloatTy::F64 => types::F64,
@@ -286,6 +291,8 @@ pub(crate) struct FunctionCx<'m, 'clif, 'tcx: 'm> {
     pub(crate) pointer_type: Type,                  // Cached from module
     pub(crate) constants_cx: ConstantCx,
     pub(crate) func_debug_cx: Option<FunctionDebugContext>,
+    /// The `-Cinstrument-coverage` counters of this function.
+    pub(crate) coverage_counters: Option<DataId>,
 
     pub(crate) instance: Instance<'tcx>,
     pub(crate) symbol_name: String,
@@ -299,6 +306,13 @@ pub(crate) struct FunctionCx<'m, 'clif, 'tcx: 'm> {
     /// When `#[track_caller]` is used, the implicit caller location is stored in this variable.
     pub(crate) caller_location: Option<CValue<'tcx>>,
 
+    /// The span of the statement or terminator that is currently being codegened.
+    pub(crate) current_span: Span,
+
+    /// The target features enabled for this function including implied target features. Only
+    /// computed once an intrinsic needs to check them.
+    pub(crate) target_features: Option<FxHashSet<Symbol>>,
+
     pub(crate) clif_comments: crate::pretty_clif::CommentWriter,
 
     /// Last accessed source file and it's debuginfo file id.
@@ -377,6 +391,13 @@ pub(crate) fn clif_pair_type(&self, ty: Ty<'tcx>) -> Option<(Type, Type)> {
         clif_pair_type_from_ty(self.tcx, ty)
     }
 
+    /// Report a construct which cg_clif doesn't support yet as an ICE pointing at the statement or
+    /// terminator that is currently being codegened.
+    #[track_caller]
+    pub(crate) fn span_unimplemented(&self, msg: impl std::fmt::Display) -> ! {
+        span_bug!(self.current_span, "not yet implemented by cg_clif: {msg}")
+    }
+
     pub(crate) fn get_block(&self, bb: BasicBlock) -> Block {
         *self.block_map.get(bb).unwrap()
     }
@@ -414,6 +435,8 @@ pub(crate) fn create_stack_slot(&mut self, size: u32, align: u32) -> Pointer {
     }
 
     pub(crate) fn set_debug_loc(&mut self, source_info: mir::SourceInfo) {
+        self.current_span = source_info.span;
+
         if let Some(debug_context) = &mut self.cx.debug_context {
             let (file, line, column) =
                 DebugContext::get_span_loc(self.tcx, self.mir.span, source_info.span);
@@ -443,6 +466,7 @@ pub(crate) fn set_debug_loc(&mut self, source_info: mir::SourceInfo) {
 
     pub(crate) fn get_caller_location(&mut self, source_info: mir::SourceInfo) -> CValue<'tcx> {
         self.mir.caller_location_span(source_info, self.caller_location, self.tcx, |span| {
+            // This omits the file, line and column as requested by `-Zlocation-detail`.
             let const_loc = self.tcx.span_as_caller_location(span);
             crate::constant::codegen_const_value(self, const_loc, self.tcx.caller_location_ty())
         })
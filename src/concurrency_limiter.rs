@@ -46,6 +46,17 @@ pub(super) fn new(sess: &Session, pending_jobs: usize) -> Self {
         }
     }
 
+    /// Create a limiter which only hands out the implicit token and never requests any tokens from
+    /// the jobserver. Every job must be finished before the next one is acquired.
+    pub(super) fn new_single_threaded(pending_jobs: usize) -> Self {
+        ConcurrencyLimiter {
+            helper_thread: None,
+            state: Arc::new(Mutex::new(state::ConcurrencyLimiterState::new(pending_jobs))),
+            available_token_condvar: Arc::new(Condvar::new()),
+            finished: false,
+        }
+    }
+
     pub(super) fn acquire(&mut self, dcx: &rustc_errors::DiagCtxt) -> ConcurrencyLimiterToken {
         let mut state = self.state.lock().unwrap();
         loop {
@@ -73,7 +84,10 @@ pub(super) fn acquire(&mut self, dcx: &rustc_errors::DiagCtxt) -> ConcurrencyLim
                 }
             }
 
-            self.helper_thread.as_mut().unwrap().request_token();
+            self.helper_thread
+                .as_mut()
+                .expect("single threaded codegen job acquired while another job is active")
+                .request_token();
             state = self.available_token_condvar.wait(state).unwrap();
         }
     }
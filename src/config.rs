@@ -1,4 +1,5 @@
 use std::env;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 fn bool_env_var(key: &str) -> bool {
@@ -57,6 +58,71 @@ pub struct BackendConfig {
     /// Defaults to true when the `CG_CLIF_DISABLE_INCR_CACHE` env var is set to 1 or false
     /// otherwise. Can be set using `-Cllvm-args=disable_incr_cache=...`.
     pub disable_incr_cache: bool,
+
+    /// Lower all panics to an immediate trap without codegening the panic message or location.
+    /// This matches building the standard library with the `panic_immediate_abort` feature and
+    /// significantly reduces code size.
+    ///
+    /// Defaults to false. Can be set using `-Cllvm-args=panic_immediate_abort=...`.
+    pub panic_immediate_abort: bool,
+
+    /// Print the computed layout (size, alignment, fields and niche) of every type with the given
+    /// name to stderr when it is used by a local during codegen. Useful for debugging layout and
+    /// ABI issues.
+    ///
+    /// Defaults to the value of `CG_CLIF_PRINT_LAYOUT`. Can be set using
+    /// `-Cllvm-args=print_layout=...`.
+    pub print_layout: Option<String>,
+
+    /// Write the optimized clif ir of all functions in a codegen unit and the final object file
+    /// for the codegen unit to the given directory. Unlike `--emit llvm-ir` this makes it possible
+    /// to inspect the whole module at once. Only used in AOT mode and skipped for codegen units
+    /// reused from the incremental cache.
+    ///
+    /// Defaults to the value of `CG_CLIF_DUMP_MODULE`. Can be set using
+    /// `-Cllvm-args=dump_module=...`.
+    pub dump_module: Option<PathBuf>,
+
+    /// Print the number of bytes every codegen unit spends on code, read-only data, debuginfo and
+    /// unwind tables to stderr after writing its object file. Useful to find out where to focus
+    /// binary size reductions. Skipped for codegen units reused from the incremental cache.
+    ///
+    /// Defaults to true when the `CG_CLIF_SIZE_REPORT` env var is set to 1 or false otherwise. Can
+    /// be set using `-Cllvm-args=size_report=...`.
+    pub size_report: bool,
+
+    /// Insert checks that abort on undefined behavior detectable at runtime, like overlapping
+    /// `copy_nonoverlapping` ranges or a zero divisor passed to `unchecked_div`. This includes the
+    /// precondition checks of the standard library. rustc doesn't have a flag to enable these
+    /// independently of debug assertions yet.
+    ///
+    /// Defaults to whether debug assertions are enabled. Can be set using
+    /// `-Cllvm-args=ub_checks=...`.
+    pub ub_checks: Option<bool>,
+
+    /// Embed the crate metadata in a `.rustc` section of an additional object file, which unlike
+    /// the separate `.rmeta` file ends up in rlibs and linked executables. rustc doesn't have an
+    /// `-Zembed-metadata` flag yet. Only supported for ELF targets. Dylibs and proc macros always
+    /// contain the metadata already, so nothing is embedded for them.
+    ///
+    /// Defaults to false. Can be set using `-Cllvm-args=embed_metadata=...`.
+    pub embed_metadata: bool,
+
+    /// Share the object files of codegen units between builds using a content addressed cache in
+    /// the given directory. Codegen units with debuginfo are never cached. Unlike the incremental
+    /// cache this also works across crates and build directories.
+    ///
+    /// Defaults to the value of `CG_CLIF_OBJECT_CACHE_DIR`. Can be set using
+    /// `-Cllvm-args=object_cache_dir=...`.
+    pub object_cache_dir: Option<PathBuf>,
+
+    /// Compile all codegen units one after another on the main thread instead of on separate
+    /// worker threads and don't request any jobserver tokens. Useful for debugging and profiling
+    /// the backend and for environments where spawning threads is not possible.
+    ///
+    /// Defaults to true when the `CG_CLIF_SINGLE_THREADED` env var is set to 1 or false otherwise.
+    /// Can be set using `-Cllvm-args=single_threaded=...`.
+    pub single_threaded: bool,
 }
 
 impl Default for BackendConfig {
@@ -69,6 +135,14 @@ fn default() -> Self {
             },
             enable_verifier: cfg!(debug_assertions) || bool_env_var("CG_CLIF_ENABLE_VERIFIER"),
             disable_incr_cache: bool_env_var("CG_CLIF_DISABLE_INCR_CACHE"),
+            panic_immediate_abort: false,
+            print_layout: env::var("CG_CLIF_PRINT_LAYOUT").ok(),
+            dump_module: env::var_os("CG_CLIF_DUMP_MODULE").map(PathBuf::from),
+            size_report: bool_env_var("CG_CLIF_SIZE_REPORT"),
+            ub_checks: None,
+            embed_metadata: false,
+            object_cache_dir: env::var_os("CG_CLIF_OBJECT_CACHE_DIR").map(PathBuf::from),
+            single_threaded: bool_env_var("CG_CLIF_SINGLE_THREADED"),
         }
     }
 }
@@ -92,6 +166,16 @@ fn parse_bool(name: &str, value: &str) -> Result<bool, String> {
                     "mode" => config.codegen_mode = value.parse()?,
                     "enable_verifier" => config.enable_verifier = parse_bool(name, value)?,
                     "disable_incr_cache" => config.disable_incr_cache = parse_bool(name, value)?,
+                    "panic_immediate_abort" => {
+                        config.panic_immediate_abort = parse_bool(name, value)?
+                    }
+                    "print_layout" => config.print_layout = Some(value.to_owned()),
+                    "dump_module" => config.dump_module = Some(PathBuf::from(value)),
+                    "size_report" => config.size_report = parse_bool(name, value)?,
+                    "ub_checks" => config.ub_checks = Some(parse_bool(name, value)?),
+                    "embed_metadata" => config.embed_metadata = parse_bool(name, value)?,
+                    "object_cache_dir" => config.object_cache_dir = Some(PathBuf::from(value)),
+                    "single_threaded" => config.single_threaded = parse_bool(name, value)?,
                     _ => return Err(format!("Unknown option `{}`", name)),
                 }
             } else {
@@ -0,0 +1,394 @@
+//! Instrumentation for `-Cinstrument-coverage` and `-Cprofile-generate` using the LLVM profiler
+//! runtime.
+//!
+//! For every instrumented function an array of counters is emitted in the `__llvm_prf_cnts`
+//! section and a profile data record describing it in the `__llvm_prf_data` section. The names of
+//! all instrumented functions are stored in the `__llvm_prf_names` section. The profiler runtime
+//! finds these sections using the `__start_`/`__stop_` symbols defined by the linker and writes
+//! them to a `.profraw` file when the program exits.
+//!
+//! With `-Cinstrument-coverage` every function additionally gets a coverage mapping record in the
+//! `__llvm_covfun` section, which maps its counters back to the source code. The file names
+//! referenced by these records are stored once per codegen unit in the `__llvm_covmap` section.
+//! Neither section is used at runtime, but `llvm-cov` reads them from the final executable.
+//!
+//! With `-Cprofile-generate` every function gets a single counter for the number of times it was
+//! called, which is what `-Cprofile-use` uses (see [`crate::profile_use`]).
+
+use cranelift_module::DataId;
+use indexmap::IndexSet;
+use md5::{Digest, Md5};
+use rustc_middle::mir::coverage::{CovTerm, CoverageKind, FunctionCoverageInfo, MappingKind, Op};
+use rustc_middle::mir::interpret::write_target_uint;
+use rustc_session::config::SwitchWithOptPath;
+use rustc_span::Symbol;
+use rustc_target::abi::Endian;
+
+use crate::prelude::*;
+
+/// Size of `__llvm_profile_data` in version 9 of the raw profile format used by LLVM 18.
+const PROFILE_DATA_SIZE: usize = 64;
+
+/// Offset of `__llvm_profile_data::CounterPtr`.
+const COUNTER_PTR_OFFSET: usize = 16;
+
+/// Offset of `__llvm_profile_data::NumCounters`.
+const NUM_COUNTERS_OFFSET: usize = 48;
+
+/// Version 6 of the coverage mapping format used by LLVM 18. The encoded value is one less.
+const COVERAGE_MAPPING_VERSION: u32 = 5;
+
+pub(crate) struct CoverageContext {
+    endian: Endian,
+    /// The value of `__llvm_profile_filename` when using `-Cprofile-generate`.
+    profile_filename: Option<String>,
+    /// The symbol name, counters and profile data record of every instrumented function.
+    functions: Vec<(String, DataId, DataId)>,
+    /// The file names referenced by the coverage mappings. The first entry is the working
+    /// directory, which relative file names are resolved against.
+    filenames: IndexSet<Symbol>,
+    /// The name hash, source hash and encoded coverage mapping of every function instrumented
+    /// using `-Cinstrument-coverage`.
+    coverage_mappings: Vec<(u64, u64, Vec<u8>)>,
+}
+
+impl CoverageContext {
+    pub(crate) fn new(tcx: TyCtxt<'_>) -> Self {
+        let target = &tcx.sess.target;
+        if target.is_like_osx
+            || target.is_like_windows
+            || target.is_like_wasm
+            || tcx.data_layout.pointer_size.bytes() != 8
+        {
+            tcx.dcx().fatal(
+                "-Cinstrument-coverage and -Cprofile-generate are only supported on 64bit ELF targets",
+            );
+        }
+
+        if tcx.sess.instrument_coverage() && tcx.sess.opts.cg.profile_generate.enabled() {
+            tcx.dcx().fatal("-Cinstrument-coverage and -Cprofile-generate can't be combined");
+        }
+
+        // Matches the default profile path used by LLVM for `-Cprofile-generate`.
+        let profile_filename = match &tcx.sess.opts.cg.profile_generate {
+            SwitchWithOptPath::Enabled(Some(dir)) => {
+                Some(dir.join("default_%m.profraw").to_string_lossy().into_owned())
+            }
+            SwitchWithOptPath::Enabled(None) => Some("default_%m.profraw".to_owned()),
+            SwitchWithOptPath::Disabled => None,
+        };
+
+        let working_dir = tcx.sess.opts.working_dir.remapped_path_if_available();
+        let mut filenames = IndexSet::new();
+        filenames.insert(Symbol::intern(&working_dir.to_string_lossy()));
+
+        CoverageContext {
+            endian: tcx.data_layout.endian,
+            profile_filename,
+            functions: vec![],
+            filenames,
+            coverage_mappings: vec![],
+        }
+    }
+
+    /// Define the counters and profile data record of a function. Returns the counters if the
+    /// function is instrumented.
+    pub(crate) fn define_function(
+        &mut self,
+        module: &mut dyn Module,
+        symbol_name: &str,
+        mir: &Body<'_>,
+    ) -> Option<DataId> {
+        let coverage_info = if self.profile_filename.is_some() {
+            None
+        } else {
+            Some(mir.function_coverage_info.as_deref()?)
+        };
+        let (num_counters, function_hash) = match coverage_info {
+            Some(coverage_info) => (coverage_info.num_counters, coverage_info.function_source_hash),
+            // Only the number of calls is counted, which doesn't depend on the body of the
+            // function, so there is nothing to hash.
+            None => (1, 0),
+        };
+        let name_hash = md5_low64(symbol_name.as_bytes());
+
+        let counters_id = module
+            .declare_data(&format!("__profc_{symbol_name}"), Linkage::Local, true, false)
+            .unwrap();
+        let mut counters = DataDescription::new();
+        counters.set_align(8);
+        counters.set_segment_section("", "__llvm_prf_cnts");
+        counters.define(vec![0; 8 * num_counters.max(1)].into_boxed_slice());
+        module.define_data(counters_id, &counters).unwrap();
+
+        // `CounterPtr` is relative to the start of the profile data record. As Cranelift can't
+        // express the difference between two symbols, it is filled in by `finalize`.
+        let mut record = vec![0; PROFILE_DATA_SIZE];
+        write_target_uint(self.endian, &mut record[0..8], name_hash.into()).unwrap();
+        write_target_uint(self.endian, &mut record[8..16], function_hash.into()).unwrap();
+        write_target_uint(
+            self.endian,
+            &mut record[NUM_COUNTERS_OFFSET..NUM_COUNTERS_OFFSET + 4],
+            num_counters as u128,
+        )
+        .unwrap();
+
+        let data_id = module
+            .declare_data(&format!("__profd_{symbol_name}"), Linkage::Local, true, false)
+            .unwrap();
+        let mut data = DataDescription::new();
+        data.set_align(8);
+        data.set_segment_section("", "__llvm_prf_data");
+        data.define(record.into_boxed_slice());
+        module.define_data(data_id, &data).unwrap();
+
+        self.functions.push((symbol_name.to_owned(), counters_id, data_id));
+
+        if let Some(coverage_info) = coverage_info {
+            let mapping = self.encode_coverage_mapping(coverage_info);
+            self.coverage_mappings.push((name_hash, function_hash, mapping));
+        }
+
+        Some(counters_id)
+    }
+
+    /// Encode the coverage regions of a function together with the counter expressions they
+    /// reference. The regions are grouped by file and sorted by their start position within each
+    /// file, as their start lines are encoded relative to the previous region.
+    fn encode_coverage_mapping(&mut self, coverage_info: &FunctionCoverageInfo) -> Vec<u8> {
+        // Maps the file ids local to this function to indices into `self.filenames`.
+        let mut virtual_files = IndexSet::new();
+        let mut regions = coverage_info
+            .mappings
+            .iter()
+            .map(|mapping| {
+                let MappingKind::Code(term) = mapping.kind;
+                let region = &mapping.code_region;
+                let (filename_index, _) = self.filenames.insert_full(region.file_name);
+                let (file_id, _) = virtual_files.insert_full(filename_index);
+                (file_id, region, term)
+            })
+            .collect::<Vec<_>>();
+        regions.sort_by_key(|&(file_id, region, _)| {
+            (file_id, region.start_line, region.start_col, region.end_line, region.end_col)
+        });
+
+        let mut encoded = vec![];
+        write_uleb128(&mut encoded, virtual_files.len() as u64);
+        for &filename_index in &virtual_files {
+            write_uleb128(&mut encoded, filename_index as u64);
+        }
+
+        write_uleb128(&mut encoded, coverage_info.expressions.len() as u64);
+        for expression in &coverage_info.expressions {
+            write_uleb128(&mut encoded, encode_counter(coverage_info, expression.lhs));
+            write_uleb128(&mut encoded, encode_counter(coverage_info, expression.rhs));
+        }
+
+        for file_id in 0..virtual_files.len() {
+            let file_regions =
+                regions.iter().filter(|&&(id, _, _)| id == file_id).collect::<Vec<_>>();
+            write_uleb128(&mut encoded, file_regions.len() as u64);
+            let mut prev_start_line = 0;
+            for &&(_, region, term) in &file_regions {
+                write_uleb128(&mut encoded, encode_counter(coverage_info, term));
+                write_uleb128(&mut encoded, (region.start_line - prev_start_line).into());
+                write_uleb128(&mut encoded, region.start_col.into());
+                write_uleb128(&mut encoded, (region.end_line - region.start_line).into());
+                write_uleb128(&mut encoded, region.end_col.into());
+                prev_start_line = region.start_line;
+            }
+        }
+
+        encoded
+    }
+
+    /// Emit the names of all instrumented functions, the coverage mappings and a constructor
+    /// filling in the `CounterPtr` field of all profile data records. Returns the data objects
+    /// which are only read by `llvm-cov` and thus need to be retained by the linker.
+    pub(crate) fn finalize(
+        self,
+        module: &mut dyn Module,
+        unwind_context: &mut UnwindContext,
+    ) -> Vec<DataId> {
+        if self.functions.is_empty() {
+            return vec![];
+        }
+
+        if let Some(profile_filename) = &self.profile_filename {
+            // Every codegen unit defines this, so it has to be a weak definition. It also
+            // overrides the weak definition in the profiler runtime.
+            let filename_id = module
+                .declare_data("__llvm_profile_filename", Linkage::Preemptible, false, false)
+                .unwrap();
+            let mut filename = DataDescription::new();
+            let mut bytes = profile_filename.clone().into_bytes();
+            bytes.push(0);
+            filename.define(bytes.into_boxed_slice());
+            module.define_data(filename_id, &filename).unwrap();
+        }
+
+        // The uncompressed size followed by a compressed size of zero to indicate that the names
+        // are not compressed.
+        let names = self.functions.iter().map(|(name, _, _)| &**name).collect::<Vec<_>>();
+        let names = names.join("\x01");
+        let mut names_blob = vec![];
+        gimli::leb128::write::unsigned(&mut names_blob, names.len() as u64).unwrap();
+        gimli::leb128::write::unsigned(&mut names_blob, 0).unwrap();
+        names_blob.extend_from_slice(names.as_bytes());
+
+        let names_id = module.declare_anonymous_data(false, false).unwrap();
+        let mut names_data = DataDescription::new();
+        names_data.set_segment_section("", "__llvm_prf_names");
+        names_data.define(names_blob.into_boxed_slice());
+        module.define_data(names_id, &names_data).unwrap();
+
+        let pointer_type = module.target_config().pointer_type();
+        let sig = Signature::new(module.target_config().default_call_conv);
+        let init_func_id = module.declare_anonymous_function(&sig).unwrap();
+
+        let mut ctx = Context::new();
+        ctx.func.signature = sig;
+        {
+            let mut func_ctx = FunctionBuilderContext::new();
+            let mut bcx = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+            let block = bcx.create_block();
+            bcx.switch_to_block(block);
+            for &(_, counters_id, data_id) in &self.functions {
+                let counters = module.declare_data_in_func(counters_id, bcx.func);
+                let counters = bcx.ins().global_value(pointer_type, counters);
+                let data = module.declare_data_in_func(data_id, bcx.func);
+                let data = bcx.ins().global_value(pointer_type, data);
+                let counter_ptr = bcx.ins().isub(counters, data);
+                bcx.ins().store(MemFlags::trusted(), counter_ptr, data, COUNTER_PTR_OFFSET as i32);
+            }
+            bcx.ins().return_(&[]);
+            bcx.seal_all_blocks();
+            bcx.finalize();
+        }
+        module.define_function(init_func_id, &mut ctx).unwrap();
+        unwind_context.add_function(init_func_id, &ctx, module.isa());
+
+        let init_array_id = module.declare_anonymous_data(true, false).unwrap();
+        let mut init_array = DataDescription::new();
+        init_array.set_align(8);
+        init_array.set_segment_section("", ".init_array");
+        init_array.define(vec![0; 8].into_boxed_slice());
+        let init_func_ref = module.declare_func_in_data(init_func_id, &mut init_array);
+        init_array.write_function_addr(0, init_func_ref);
+        module.define_data(init_array_id, &init_array).unwrap();
+
+        self.define_coverage_mappings(module)
+    }
+
+    fn define_coverage_mappings(&self, module: &mut dyn Module) -> Vec<DataId> {
+        if self.coverage_mappings.is_empty() {
+            return vec![];
+        }
+
+        // The number of file names and the uncompressed size followed by a compressed size of zero
+        // to indicate that the file names are not compressed.
+        let mut filenames = vec![];
+        for filename in &self.filenames {
+            let filename = filename.as_str();
+            write_uleb128(&mut filenames, filename.len() as u64);
+            filenames.extend_from_slice(filename.as_bytes());
+        }
+        let mut filenames_blob = vec![];
+        write_uleb128(&mut filenames_blob, self.filenames.len() as u64);
+        write_uleb128(&mut filenames_blob, filenames.len() as u64);
+        write_uleb128(&mut filenames_blob, 0);
+        filenames_blob.extend_from_slice(&filenames);
+        let filenames_hash = md5_low64(&filenames_blob);
+
+        let mut retained_data = vec![];
+
+        // The header consists of the number of function records and the size of the coverage
+        // mappings, which are both unused since version 4, the size of the file names and the
+        // format version.
+        let mut covmap = vec![0; 16];
+        write_target_uint(self.endian, &mut covmap[4..8], filenames_blob.len() as u128).unwrap();
+        write_target_uint(self.endian, &mut covmap[12..16], COVERAGE_MAPPING_VERSION.into())
+            .unwrap();
+        covmap.extend_from_slice(&filenames_blob);
+        retained_data.push(define_coverage_data(module, "__llvm_covmap", covmap));
+
+        for (name_hash, function_hash, mapping) in &self.coverage_mappings {
+            let mut covfun = vec![0; 28];
+            write_target_uint(self.endian, &mut covfun[0..8], (*name_hash).into()).unwrap();
+            write_target_uint(self.endian, &mut covfun[8..12], mapping.len() as u128).unwrap();
+            write_target_uint(self.endian, &mut covfun[12..20], (*function_hash).into()).unwrap();
+            write_target_uint(self.endian, &mut covfun[20..28], filenames_hash.into()).unwrap();
+            covfun.extend_from_slice(mapping);
+            retained_data.push(define_coverage_data(module, "__llvm_covfun", covfun));
+        }
+
+        retained_data
+    }
+}
+
+/// Define a coverage mapping record. `llvm-cov` expects every record to be 8 byte aligned.
+fn define_coverage_data(module: &mut dyn Module, section: &str, bytes: Vec<u8>) -> DataId {
+    let data_id = module.declare_anonymous_data(false, false).unwrap();
+    let mut data = DataDescription::new();
+    data.set_align(8);
+    data.set_segment_section("", section);
+    data.define(bytes.into_boxed_slice());
+    module.define_data(data_id, &data).unwrap();
+    data_id
+}
+
+fn write_uleb128(bytes: &mut Vec<u8>, value: u64) {
+    gimli::leb128::write::unsigned(bytes, value).unwrap();
+}
+
+/// Encode a reference to a counter or counter expression. The lower two bits are the kind, with
+/// the kind of expressions distinguishing subtractions from additions.
+fn encode_counter(coverage_info: &FunctionCoverageInfo, term: CovTerm) -> u64 {
+    match term {
+        CovTerm::Zero => 0,
+        CovTerm::Counter(id) => (u64::from(id.as_u32()) << 2) | 1,
+        CovTerm::Expression(id) => {
+            let kind = match coverage_info.expressions[id].op {
+                Op::Subtract => 2,
+                Op::Add => 3,
+            };
+            (u64::from(id.as_u32()) << 2) | kind
+        }
+    }
+}
+
+pub(crate) fn codegen_coverage(fx: &mut FunctionCx<'_, '_, '_>, kind: &CoverageKind) {
+    let CoverageKind::CounterIncrement { id } = *kind else {
+        // Only counters are lowered. Everything else is only used for computing coverage regions.
+        return;
+    };
+    increment_counter(fx, id.as_usize());
+}
+
+/// Count the calls of the current function when using `-Cprofile-generate`. Must be called once
+/// at the start of the function.
+pub(crate) fn codegen_call_counter(fx: &mut FunctionCx<'_, '_, '_>) {
+    if fx.tcx.sess.opts.cg.profile_generate.enabled() {
+        increment_counter(fx, 0);
+    }
+}
+
+fn increment_counter(fx: &mut FunctionCx<'_, '_, '_>, index: usize) {
+    let Some(counters_id) = fx.coverage_counters else {
+        return;
+    };
+
+    let counters = fx.module.declare_data_in_func(counters_id, fx.bcx.func);
+    let counters = fx.bcx.ins().global_value(fx.pointer_type, counters);
+    let offset = i32::try_from(index * 8).unwrap();
+    let counter = fx.bcx.ins().load(types::I64, MemFlags::trusted(), counters, offset);
+    let counter = fx.bcx.ins().iadd_imm(counter, 1);
+    fx.bcx.ins().store(MemFlags::trusted(), counter, counters, offset);
+}
+
+/// The lower 64 bits of the MD5 hash of `bytes`. This is how LLVM identifies functions and file
+/// name tables in profiles.
+fn md5_low64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(Md5::digest(bytes)[..8].try_into().unwrap())
+}
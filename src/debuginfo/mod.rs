@@ -3,20 +3,23 @@
 mod emit;
 mod line_info;
 mod object;
+mod types;
 mod unwind;
 
 use cranelift_codegen::ir::Endianness;
 use cranelift_codegen::isa::TargetIsa;
 use gimli::write::{
-    Address, AttributeValue, DwarfUnit, FileId, LineProgram, LineString, Range, RangeList,
-    UnitEntryId,
+    Address, AttributeValue, DwarfUnit, Expression, FileId, LineProgram, LineString, Range,
+    RangeList, UnitEntryId,
 };
-use gimli::{Encoding, Format, LineEncoding, RunTimeEndian};
+use gimli::{Encoding, Format, LineEncoding, Register, RunTimeEndian};
 use indexmap::IndexSet;
 use rustc_session::Session;
 
 pub(crate) use self::emit::{DebugReloc, DebugRelocName};
+pub(crate) use self::types::TypeDebugContext;
 pub(crate) use self::unwind::UnwindContext;
+use crate::pointer::PointerBase;
 use crate::prelude::*;
 
 pub(crate) fn producer(sess: &Session) -> String {
@@ -30,28 +33,39 @@ pub(crate) struct DebugContext {
     unit_range_list: RangeList,
 
     should_remap_filepaths: bool,
+
+    /// The DWARF register used to describe the location of stack slots. `None` if the
+    /// architecture isn't supported yet, in which case no variable locations are emitted.
+    stack_pointer_register: Option<Register>,
 }
 
 pub(crate) struct FunctionDebugContext {
     entry_id: UnitEntryId,
     function_source_loc: (FileId, u64, u64),
     source_loc_set: IndexSet<(FileId, u64, u64)>,
+    /// Variables stored in a stack slot together with the offset of the variable within the
+    /// stack slot. The location is filled in by `finalize` once the stack frame layout is known.
+    stack_slot_vars: Vec<(UnitEntryId, StackSlot, i64)>,
 }
 
 impl DebugContext {
     pub(crate) fn new(tcx: TyCtxt<'_>, isa: &dyn TargetIsa) -> Self {
+        let version = match tcx.sess.opts.unstable_opts.dwarf_version {
+            // Version 5 is required for md5 file hashes and emits range and location lists in the
+            // more compact `.debug_rnglists` and `.debug_loclists` sections.
+            Some(version @ 2..=5) => version as u16,
+            Some(version) => {
+                tcx.dcx().fatal(format!("DWARF version {version} is not supported by cg_clif"))
+            }
+            // macOS doesn't seem to support DWARF > 3
+            None if tcx.sess.target.is_like_osx => 3,
+            // FIXME change to version 5 once the gdb and lldb shipping with the latest debian
+            // support it.
+            None => 4,
+        };
         let encoding = Encoding {
             format: Format::Dwarf32,
-            // FIXME this should be configurable
-            // macOS doesn't seem to support DWARF > 3
-            // 5 version is required for md5 file hash
-            version: if tcx.sess.target.is_like_osx {
-                3
-            } else {
-                // FIXME change to version 5 once the gdb and lldb shipping with the latest debian
-                // support it.
-                4
-            },
+            version,
             address_size: isa.frontend_config().pointer_bytes(),
         };
 
@@ -64,6 +78,14 @@ pub(crate) fn new(tcx: TyCtxt<'_>, isa: &dyn TargetIsa) -> Self {
 
         let should_remap_filepaths = tcx.sess.should_prefer_remapped_for_codegen();
 
+        let stack_pointer_register = match isa.triple().architecture {
+            target_lexicon::Architecture::X86_64 => Some(gimli::X86_64::RSP),
+            target_lexicon::Architecture::Aarch64(_) => Some(gimli::AArch64::SP),
+            target_lexicon::Architecture::Riscv64(_) => Some(gimli::RiscV::SP),
+            // FIXME support s390x
+            _ => None,
+        };
+
         let producer = producer(tcx.sess);
         let comp_dir = tcx
             .sess
@@ -112,6 +134,7 @@ pub(crate) fn new(tcx: TyCtxt<'_>, isa: &dyn TargetIsa) -> Self {
             dwarf,
             unit_range_list: RangeList(Vec::new()),
             should_remap_filepaths,
+            stack_pointer_register,
         }
     }
 
@@ -143,8 +166,49 @@ pub(crate) fn define_function(
             entry_id,
             function_source_loc: (file_id, line, column),
             source_loc_set: IndexSet::new(),
+            stack_slot_vars: Vec::new(),
         }
     }
+
+    /// Define a local variable or function parameter stored in memory at `ptr`.
+    ///
+    /// Only variables stored in a stack slot are supported for now.
+    pub(crate) fn define_local<'tcx>(
+        &mut self,
+        tcx: TyCtxt<'tcx>,
+        type_dbg: &mut TypeDebugContext<'tcx>,
+        func_debug_cx: &mut FunctionDebugContext,
+        var: &VarDebugInfo<'tcx>,
+        ty: Ty<'tcx>,
+        ptr: Pointer,
+    ) {
+        if self.stack_pointer_register.is_none() {
+            return;
+        }
+        let (PointerBase::Stack(stack_slot), offset) = ptr.debug_base_and_offset() else {
+            return;
+        };
+
+        let dw_ty = self.debug_type(tcx, type_dbg, ty);
+        let (file, line, _column) =
+            DebugContext::get_span_loc(tcx, var.source_info.span, var.source_info.span);
+        let file_id = self.add_source_file(&file);
+
+        let tag = if var.argument_index.is_some() {
+            gimli::DW_TAG_formal_parameter
+        } else {
+            gimli::DW_TAG_variable
+        };
+        let var_id = self.dwarf.unit.add(func_debug_cx.entry_id, tag);
+        let var_entry = self.dwarf.unit.get_mut(var_id);
+        let name_id = self.dwarf.strings.add(var.name.as_str());
+        var_entry.set(gimli::DW_AT_name, AttributeValue::StringRef(name_id));
+        var_entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(dw_ty));
+        var_entry.set(gimli::DW_AT_decl_file, AttributeValue::FileIndex(Some(file_id)));
+        var_entry.set(gimli::DW_AT_decl_line, AttributeValue::Udata(line));
+
+        func_debug_cx.stack_slot_vars.push((var_id, stack_slot, offset.into()));
+    }
 }
 
 impl FunctionDebugContext {
@@ -171,5 +235,20 @@ pub(crate) fn finalize(
         );
         // Using Udata for DW_AT_high_pc requires at least DWARF4
         func_entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(u64::from(end)));
+
+        if let Some(stack_pointer_register) = debug_context.stack_pointer_register {
+            let stack_slot_offsets = &context.compiled_code().unwrap().sized_stackslot_offsets;
+            for (var_id, stack_slot, offset) in self.stack_slot_vars {
+                // FIXME the stack pointer may not point to the bottom of the stack frame during
+                // the prologue and epilogue, use a location list or the frame base instead.
+                let mut expr = Expression::new();
+                expr.op_breg(
+                    stack_pointer_register,
+                    i64::from(stack_slot_offsets[stack_slot]) + offset,
+                );
+                let var_entry = debug_context.dwarf.unit.get_mut(var_id);
+                var_entry.set(gimli::DW_AT_location, AttributeValue::Exprloc(expr));
+            }
+        }
     }
 }
@@ -0,0 +1,188 @@
+//! Type debuginfo generation (`DW_TAG_*_type`)
+
+use gimli::write::{AttributeValue, UnitEntryId};
+use rustc_codegen_ssa::debuginfo::type_names;
+use rustc_target::abi::{FieldsShape, Variants};
+
+use crate::debuginfo::DebugContext;
+use crate::prelude::*;
+
+/// Caches the debuginfo type entries of a single codegen unit.
+#[derive(Default)]
+pub(crate) struct TypeDebugContext<'tcx> {
+    type_map: FxHashMap<Ty<'tcx>, UnitEntryId>,
+}
+
+impl DebugContext {
+    pub(crate) fn debug_type<'tcx>(
+        &mut self,
+        tcx: TyCtxt<'tcx>,
+        type_dbg: &mut TypeDebugContext<'tcx>,
+        ty: Ty<'tcx>,
+    ) -> UnitEntryId {
+        if let Some(&type_id) = type_dbg.type_map.get(&ty) {
+            return type_id;
+        }
+
+        let type_id = match ty.kind() {
+            ty::Bool | ty::Char | ty::Int(_) | ty::Uint(_) | ty::Float(_) => {
+                self.basic_type(tcx, ty)
+            }
+            ty::Tuple(elems) if elems.is_empty() => self.basic_type(tcx, ty),
+            ty::Array(elem_ty, len) => self.array_type(
+                tcx,
+                type_dbg,
+                ty,
+                *elem_ty,
+                len.eval_target_usize(tcx, ParamEnv::reveal_all()),
+            ),
+            ty::RawPtr(TypeAndMut { ty: pointee_ty, mutbl: _ }) | ty::Ref(_, pointee_ty, _)
+                if !has_ptr_meta(tcx, *pointee_ty) =>
+            {
+                self.pointer_type(tcx, type_dbg, ty, *pointee_ty)
+            }
+            ty::Adt(adt_def, _) if adt_def.is_struct() => self.struct_type(tcx, type_dbg, ty),
+            ty::Tuple(_) => self.struct_type(tcx, type_dbg, ty),
+            // FIXME emit debuginfo for enums, closures and fat pointers
+            _ => self.placeholder_for_type(tcx, ty),
+        };
+
+        type_dbg.type_map.insert(ty, type_id);
+
+        type_id
+    }
+
+    fn basic_type<'tcx>(&mut self, tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> UnitEntryId {
+        let (name, encoding) = match ty.kind() {
+            ty::Tuple(_) => ("()", gimli::DW_ATE_unsigned),
+            ty::Bool => ("bool", gimli::DW_ATE_boolean),
+            ty::Char => ("char", gimli::DW_ATE_UTF),
+            ty::Int(int_ty) => (int_ty.name_str(), gimli::DW_ATE_signed),
+            ty::Uint(uint_ty) => (uint_ty.name_str(), gimli::DW_ATE_unsigned),
+            ty::Float(float_ty) => (float_ty.name_str(), gimli::DW_ATE_float),
+            _ => unreachable!(),
+        };
+
+        let type_id = self.dwarf.unit.add(self.dwarf.unit.root(), gimli::DW_TAG_base_type);
+        let type_entry = self.dwarf.unit.get_mut(type_id);
+        type_entry.set(gimli::DW_AT_name, AttributeValue::StringRef(self.dwarf.strings.add(name)));
+        type_entry.set(gimli::DW_AT_encoding, AttributeValue::Encoding(encoding));
+        type_entry.set(
+            gimli::DW_AT_byte_size,
+            AttributeValue::Udata(RevealAllLayoutCx(tcx).layout_of(ty).size.bytes()),
+        );
+
+        type_id
+    }
+
+    fn array_type<'tcx>(
+        &mut self,
+        tcx: TyCtxt<'tcx>,
+        type_dbg: &mut TypeDebugContext<'tcx>,
+        array_ty: Ty<'tcx>,
+        elem_ty: Ty<'tcx>,
+        len: u64,
+    ) -> UnitEntryId {
+        let elem_dw_ty = self.debug_type(tcx, type_dbg, elem_ty);
+
+        let array_type_id = self.dwarf.unit.add(self.dwarf.unit.root(), gimli::DW_TAG_array_type);
+        let array_type_entry = self.dwarf.unit.get_mut(array_type_id);
+        array_type_entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(elem_dw_ty));
+        array_type_entry.set(
+            gimli::DW_AT_byte_size,
+            AttributeValue::Udata(RevealAllLayoutCx(tcx).layout_of(array_ty).size.bytes()),
+        );
+
+        let subrange_id = self.dwarf.unit.add(array_type_id, gimli::DW_TAG_subrange_type);
+        let subrange_entry = self.dwarf.unit.get_mut(subrange_id);
+        subrange_entry.set(gimli::DW_AT_count, AttributeValue::Udata(len));
+
+        array_type_id
+    }
+
+    fn pointer_type<'tcx>(
+        &mut self,
+        tcx: TyCtxt<'tcx>,
+        type_dbg: &mut TypeDebugContext<'tcx>,
+        ptr_ty: Ty<'tcx>,
+        pointee_ty: Ty<'tcx>,
+    ) -> UnitEntryId {
+        let name = type_names::compute_debuginfo_type_name(tcx, ptr_ty, true);
+
+        let type_id = self.dwarf.unit.add(self.dwarf.unit.root(), gimli::DW_TAG_pointer_type);
+        let type_entry = self.dwarf.unit.get_mut(type_id);
+        type_entry.set(gimli::DW_AT_name, AttributeValue::StringRef(self.dwarf.strings.add(name)));
+        type_entry.set(
+            gimli::DW_AT_byte_size,
+            AttributeValue::Udata(u64::from(self.dwarf.unit.encoding().address_size)),
+        );
+
+        // Register the pointer before visiting the pointee to handle recursive types.
+        type_dbg.type_map.insert(ptr_ty, type_id);
+
+        let pointee_dw_ty = self.debug_type(tcx, type_dbg, pointee_ty);
+        let type_entry = self.dwarf.unit.get_mut(type_id);
+        type_entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(pointee_dw_ty));
+
+        type_id
+    }
+
+    fn struct_type<'tcx>(
+        &mut self,
+        tcx: TyCtxt<'tcx>,
+        type_dbg: &mut TypeDebugContext<'tcx>,
+        ty: Ty<'tcx>,
+    ) -> UnitEntryId {
+        let layout = RevealAllLayoutCx(tcx).layout_of(ty);
+        if !matches!(layout.variants, Variants::Single { .. })
+            || !matches!(layout.fields, FieldsShape::Arbitrary { .. })
+        {
+            return self.placeholder_for_type(tcx, ty);
+        }
+
+        let type_id = self.placeholder_for_type(tcx, ty);
+        // Register the struct before visiting the fields to handle recursive types.
+        type_dbg.type_map.insert(ty, type_id);
+
+        for i in 0..layout.fields.count() {
+            let field_name = match ty.kind() {
+                ty::Adt(adt_def, _) => {
+                    adt_def.non_enum_variant().fields[FieldIdx::from_usize(i)].name.to_string()
+                }
+                ty::Tuple(_) => format!("__{i}"),
+                _ => unreachable!(),
+            };
+            let field_layout = layout.field(&RevealAllLayoutCx(tcx), i);
+            let field_dw_ty = self.debug_type(tcx, type_dbg, field_layout.ty);
+
+            let field_id = self.dwarf.unit.add(type_id, gimli::DW_TAG_member);
+            let field_entry = self.dwarf.unit.get_mut(field_id);
+            field_entry.set(
+                gimli::DW_AT_name,
+                AttributeValue::StringRef(self.dwarf.strings.add(field_name)),
+            );
+            field_entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(field_dw_ty));
+            field_entry.set(
+                gimli::DW_AT_data_member_location,
+                AttributeValue::Udata(layout.fields.offset(i).bytes()),
+            );
+        }
+
+        type_id
+    }
+
+    /// An opaque type of the right size for types we can't yet describe in more detail.
+    fn placeholder_for_type<'tcx>(&mut self, tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> UnitEntryId {
+        let name = type_names::compute_debuginfo_type_name(tcx, ty, true);
+
+        let type_id = self.dwarf.unit.add(self.dwarf.unit.root(), gimli::DW_TAG_structure_type);
+        let type_entry = self.dwarf.unit.get_mut(type_id);
+        type_entry.set(gimli::DW_AT_name, AttributeValue::StringRef(self.dwarf.strings.add(name)));
+        type_entry.set(
+            gimli::DW_AT_byte_size,
+            AttributeValue::Udata(RevealAllLayoutCx(tcx).layout_of(ty).size.bytes()),
+        );
+
+        type_id
+    }
+}
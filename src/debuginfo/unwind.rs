@@ -3,7 +3,8 @@
 use cranelift_codegen::ir::Endianness;
 use cranelift_codegen::isa::{unwind::UnwindInfo, TargetIsa};
 use cranelift_object::ObjectProduct;
-use gimli::write::{Address, CieId, EhFrame, FrameTable, Section};
+use gimli::read::{BaseAddresses, CallFrameInstruction, CieOrFde, UnwindSection};
+use gimli::write::{Address, CieId, EhFrame, EndianVec, FrameTable, Section};
 use gimli::RunTimeEndian;
 
 use super::object::WriteDebugInfo;
@@ -16,14 +17,20 @@ pub(crate) struct UnwindContext {
 }
 
 impl UnwindContext {
-    pub(crate) fn new(isa: &dyn TargetIsa, pic_eh_frame: bool) -> Self {
+    /// If `emit_unwind_tables` is false no unwind info is emitted at all. It is expected to be
+    /// `Session::must_emit_unwind_tables`, which is only false for `-Cpanic=abort` together with
+    /// `-Cforce-unwind-tables=no` or a target which doesn't use unwind tables by default. With just
+    /// `-Cpanic=abort` the unwind tables are kept on most targets as they are used for backtraces.
+    pub(crate) fn new(isa: &dyn TargetIsa, pic_eh_frame: bool, emit_unwind_tables: bool) -> Self {
         let endian = match isa.endianness() {
             Endianness::Little => RunTimeEndian::Little,
             Endianness::Big => RunTimeEndian::Big,
         };
         let mut frame_table = FrameTable::default();
 
-        let cie_id = if let Some(mut cie) = isa.create_systemv_cie() {
+        let cie_id = if !emit_unwind_tables {
+            None
+        } else if let Some(mut cie) = isa.create_systemv_cie() {
             if pic_eh_frame {
                 cie.fde_address_encoding =
                     gimli::DwEhPe(gimli::DW_EH_PE_pcrel.0 | gimli::DW_EH_PE_sdata4.0);
@@ -37,6 +44,10 @@ pub(crate) fn new(isa: &dyn TargetIsa, pic_eh_frame: bool) -> Self {
     }
 
     pub(crate) fn add_function(&mut self, func_id: FuncId, context: &Context, isa: &dyn TargetIsa) {
+        if self.cie_id.is_none() {
+            return;
+        }
+
         let unwind_info = if let Some(unwind_info) =
             context.compiled_code().unwrap().create_unwind_info(isa).unwrap()
         {
@@ -56,10 +67,121 @@ pub(crate) fn add_function(&mut self, func_id: FuncId, context: &Context, isa: &
             UnwindInfo::WindowsX64(_) => {
                 // FIXME implement this
             }
-            unwind_info => unimplemented!("{:?}", unwind_info),
+            // Unwind info is created after codegen on the thread compiling the codegen unit, so
+            // there is no span to point at. It depends only on the target anyway.
+            unwind_info => bug!("unsupported unwind info for this target: {:?}", unwind_info),
         }
     }
 
+    /// Returns the `.cfi_*` directives describing the frame of the function compiled in `context`
+    /// together with the code offset after which each of them applies. `None` is returned for
+    /// functions which don't get an FDE in `.eh_frame`.
+    ///
+    /// Cranelift only provides the unwind info as FDE, so it is encoded into a standalone
+    /// `.eh_frame` and read back to get at the individual call frame instructions.
+    pub(crate) fn cfi_directives(
+        &self,
+        context: &Context,
+        isa: &dyn TargetIsa,
+    ) -> Option<Vec<(u32, String)>> {
+        if self.cie_id.is_none() {
+            return None;
+        }
+
+        let UnwindInfo::SystemV(unwind_info) =
+            context.compiled_code().unwrap().create_unwind_info(isa).unwrap()?
+        else {
+            return None;
+        };
+
+        let mut frame_table = FrameTable::default();
+        let cie_id = frame_table.add_cie(isa.create_systemv_cie().unwrap());
+        frame_table.add_fde(cie_id, unwind_info.to_fde(Address::Constant(0)));
+        let mut eh_frame = EhFrame(EndianVec::new(self.endian));
+        frame_table.write_eh_frame(&mut eh_frame).unwrap();
+        let eh_frame_data = eh_frame.0.into_vec();
+
+        let mut eh_frame = gimli::read::EhFrame::new(&eh_frame_data, self.endian);
+        eh_frame.set_address_size(isa.pointer_bytes());
+        let bases = BaseAddresses::default();
+        let mut entries = eh_frame.entries(&bases);
+        let fde = loop {
+            match entries.next().unwrap().unwrap() {
+                CieOrFde::Cie(_) => {}
+                CieOrFde::Fde(partial) => {
+                    break partial.parse(gimli::read::EhFrame::cie_from_offset).unwrap();
+                }
+            }
+        };
+        let code_alignment_factor = fde.cie().code_alignment_factor();
+        let data_alignment_factor = fde.cie().data_alignment_factor();
+
+        let mut directives = vec![];
+        let mut offset = 0;
+        let mut instructions = fde.instructions(&eh_frame, &bases);
+        while let Some(instruction) = instructions.next().unwrap() {
+            let directive = match instruction {
+                CallFrameInstruction::AdvanceLoc { delta } => {
+                    offset += delta * code_alignment_factor as u32;
+                    continue;
+                }
+                CallFrameInstruction::DefCfa { register, offset } => {
+                    format!(".cfi_def_cfa {}, {offset}", register.0)
+                }
+                CallFrameInstruction::DefCfaSf { register, factored_offset } => {
+                    format!(
+                        ".cfi_def_cfa {}, {}",
+                        register.0,
+                        factored_offset * data_alignment_factor
+                    )
+                }
+                CallFrameInstruction::DefCfaRegister { register } => {
+                    format!(".cfi_def_cfa_register {}", register.0)
+                }
+                CallFrameInstruction::DefCfaOffset { offset } => {
+                    format!(".cfi_def_cfa_offset {offset}")
+                }
+                CallFrameInstruction::DefCfaOffsetSf { factored_offset } => {
+                    format!(".cfi_def_cfa_offset {}", factored_offset * data_alignment_factor)
+                }
+                CallFrameInstruction::Offset { register, factored_offset } => {
+                    format!(
+                        ".cfi_offset {}, {}",
+                        register.0,
+                        factored_offset as i64 * data_alignment_factor
+                    )
+                }
+                CallFrameInstruction::OffsetExtendedSf { register, factored_offset } => {
+                    format!(
+                        ".cfi_offset {}, {}",
+                        register.0,
+                        factored_offset * data_alignment_factor
+                    )
+                }
+                CallFrameInstruction::Restore { register } => {
+                    format!(".cfi_restore {}", register.0)
+                }
+                CallFrameInstruction::Undefined { register } => {
+                    format!(".cfi_undefined {}", register.0)
+                }
+                CallFrameInstruction::SameValue { register } => {
+                    format!(".cfi_same_value {}", register.0)
+                }
+                CallFrameInstruction::RememberState => ".cfi_remember_state".to_owned(),
+                CallFrameInstruction::RestoreState => ".cfi_restore_state".to_owned(),
+                CallFrameInstruction::NegateRaState => ".cfi_negate_ra_state".to_owned(),
+                CallFrameInstruction::Nop => continue,
+                instruction => bug!("unexpected call frame instruction {:?}", instruction),
+            };
+            directives.push((offset, directive));
+        }
+
+        Some(directives)
+    }
+
+    /// FDEs are added right after the function they describe is defined, so they are emitted in
+    /// the same order as the functions in `.text`. The sorted `.eh_frame_hdr` search table is
+    /// created by the linker.
     pub(crate) fn emit(self, product: &mut ObjectProduct) {
         let mut eh_frame = EhFrame::from(super::emit::WriterRelocate::new(self.endian));
         self.frame_table.write_eh_frame(&mut eh_frame).unwrap();
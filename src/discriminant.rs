@@ -76,6 +76,13 @@ pub(crate) fn codegen_get_discriminant<'tcx>(
     let layout = value.layout();
 
     if layout.abi.is_uninhabited() {
+        // There are no values of this type, so this code is unreachable. Don't read the tag.
+        fx.bcx.ins().trap(TrapCode::UnreachableCodeReached);
+
+        // The rest of the basic block still needs to be codegened. Do so in an unreachable block.
+        let dead_block = fx.bcx.create_block();
+        fx.bcx.switch_to_block(dead_block);
+        fx.bcx.seal_block(dead_block);
         return;
     }
 
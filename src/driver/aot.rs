@@ -2,11 +2,13 @@
 //! standalone executable.
 
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::thread::JoinHandle;
 
-use cranelift_object::{ObjectBuilder, ObjectModule};
+use cranelift_module::DataId;
+use cranelift_object::{ObjectBuilder, ObjectModule, ObjectProduct};
 use rustc_codegen_ssa::assert_module_sources::CguReuse;
 use rustc_codegen_ssa::back::metadata::create_compressed_metadata_file;
 use rustc_codegen_ssa::base::determine_cgu_reuse;
@@ -15,12 +17,17 @@
 use rustc_data_structures::stable_hasher::{HashStable, StableHasher};
 use rustc_metadata::EncodedMetadata;
 use rustc_middle::dep_graph::{WorkProduct, WorkProductId};
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
 use rustc_middle::mir::mono::{CodegenUnit, MonoItem};
-use rustc_session::config::{DebugInfo, OutputFilenames, OutputType};
+use rustc_session::config::{DebugInfo, OutFileName, OutputFilenames, OutputType};
 use rustc_session::Session;
 
+use crate::base::{FunctionInSection, FunctionRelocTarget};
 use crate::concurrency_limiter::{ConcurrencyLimiter, ConcurrencyLimiterToken};
+use crate::debuginfo::TypeDebugContext;
 use crate::global_asm::GlobalAsmConfig;
+use crate::object_cache::{HashingModule, ObjectCache};
+use crate::profile_use::ProfileUse;
 use crate::{prelude::*, BackendConfig};
 
 struct ModuleCodegenResult {
@@ -44,6 +51,7 @@ pub(crate) struct OngoingCodegen {
     modules: Vec<OngoingModuleCodegen>,
     allocator_module: Option<CompiledModule>,
     metadata_module: Option<CompiledModule>,
+    embedded_metadata_module: Option<CompiledModule>,
     metadata: EncodedMetadata,
     crate_info: CrateInfo,
     concurrency_limiter: ConcurrencyLimiter,
@@ -53,6 +61,7 @@ impl OngoingCodegen {
     pub(crate) fn join(
         self,
         sess: &Session,
+        outputs: &OutputFilenames,
         backend_config: &BackendConfig,
     ) -> (CodegenResults, FxIndexMap<WorkProductId, WorkProduct>) {
         let mut work_products = FxIndexMap::default();
@@ -106,24 +115,218 @@ pub(crate) fn join(
             }
         }
 
+        modules.extend(self.embedded_metadata_module);
+
         self.concurrency_limiter.finished();
 
         sess.dcx().abort_if_errors();
 
-        (
-            CodegenResults {
-                modules,
-                allocator_module: self.allocator_module,
-                metadata_module: self.metadata_module,
-                metadata: self.metadata,
-                crate_info: self.crate_info,
-            },
-            work_products,
-        )
+        let mut codegen_results = CodegenResults {
+            modules,
+            allocator_module: self.allocator_module,
+            metadata_module: self.metadata_module,
+            metadata: self.metadata,
+            crate_info: self.crate_info,
+        };
+
+        produce_final_output_artifacts(sess, &codegen_results, outputs);
+
+        let upstream_objects = upstream_objects_for_lto(sess, &codegen_results.crate_info, outputs);
+        codegen_results.modules.extend(upstream_objects);
+
+        link_libatomic_if_needed(sess, &mut codegen_results);
+
+        (codegen_results, work_products)
     }
 }
 
-fn make_module(sess: &Session, backend_config: &BackendConfig, name: String) -> ObjectModule {
+/// 128bit atomics are lowered to calls into libatomic. Link it if any object file of the crate
+/// references these functions. Object files are checked rather than recording the libcalls during
+/// codegen to also handle codegen units reused from the incremental cache. The dependency can't be
+/// recorded for rlibs as their metadata is written before codegen, so crates linking an rlib which
+/// uses 128bit atomics have to pass `-latomic` themselves.
+fn link_libatomic_if_needed(sess: &Session, codegen_results: &mut CodegenResults) {
+    use object::{Object, ObjectSymbol};
+    use rustc_codegen_ssa::NativeLib;
+    use rustc_session::config::CrateType;
+    use rustc_session::utils::NativeLibKind;
+
+    if sess.target.os != "linux"
+        || codegen_results.crate_info.crate_types.iter().all(|&ty| ty == CrateType::Rlib)
+    {
+        return;
+    }
+
+    let references_libatomic = |path: &Path| {
+        let data = std::fs::read(path).unwrap_or_else(|err| {
+            sess.dcx().fatal(format!("error reading object file {}: {err}", path.display()))
+        });
+        let object = object::File::parse(&*data).unwrap_or_else(|err| {
+            sess.dcx().fatal(format!("error parsing object file {}: {err}", path.display()))
+        });
+        object.symbols().any(|symbol| {
+            symbol.is_undefined()
+                && symbol
+                    .name()
+                    .is_ok_and(|name| name.starts_with("__atomic_") && name.ends_with("_16"))
+        })
+    };
+    if codegen_results
+        .modules
+        .iter()
+        .filter_map(|module| module.object.as_deref())
+        .any(references_libatomic)
+    {
+        codegen_results.crate_info.used_libraries.push(NativeLib {
+            kind: NativeLibKind::Dylib { as_needed: None },
+            name: rustc_span::Symbol::intern("atomic"),
+            filename: None,
+            cfg: None,
+            verbatim: false,
+            dll_imports: vec![],
+        });
+    }
+}
+
+/// Cranelift doesn't perform LTO. When `-Clto` is used rustc however expects all Rust objects of
+/// upstream rlibs to have been merged into the local modules and leaves them out when linking. To
+/// still produce a working binary, the upstream objects are extracted and linked as local modules.
+fn upstream_objects_for_lto(
+    sess: &Session,
+    crate_info: &CrateInfo,
+    outputs: &OutputFilenames,
+) -> Vec<CompiledModule> {
+    use rustc_codegen_ssa::back::link::{
+        are_upstream_rust_objects_already_included, each_linked_rlib, ignored_for_lto,
+    };
+    use rustc_session::config::CrateType;
+
+    // LTO is rejected for rlibs by cg_llvm. Including the upstream objects in an rlib would cause
+    // duplicate symbols once it gets linked.
+    if !are_upstream_rust_objects_already_included(sess)
+        || crate_info.crate_types.contains(&CrateType::Rlib)
+    {
+        return vec![];
+    }
+
+    let mut modules = vec![];
+    let res = each_linked_rlib(crate_info, None, &mut |cnum, path| {
+        if ignored_for_lto(sess, crate_info, cnum) {
+            // Linked as usual by rustc.
+            return;
+        }
+
+        let data = std::fs::read(path).unwrap_or_else(|err| {
+            sess.dcx().fatal(format!("failed to read {}: {err}", path.display()))
+        });
+        let archive = object::read::archive::ArchiveFile::parse(&*data).unwrap_or_else(|err| {
+            sess.dcx().fatal(format!("failed to parse {}: {err}", path.display()))
+        });
+        for member in archive.members() {
+            let member = member.unwrap();
+            let member_name = String::from_utf8_lossy(member.name());
+            if !rustc_codegen_ssa::looks_like_rust_object_file(&member_name) {
+                continue;
+            }
+
+            let name = format!(
+                "{}.lto.{}",
+                crate_info.crate_name[&cnum],
+                member_name.trim_end_matches(".rcgu.o"),
+            );
+            let object = outputs.temp_path(OutputType::Object, Some(&name));
+            if let Err(err) = std::fs::write(&object, member.data(&*data).unwrap()) {
+                sess.dcx().fatal(format!("failed to write {}: {err}", object.display()));
+            }
+            modules.push(CompiledModule {
+                name,
+                kind: ModuleKind::Regular,
+                object: Some(object),
+                dwarf_object: None,
+                bytecode: None,
+            });
+        }
+    });
+    if let Err(err) = res {
+        sess.dcx().emit_fatal(err);
+    }
+
+    modules
+}
+
+/// Copy the object file and assembly to the paths requested using `--emit obj`, `--emit asm` or
+/// `--emit <kind>=<path>`. The metadata, dep-info and linked outputs are written to their
+/// requested paths by rustc itself.
+// Adapted from rustc_codegen_ssa::back::write::produce_final_output_artifacts
+fn produce_final_output_artifacts(
+    sess: &Session,
+    codegen_results: &CodegenResults,
+    crate_output: &OutputFilenames,
+) {
+    for output_type in [OutputType::Object, OutputType::Assembly] {
+        copy_if_one_unit(sess, codegen_results, crate_output, output_type);
+    }
+}
+
+fn copy_if_one_unit(
+    sess: &Session,
+    codegen_results: &CodegenResults,
+    crate_output: &OutputFilenames,
+    output_type: OutputType,
+) {
+    if !crate_output.outputs.contains_key(&output_type) {
+        return;
+    }
+
+    let (extension, kind) = match output_type {
+        OutputType::Object => ("o", "object file"),
+        OutputType::Assembly => ("s", "assembly file"),
+        _ => unreachable!(),
+    };
+
+    if codegen_results.modules.len() != 1 {
+        // Leave the `foo.<cgu>.rcgu.<extension>` files in place. There is no single file to copy.
+        if crate_output.outputs.contains_explicit_name(&output_type) {
+            sess.dcx().warn(format!(
+                "ignoring emit path because multiple .{extension} files were produced; use \
+                -Ccodegen-units=1 to get a single {kind}",
+            ));
+        } else if crate_output.single_output_file.is_some() {
+            sess.dcx().warn(format!(
+                "ignoring -o because multiple .{extension} files were produced; use \
+                -Ccodegen-units=1 to get a single {kind}",
+            ));
+        }
+        return;
+    }
+
+    let module = &codegen_results.modules[0];
+    let path = crate_output.temp_path(output_type, Some(&module.name));
+    let result = match crate_output.path(output_type) {
+        // Only the object file is binary output.
+        OutFileName::Stdout
+            if output_type == OutputType::Object && std::io::stdout().is_terminal() =>
+        {
+            sess.dcx().err(
+                "option `-o` or `--emit` is used to write binary output type `obj` to stdout, but \
+                stdout is a tty",
+            );
+            return;
+        }
+        OutFileName::Stdout => File::open(&path)
+            .and_then(|mut file| std::io::copy(&mut file, &mut std::io::stdout().lock())),
+        OutFileName::Real(output) => std::fs::copy(&path, &output),
+    };
+    if let Err(err) = result {
+        sess.dcx().err(format!("failed to copy {} to the requested output: {err}", path.display()));
+    }
+}
+
+pub(crate) fn make_module(
+    sess: &Session,
+    backend_config: &BackendConfig,
+    name: String,
+) -> ObjectModule {
     let isa = crate::build_isa(sess, backend_config);
 
     let mut builder =
@@ -142,11 +345,19 @@ fn emit_cgu(
     module: ObjectModule,
     debug: Option<DebugContext>,
     unwind_context: UnwindContext,
+    used_statics: &[DataId],
+    functions_in_sections: &[FunctionInSection],
     global_asm_object_file: Option<PathBuf>,
     producer: &str,
+    size_report: bool,
 ) -> Result<ModuleCodegenResult, String> {
     let mut product = module.finish();
 
+    retain_used_statics(&mut product, used_statics);
+
+    // Must happen before emitting debuginfo, which refers to the final location of functions.
+    define_functions_in_sections(&mut product, functions_in_sections)?;
+
     if let Some(mut debug) = debug {
         debug.emit(&mut product);
     }
@@ -162,6 +373,10 @@ fn emit_cgu(
         producer,
     )?;
 
+    if size_report {
+        print_size_report(&name, module_regular.object.as_ref().unwrap())?;
+    }
+
     Ok(ModuleCodegenResult {
         module_regular,
         module_global_asm: global_asm_object_file.map(|global_asm_object_file| CompiledModule {
@@ -175,6 +390,217 @@ fn emit_cgu(
     })
 }
 
+/// Prevent the linker from garbage collecting the data objects of `#[used(linker)]` statics and
+/// of the coverage mappings.
+fn retain_used_statics(product: &mut ObjectProduct, used_statics: &[DataId]) {
+    use cranelift_object::object::write::{SectionFlags, SymbolFlags, SymbolSection};
+    use cranelift_object::object::{elf, macho, BinaryFormat, SectionKind};
+
+    for &data_id in used_statics {
+        let symbol_id = product.data_symbol(data_id);
+        match product.object.format() {
+            BinaryFormat::Elf => {
+                // ELF linkers garbage collect whole sections, so retain the section containing the
+                // static. Explicitly setting the flags overrides the defaults of the section kind.
+                let SymbolSection::Section(section_id) = product.object.symbol(symbol_id).section
+                else {
+                    continue;
+                };
+                let section = product.object.section_mut(section_id);
+                let sh_flags = match section.flags {
+                    SectionFlags::Elf { sh_flags } => sh_flags,
+                    _ => u64::from(match section.kind {
+                        SectionKind::Tls | SectionKind::UninitializedTls => {
+                            elf::SHF_ALLOC | elf::SHF_WRITE | elf::SHF_TLS
+                        }
+                        SectionKind::ReadOnlyData => elf::SHF_ALLOC,
+                        _ => elf::SHF_ALLOC | elf::SHF_WRITE,
+                    }),
+                };
+                section.flags =
+                    SectionFlags::Elf { sh_flags: sh_flags | u64::from(elf::SHF_GNU_RETAIN) };
+            }
+            BinaryFormat::MachO => {
+                let symbol = product.object.symbol_mut(symbol_id);
+                let n_desc = match symbol.flags {
+                    SymbolFlags::MachO { n_desc } => n_desc,
+                    _ => 0,
+                };
+                symbol.flags = SymbolFlags::MachO { n_desc: n_desc | macho::N_NO_DEAD_STRIP };
+            }
+            // `/OPT:REF` only removes COMDAT sections, which statics are never emitted into.
+            _ => {}
+        }
+    }
+}
+
+/// Define the functions with `#[link_section]` in the requested section. They are only declared
+/// in the module, so this adds their code together with its relocations.
+fn define_functions_in_sections(
+    product: &mut ObjectProduct,
+    functions_in_sections: &[FunctionInSection],
+) -> Result<(), String> {
+    use cranelift_codegen::binemit::Reloc;
+    use cranelift_object::object::write::{Relocation, Symbol, SymbolSection};
+    use cranelift_object::object::{
+        elf, BinaryFormat, RelocationEncoding, RelocationKind, SectionKind, SymbolFlags, SymbolKind,
+        SymbolScope,
+    };
+
+    if functions_in_sections.is_empty() {
+        return Ok(());
+    }
+    if product.object.format() != BinaryFormat::Elf {
+        return Err("#[link_section] on functions is only supported for ELF targets".to_owned());
+    }
+
+    let libcall_names = cranelift_module::default_libcall_names();
+    let mut sections = FxHashMap::default();
+    for function in functions_in_sections {
+        let symbol_id = product.function_symbol(function.func_id);
+        let section = *sections.entry(&function.section).or_insert_with(|| {
+            product.object.add_section(
+                Vec::new(),
+                function.section.as_bytes().to_vec(),
+                SectionKind::Text,
+            )
+        });
+        // Functions sharing a section are appended one after another.
+        let offset =
+            product.object.add_symbol_data(symbol_id, section, &function.code, function.alignment);
+
+        for reloc in &function.relocs {
+            // Mirrors the relocation handling of cranelift-object for ELF.
+            let (kind, encoding, size) = match reloc.kind {
+                Reloc::Abs4 => (RelocationKind::Absolute, RelocationEncoding::Generic, 32),
+                Reloc::Abs8 => (RelocationKind::Absolute, RelocationEncoding::Generic, 64),
+                Reloc::X86PCRel4 => (RelocationKind::Relative, RelocationEncoding::Generic, 32),
+                Reloc::X86CallPCRel4 => {
+                    (RelocationKind::Relative, RelocationEncoding::X86Branch, 32)
+                }
+                Reloc::X86CallPLTRel4 => {
+                    (RelocationKind::PltRelative, RelocationEncoding::X86Branch, 32)
+                }
+                Reloc::X86GOTPCRel4 => {
+                    (RelocationKind::GotRelative, RelocationEncoding::Generic, 32)
+                }
+                Reloc::Arm64Call => (RelocationKind::Relative, RelocationEncoding::AArch64Call, 26),
+                Reloc::Aarch64AdrGotPage21 => (
+                    RelocationKind::Elf(elf::R_AARCH64_ADR_GOT_PAGE),
+                    RelocationEncoding::Generic,
+                    21,
+                ),
+                Reloc::Aarch64Ld64GotLo12Nc => (
+                    RelocationKind::Elf(elf::R_AARCH64_LD64_GOT_LO12_NC),
+                    RelocationEncoding::Generic,
+                    12,
+                ),
+                Reloc::Aarch64TlsGdAdrPage21 => (
+                    RelocationKind::Elf(elf::R_AARCH64_TLSGD_ADR_PAGE21),
+                    RelocationEncoding::Generic,
+                    21,
+                ),
+                Reloc::Aarch64TlsGdAddLo12Nc => (
+                    RelocationKind::Elf(elf::R_AARCH64_TLSGD_ADD_LO12_NC),
+                    RelocationEncoding::Generic,
+                    12,
+                ),
+                kind => {
+                    return Err(format!(
+                        "relocation {kind:?} is not supported in functions with #[link_section]"
+                    ));
+                }
+            };
+            let (symbol, addend) = match reloc.target {
+                FunctionRelocTarget::Func(func_id) => {
+                    (product.function_symbol(func_id), reloc.addend)
+                }
+                FunctionRelocTarget::Data(data_id) => (product.data_symbol(data_id), reloc.addend),
+                FunctionRelocTarget::LibCall(libcall) => {
+                    let name = libcall_names(libcall);
+                    // Declared like cranelift-object does for libcalls of other functions.
+                    let symbol = product.object.symbol_id(name.as_bytes()).unwrap_or_else(|| {
+                        product.object.add_symbol(Symbol {
+                            name: name.into_bytes(),
+                            value: 0,
+                            size: 0,
+                            kind: SymbolKind::Text,
+                            scope: SymbolScope::Unknown,
+                            weak: false,
+                            section: SymbolSection::Undefined,
+                            flags: SymbolFlags::None,
+                        })
+                    });
+                    (symbol, reloc.addend)
+                }
+                FunctionRelocTarget::FunctionOffset(func_offset) => {
+                    (symbol_id, reloc.addend + i64::from(func_offset))
+                }
+            };
+            product
+                .object
+                .add_relocation(
+                    section,
+                    Relocation {
+                        offset: offset + u64::from(reloc.offset),
+                        size,
+                        kind,
+                        encoding,
+                        symbol,
+                        addend,
+                    },
+                )
+                .map_err(|err| format!("failed to add relocation: {err}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the size of all sections in the object file of a codegen unit grouped by what they
+/// contain.
+fn print_size_report(cgu_name: &str, object_file: &Path) -> Result<(), String> {
+    use object::{Object, ObjectSection, SectionKind};
+
+    let data = match std::fs::read(object_file) {
+        Ok(data) => data,
+        Err(err) => return Err(format!("error reading object file for size report: {}", err)),
+    };
+    let object = match object::File::parse(&*data) {
+        Ok(object) => object,
+        Err(err) => return Err(format!("error parsing object file for size report: {}", err)),
+    };
+
+    let (mut code, mut rodata, mut debug, mut unwind, mut other) = (0, 0, 0, 0, 0);
+    for section in object.sections() {
+        let name = section.name().unwrap_or("");
+        let size = section.size();
+        if matches!(name, ".eh_frame" | "__eh_frame" | ".pdata" | ".xdata") {
+            unwind += size;
+        } else if section.kind() == SectionKind::Debug
+            || name.starts_with(".debug")
+            || name.starts_with("__debug")
+        {
+            debug += size;
+        } else {
+            match section.kind() {
+                SectionKind::Text => code += size,
+                SectionKind::ReadOnlyData
+                | SectionKind::ReadOnlyDataWithRel
+                | SectionKind::ReadOnlyString => rodata += size,
+                _ => other += size,
+            }
+        }
+    }
+
+    eprintln!(
+        "size report for {cgu_name}: code={code} rodata={rodata} debug={debug} unwind={unwind} \
+        other={other}"
+    );
+
+    Ok(())
+}
+
 fn emit_module(
     output_filenames: &OutputFilenames,
     prof: &SelfProfilerRef,
@@ -269,19 +695,24 @@ fn reuse_workproduct_for_cgu(
 
 fn module_codegen(
     tcx: TyCtxt<'_>,
-    (backend_config, global_asm_config, cgu_name, token): (
+    (backend_config, global_asm_config, object_cache, profile_use, cgu_name, token): (
         BackendConfig,
         Arc<GlobalAsmConfig>,
+        Option<Arc<ObjectCache>>,
+        Option<Arc<ProfileUse>>,
         rustc_span::Symbol,
         ConcurrencyLimiterToken,
     ),
 ) -> OngoingModuleCodegen {
-    let (cgu_name, mut cx, mut module, codegened_functions) =
+    let (cgu_name, mut cx, mut module, codegened_functions, used_statics) =
         tcx.prof.generic_activity_with_arg("codegen cgu", cgu_name.as_str()).run(|| {
             let cgu = tcx.codegen_unit(cgu_name);
             let mono_items = cgu.items_in_deterministic_order(tcx);
 
-            let mut module = make_module(tcx.sess, &backend_config, cgu_name.as_str().to_string());
+            let mut module = HashingModule::new(
+                make_module(tcx.sess, &backend_config, cgu_name.as_str().to_string()),
+                object_cache.as_deref(),
+            );
 
             let mut cx = crate::CodegenCx::new(
                 tcx,
@@ -290,14 +721,33 @@ fn module_codegen(
                 tcx.sess.opts.debuginfo != DebugInfo::None,
                 cgu_name,
             );
+            cx.profile_use = profile_use;
+            let mut type_dbg = TypeDebugContext::default();
             super::predefine_mono_items(tcx, &mut module, &mono_items);
             let mut codegened_functions = vec![];
-            for (mono_item, _) in mono_items {
+            let mut used_statics = vec![];
+            for (mono_item, data) in mono_items {
                 match mono_item {
+                    MonoItem::Fn(inst) if super::is_naked(tcx, inst) => {
+                        let linkage = crate::linkage::get_clif_linkage(
+                            tcx,
+                            mono_item,
+                            data.linkage,
+                            data.visibility,
+                            tcx.is_compiler_builtins(LOCAL_CRATE),
+                        );
+                        crate::global_asm::codegen_naked_asm(
+                            tcx,
+                            &mut cx.global_asm,
+                            inst,
+                            linkage,
+                        );
+                    }
                     MonoItem::Fn(inst) => {
                         let codegened_function = crate::base::codegen_fn(
                             tcx,
                             &mut cx,
+                            &mut type_dbg,
                             Function::new(),
                             &mut module,
                             inst,
@@ -305,7 +755,12 @@ fn module_codegen(
                         codegened_functions.push(codegened_function);
                     }
                     MonoItem::Static(def_id) => {
-                        crate::constant::codegen_static(tcx, &mut module, def_id)
+                        let data_id = crate::constant::codegen_static(tcx, &mut module, def_id);
+                        // `#[used(compiler)]` needs no handling as all statics are emitted anyway.
+                        let flags = tcx.codegen_fn_attrs(def_id).flags;
+                        if flags.contains(CodegenFnAttrFlags::USED_LINKER) {
+                            used_statics.push(data_id);
+                        }
                     }
                     MonoItem::GlobalAsm(item_id) => {
                         crate::global_asm::codegen_global_asm_item(
@@ -323,15 +778,48 @@ fn module_codegen(
                 false,
                 cgu.is_primary(),
             );
+            if let Some(coverage_context) = cx.coverage_context.take() {
+                used_statics.extend(coverage_context.finalize(&mut module, &mut cx.unwind_context));
+            }
 
             let cgu_name = cgu.name().as_str().to_owned();
 
-            (cgu_name, cx, module, codegened_functions)
+            (cgu_name, cx, module, codegened_functions, used_statics)
         });
 
     let producer = crate::debuginfo::producer(tcx.sess);
 
-    OngoingModuleCodegen::Async(std::thread::spawn(move || {
+    let single_threaded = backend_config.single_threaded;
+    let compile = move || -> Result<ModuleCodegenResult, String> {
+        let object_cache_key = object_cache
+            .as_ref()
+            .map(|object_cache| {
+                object_cache.key(&mut module, &cgu_name, &codegened_functions, &used_statics)
+            })
+            // Global asm is assembled by an external assembler, which isn't covered by the key.
+            .filter(|_| cx.global_asm.is_empty());
+        if let (Some(object_cache), Some(key)) = (&object_cache, &object_cache_key) {
+            if let Some(object) =
+                object_cache.lookup(key, &global_asm_config.output_filenames, &cgu_name)
+            {
+                std::mem::drop(token);
+                if backend_config.size_report {
+                    print_size_report(&cgu_name, &object)?;
+                }
+                return Ok(ModuleCodegenResult {
+                    module_regular: CompiledModule {
+                        name: cgu_name,
+                        kind: ModuleKind::Regular,
+                        object: Some(object),
+                        dwarf_object: None,
+                        bytecode: None,
+                    },
+                    module_global_asm: None,
+                    existing_work_product: None,
+                });
+            }
+        }
+
         cx.profiler.clone().generic_activity_with_arg("compile functions", &*cgu_name).run(|| {
             cranelift_codegen::timing::set_thread_profiler(Box::new(super::MeasuremeProfiler(
                 cx.profiler.clone(),
@@ -348,22 +836,65 @@ fn module_codegen(
                 crate::global_asm::compile_global_asm(&global_asm_config, &cgu_name, &cx.global_asm)
             })?;
 
+        if let Some(module_asm) = &mut cx.module_asm {
+            // FIXME emit the data objects too, such that the output can be assembled again. For
+            // now it is only meant for reading.
+            module_asm.push_str(&cx.global_asm);
+            let asm_file =
+                global_asm_config.output_filenames.temp_path(OutputType::Assembly, Some(&cgu_name));
+            if let Err(err) = std::fs::write(&asm_file, module_asm) {
+                return Err(format!("error writing assembly file {}: {err}", asm_file.display()));
+            }
+        }
+
         let codegen_result =
             cx.profiler.generic_activity_with_arg("write object file", &*cgu_name).run(|| {
                 emit_cgu(
                     &global_asm_config.output_filenames,
                     &cx.profiler,
-                    cgu_name,
-                    module,
+                    cgu_name.clone(),
+                    module.into_inner(),
                     cx.debug_context,
                     cx.unwind_context,
+                    &used_statics,
+                    cx.functions_in_sections.as_deref().unwrap(),
                     global_asm_object_file,
                     &producer,
+                    backend_config.size_report,
                 )
             });
         std::mem::drop(token);
+
+        if let (Some(object_cache), Some(key), Ok(result)) =
+            (&object_cache, &object_cache_key, &codegen_result)
+        {
+            object_cache.store(key, result.module_regular.object.as_ref().unwrap());
+        }
+
+        if let (Some(dir), Some(module_clif), Ok(result)) =
+            (&backend_config.dump_module, &cx.module_clif, &codegen_result)
+        {
+            crate::pretty_clif::dump_module(
+                dir,
+                &cgu_name,
+                module_clif,
+                result.module_regular.object.as_ref().unwrap(),
+            )?;
+        }
+
         codegen_result
-    }))
+    };
+
+    if single_threaded {
+        OngoingModuleCodegen::Sync(compile())
+    } else {
+        OngoingModuleCodegen::Async(
+            std::thread::Builder::new()
+                .name("cgclif-codegen".to_owned())
+                .spawn(compile)
+                .expect("failed to spawn codegen thread"),
+        )
+    }
 }
 
 pub(crate) fn run_aot(
@@ -388,6 +919,7 @@ pub(crate) fn run_aot(
             modules: vec![],
             allocator_module: None,
             metadata_module: None,
+            embedded_metadata_module: None,
             metadata,
             crate_info: CrateInfo::new(tcx, target_cpu),
             concurrency_limiter: ConcurrencyLimiter::new(tcx.sess, 0),
@@ -402,7 +934,16 @@ pub(crate) fn run_aot(
 
     // Calculate the CGU reuse
     let cgu_reuse = tcx.sess.time("find_cgu_reuse", || {
-        cgus.iter().map(|cgu| determine_cgu_reuse(tcx, &cgu)).collect::<Vec<_>>()
+        cgus.iter()
+            .map(|cgu| {
+                // Report the actual reuse to `assert_module_sources` when the cache is disabled.
+                if backend_config.disable_incr_cache {
+                    CguReuse::No
+                } else {
+                    determine_cgu_reuse(tcx, &cgu)
+                }
+            })
+            .collect::<Vec<_>>()
     });
 
     rustc_codegen_ssa::assert_module_sources::assert_module_sources(tcx, &|cgu_reuse_tracker| {
@@ -414,43 +955,52 @@ pub(crate) fn run_aot(
 
     let global_asm_config = Arc::new(crate::global_asm::GlobalAsmConfig::new(tcx));
 
-    let mut concurrency_limiter = ConcurrencyLimiter::new(tcx.sess, cgus.len());
+    let object_cache = ObjectCache::new(tcx, &backend_config);
+    let profile_use = ProfileUse::load(tcx.sess);
+
+    let mut concurrency_limiter = if backend_config.single_threaded {
+        ConcurrencyLimiter::new_single_threaded(cgus.len())
+    } else {
+        ConcurrencyLimiter::new(tcx.sess, cgus.len())
+    };
 
     let modules = tcx.sess.time("codegen mono items", || {
         cgus.iter()
             .enumerate()
-            .map(|(i, cgu)| {
-                let cgu_reuse =
-                    if backend_config.disable_incr_cache { CguReuse::No } else { cgu_reuse[i] };
-                match cgu_reuse {
-                    CguReuse::No => {
-                        let dep_node = cgu.codegen_dep_node(tcx);
-                        tcx.dep_graph
-                            .with_task(
-                                dep_node,
-                                tcx,
-                                (
-                                    backend_config.clone(),
-                                    global_asm_config.clone(),
-                                    cgu.name(),
-                                    concurrency_limiter.acquire(tcx.dcx()),
-                                ),
-                                module_codegen,
-                                Some(rustc_middle::dep_graph::hash_result),
-                            )
-                            .0
-                    }
-                    CguReuse::PreLto | CguReuse::PostLto => {
-                        concurrency_limiter.job_already_done();
-                        OngoingModuleCodegen::Sync(reuse_workproduct_for_cgu(tcx, cgu))
-                    }
+            .map(|(i, cgu)| match cgu_reuse[i] {
+                CguReuse::No => {
+                    let dep_node = cgu.codegen_dep_node(tcx);
+                    tcx.dep_graph
+                        .with_task(
+                            dep_node,
+                            tcx,
+                            (
+                                backend_config.clone(),
+                                global_asm_config.clone(),
+                                object_cache.clone(),
+                                profile_use.clone(),
+                                cgu.name(),
+                                concurrency_limiter.acquire(tcx.dcx()),
+                            ),
+                            module_codegen,
+                            Some(rustc_middle::dep_graph::hash_result),
+                        )
+                        .0
+                }
+                CguReuse::PreLto | CguReuse::PostLto => {
+                    concurrency_limiter.job_already_done();
+                    OngoingModuleCodegen::Sync(reuse_workproduct_for_cgu(tcx, cgu))
                 }
             })
             .collect::<Vec<_>>()
     });
 
     let mut allocator_module = make_module(tcx.sess, &backend_config, "allocator_shim".to_string());
-    let mut allocator_unwind_context = UnwindContext::new(allocator_module.isa(), true);
+    let mut allocator_unwind_context = UnwindContext::new(
+        allocator_module.isa(),
+        allocator_module.isa().flags().is_pic(),
+        tcx.sess.must_emit_unwind_tables(),
+    );
     let created_alloc_shim =
         crate::allocator::codegen(tcx, &mut allocator_module, &mut allocator_unwind_context);
 
@@ -473,6 +1023,14 @@ pub(crate) fn run_aot(
         None
     };
 
+    // Dylibs and proc macros already get the metadata in a `.rustc` section through the metadata
+    // module. The linker would concatenate both sections and corrupt the metadata.
+    let embedded_metadata_module = if backend_config.embed_metadata && !need_metadata_module {
+        Some(emit_embedded_metadata_module(tcx, &backend_config, &metadata))
+    } else {
+        None
+    };
+
     let metadata_module = if need_metadata_module {
         let (metadata_cgu_name, tmp_file) = tcx.sess.time("write compressed metadata", || {
             use rustc_middle::mir::mono::CodegenUnitNameBuilder;
@@ -511,8 +1069,51 @@ pub(crate) fn run_aot(
         modules,
         allocator_module,
         metadata_module,
+        embedded_metadata_module,
         metadata,
         crate_info: CrateInfo::new(tcx, target_cpu),
         concurrency_limiter,
     })
 }
+
+/// Emit an object file containing the crate metadata in a `.rustc` section for
+/// `-Cllvm-args=embed_metadata=true`. Unlike the metadata module rustc requests for dylibs this is
+/// a regular module, so it is included in rlibs and executables too. Not used when the metadata
+/// module is emitted.
+fn emit_embedded_metadata_module(
+    tcx: TyCtxt<'_>,
+    backend_config: &BackendConfig,
+    metadata: &EncodedMetadata,
+) -> CompiledModule {
+    use cranelift_object::object::write::SectionFlags;
+    use cranelift_object::object::{elf, BinaryFormat, SectionKind};
+    use rustc_middle::mir::mono::CodegenUnitNameBuilder;
+
+    let name = CodegenUnitNameBuilder::new(tcx)
+        .build_cgu_name(LOCAL_CRATE, ["crate"], Some("embedded_metadata"))
+        .to_string();
+
+    let mut product = make_module(tcx.sess, backend_config, name.clone()).finish();
+    if product.object.format() != BinaryFormat::Elf {
+        tcx.dcx().fatal("`-Cllvm-args=embed_metadata` is only supported for ELF targets");
+    }
+
+    // Without `SHF_ALLOC` the section isn't loaded at runtime, while `SHF_GNU_RETAIN` keeps the
+    // linker from garbage collecting it.
+    let section = product.object.add_section(Vec::new(), b".rustc".to_vec(), SectionKind::Metadata);
+    product.object.section_mut(section).flags =
+        SectionFlags::Elf { sh_flags: u64::from(elf::SHF_GNU_RETAIN) };
+    product.object.set_section_data(section, metadata.raw_data().to_vec(), 1);
+
+    match emit_module(
+        tcx.output_filenames(()),
+        &tcx.sess.prof,
+        product.object,
+        ModuleKind::Regular,
+        name,
+        &crate::debuginfo::producer(tcx.sess),
+    ) {
+        Ok(module) => module,
+        Err(err) => tcx.dcx().fatal(err),
+    }
+}
@@ -12,6 +12,7 @@
 use rustc_session::Session;
 use rustc_span::Symbol;
 
+use crate::debuginfo::TypeDebugContext;
 use crate::{prelude::*, BackendConfig};
 use crate::{CodegenCx, CodegenMode};
 
@@ -121,6 +122,12 @@ pub(crate) fn run_jit(tcx: TyCtxt<'_>, backend_config: BackendConfig) -> ! {
         super::predefine_mono_items(tcx, &mut jit_module, &mono_items);
         for (mono_item, _) in mono_items {
             match mono_item {
+                MonoItem::Fn(inst) if super::is_naked(tcx, inst) => {
+                    tcx.dcx().span_fatal(
+                        tcx.def_span(inst.def_id()),
+                        "Naked functions are not supported in JIT mode",
+                    );
+                }
                 MonoItem::Fn(inst) => match backend_config.codegen_mode {
                     CodegenMode::Aot => unreachable!(),
                     CodegenMode::Jit => {
@@ -153,6 +160,11 @@ pub(crate) fn run_jit(tcx: TyCtxt<'_>, backend_config: BackendConfig) -> ! {
 
     tcx.dcx().abort_if_errors();
 
+    // Rustc prints the type sizes after codegen, but running the jitted program never returns.
+    if tcx.sess.opts.unstable_opts.print_type_sizes {
+        tcx.sess.code_stats.print_type_sizes();
+    }
+
     jit_module.finalize_definitions().unwrap();
     unsafe { cx.unwind_context.register_jit(&jit_module) };
 
@@ -229,7 +241,10 @@ pub(crate) fn codegen_and_compile_fn<'tcx>(
             crate::PrintOnPanic(|| format!("{:?} {}", instance, tcx.symbol_name(instance).name));
 
         let cached_func = std::mem::replace(&mut cached_context.func, Function::new());
-        let codegened_func = crate::base::codegen_fn(tcx, cx, cached_func, module, instance);
+        // The JIT doesn't emit debuginfo, so type debuginfo doesn't need to be shared.
+        let mut type_dbg = TypeDebugContext::default();
+        let codegened_func =
+            crate::base::codegen_fn(tcx, cx, &mut type_dbg, cached_func, module, instance);
 
         crate::base::compile_fn(cx, cached_context, module, codegened_func);
     });
@@ -4,7 +4,9 @@
 //! [`codegen_fn`]: crate::base::codegen_fn
 //! [`codegen_static`]: crate::constant::codegen_static
 
+use rustc_data_structures::fx::FxHashSet;
 use rustc_data_structures::profiling::SelfProfilerRef;
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
 use rustc_middle::mir::mono::{MonoItem, MonoItemData};
 
 use crate::prelude::*;
@@ -20,6 +22,14 @@ fn predefine_mono_items<'tcx>(
 ) {
     tcx.prof.generic_activity("predefine functions").run(|| {
         let is_compiler_builtins = tcx.is_compiler_builtins(LOCAL_CRATE);
+        let global_asm_sym_fns = mono_items
+            .iter()
+            .filter_map(|&(mono_item, _)| match mono_item {
+                MonoItem::GlobalAsm(item_id) => Some(item_id),
+                _ => None,
+            })
+            .flat_map(|item_id| crate::global_asm::global_asm_sym_fns(tcx, item_id))
+            .collect::<FxHashSet<_>>();
         for &(mono_item, data) in mono_items {
             match mono_item {
                 MonoItem::Fn(instance) => {
@@ -27,12 +37,26 @@ fn predefine_mono_items<'tcx>(
                     let _inst_guard = crate::PrintOnPanic(|| format!("{:?} {}", instance, name));
                     let sig =
                         get_function_sig(tcx, module.target_config().default_call_conv, instance);
-                    let linkage = crate::linkage::get_clif_linkage(
-                        mono_item,
-                        data.linkage,
-                        data.visibility,
-                        is_compiler_builtins,
-                    );
+                    let linkage = if is_naked(tcx, instance) {
+                        // The body of naked functions is emitted as global asm.
+                        Linkage::Import
+                    } else {
+                        match crate::linkage::get_clif_linkage(
+                            tcx,
+                            mono_item,
+                            data.linkage,
+                            data.visibility,
+                            is_compiler_builtins,
+                        ) {
+                            // Global asm is assembled into a separate object file, so functions
+                            // it references must be visible outside of the object file of the
+                            // codegen unit.
+                            Linkage::Local if global_asm_sym_fns.contains(&instance) => {
+                                Linkage::Hidden
+                            }
+                            linkage => linkage,
+                        }
+                    };
                     module.declare_function(name, linkage, &sig).unwrap();
                 }
                 MonoItem::Static(_) | MonoItem::GlobalAsm(_) => {}
@@ -41,6 +65,10 @@ fn predefine_mono_items<'tcx>(
     });
 }
 
+fn is_naked<'tcx>(tcx: TyCtxt<'tcx>, instance: Instance<'tcx>) -> bool {
+    tcx.codegen_fn_attrs(instance.def_id()).flags.contains(CodegenFnAttrFlags::NAKED)
+}
+
 struct MeasuremeProfiler(SelfProfilerRef);
 
 struct TimingGuard {
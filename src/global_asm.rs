@@ -1,6 +1,7 @@
 //! The AOT driver uses [`cranelift_object`] to write object files suitable for linking into a
 //! standalone executable.
 
+use std::fmt::Write as _;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
@@ -10,6 +11,7 @@
 use rustc_hir::{InlineAsmOperand, ItemId};
 use rustc_session::config::{OutputFilenames, OutputType};
 use rustc_target::asm::InlineAsmArch;
+use target_lexicon::BinaryFormat;
 
 use crate::prelude::*;
 
@@ -53,15 +55,10 @@ pub(crate) fn codegen_global_asm_item(tcx: TyCtxt<'_>, global_asm: &mut String,
                                 );
                             }
 
-                            let ty = tcx.typeck_body(anon_const.body).node_type(anon_const.hir_id);
-                            let instance = match ty.kind() {
-                                &ty::FnDef(def_id, args) => Instance::new(def_id, args),
-                                _ => span_bug!(op_sp, "asm sym is not a function"),
-                            };
-                            let symbol = tcx.symbol_name(instance);
-                            // FIXME handle the case where the function was made private to the
-                            // current codegen unit
-                            global_asm.push_str(symbol.name);
+                            // `predefine_mono_items` ensures the function is visible to the
+                            // global asm object file even if it is local to the codegen unit.
+                            let instance = sym_fn_instance(tcx, &anon_const, op_sp);
+                            global_asm.push_str(&asm_symbol_name(tcx, instance));
                         }
                         InlineAsmOperand::SymStatic { path: _, def_id } => {
                             if cfg!(not(feature = "inline_asm_sym")) {
@@ -72,8 +69,7 @@ pub(crate) fn codegen_global_asm_item(tcx: TyCtxt<'_>, global_asm: &mut String,
                             }
 
                             let instance = Instance::mono(tcx, def_id).polymorphize(tcx);
-                            let symbol = tcx.symbol_name(instance);
-                            global_asm.push_str(symbol.name);
+                            global_asm.push_str(&asm_symbol_name(tcx, instance));
                         }
                         InlineAsmOperand::In { .. }
                         | InlineAsmOperand::Out { .. }
@@ -95,6 +91,193 @@ pub(crate) fn codegen_global_asm_item(tcx: TyCtxt<'_>, global_asm: &mut String,
     }
 }
 
+fn sym_fn_instance<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    anon_const: &rustc_hir::AnonConst,
+    span: Span,
+) -> Instance<'tcx> {
+    let ty = tcx.typeck_body(anon_const.body).node_type(anon_const.hir_id);
+    match ty.kind() {
+        &ty::FnDef(def_id, args) => Instance::new(def_id, args),
+        _ => span_bug!(span, "asm sym is not a function"),
+    }
+}
+
+/// All functions referenced by `sym` operands of the given `global_asm!`.
+pub(crate) fn global_asm_sym_fns(tcx: TyCtxt<'_>, item_id: ItemId) -> Vec<Instance<'_>> {
+    let item = tcx.hir().item(item_id);
+    let rustc_hir::ItemKind::GlobalAsm(asm) = item.kind else {
+        bug!("Expected GlobalAsm found {:?}", item);
+    };
+    asm.operands
+        .iter()
+        .filter_map(|&(ref operand, op_sp)| match *operand {
+            InlineAsmOperand::SymFn { ref anon_const } => {
+                Some(sym_fn_instance(tcx, anon_const, op_sp))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The name of the symbol as it has to be written in assembly. On Mach-O all C symbols are
+/// prefixed with an underscore.
+fn asm_symbol_name<'tcx>(tcx: TyCtxt<'tcx>, instance: Instance<'tcx>) -> String {
+    let name = tcx.symbol_name(instance).name;
+    if tcx.sess.target.is_like_osx { format!("_{name}") } else { name.to_owned() }
+}
+
+/// Naked functions are emitted as global asm consisting of the symbol definition followed by the
+/// body of the single `asm!` block of the function. No prologue, epilogue or return is added.
+///
+/// As the global asm ends up in a separate object file, the symbol has to be visible to the object
+/// file of the Cranelift module, which declares the function as imported. Local functions are made
+/// hidden weak definitions (a discardable COMDAT on COFF) instead of local symbols. They may be
+/// instantiated in multiple codegen units with the same symbol name, so making them global would
+/// cause duplicate definitions.
+pub(crate) fn codegen_naked_asm<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    global_asm: &mut String,
+    instance: Instance<'tcx>,
+    linkage: Linkage,
+) {
+    let mir = tcx.instance_mir(instance.def);
+    let terminator = mir.basic_blocks[START_BLOCK].terminator();
+    let TerminatorKind::InlineAsm { template, ref operands, options, .. } = terminator.kind else {
+        span_bug!(terminator.source_info.span, "naked function doesn't consist of a single asm!");
+    };
+    let span = terminator.source_info.span;
+
+    let is_x86 = matches!(tcx.sess.asm_arch.unwrap(), InlineAsmArch::X86 | InlineAsmArch::X86_64);
+    let binary_format = crate::target_triple(tcx.sess).binary_format;
+    let name = tcx.symbol_name(instance).name;
+
+    match binary_format {
+        BinaryFormat::Elf => {
+            writeln!(global_asm, ".pushsection .text.{name},\"ax\",@progbits").unwrap();
+            writeln!(global_asm, ".balign 4").unwrap();
+            match linkage {
+                Linkage::Preemptible => writeln!(global_asm, ".weak {name}").unwrap(),
+                Linkage::Export => writeln!(global_asm, ".globl {name}").unwrap(),
+                Linkage::Local => writeln!(global_asm, ".weak {name}\n.hidden {name}").unwrap(),
+                _ => writeln!(global_asm, ".globl {name}\n.hidden {name}").unwrap(),
+            }
+            writeln!(global_asm, ".type {name},@function").unwrap();
+            writeln!(global_asm, "{name}:").unwrap();
+        }
+        BinaryFormat::Macho => {
+            writeln!(global_asm, ".pushsection __TEXT,__text,regular,pure_instructions").unwrap();
+            writeln!(global_asm, ".balign 4").unwrap();
+            match linkage {
+                Linkage::Preemptible => writeln!(global_asm, ".weak_definition _{name}").unwrap(),
+                Linkage::Export => writeln!(global_asm, ".globl _{name}").unwrap(),
+                Linkage::Local => {
+                    writeln!(global_asm, ".weak_definition _{name}\n.private_extern _{name}")
+                        .unwrap()
+                }
+                _ => writeln!(global_asm, ".globl _{name}\n.private_extern _{name}").unwrap(),
+            }
+            writeln!(global_asm, "_{name}:").unwrap();
+        }
+        BinaryFormat::Coff => {
+            writeln!(global_asm, ".pushsection .text${name},\"xr\"").unwrap();
+            if linkage == Linkage::Local {
+                writeln!(global_asm, ".linkonce discard").unwrap();
+            }
+            writeln!(global_asm, ".balign 4").unwrap();
+            writeln!(global_asm, ".globl {name}").unwrap();
+            writeln!(global_asm, ".def {name}\n.scl 2\n.type 32\n.endef").unwrap();
+            writeln!(global_asm, "{name}:").unwrap();
+        }
+        _ => tcx.dcx().span_fatal(
+            span,
+            format!("Unsupported binary format for naked functions: {binary_format:?}"),
+        ),
+    }
+
+    if is_x86 {
+        if !options.contains(InlineAsmOptions::ATT_SYNTAX) {
+            global_asm.push_str(".intel_syntax noprefix\n");
+        } else {
+            global_asm.push_str(".att_syntax\n");
+        }
+    }
+    for piece in template {
+        match *piece {
+            InlineAsmTemplatePiece::String(ref s) => global_asm.push_str(s),
+            InlineAsmTemplatePiece::Placeholder { operand_idx, modifier: _, span: op_sp } => {
+                match operands[operand_idx] {
+                    mir::InlineAsmOperand::Const { ref value } => {
+                        let const_ = instance.instantiate_mir_and_normalize_erasing_regions(
+                            tcx,
+                            ParamEnv::reveal_all(),
+                            ty::EarlyBinder::bind(value.const_),
+                        );
+                        let const_value = const_
+                            .eval(tcx, ParamEnv::reveal_all(), Some(value.span))
+                            .unwrap_or_else(|_| span_bug!(op_sp, "asm const cannot be resolved"));
+                        let string = rustc_codegen_ssa::common::asm_const_to_str(
+                            tcx,
+                            op_sp,
+                            const_value,
+                            RevealAllLayoutCx(tcx).layout_of(const_.ty()),
+                        );
+                        global_asm.push_str(&string);
+                    }
+                    mir::InlineAsmOperand::SymFn { ref value } => {
+                        if cfg!(not(feature = "inline_asm_sym")) {
+                            tcx.dcx().span_err(
+                                span,
+                                "asm! and global_asm! sym operands are not yet supported",
+                            );
+                        }
+
+                        let const_ = instance.instantiate_mir_and_normalize_erasing_regions(
+                            tcx,
+                            ParamEnv::reveal_all(),
+                            ty::EarlyBinder::bind(value.const_),
+                        );
+                        let &ty::FnDef(def_id, args) = const_.ty().kind() else {
+                            span_bug!(op_sp, "asm sym is not a function");
+                        };
+                        let instance =
+                            Instance::resolve_for_fn_ptr(tcx, ParamEnv::reveal_all(), def_id, args)
+                                .unwrap();
+                        // FIXME handle the case where the function was made private to the
+                        // current codegen unit
+                        global_asm.push_str(&asm_symbol_name(tcx, instance));
+                    }
+                    mir::InlineAsmOperand::SymStatic { def_id } => {
+                        if cfg!(not(feature = "inline_asm_sym")) {
+                            tcx.dcx().span_err(
+                                span,
+                                "asm! and global_asm! sym operands are not yet supported",
+                            );
+                        }
+
+                        let instance = Instance::mono(tcx, def_id).polymorphize(tcx);
+                        global_asm.push_str(&asm_symbol_name(tcx, instance));
+                    }
+                    mir::InlineAsmOperand::In { .. }
+                    | mir::InlineAsmOperand::Out { .. }
+                    | mir::InlineAsmOperand::InOut { .. } => {
+                        span_bug!(op_sp, "invalid operand type for naked function asm!")
+                    }
+                }
+            }
+        }
+    }
+    global_asm.push('\n');
+    if is_x86 {
+        global_asm.push_str(".att_syntax\n");
+    }
+
+    if binary_format == BinaryFormat::Elf {
+        writeln!(global_asm, ".size {name}, . - {name}").unwrap();
+    }
+    global_asm.push_str(".popsection\n\n");
+}
+
 #[derive(Debug)]
 pub(crate) struct GlobalAsmConfig {
     assembler: PathBuf,
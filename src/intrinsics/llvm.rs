@@ -50,9 +50,19 @@ pub(crate) fn codegen_llvm_intrinsic_call<'tcx>(
         _ if intrinsic.starts_with("llvm.ctpop.v") => {
             intrinsic_args!(fx, args => (a); intrinsic);
 
-            simd_for_each_lane(fx, a, ret, &|fx, _lane_ty, _res_lane_ty, lane| {
-                fx.bcx.ins().popcnt(lane)
-            });
+            // Cranelift lowers a popcnt of a whole vector to a single `cnt` on AArch64. This is
+            // only supported for 8bit lanes, so other vectors are counted lane by lane.
+            if fx.tcx.sess.target.arch == "aarch64"
+                && matches!(a.layout().abi, Abi::Vector { .. })
+                && clif_vector_type(fx.tcx, a.layout()) == types::I8X16
+            {
+                let res = fx.bcx.ins().popcnt(a.load_scalar(fx));
+                ret.write_cvalue(fx, CValue::by_val(res, a.layout()));
+            } else {
+                simd_for_each_lane(fx, a, ret, &|fx, _lane_ty, _res_lane_ty, lane| {
+                    fx.bcx.ins().popcnt(lane)
+                });
+            }
         }
 
         _ if intrinsic.starts_with("llvm.fma.v") => {
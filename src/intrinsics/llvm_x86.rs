@@ -16,6 +16,23 @@ pub(crate) fn codegen_x86_llvm_intrinsic_call<'tcx>(
     target: Option<BasicBlock>,
     span: Span,
 ) {
+    // `pause` is executed as `nop` by cpus without SSE2, so `core::hint::spin_loop` uses it even
+    // when SSE2 is disabled.
+    if let Some(&(_, feature)) = INTRINSIC_TARGET_FEATURES
+        .iter()
+        .find(|&&(prefix, _)| intrinsic.starts_with(prefix) && intrinsic != "llvm.x86.sse2.pause")
+    {
+        if !target_feature_enabled(fx, feature) {
+            fx.tcx.dcx().span_err(
+                span,
+                format!(
+                    "`{intrinsic}` requires the `{feature}` target feature, which is not enabled \
+                    for this function"
+                ),
+            );
+        }
+    }
+
     match intrinsic {
         "llvm.x86.sse2.pause" | "llvm.aarch64.isb" => {
             // Spin loop hint
@@ -199,11 +216,15 @@ pub(crate) fn codegen_x86_llvm_intrinsic_call<'tcx>(
                 _CMP_EQ_UQ | _CMP_EQ_US => FloatCC::UnorderedOrEqual,
                 _CMP_NGE_US | _CMP_NGE_UQ => FloatCC::UnorderedOrLessThan,
                 _CMP_NGT_US | _CMP_NGT_UQ => FloatCC::UnorderedOrLessThanOrEqual,
-                _CMP_FALSE_OQ | _CMP_FALSE_OS => todo!(),
+                _CMP_FALSE_OQ | _CMP_FALSE_OS => {
+                    fx.span_unimplemented(format_args!("{intrinsic} with _CMP_FALSE"))
+                }
                 _CMP_NEQ_OQ | _CMP_NEQ_OS => FloatCC::OrderedNotEqual,
                 _CMP_GE_OS | _CMP_GE_OQ => FloatCC::GreaterThanOrEqual,
                 _CMP_GT_OS | _CMP_GT_OQ => FloatCC::GreaterThan,
-                _CMP_TRUE_UQ | _CMP_TRUE_US => todo!(),
+                _CMP_TRUE_UQ | _CMP_TRUE_US => {
+                    fx.span_unimplemented(format_args!("{intrinsic} with _CMP_TRUE"))
+                }
 
                 kind => unreachable!("kind {:?}", kind),
             };
@@ -380,6 +401,84 @@ fn select4(
                 fx.bcx.ins().iabs(lane)
             });
         }
+        "llvm.x86.sse.max.ps"
+        | "llvm.x86.sse2.max.pd"
+        | "llvm.x86.sse.min.ps"
+        | "llvm.x86.sse2.min.pd" => {
+            intrinsic_args!(fx, args => (a, b); intrinsic);
+
+            // Unlike `fmax` and `fmin` these return the second operand if either operand is NaN or
+            // both operands are zero.
+            let cc =
+                if intrinsic.contains(".max.") { FloatCC::GreaterThan } else { FloatCC::LessThan };
+            simd_pair_for_each_lane(
+                fx,
+                a,
+                b,
+                ret,
+                &|fx, _lane_ty, _res_lane_ty, a_lane, b_lane| {
+                    let pick_a = fx.bcx.ins().fcmp(cc, a_lane, b_lane);
+                    fx.bcx.ins().select(pick_a, a_lane, b_lane)
+                },
+            );
+        }
+        "llvm.x86.sse.sqrt.ps" | "llvm.x86.sse2.sqrt.pd" => {
+            intrinsic_args!(fx, args => (a); intrinsic);
+
+            simd_for_each_lane(fx, a, ret, &|fx, _lane_ty, _res_lane_ty, lane| {
+                fx.bcx.ins().sqrt(lane)
+            });
+        }
+        "llvm.x86.sse.comieq.ss"
+        | "llvm.x86.sse.comineq.ss"
+        | "llvm.x86.sse.comilt.ss"
+        | "llvm.x86.sse.comile.ss"
+        | "llvm.x86.sse.comigt.ss"
+        | "llvm.x86.sse.comige.ss"
+        | "llvm.x86.sse.ucomieq.ss"
+        | "llvm.x86.sse.ucomineq.ss"
+        | "llvm.x86.sse.ucomilt.ss"
+        | "llvm.x86.sse.ucomile.ss"
+        | "llvm.x86.sse.ucomigt.ss"
+        | "llvm.x86.sse.ucomige.ss"
+        | "llvm.x86.sse2.comieq.sd"
+        | "llvm.x86.sse2.comineq.sd"
+        | "llvm.x86.sse2.comilt.sd"
+        | "llvm.x86.sse2.comile.sd"
+        | "llvm.x86.sse2.comigt.sd"
+        | "llvm.x86.sse2.comige.sd"
+        | "llvm.x86.sse2.ucomieq.sd"
+        | "llvm.x86.sse2.ucomineq.sd"
+        | "llvm.x86.sse2.ucomilt.sd"
+        | "llvm.x86.sse2.ucomile.sd"
+        | "llvm.x86.sse2.ucomigt.sd"
+        | "llvm.x86.sse2.ucomige.sd" => {
+            intrinsic_args!(fx, args => (a, b); intrinsic);
+
+            // Compare the lowest lanes. The comi and ucomi variants only differ in which NaNs raise
+            // an invalid operation exception. Only `neq` is true for unordered operands.
+            let cmp = intrinsic.split('.').nth(3).unwrap();
+            let flt_cc = match cmp.trim_start_matches('u').trim_start_matches("comi") {
+                "eq" => FloatCC::Equal,
+                "neq" => FloatCC::NotEqual,
+                "lt" => FloatCC::LessThan,
+                "le" => FloatCC::LessThanOrEqual,
+                "gt" => FloatCC::GreaterThan,
+                "ge" => FloatCC::GreaterThanOrEqual,
+                _ => unreachable!("{intrinsic}"),
+            };
+            let a_lane = a.value_lane(fx, 0).load_scalar(fx);
+            let b_lane = b.value_lane(fx, 0).load_scalar(fx);
+            let res = fx.bcx.ins().fcmp(flt_cc, a_lane, b_lane);
+            let res = fx.bcx.ins().uextend(types::I32, res);
+            ret.write_cvalue(fx, CValue::by_val(res, fx.layout_of(fx.tcx.types.i32)));
+        }
+        "llvm.x86.sse.sfence" | "llvm.x86.sse2.lfence" | "llvm.x86.sse2.mfence" => {
+            intrinsic_args!(fx, args => (); intrinsic);
+
+            // Cranelift only has a full fence, which is stronger than lfence and sfence.
+            fx.bcx.ins().fence();
+        }
         "llvm.x86.sse2.cvttps2dq" => {
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cvttps_epi32&ig_expand=2429
             intrinsic_args!(fx, args => (a); intrinsic);
@@ -449,7 +548,7 @@ fn select4(
                 },
             );
         }
-        "llvm.x86.sse2.psra.w" => {
+        "llvm.x86.sse2.psra.w" | "llvm.x86.sse2.psra.d" => {
             intrinsic_args!(fx, args => (a, count); intrinsic);
 
             let count_lane = count.force_stack(fx).0.load(fx, types::I64, MemFlags::trusted());
@@ -462,6 +561,35 @@ fn select4(
                 fx.bcx.ins().sshr(a_lane, saturated_count)
             });
         }
+        "llvm.x86.sse2.psll.w"
+        | "llvm.x86.sse2.psll.d"
+        | "llvm.x86.sse2.psll.q"
+        | "llvm.x86.sse2.psrl.w"
+        | "llvm.x86.sse2.psrl.d"
+        | "llvm.x86.sse2.psrl.q" => {
+            intrinsic_args!(fx, args => (a, count); intrinsic);
+
+            // The shift amount is the low 64 bits of `count`. Shifting out all bits gives zero.
+            let count_lane = count.force_stack(fx).0.load(fx, types::I64, MemFlags::trusted());
+            let lane_ty = fx.clif_type(a.layout().ty.simd_size_and_type(fx.tcx).1).unwrap();
+            let oversized = fx.bcx.ins().icmp_imm(
+                IntCC::UnsignedGreaterThan,
+                count_lane,
+                i64::from(lane_ty.bits() - 1),
+            );
+            let is_left_shift = intrinsic.starts_with("llvm.x86.sse2.psll");
+
+            // FIXME use vector instructions when possible
+            simd_for_each_lane(fx, a, ret, &|fx, _lane_ty, _res_lane_ty, a_lane| {
+                let shifted = if is_left_shift {
+                    fx.bcx.ins().ishl(a_lane, count_lane)
+                } else {
+                    fx.bcx.ins().ushr(a_lane, count_lane)
+                };
+                let zero = fx.bcx.ins().iconst(lane_ty, 0);
+                fx.bcx.ins().select(oversized, zero, shifted)
+            });
+        }
         "llvm.x86.sse2.psad.bw" | "llvm.x86.avx2.psad.bw" => {
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sad_epu8&ig_expand=5770
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_sad_epu8&ig_expand=5771
@@ -574,8 +702,9 @@ fn select4(
             }
         }
 
-        "llvm.x86.ssse3.pmul.hr.sw.128" => {
+        "llvm.x86.ssse3.pmul.hr.sw.128" | "llvm.x86.avx2.pmul.hr.sw" => {
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_mulhrs_epi16&ig_expand=4782
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_mulhrs_epi16&ig_expand=4783
             intrinsic_args!(fx, args => (a, b); intrinsic);
 
             assert_eq!(a.layout(), b.layout());
@@ -1306,9 +1435,10 @@ fn select4(
         }
 
         _ => {
-            fx.tcx
-                .dcx()
-                .warn(format!("unsupported x86 llvm intrinsic {}; replacing with trap", intrinsic));
+            fx.tcx.dcx().span_warn(
+                span,
+                format!("unsupported x86 llvm intrinsic {}; replacing with trap", intrinsic),
+            );
             crate::trap::trap_unimplemented(fx, intrinsic);
             return;
         }
@@ -1323,6 +1453,51 @@ fn select4(
 // llvm.x86.ssse3.pshuf.b.128
 // llvm.x86.avx2.pshuf.b
 
+/// The target feature required by all `llvm.x86.*` intrinsics starting with the given prefix. LLVM
+/// refuses to compile calls to these intrinsics from functions without the target feature.
+///
+/// The `llvm.x86.avx512.*` intrinsics are not checked as some of them are also used by target
+/// features which don't imply `avx512f`.
+const INTRINSIC_TARGET_FEATURES: &[(&str, &str)] = &[
+    ("llvm.x86.sse.", "sse"),
+    ("llvm.x86.sse2.", "sse2"),
+    ("llvm.x86.sse3.", "sse3"),
+    ("llvm.x86.ssse3.", "ssse3"),
+    ("llvm.x86.sse41.", "sse4.1"),
+    ("llvm.x86.sse42.", "sse4.2"),
+    ("llvm.x86.avx.", "avx"),
+    ("llvm.x86.avx2.", "avx2"),
+    ("llvm.x86.fma.", "fma"),
+    ("llvm.x86.aesni.", "aes"),
+    ("llvm.x86.pclmulqdq", "pclmulqdq"),
+    ("llvm.x86.sha1", "sha"),
+    ("llvm.x86.sha256", "sha"),
+    ("llvm.x86.bmi.bextr.", "bmi1"),
+    ("llvm.x86.bmi.bzhi.", "bmi2"),
+    ("llvm.x86.bmi.pdep.", "bmi2"),
+    ("llvm.x86.bmi.pext.", "bmi2"),
+];
+
+/// Whether `feature` is enabled for the current function either through the target features of
+/// the session or through `#[target_feature]`.
+///
+/// Like for the LLVM backend only the function calling the intrinsic matters. Calling a
+/// `#[target_feature]` function like the `core::arch` wrappers from a function without the target
+/// feature is allowed, as the caller may have checked for the target feature at runtime.
+fn target_feature_enabled(fx: &mut FunctionCx<'_, '_, '_>, feature: &str) -> bool {
+    let tcx = fx.tcx;
+    let def_id = fx.instance.def_id();
+    fx.target_features
+        .get_or_insert_with(|| {
+            let mut features =
+                tcx.asm_target_features(def_id).iter().map(|feature| feature.as_str()).collect();
+            // `#[target_feature]` doesn't add implied target features.
+            crate::add_implied_target_features(&mut features);
+            features.into_iter().map(Symbol::intern).collect()
+        })
+        .contains(&Symbol::intern(feature))
+}
+
 fn llvm_add_sub<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     bin_op: BinOp,
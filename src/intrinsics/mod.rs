@@ -24,6 +24,7 @@ macro_rules! intrinsic_args {
 use rustc_middle::ty::GenericArgsRef;
 use rustc_span::source_map::Spanned;
 use rustc_span::symbol::{kw, sym, Symbol};
+use rustc_target::spec::SanitizerSet;
 
 pub(crate) use self::llvm::codegen_llvm_intrinsic_call;
 use crate::prelude::*;
@@ -49,6 +50,143 @@ fn report_atomic_type_validation_error<'tcx>(
     fx.bcx.ins().trap(TrapCode::UnreachableCodeReached);
 }
 
+/// The `__ATOMIC_*` memory order passed to the `__atomic_*` libcalls for the ordering of an atomic
+/// intrinsic. `index` counts the orderings in the intrinsic name from the end.
+fn atomic_memory_order(fx: &mut FunctionCx<'_, '_, '_>, intrinsic: Symbol, index: usize) -> Value {
+    let order = match intrinsic.as_str().rsplit('_').nth(index).unwrap() {
+        "unordered" | "relaxed" => 0,
+        "acquire" => 2,
+        "release" => 3,
+        "acqrel" => 4,
+        "seqcst" => 5,
+        ordering => bug!("unknown atomic ordering `{ordering}` in {intrinsic}"),
+    };
+    fx.bcx.ins().iconst(types::I32, order)
+}
+
+// Cranelift doesn't support 128bit atomics. Like LLVM does when the target lacks a native
+// 128bit compare-exchange, they are lowered to calls to the `__atomic_*_16` functions provided by
+// libatomic. The AOT driver adds libatomic to the linked libraries when these calls are present.
+
+fn codegen_i128_atomic_load(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    intrinsic: Symbol,
+    ptr: Value,
+) -> Value {
+    let order = atomic_memory_order(fx, intrinsic, 0);
+    fx.lib_call(
+        "__atomic_load_16",
+        vec![AbiParam::new(fx.pointer_type), AbiParam::new(types::I32)],
+        vec![AbiParam::new(types::I128)],
+        &[ptr, order],
+    )[0]
+}
+
+fn codegen_i128_atomic_store(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    intrinsic: Symbol,
+    ptr: Value,
+    val: Value,
+) {
+    let order = atomic_memory_order(fx, intrinsic, 0);
+    fx.lib_call(
+        "__atomic_store_16",
+        vec![AbiParam::new(fx.pointer_type), AbiParam::new(types::I128), AbiParam::new(types::I32)],
+        vec![],
+        &[ptr, val, order],
+    );
+}
+
+/// Returns the old value and whether the exchange succeeded.
+fn codegen_i128_atomic_cas(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    ptr: Value,
+    test_old: Value,
+    new: Value,
+    success_order: Value,
+    failure_order: Value,
+) -> (Value, Value) {
+    // On failure the current value is written back to `expected`.
+    let expected = fx.create_stack_slot(16, 16);
+    expected.store(fx, test_old, MemFlags::trusted());
+    let expected_addr = expected.get_addr(fx);
+    let is_eq = fx.lib_call(
+        "__atomic_compare_exchange_16",
+        vec![
+            AbiParam::new(fx.pointer_type),
+            AbiParam::new(fx.pointer_type),
+            AbiParam::new(types::I128),
+            AbiParam::new(types::I32),
+            AbiParam::new(types::I32),
+        ],
+        vec![AbiParam::new(types::I8)],
+        &[ptr, expected_addr, new, success_order, failure_order],
+    )[0];
+    let old = expected.load(fx, types::I128, MemFlags::trusted());
+    (old, is_eq)
+}
+
+fn codegen_i128_atomic_rmw(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    intrinsic: Symbol,
+    op: AtomicRmwOp,
+    ptr: Value,
+    src: Value,
+) -> Value {
+    let order = atomic_memory_order(fx, intrinsic, 0);
+    let (libcall, cc) = match op {
+        AtomicRmwOp::Xchg => ("__atomic_exchange_16", None),
+        AtomicRmwOp::Add => ("__atomic_fetch_add_16", None),
+        AtomicRmwOp::Sub => ("__atomic_fetch_sub_16", None),
+        AtomicRmwOp::And => ("__atomic_fetch_and_16", None),
+        AtomicRmwOp::Nand => ("__atomic_fetch_nand_16", None),
+        AtomicRmwOp::Or => ("__atomic_fetch_or_16", None),
+        AtomicRmwOp::Xor => ("__atomic_fetch_xor_16", None),
+        AtomicRmwOp::Umin => ("", Some(IntCC::UnsignedLessThan)),
+        AtomicRmwOp::Umax => ("", Some(IntCC::UnsignedGreaterThan)),
+        AtomicRmwOp::Smin => ("", Some(IntCC::SignedLessThan)),
+        AtomicRmwOp::Smax => ("", Some(IntCC::SignedGreaterThan)),
+    };
+
+    let Some(cc) = cc else {
+        return fx.lib_call(
+            libcall,
+            vec![
+                AbiParam::new(fx.pointer_type),
+                AbiParam::new(types::I128),
+                AbiParam::new(types::I32),
+            ],
+            vec![AbiParam::new(types::I128)],
+            &[ptr, src, order],
+        )[0];
+    };
+
+    // libatomic doesn't have min and max operations, so use a compare-exchange loop instead.
+    let relaxed = fx.bcx.ins().iconst(types::I32, 0);
+    let initial = fx.lib_call(
+        "__atomic_load_16",
+        vec![AbiParam::new(fx.pointer_type), AbiParam::new(types::I32)],
+        vec![AbiParam::new(types::I128)],
+        &[ptr, relaxed],
+    )[0];
+
+    let loop_block = fx.bcx.create_block();
+    let done_block = fx.bcx.create_block();
+    let old = fx.bcx.append_block_param(loop_block, types::I128);
+    fx.bcx.ins().jump(loop_block, &[initial]);
+
+    fx.bcx.switch_to_block(loop_block);
+    let keep_old = fx.bcx.ins().icmp(cc, old, src);
+    let new = fx.bcx.ins().select(keep_old, old, src);
+    let (current, is_eq) = codegen_i128_atomic_cas(fx, ptr, old, new, order, relaxed);
+    fx.bcx.ins().brif(is_eq, done_block, &[], loop_block, &[current]);
+    fx.bcx.seal_block(loop_block);
+    fx.bcx.seal_block(done_block);
+
+    fx.bcx.switch_to_block(done_block);
+    old
+}
+
 pub(crate) fn clif_vector_type<'tcx>(tcx: TyCtxt<'tcx>, layout: TyAndLayout<'tcx>) -> Type {
     let (element, count) = match layout.abi {
         Abi::Vector { element, count } => (element, count),
@@ -213,6 +351,85 @@ fn simd_reduce<'tcx>(
     ret.write_cvalue(fx, res);
 }
 
+/// Cranelift doesn't support volatile memory accesses. When optimizing, its alias analysis merges
+/// a load with an earlier load of the same address and forwards stored values to later loads of
+/// the same address, as long as no store to the same alias region happens in between. Volatile
+/// loads therefore use the otherwise unused `heap` alias region and are preceded by a store to a
+/// fresh stack slot in that region. No other memory access can see this store, so the load is
+/// never merged with another load or forwarded a stored value. Unlike a fence this doesn't stop
+/// the cpu from reordering memory accesses, which volatile accesses don't require.
+// FIXME use a volatile flag once Cranelift supports it. See the upstream Cranelift issue about
+// volatile memory accesses not being respected by alias analysis.
+fn codegen_volatile_load<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    ptr: Value,
+    layout: TyAndLayout<'tcx>,
+) -> CValue<'tcx> {
+    if layout.is_zst() {
+        return CValue::by_ref(Pointer::new(ptr), layout);
+    }
+
+    let mut flags = MemFlags::new();
+    flags.set_heap();
+
+    // Alias analysis only runs when optimizing.
+    let optimize =
+        fx.module.isa().flags().opt_level() != cranelift_codegen::settings::OptLevel::None;
+
+    if let Abi::Scalar(scalar) = layout.abi {
+        if optimize {
+            let tmp = fx.create_stack_slot(1, 1);
+            let tmp_addr = tmp.get_addr(fx);
+            let zero = fx.bcx.ins().iconst(types::I8, 0);
+            fx.bcx.ins().store(flags, zero, tmp_addr, 0);
+        }
+        let val = Pointer::new(ptr).load(fx, scalar_to_clif_type(fx.tcx, scalar), flags);
+        return CValue::by_val(val, layout);
+    }
+
+    let size = layout.size.bytes();
+    let tmp = fx.create_stack_slot(u32::try_from(size).unwrap(), layout.align.abi.bytes() as u32);
+    let tmp_addr = tmp.get_addr(fx);
+    if optimize {
+        let zero = fx.bcx.ins().iconst(types::I8, 0);
+        fx.bcx.ins().store(flags, zero, tmp_addr, 0);
+    }
+
+    let align = u8::try_from(layout.align.abi.bytes()).unwrap_or(128);
+    fx.bcx.emit_small_memory_copy(fx.target_config, tmp_addr, ptr, size, align, align, true, flags);
+    CValue::by_ref(tmp, layout)
+}
+
+/// Reduces all lanes using a balanced tree of `f`. A lane without a partner at any level of the
+/// tree is passed through unchanged. Only valid for reductions that don't need to be performed in
+/// lane order. SIMD types without lanes are rejected by layout computation.
+fn simd_reduce_tree<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    val: CValue<'tcx>,
+    ret: CPlace<'tcx>,
+    f: &dyn Fn(&mut FunctionCx<'_, '_, 'tcx>, Ty<'tcx>, Value, Value) -> Value,
+) {
+    let (lane_count, lane_ty) = val.layout().ty.simd_size_and_type(fx.tcx);
+    let lane_layout = fx.layout_of(lane_ty);
+    assert_eq!(lane_layout, ret.layout());
+
+    let mut lanes = (0..lane_count)
+        .map(|lane_idx| val.value_lane(fx, lane_idx).load_scalar(fx))
+        .collect::<Vec<_>>();
+    while lanes.len() > 1 {
+        lanes = lanes
+            .chunks(2)
+            .map(|pair| match *pair {
+                [a, b] => f(fx, lane_ty, a, b),
+                [a] => a,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    let res = CValue::by_val(lanes[0], lane_layout);
+    ret.write_cvalue(fx, res);
+}
+
 // FIXME move all uses to `simd_reduce`
 fn simd_reduce_bool<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
@@ -337,6 +554,8 @@ fn codegen_float_intrinsic_call<'tcx>(
         sym::truncf64 => ("trunc", 1, fx.tcx.types.f64, types::F64),
         sym::rintf32 => ("rintf", 1, fx.tcx.types.f32, types::F32),
         sym::rintf64 => ("rint", 1, fx.tcx.types.f64, types::F64),
+        sym::nearbyintf32 => ("nearbyintf", 1, fx.tcx.types.f32, types::F32),
+        sym::nearbyintf64 => ("nearbyint", 1, fx.tcx.types.f64, types::F64),
         sym::roundf32 => ("roundf", 1, fx.tcx.types.f32, types::F32),
         sym::roundf64 => ("round", 1, fx.tcx.types.f64, types::F64),
         sym::roundevenf32 => ("roundevenf", 1, fx.tcx.types.f32, types::F32),
@@ -391,12 +610,26 @@ fn codegen_float_intrinsic_call<'tcx>(
         | sym::ceilf32
         | sym::ceilf64
         | sym::truncf32
-        | sym::truncf64 => {
+        | sym::truncf64
+        | sym::rintf32
+        | sym::rintf64
+        | sym::nearbyintf32
+        | sym::nearbyintf64
+        | sym::roundevenf32
+        | sym::roundevenf64 => {
             let val = match intrinsic {
                 sym::fabsf32 | sym::fabsf64 => fx.bcx.ins().fabs(args[0]),
                 sym::floorf32 | sym::floorf64 => fx.bcx.ins().floor(args[0]),
                 sym::ceilf32 | sym::ceilf64 => fx.bcx.ins().ceil(args[0]),
                 sym::truncf32 | sym::truncf64 => fx.bcx.ins().trunc(args[0]),
+                // Rust always uses the default rounding mode, in which `rint` and `nearbyint`
+                // round half to even like `nearest`.
+                sym::rintf32
+                | sym::rintf64
+                | sym::nearbyintf32
+                | sym::nearbyintf64
+                | sym::roundevenf32
+                | sym::roundevenf64 => fx.bcx.ins().nearest(args[0]),
                 _ => unreachable!(),
             };
 
@@ -404,7 +637,8 @@ fn codegen_float_intrinsic_call<'tcx>(
         }
 
         // These intrinsics aren't supported natively by Cranelift.
-        // Lower them to a libcall.
+        // Lower them to a libcall. This includes `round`, which rounds half away from zero rather
+        // than to even like `nearest`.
         sym::powif32 | sym::powif64 => {
             let input_tys: Vec<_> = vec![AbiParam::new(clif_ty), AbiParam::new(types::I32)];
             let ret_val = fx.lib_call(name, input_tys, vec![AbiParam::new(clif_ty)], &args)[0];
@@ -451,19 +685,14 @@ fn codegen_regular_intrinsic_call<'tcx>(
             fx.bcx.ins().debugtrap();
         }
         sym::copy => {
-            intrinsic_args!(fx, args => (src, dst, count); intrinsic);
-            let src = src.load_scalar(fx);
-            let dst = dst.load_scalar(fx);
-            let count = count.load_scalar(fx);
-
-            let elem_ty = generic_args.type_at(0);
-            let elem_size: u64 = fx.layout_of(elem_ty).size.bytes();
-            assert_eq!(args.len(), 3);
-            let byte_amount =
-                if elem_size != 1 { fx.bcx.ins().imul_imm(count, elem_size as i64) } else { count };
+            let [src, dst, count] = args else {
+                bug_on_incorrect_arg_count(intrinsic);
+            };
+            let src = codegen_operand(fx, &src.node).load_scalar(fx);
+            let dst = codegen_operand(fx, &dst.node).load_scalar(fx);
 
-            // FIXME emit_small_memmove
-            fx.bcx.call_memmove(fx.target_config, dst, src, byte_amount);
+            let elem_layout = fx.layout_of(generic_args.type_at(0));
+            codegen_copy(fx, dst, src, &count.node, elem_layout, false);
         }
         sym::volatile_copy_memory | sym::volatile_copy_nonoverlapping_memory => {
             // NOTE: the volatile variants have src and dst swapped
@@ -535,8 +764,7 @@ fn codegen_regular_intrinsic_call<'tcx>(
         sym::exact_div => {
             intrinsic_args!(fx, args => (x, y); intrinsic);
 
-            // FIXME trap on inexact
-            let res = crate::num::codegen_int_binop(fx, BinOp::Div, x, y);
+            let res = crate::num::codegen_exact_div(fx, x, y);
             ret.write_cvalue(fx, res);
         }
         sym::saturating_add | sym::saturating_sub => {
@@ -571,22 +799,18 @@ fn codegen_regular_intrinsic_call<'tcx>(
             ret.write_cvalue(fx, CValue::by_val(res, layout));
         }
 
-        // The only difference between offset and arith_offset is regarding UB. Because Cranelift
-        // doesn't have UB both are codegen'ed the same way
+        // `offset` is normally already lowered to `BinOp::Offset` in MIR.
+        sym::offset => {
+            intrinsic_args!(fx, args => (base, offset); intrinsic);
+
+            let res = crate::num::codegen_ptr_offset(fx, base, offset, true);
+            ret.write_cvalue(fx, res);
+        }
         sym::arith_offset => {
             intrinsic_args!(fx, args => (base, offset); intrinsic);
-            let offset = offset.load_scalar(fx);
 
-            let pointee_ty = base.layout().ty.builtin_deref(true).unwrap().ty;
-            let pointee_size = fx.layout_of(pointee_ty).size.bytes();
-            let ptr_diff = if pointee_size != 1 {
-                fx.bcx.ins().imul_imm(offset, pointee_size as i64)
-            } else {
-                offset
-            };
-            let base_val = base.load_scalar(fx);
-            let res = fx.bcx.ins().iadd(base_val, ptr_diff);
-            ret.write_cvalue(fx, CValue::by_val(res, base.layout()));
+            let res = crate::num::codegen_ptr_offset(fx, base, offset, false);
+            ret.write_cvalue(fx, res);
         }
 
         sym::ptr_mask => {
@@ -617,7 +841,9 @@ fn codegen_regular_intrinsic_call<'tcx>(
             intrinsic_args!(fx, args => (arg); intrinsic);
             let val = arg.load_scalar(fx);
 
-            // FIXME trap on `ctlz_nonzero` with zero arg.
+            if intrinsic == sym::ctlz_nonzero {
+                ub_check_nonzero_arg(fx, val, "`ctlz_nonzero` called with zero");
+            }
             let res = fx.bcx.ins().clz(val);
             let res = CValue::by_val(res, arg.layout());
             ret.write_cvalue(fx, res);
@@ -626,7 +852,9 @@ fn codegen_regular_intrinsic_call<'tcx>(
             intrinsic_args!(fx, args => (arg); intrinsic);
             let val = arg.load_scalar(fx);
 
-            // FIXME trap on `cttz_nonzero` with zero arg.
+            if intrinsic == sym::cttz_nonzero {
+                ub_check_nonzero_arg(fx, val, "`cttz_nonzero` called with zero");
+            }
             let res = fx.bcx.ins().ctz(val);
             let res = CValue::by_val(res, arg.layout());
             ret.write_cvalue(fx, res);
@@ -651,10 +879,10 @@ fn codegen_regular_intrinsic_call<'tcx>(
             intrinsic_args!(fx, args => (arg); intrinsic);
             let val = arg.load_scalar(fx);
 
-            let res = if fx.bcx.func.dfg.value_type(val) == types::I8 {
-                val
-            } else {
-                fx.bcx.ins().bswap(val)
+            let res = match fx.bcx.func.dfg.value_type(val) {
+                types::I8 => val,
+                types::I128 => crate::codegen_i128::codegen_bswap(fx, val),
+                _ => fx.bcx.ins().bswap(val),
             };
             let res = CValue::by_val(res, arg.layout());
             ret.write_cvalue(fx, res);
@@ -701,17 +929,17 @@ fn codegen_regular_intrinsic_call<'tcx>(
         sym::volatile_load | sym::unaligned_volatile_load => {
             intrinsic_args!(fx, args => (ptr); intrinsic);
 
-            // Cranelift treats loads as volatile by default
             // FIXME correctly handle unaligned_volatile_load
             let inner_layout = fx.layout_of(ptr.layout().ty.builtin_deref(true).unwrap().ty);
-            let val = CValue::by_ref(Pointer::new(ptr.load_scalar(fx)), inner_layout);
+            let ptr = ptr.load_scalar(fx);
+            let val = codegen_volatile_load(fx, ptr, inner_layout);
             ret.write_cvalue(fx, val);
         }
         sym::volatile_store | sym::unaligned_volatile_store | sym::nontemporal_store => {
             intrinsic_args!(fx, args => (ptr, val); intrinsic);
             let ptr = ptr.load_scalar(fx);
 
-            // Cranelift treats stores as volatile by default
+            // Cranelift never removes stores. Loads are protected by `codegen_volatile_load`.
             // FIXME correctly handle unaligned_volatile_store
             // FIXME actually do nontemporal stores if requested
             let dest = CPlace::for_ptr(Pointer::new(ptr), val.layout());
@@ -739,15 +967,19 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let pointee_size: u64 = fx.layout_of(ty).size.bytes();
             let diff_bytes = fx.bcx.ins().isub(ptr, base);
-            // FIXME this can be an exact division.
+            // The distance between the pointers is a multiple of the pointee size.
             let val = if intrinsic == sym::ptr_offset_from_unsigned {
                 let usize_layout = fx.layout_of(fx.tcx.types.usize);
                 // Because diff_bytes ULE isize::MAX, this would be fine as signed,
                 // but unsigned is slightly easier to codegen, so might as well.
-                CValue::by_val(fx.bcx.ins().udiv_imm(diff_bytes, pointee_size as i64), usize_layout)
+                let val =
+                    crate::num::codegen_exact_div_imm(fx, diff_bytes, pointee_size as i64, false);
+                CValue::by_val(val, usize_layout)
             } else {
                 let isize_layout = fx.layout_of(fx.tcx.types.isize);
-                CValue::by_val(fx.bcx.ins().sdiv_imm(diff_bytes, pointee_size as i64), isize_layout)
+                let val =
+                    crate::num::codegen_exact_div_imm(fx, diff_bytes, pointee_size as i64, true);
+                CValue::by_val(val, isize_layout)
             };
             ret.write_cvalue(fx, val);
         }
@@ -784,16 +1016,14 @@ fn codegen_regular_intrinsic_call<'tcx>(
             let ty = generic_args.type_at(0);
             match ty.kind() {
                 ty::Uint(UintTy::U128) | ty::Int(IntTy::I128) => {
-                    // FIXME implement 128bit atomics
                     if fx.tcx.is_compiler_builtins(LOCAL_CRATE) {
                         // special case for compiler-builtins to avoid having to patch it
                         crate::trap::trap_unimplemented(fx, "128bit atomics not yet supported");
                         return Ok(());
-                    } else {
-                        fx.tcx
-                            .dcx()
-                            .span_fatal(source_info.span, "128bit atomics not yet supported");
                     }
+                    let val = codegen_i128_atomic_load(fx, intrinsic, ptr);
+                    ret.write_cvalue(fx, CValue::by_val(val, fx.layout_of(ty)));
+                    return Ok(());
                 }
                 ty::Uint(_) | ty::Int(_) | ty::RawPtr(..) => {}
                 _ => {
@@ -815,16 +1045,14 @@ fn codegen_regular_intrinsic_call<'tcx>(
             let ty = generic_args.type_at(0);
             match ty.kind() {
                 ty::Uint(UintTy::U128) | ty::Int(IntTy::I128) => {
-                    // FIXME implement 128bit atomics
                     if fx.tcx.is_compiler_builtins(LOCAL_CRATE) {
                         // special case for compiler-builtins to avoid having to patch it
                         crate::trap::trap_unimplemented(fx, "128bit atomics not yet supported");
                         return Ok(());
-                    } else {
-                        fx.tcx
-                            .dcx()
-                            .span_fatal(source_info.span, "128bit atomics not yet supported");
                     }
+                    let val = val.load_scalar(fx);
+                    codegen_i128_atomic_store(fx, intrinsic, ptr, val);
+                    return Ok(());
                 }
                 ty::Uint(_) | ty::Int(_) | ty::RawPtr(..) => {}
                 _ => {
@@ -853,7 +1081,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let new = new.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Xchg, ptr, new);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Xchg, ptr, new)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Xchg, ptr, new)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -875,8 +1107,14 @@ fn codegen_regular_intrinsic_call<'tcx>(
             let test_old = test_old.load_scalar(fx);
             let new = new.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_cas(MemFlags::trusted(), ptr, test_old, new);
-            let is_eq = fx.bcx.ins().icmp(IntCC::Equal, old, test_old);
+            let (old, is_eq) = if fx.clif_type(layout.ty).unwrap() == types::I128 {
+                let success_order = atomic_memory_order(fx, intrinsic, 1);
+                let failure_order = atomic_memory_order(fx, intrinsic, 0);
+                codegen_i128_atomic_cas(fx, ptr, test_old, new, success_order, failure_order)
+            } else {
+                let old = fx.bcx.ins().atomic_cas(MemFlags::trusted(), ptr, test_old, new);
+                (old, fx.bcx.ins().icmp(IntCC::Equal, old, test_old))
+            };
 
             let ret_val = CValue::by_val_pair(old, is_eq, ret.layout());
             ret.write_cvalue(fx, ret_val)
@@ -898,8 +1136,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let amount = amount.load_scalar(fx);
 
-            let old =
-                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Add, ptr, amount);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Add, ptr, amount)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Add, ptr, amount)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -920,8 +1161,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let amount = amount.load_scalar(fx);
 
-            let old =
-                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Sub, ptr, amount);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Sub, ptr, amount)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Sub, ptr, amount)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -942,7 +1186,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let src = src.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::And, ptr, src);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::And, ptr, src)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::And, ptr, src)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -963,7 +1211,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let src = src.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Or, ptr, src);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Or, ptr, src)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Or, ptr, src)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -984,7 +1236,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let src = src.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Xor, ptr, src);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Xor, ptr, src)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Xor, ptr, src)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -1005,7 +1261,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let src = src.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Nand, ptr, src);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Nand, ptr, src)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Nand, ptr, src)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -1026,7 +1286,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let src = src.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Smax, ptr, src);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Smax, ptr, src)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Smax, ptr, src)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -1047,7 +1311,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let src = src.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Umax, ptr, src);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Umax, ptr, src)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Umax, ptr, src)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -1068,7 +1336,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let src = src.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Smin, ptr, src);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Smin, ptr, src)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Smin, ptr, src)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -1089,7 +1361,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let src = src.load_scalar(fx);
 
-            let old = fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Umin, ptr, src);
+            let old = if ty == types::I128 {
+                codegen_i128_atomic_rmw(fx, intrinsic, AtomicRmwOp::Umin, ptr, src)
+            } else {
+                fx.bcx.ins().atomic_rmw(ty, MemFlags::trusted(), AtomicRmwOp::Umin, ptr, src)
+            };
 
             let old = CValue::by_val(old, layout);
             ret.write_cvalue(fx, old);
@@ -1229,6 +1505,19 @@ fn codegen_regular_intrinsic_call<'tcx>(
             ret.write_cvalue(fx, CValue::by_val(cmp, ret.layout()));
         }
 
+        sym::is_val_statically_known => {
+            intrinsic_args!(fx, args => (a); intrinsic);
+
+            // Only values which are constants in the clif ir are known. Values stored in memory are
+            // never considered known, even if they were written by a constant.
+            let known = a.try_to_vals().is_some_and(|(a, b)| {
+                crate::optimize::peephole::is_known_constant(&fx.bcx, a)
+                    && b.map_or(true, |b| crate::optimize::peephole::is_known_constant(&fx.bcx, b))
+            });
+            let res = fx.bcx.ins().iconst(types::I8, i64::from(known));
+            ret.write_cvalue(fx, CValue::by_val(res, ret.layout()));
+        }
+
         sym::black_box => {
             intrinsic_args!(fx, args => (a); intrinsic);
 
@@ -1236,12 +1525,21 @@ fn codegen_regular_intrinsic_call<'tcx>(
             ret.write_cvalue(fx, a);
         }
 
-        // FIXME implement variadics in cranelift
-        sym::va_copy | sym::va_arg | sym::va_end => {
-            fx.tcx.dcx().span_fatal(
-                source_info.span,
-                "Defining variadic functions is not yet supported by Cranelift",
-            );
+        sym::va_arg => {
+            intrinsic_args!(fx, args => (ap); intrinsic);
+
+            crate::abi::codegen_va_arg(fx, ap, ret, source_info.span);
+        }
+        sym::va_copy => {
+            intrinsic_args!(fx, args => (dest, src); intrinsic);
+
+            let va_list_layout = fx.layout_of(src.layout().ty.builtin_deref(true).unwrap().ty);
+            let src = CPlace::for_ptr(Pointer::new(src.load_scalar(fx)), va_list_layout);
+            let dest = CPlace::for_ptr(Pointer::new(dest.load_scalar(fx)), va_list_layout);
+            dest.write_cvalue(fx, src.to_cvalue(fx));
+        }
+        sym::va_end => {
+            // Nothing to clean up
         }
 
         // Unimplemented intrinsics must have a fallback body. The fallback body is obtained
@@ -1253,3 +1551,100 @@ fn codegen_regular_intrinsic_call<'tcx>(
     fx.bcx.ins().jump(ret_block, &[]);
     Ok(())
 }
+
+/// Copy `count` elements of `elem_layout` from `src` to `dst`. Unless `non_overlapping` is set
+/// the source and destination may overlap like for `memmove`. Copies of a small constant size are
+/// done inline.
+pub(crate) fn codegen_copy<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    dst: Value,
+    src: Value,
+    count: &mir::Operand<'tcx>,
+    elem_layout: TyAndLayout<'tcx>,
+    non_overlapping: bool,
+) {
+    let elem_size = elem_layout.size.bytes();
+
+    let const_byte_amount = crate::constant::mir_operand_get_const_val(fx, count)
+        .and_then(|count| count.try_to_bits(fx.tcx.data_layout.pointer_size).ok())
+        .and_then(|count| u64::try_from(count).ok()?.checked_mul(elem_size))
+        // ASan only checks copies through its `memcpy` and `memmove` interceptors.
+        .filter(|_| !fx.tcx.sess.opts.unstable_opts.sanitizer.contains(SanitizerSet::ADDRESS));
+    if let Some(byte_amount) = const_byte_amount {
+        if non_overlapping {
+            let byte_amount_val = fx.bcx.ins().iconst(fx.pointer_type, byte_amount as i64);
+            ub_check_nonoverlapping(fx, dst, src, byte_amount_val);
+        }
+
+        // `emit_small_memory_copy` loads everything before storing anything, so this is correct
+        // for overlapping copies too. Large copies are still lowered to a libcall.
+        let align = u8::try_from(elem_layout.align.abi.bytes()).unwrap_or(128);
+        // Unlike `MemFlags::trusted()` this doesn't claim that every access is aligned to its own
+        // size, which `align` doesn't guarantee.
+        let mut flags = MemFlags::new();
+        flags.set_notrap();
+        fx.bcx.emit_small_memory_copy(
+            fx.target_config,
+            dst,
+            src,
+            byte_amount,
+            align,
+            align,
+            non_overlapping,
+            flags,
+        );
+        return;
+    }
+
+    let count = codegen_operand(fx, count).load_scalar(fx);
+    let byte_amount =
+        if elem_size != 1 { fx.bcx.ins().imul_imm(count, elem_size as i64) } else { count };
+    if non_overlapping {
+        ub_check_nonoverlapping(fx, dst, src, byte_amount);
+        fx.bcx.call_memcpy(fx.target_config, dst, src, byte_amount);
+    } else {
+        fx.bcx.call_memmove(fx.target_config, dst, src, byte_amount);
+    }
+}
+
+/// Panic if `val` is zero when UB checks are enabled. Without UB checks a zero argument is UB and
+/// no check is emitted.
+fn ub_check_nonzero_arg(fx: &mut FunctionCx<'_, '_, '_>, val: Value, msg: &str) {
+    if !fx.cx.ub_checks {
+        return;
+    }
+
+    let is_zero = fx.bcx.ins().icmp_imm(IntCC::Equal, val, 0);
+    panic_nounwind_if(fx, is_zero, msg);
+}
+
+/// Panic if the `byte_amount` bytes at `dst` and `src` overlap when UB checks are enabled.
+fn ub_check_nonoverlapping(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    dst: Value,
+    src: Value,
+    byte_amount: Value,
+) {
+    if !fx.cx.ub_checks {
+        return;
+    }
+
+    // The ranges overlap iff the distance between the start addresses is less than the size.
+    let diff = fx.bcx.ins().isub(dst, src);
+    let neg_diff = fx.bcx.ins().ineg(diff);
+    let distance = fx.bcx.ins().umin(diff, neg_diff);
+    let overlaps = fx.bcx.ins().icmp(IntCC::UnsignedLessThan, distance, byte_amount);
+    panic_nounwind_if(fx, overlaps, "`copy_nonoverlapping` called with overlapping memory ranges");
+}
+
+pub(crate) fn panic_nounwind_if(fx: &mut FunctionCx<'_, '_, '_>, cond: Value, msg: &str) {
+    let panic_block = fx.bcx.create_block();
+    let next_block = fx.bcx.create_block();
+    fx.bcx.set_cold_block(panic_block);
+    fx.bcx.ins().brif(cond, panic_block, &[], next_block, &[]);
+    fx.bcx.seal_block(panic_block);
+    fx.bcx.seal_block(next_block);
+    fx.bcx.switch_to_block(panic_block);
+    crate::base::codegen_panic_nounwind(fx, msg, None);
+    fx.bcx.switch_to_block(next_block);
+}
@@ -135,32 +135,11 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 .unwrap()
                 .unwrap_branch();
 
-            assert_eq!(x.layout(), y.layout());
-            let layout = x.layout();
-
-            let (lane_count, lane_ty) = layout.ty.simd_size_and_type(fx.tcx);
-            let (ret_lane_count, ret_lane_ty) = ret.layout().ty.simd_size_and_type(fx.tcx);
-
-            assert_eq!(lane_ty, ret_lane_ty);
-            assert_eq!(idx.len() as u64, ret_lane_count);
-
-            let total_len = lane_count * 2;
-
             let indexes =
                 idx.iter().map(|idx| idx.unwrap_leaf().try_to_u32().unwrap()).collect::<Vec<u32>>();
 
-            for &idx in &indexes {
-                assert!(u64::from(idx) < total_len, "idx {} out of range 0..{}", idx, total_len);
-            }
-
-            for (out_idx, in_idx) in indexes.into_iter().enumerate() {
-                let in_lane = if u64::from(in_idx) < lane_count {
-                    x.value_lane(fx, in_idx.into())
-                } else {
-                    y.value_lane(fx, u64::from(in_idx) - lane_count)
-                };
-                let out_lane = ret.place_lane(fx, u64::try_from(out_idx).unwrap());
-                out_lane.write_cvalue(fx, in_lane);
+            if !codegen_shuffle(fx, intrinsic, span, x, y, &indexes, ret) {
+                return;
             }
         }
 
@@ -202,17 +181,6 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 }
             };
 
-            assert_eq!(x.layout(), y.layout());
-            let layout = x.layout();
-
-            let (lane_count, lane_ty) = layout.ty.simd_size_and_type(fx.tcx);
-            let (ret_lane_count, ret_lane_ty) = ret.layout().ty.simd_size_and_type(fx.tcx);
-
-            assert_eq!(lane_ty, ret_lane_ty);
-            assert_eq!(u64::from(n), ret_lane_count);
-
-            let total_len = lane_count * 2;
-
             let indexes = {
                 use rustc_middle::mir::interpret::*;
                 let idx_const = match &idx.node {
@@ -223,9 +191,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 let idx_bytes = match idx_const {
                     ConstValue::Indirect { alloc_id, offset } => {
                         let alloc = fx.tcx.global_alloc(alloc_id).unwrap_memory();
-                        let size = Size::from_bytes(
-                            4 * ret_lane_count, /* size_of([u32; ret_lane_count]) */
-                        );
+                        let size = Size::from_bytes(4 * u64::from(n) /* size_of([u32; n]) */);
                         alloc
                             .inner()
                             .get_bytes_strip_provenance(fx, alloc_range(offset, size))
@@ -234,7 +200,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                     _ => unreachable!("{:?}", idx_const),
                 };
 
-                (0..ret_lane_count)
+                (0..u64::from(n))
                     .map(|i| {
                         let i = usize::try_from(i).unwrap();
                         let idx = rustc_middle::mir::interpret::read_target_uint(
@@ -242,23 +208,13 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                             &idx_bytes[4 * i..4 * i + 4],
                         )
                         .expect("read_target_uint");
-                        u16::try_from(idx).expect("try_from u32")
+                        u32::try_from(idx).expect("try_from u32")
                     })
-                    .collect::<Vec<u16>>()
+                    .collect::<Vec<u32>>()
             };
 
-            for &idx in &indexes {
-                assert!(u64::from(idx) < total_len, "idx {} out of range 0..{}", idx, total_len);
-            }
-
-            for (out_idx, in_idx) in indexes.into_iter().enumerate() {
-                let in_lane = if u64::from(in_idx) < lane_count {
-                    x.value_lane(fx, in_idx.into())
-                } else {
-                    y.value_lane(fx, u64::from(in_idx) - lane_count)
-                };
-                let out_lane = ret.place_lane(fx, u64::try_from(out_idx).unwrap());
-                out_lane.write_cvalue(fx, in_lane);
+            if !codegen_shuffle(fx, intrinsic, span, x, y, &indexes, ret) {
+                return;
             }
         }
 
@@ -356,6 +312,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
+            // FIXME use a vector clz on AArch64 once Cranelift supports lowering it.
             simd_for_each_lane(fx, a, ret, &|fx, lane_ty, _ret_lane_ty, lane| match (
                 lane_ty.kind(),
                 intrinsic,
@@ -642,7 +599,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
-            simd_reduce(fx, v, None, ret, &|fx, lane_ty, a, b| {
+            simd_reduce_tree(fx, v, ret, &|fx, lane_ty, a, b| {
                 if lane_ty.is_floating_point() {
                     fx.bcx.ins().fadd(a, b)
                 } else {
@@ -679,7 +636,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
-            simd_reduce(fx, v, None, ret, &|fx, lane_ty, a, b| {
+            simd_reduce_tree(fx, v, ret, &|fx, lane_ty, a, b| {
                 if lane_ty.is_floating_point() {
                     fx.bcx.ins().fmul(a, b)
                 } else {
@@ -718,7 +675,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
-            simd_reduce(fx, v, None, ret, &|fx, _ty, a, b| fx.bcx.ins().band(a, b));
+            simd_reduce_tree(fx, v, ret, &|fx, _ty, a, b| fx.bcx.ins().band(a, b));
         }
 
         sym::simd_reduce_or => {
@@ -729,7 +686,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
-            simd_reduce(fx, v, None, ret, &|fx, _ty, a, b| fx.bcx.ins().bor(a, b));
+            simd_reduce_tree(fx, v, ret, &|fx, _ty, a, b| fx.bcx.ins().bor(a, b));
         }
 
         sym::simd_reduce_xor => {
@@ -740,7 +697,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
-            simd_reduce(fx, v, None, ret, &|fx, _ty, a, b| fx.bcx.ins().bxor(a, b));
+            simd_reduce_tree(fx, v, ret, &|fx, _ty, a, b| fx.bcx.ins().bxor(a, b));
         }
 
         sym::simd_reduce_min | sym::simd_reduce_min_nanless => {
@@ -751,7 +708,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
-            simd_reduce(fx, v, None, ret, &|fx, ty, a, b| {
+            simd_reduce_tree(fx, v, ret, &|fx, ty, a, b| {
                 let lt = match ty.kind() {
                     ty::Int(_) => fx.bcx.ins().icmp(IntCC::SignedLessThan, a, b),
                     ty::Uint(_) => fx.bcx.ins().icmp(IntCC::UnsignedLessThan, a, b),
@@ -770,7 +727,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
-            simd_reduce(fx, v, None, ret, &|fx, ty, a, b| {
+            simd_reduce_tree(fx, v, ret, &|fx, ty, a, b| {
                 let gt = match ty.kind() {
                     ty::Int(_) => fx.bcx.ins().icmp(IntCC::SignedGreaterThan, a, b),
                     ty::Uint(_) => fx.bcx.ins().icmp(IntCC::UnsignedGreaterThan, a, b),
@@ -1160,3 +1117,53 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
     let ret_block = fx.get_block(target);
     fx.bcx.ins().jump(ret_block, &[]);
 }
+
+/// Lower `simd_shuffle` and `simd_shuffle_generic` once the shuffle indexes are known. Indexes
+/// below the lane count of `x` select a lane of `x`, the rest select a lane of `y`.
+///
+/// Returns `false` if an index is out of bounds. In that case an error has been reported and the
+/// current block has been terminated, so the caller must not emit the jump to the return block.
+fn codegen_shuffle<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    intrinsic: Symbol,
+    span: Span,
+    x: CValue<'tcx>,
+    y: CValue<'tcx>,
+    indexes: &[u32],
+    ret: CPlace<'tcx>,
+) -> bool {
+    assert_eq!(x.layout(), y.layout());
+    let (lane_count, lane_ty) = x.layout().ty.simd_size_and_type(fx.tcx);
+    let (ret_lane_count, ret_lane_ty) = ret.layout().ty.simd_size_and_type(fx.tcx);
+
+    assert_eq!(lane_ty, ret_lane_ty);
+    assert_eq!(indexes.len() as u64, ret_lane_count);
+
+    let total_len = lane_count * 2;
+    for (i, &idx) in indexes.iter().enumerate() {
+        if u64::from(idx) >= total_len {
+            fx.tcx.dcx().span_err(
+                span,
+                format!(
+                    "invalid monomorphization of `{intrinsic}` intrinsic: shuffle index #{i} is out \
+                    of bounds (limit {total_len})"
+                ),
+            );
+            // Prevent verifier error
+            fx.bcx.ins().trap(TrapCode::UnreachableCodeReached);
+            return false;
+        }
+    }
+
+    for (out_idx, &in_idx) in indexes.iter().enumerate() {
+        let in_lane = if u64::from(in_idx) < lane_count {
+            x.value_lane(fx, in_idx.into())
+        } else {
+            y.value_lane(fx, u64::from(in_idx) - lane_count)
+        };
+        let out_lane = ret.place_lane(fx, u64::try_from(out_idx).unwrap());
+        out_lane.write_cvalue(fx, in_lane);
+    }
+
+    true
+}
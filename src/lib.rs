@@ -10,6 +10,7 @@
 #![warn(unreachable_pub)]
 
 extern crate jobserver;
+extern crate md5;
 #[macro_use]
 extern crate rustc_middle;
 extern crate rustc_ast;
@@ -37,13 +38,15 @@
 use cranelift_codegen::settings::{self, Configurable};
 use rustc_codegen_ssa::traits::CodegenBackend;
 use rustc_codegen_ssa::CodegenResults;
+use rustc_data_structures::fx::FxHashSet;
 use rustc_data_structures::profiling::SelfProfilerRef;
 use rustc_errors::ErrorGuaranteed;
 use rustc_metadata::EncodedMetadata;
 use rustc_middle::dep_graph::{WorkProduct, WorkProductId};
-use rustc_session::config::OutputFilenames;
+use rustc_session::config::{OutputFilenames, OutputType};
 use rustc_session::Session;
 use rustc_span::{sym, Symbol};
+use rustc_target::spec::TlsModel;
 
 pub use crate::config::*;
 use crate::prelude::*;
@@ -60,6 +63,7 @@
 mod concurrency_limiter;
 mod config;
 mod constant;
+mod coverage;
 mod debuginfo;
 mod discriminant;
 mod driver;
@@ -69,9 +73,13 @@
 mod linkage;
 mod main_shim;
 mod num;
+mod object_cache;
 mod optimize;
 mod pointer;
 mod pretty_clif;
+mod profile_use;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod toolchain;
 mod trap;
 mod unsize;
@@ -127,10 +135,22 @@ struct CodegenCx {
     profiler: SelfProfilerRef,
     output_filenames: Arc<OutputFilenames>,
     should_write_ir: bool,
+    module_clif: Option<String>,
+    /// The disassembly of all functions when `--emit asm` is used.
+    module_asm: Option<String>,
     global_asm: String,
     inline_asm_index: Cell<usize>,
     debug_context: Option<DebugContext>,
     unwind_context: UnwindContext,
+    coverage_context: Option<crate::coverage::CoverageContext>,
+    /// The profile passed to `-Cprofile-use`. Only used in AOT mode.
+    profile_use: Option<Arc<crate::profile_use::ProfileUse>>,
+    /// Functions with `#[link_section]`. Only used in AOT mode, the JIT ignores `#[link_section]`.
+    functions_in_sections: Option<Vec<crate::base::FunctionInSection>>,
+    panic_immediate_abort: bool,
+    ub_checks: bool,
+    print_layout: Option<String>,
+    printed_layouts: FxHashSet<String>,
     cgu_name: Symbol,
 }
 
@@ -144,21 +164,54 @@ fn new(
     ) -> Self {
         assert_eq!(pointer_ty(tcx), isa.pointer_type());
 
-        let unwind_context =
-            UnwindContext::new(isa, matches!(backend_config.codegen_mode, CodegenMode::Aot));
+        let unwind_context = UnwindContext::new(
+            isa,
+            matches!(backend_config.codegen_mode, CodegenMode::Aot) && isa.flags().is_pic(),
+            tcx.sess.must_emit_unwind_tables(),
+        );
         let debug_context = if debug_info && !tcx.sess.target.options.is_like_windows {
             Some(DebugContext::new(tcx, isa))
         } else {
             None
         };
+        let instrument =
+            tcx.sess.instrument_coverage() || tcx.sess.opts.cg.profile_generate.enabled();
+        let coverage_context = if instrument {
+            if !matches!(backend_config.codegen_mode, CodegenMode::Aot) {
+                tcx.dcx().fatal(
+                    "-Cinstrument-coverage and -Cprofile-generate are not supported in JIT mode",
+                );
+            }
+            Some(crate::coverage::CoverageContext::new(tcx))
+        } else {
+            None
+        };
         CodegenCx {
             profiler: tcx.prof.clone(),
             output_filenames: tcx.output_filenames(()).clone(),
             should_write_ir: crate::pretty_clif::should_write_ir(tcx),
+            module_clif: backend_config
+                .dump_module
+                .is_some()
+                .then(|| crate::pretty_clif::clif_header(isa)),
+            module_asm: tcx
+                .sess
+                .opts
+                .output_types
+                .contains_key(&OutputType::Assembly)
+                .then(String::new),
             global_asm: String::new(),
             inline_asm_index: Cell::new(0),
             debug_context,
             unwind_context,
+            coverage_context,
+            profile_use: None,
+            functions_in_sections: matches!(backend_config.codegen_mode, CodegenMode::Aot)
+                .then(Vec::new),
+            panic_immediate_abort: backend_config.panic_immediate_abort,
+            ub_checks: backend_config.ub_checks.unwrap_or(tcx.sess.opts.debug_assertions),
+            print_layout: backend_config.print_layout,
+            printed_layouts: FxHashSet::default(),
             cgu_name,
         }
     }
@@ -177,10 +230,36 @@ fn locale_resource(&self) -> &'static str {
     fn init(&self, sess: &Session) {
         use rustc_session::config::Lto;
         match sess.lto() {
+            // Local ThinLTO is enabled by default for optimized builds with multiple codegen
+            // units. Cranelift doesn't do cross-module optimizations anyway.
             Lto::No | Lto::ThinLocal => {}
-            Lto::Thin | Lto::Fat => {
-                sess.dcx().warn("LTO is not supported. You may get a linker error.")
+            // See `upstream_objects_for_lto` for how linking is made to work without LTO.
+            Lto::Thin => {
+                sess.dcx().warn("ThinLTO is not supported by Cranelift; no LTO is performed")
             }
+            Lto::Fat => sess.dcx().warn("LTO is not supported by Cranelift; no LTO is performed"),
+        }
+
+        // Cranelift only implements the general dynamic model for ELF, so thread locals keep being
+        // accessed through `__tls_get_addr`.
+        match sess.opts.unstable_opts.tls_model {
+            None | Some(TlsModel::GeneralDynamic) => {}
+            Some(_) => sess.dcx().warn(
+                "`-Ztls-model` is not yet supported by Cranelift. Thread locals are accessed using \
+                the general dynamic model.",
+            ),
+        }
+
+        // `-Cembed-bitcode` is accepted but ignored. Embedding an empty bitcode section would
+        // make rustc try to use it when performing LTO with the LLVM backend.
+
+        // `--emit llvm-ir` is used to write the clif ir instead. There is no equivalent for
+        // bitcode.
+        if sess.opts.output_types.contains_key(&OutputType::Bitcode) {
+            sess.dcx().fatal(
+                "`--emit llvm-bc` is not supported by the Cranelift backend as it doesn't produce \
+                LLVM bitcode",
+            );
         }
 
         let mut config = self.config.borrow_mut();
@@ -192,16 +271,7 @@ fn init(&self, sess: &Session) {
     }
 
     fn target_features(&self, sess: &Session, _allow_unstable: bool) -> Vec<rustc_span::Symbol> {
-        // FIXME return the actually used target features. this is necessary for #[cfg(target_feature)]
-        if sess.target.arch == "x86_64" && sess.target.os != "none" {
-            // x86_64 mandates SSE2 support
-            vec![Symbol::intern("fxsr"), sym::sse, Symbol::intern("sse2")]
-        } else if sess.target.arch == "aarch64" && sess.target.os != "none" {
-            // AArch64 mandates Neon support
-            vec![sym::neon]
-        } else {
-            vec![]
-        }
+        target_features(sess)
     }
 
     fn print_version(&self) {
@@ -215,6 +285,11 @@ fn codegen_crate(
         need_metadata_module: bool,
     ) -> Box<dyn Any> {
         tcx.dcx().abort_if_errors();
+        // Tell `rustc-clif --with-llvm-backend-fallback` that the frontend succeeded, so that it
+        // only falls back to the LLVM backend for errors during codegen.
+        if let Some(marker) = std::env::var_os("__CG_CLIF_CODEGEN_STARTED_MARKER") {
+            let _ = std::fs::write(marker, "");
+        }
         let config = self.config.borrow().clone().unwrap();
         match config.codegen_mode {
             CodegenMode::Aot => driver::aot::run_aot(tcx, config, metadata, need_metadata_module),
@@ -232,12 +307,12 @@ fn join_codegen(
         &self,
         ongoing_codegen: Box<dyn Any>,
         sess: &Session,
-        _outputs: &OutputFilenames,
+        outputs: &OutputFilenames,
     ) -> (CodegenResults, FxIndexMap<WorkProductId, WorkProduct>) {
         ongoing_codegen
             .downcast::<driver::aot::OngoingCodegen>()
             .unwrap()
-            .join(sess, self.config.borrow().as_ref().unwrap())
+            .join(sess, outputs, self.config.borrow().as_ref().unwrap())
     }
 
     fn link(
@@ -259,13 +334,173 @@ fn target_triple(sess: &Session) -> target_lexicon::Triple {
     }
 }
 
+/// Cranelift ISA flags and the rustc target features they correspond to.
+const ISA_FLAG_TARGET_FEATURES: &[(&str, &str)] = &[
+    ("has_sse3", "sse3"),
+    ("has_ssse3", "ssse3"),
+    ("has_sse41", "sse4.1"),
+    ("has_sse42", "sse4.2"),
+    ("has_popcnt", "popcnt"),
+    ("has_avx", "avx"),
+    ("has_avx2", "avx2"),
+    ("has_fma", "fma"),
+    ("has_bmi1", "bmi1"),
+    ("has_bmi2", "bmi2"),
+    ("has_lzcnt", "lzcnt"),
+    ("has_avx512f", "avx512f"),
+    ("has_avx512dq", "avx512dq"),
+    ("has_avx512vl", "avx512vl"),
+    ("has_avx512vbmi", "avx512vbmi"),
+    ("has_avx512bitalg", "avx512bitalg"),
+    ("has_lse", "lse"),
+];
+
+/// Target features implied by another target feature in the same way as LLVM does.
+const IMPLIED_TARGET_FEATURES: &[(&str, &str)] = &[
+    ("sse2", "sse"),
+    ("sse3", "sse2"),
+    ("ssse3", "sse3"),
+    ("sse4.1", "ssse3"),
+    ("sse4.2", "sse4.1"),
+    ("avx", "sse4.2"),
+    ("avx2", "avx"),
+    ("fma", "avx"),
+    ("avx512f", "avx2"),
+    ("avx512f", "fma"),
+    ("avx512dq", "avx512f"),
+    ("avx512vl", "avx512f"),
+    ("avx512vbmi", "avx512f"),
+    ("avx512bitalg", "avx512f"),
+    ("avx512bw", "avx512f"),
+    ("avx512cd", "avx512f"),
+    ("avx512ifma", "avx512f"),
+    ("avx512vbmi2", "avx512f"),
+    ("avx512vnni", "avx512f"),
+    ("avx512vpopcntdq", "avx512f"),
+    ("avx512bf16", "avx512f"),
+    ("avx512fp16", "avx512f"),
+    ("avx512vp2intersect", "avx512f"),
+    ("avxvnni", "avx2"),
+    ("avxifma", "avx2"),
+    ("avxneconvert", "avx2"),
+    ("avxvnniint8", "avx2"),
+    ("avxvnniint16", "avx2"),
+    ("f16c", "avx"),
+    ("vaes", "avx"),
+    ("vaes", "aes"),
+    ("vpclmulqdq", "avx"),
+    ("vpclmulqdq", "pclmulqdq"),
+    ("aes", "sse2"),
+    ("pclmulqdq", "sse2"),
+    ("sha", "sse2"),
+    ("gfni", "sse2"),
+];
+
+/// Add the target features implied by any of `features` to `features`, transitively.
+fn add_implied_target_features(features: &mut Vec<&str>) {
+    let mut i = 0;
+    while i < features.len() {
+        for &(feature, implied) in IMPLIED_TARGET_FEATURES {
+            if feature == features[i] && !features.contains(&implied) {
+                features.push(implied);
+            }
+        }
+        i += 1;
+    }
+}
+
+/// Remove `feature` and every feature implying it from `features`, transitively.
+fn remove_implying_target_features<'a>(features: &mut Vec<&'a str>, feature: &'a str) {
+    let mut removed = vec![feature];
+    let mut i = 0;
+    while i < removed.len() {
+        for &(implying, implied) in IMPLIED_TARGET_FEATURES {
+            if implied == removed[i] && !removed.contains(&implying) {
+                removed.push(implying);
+            }
+        }
+        i += 1;
+    }
+    features.retain(|feature| !removed.contains(feature));
+}
+
+/// Compute the target features for `#[cfg(target_feature)]` from the target, `-Ctarget-cpu` and
+/// `-Ctarget-feature` the same way as the LLVM backend does.
+fn target_features(sess: &Session) -> Vec<Symbol> {
+    let mut features: Vec<&str> = if sess.target.arch == "x86_64" && sess.target.os != "none" {
+        // x86_64 mandates SSE2 support
+        vec!["fxsr", "sse", "sse2"]
+    } else if sess.target.arch == "aarch64" && sess.target.os != "none" {
+        // AArch64 mandates Neon support
+        vec!["neon"]
+    } else {
+        vec![]
+    };
+
+    // Unlike for codegen the baseline cpu of the target is used when no cpu is specified to match
+    // the features reported by the LLVM backend.
+    if let Some(target_cpu) = sess.opts.cg.target_cpu.as_deref() {
+        let isa_builder = match target_cpu {
+            "native" => cranelift_native::builder_with_options(true).ok(),
+            _ => match cranelift_codegen::isa::lookup(target_triple(sess)) {
+                Ok(mut builder) if builder.enable(target_cpu).is_ok() => Some(builder),
+                _ => None,
+            },
+        };
+        let flags = settings::Flags::new(settings::builder());
+        if let Some(Ok(isa)) = isa_builder.map(|builder| builder.finish(flags)) {
+            for flag in isa.isa_flags() {
+                if flag.as_bool() != Some(true) {
+                    continue;
+                }
+                if let Some(&(_, feature)) =
+                    ISA_FLAG_TARGET_FEATURES.iter().find(|&&(name, _)| name == flag.name)
+                {
+                    features.push(feature);
+                }
+            }
+        }
+    }
+
+    // Like LLVM, `+feature` also enables the features it implies and `-feature` also disables every
+    // feature implying it. Otherwise a feature disabled with `-Ctarget-feature` could be re-enabled
+    // by a feature of the target cpu which implies it.
+    add_implied_target_features(&mut features);
+    for feature in sess.target.features.split(',').chain(sess.opts.cg.target_feature.split(',')) {
+        if let Some(feature) = feature.strip_prefix('+') {
+            features.push(feature);
+            add_implied_target_features(&mut features);
+        } else if let Some(feature) = feature.strip_prefix('-') {
+            remove_implying_target_features(&mut features, feature);
+        }
+    }
+
+    let supported_features = rustc_codegen_ssa::target_features::supported_target_features(sess);
+    let mut features = features
+        .into_iter()
+        .filter(|&feature| supported_features.iter().any(|&(name, _)| name == feature))
+        .map(Symbol::intern)
+        .collect::<Vec<_>>();
+    features.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    features.dedup();
+    features
+}
+
 fn build_isa(sess: &Session, backend_config: &BackendConfig) -> Arc<dyn isa::TargetIsa + 'static> {
     use target_lexicon::BinaryFormat;
 
     let target_triple = crate::target_triple(sess);
 
     let mut flags_builder = settings::builder();
-    flags_builder.enable("is_pic").unwrap();
+    // The JIT always uses PIC as code and data may be placed anywhere in memory.
+    let is_pic = match backend_config.codegen_mode {
+        CodegenMode::Aot => !matches!(
+            sess.relocation_model(),
+            rustc_target::spec::RelocModel::Static | rustc_target::spec::RelocModel::DynamicNoPic
+        ),
+        CodegenMode::Jit | CodegenMode::JitLazy => true,
+    };
+    flags_builder.set("is_pic", if is_pic { "true" } else { "false" }).unwrap();
     let enable_verifier = if backend_config.enable_verifier { "true" } else { "false" };
     flags_builder.set("enable_verifier", enable_verifier).unwrap();
     flags_builder.set("regalloc_checker", enable_verifier).unwrap();
@@ -277,6 +512,8 @@ fn build_isa(sess: &Session, backend_config: &BackendConfig) -> Arc<dyn isa::Tar
         .set("preserve_frame_pointers", if preserve_frame_pointer { "true" } else { "false" })
         .unwrap();
 
+    // On Mach-O Cranelift accesses thread locals through the tlv descriptor in `__thread_vars`
+    // which is initialized by `_tlv_bootstrap`, the same scheme LLVM uses.
     let tls_model = match target_triple.binary_format {
         BinaryFormat::Elf => "elf_gd",
         BinaryFormat::Macho => "macho",
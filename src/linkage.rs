@@ -3,6 +3,7 @@
 use crate::prelude::*;
 
 pub(crate) fn get_clif_linkage(
+    tcx: TyCtxt<'_>,
     mono_item: MonoItem<'_>,
     linkage: RLinkage,
     visibility: Visibility,
@@ -11,9 +12,19 @@ pub(crate) fn get_clif_linkage(
     match (linkage, visibility) {
         (RLinkage::External, Visibility::Default) if is_compiler_builtins => Linkage::Hidden,
         (RLinkage::External, Visibility::Default) => Linkage::Export,
-        (RLinkage::Internal, Visibility::Default) => Linkage::Local,
+        (RLinkage::Internal | RLinkage::Private, Visibility::Default) => Linkage::Local,
         (RLinkage::External, Visibility::Hidden) => Linkage::Hidden,
-        (RLinkage::WeakAny, Visibility::Default) => Linkage::Preemptible,
+        (
+            RLinkage::WeakAny
+            | RLinkage::WeakODR
+            | RLinkage::LinkOnceAny
+            | RLinkage::LinkOnceODR
+            | RLinkage::Common,
+            Visibility::Default,
+        ) => Linkage::Preemptible,
+        (RLinkage::Appending, _) => {
+            tcx.dcx().fatal(format!("{mono_item:?}: appending linkage is not supported"))
+        }
         _ => panic!("{:?} = {:?} {:?}", mono_item, linkage, visibility),
     }
 }
@@ -24,8 +35,20 @@ pub(crate) fn get_static_linkage(tcx: TyCtxt<'_>, def_id: DefId) -> Linkage {
     if let Some(linkage) = fn_attrs.linkage {
         match linkage {
             RLinkage::External => Linkage::Export,
-            RLinkage::Internal => Linkage::Local,
-            RLinkage::ExternalWeak | RLinkage::WeakAny => Linkage::Preemptible,
+            RLinkage::Internal | RLinkage::Private => Linkage::Local,
+            // Cranelift doesn't distinguish between the different kinds of symbols which may be
+            // defined multiple times. Emitting all of them as weak definitions keeps the linker
+            // from reporting duplicate symbols. Unlike real common symbols, definitions of
+            // different sizes aren't merged.
+            RLinkage::ExternalWeak
+            | RLinkage::WeakAny
+            | RLinkage::WeakODR
+            | RLinkage::LinkOnceAny
+            | RLinkage::LinkOnceODR
+            | RLinkage::Common => Linkage::Preemptible,
+            RLinkage::Appending => {
+                tcx.dcx().span_fatal(tcx.def_span(def_id), "appending linkage is not supported")
+            }
             _ => panic!("{:?}", linkage),
         }
     } else if tcx.is_reachable_non_generic(def_id) {
@@ -83,6 +83,46 @@ pub(crate) fn codegen_binop<'tcx>(
     }
 }
 
+/// The caller of `unchecked_div` and `unchecked_rem` promises that the divisor is non-zero and that
+/// the division doesn't overflow, which is verified here. Divisors known at compile time to be
+/// valid don't need the check.
+pub(crate) fn codegen_unchecked_div_ub_check<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    in_lhs: CValue<'tcx>,
+    in_rhs: CValue<'tcx>,
+) {
+    let signed = type_sign(in_lhs.layout().ty);
+    let rhs = in_rhs.load_scalar(fx);
+    let clif_ty = fx.bcx.func.dfg.value_type(rhs);
+
+    // 128bit constants are not materialized using a single `iconst`.
+    let known_divisor = crate::optimize::peephole::maybe_known_iconst(&fx.bcx, rhs)
+        .filter(|_| clif_ty.bits() <= 64);
+    if let Some(divisor) = known_divisor {
+        // The immediate isn't necessarily sign extended to 64 bits.
+        let shift = 64 - clif_ty.bits();
+        let divisor = (divisor << shift) >> shift;
+        if divisor != 0 && !(signed && divisor == -1) {
+            return;
+        }
+    }
+
+    let mut is_invalid = codegen_icmp_imm(fx, IntCC::Equal, rhs, 0);
+    if signed {
+        let lhs = in_lhs.load_scalar(fx);
+        let (min, _max) = type_min_max_value(&mut fx.bcx, clif_ty, signed);
+        let lhs_is_min = fx.bcx.ins().icmp(IntCC::Equal, lhs, min);
+        let rhs_is_minus_one = codegen_icmp_imm(fx, IntCC::Equal, rhs, -1);
+        let is_overflow = fx.bcx.ins().band(lhs_is_min, rhs_is_minus_one);
+        is_invalid = fx.bcx.ins().bor(is_invalid, is_overflow);
+    }
+    crate::intrinsics::panic_nounwind_if(
+        fx,
+        is_invalid,
+        "`unchecked_div` or `unchecked_rem` called with a zero divisor or overflowing operands",
+    );
+}
+
 pub(crate) fn codegen_bool_binop<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     bin_op: BinOp,
@@ -168,6 +208,89 @@ pub(crate) fn codegen_int_binop<'tcx>(
     CValue::by_val(val, in_lhs.layout())
 }
 
+/// Lowers the `exact_div` intrinsic. The caller promises that `in_lhs` is a multiple of `in_rhs`,
+/// which allows dividing by a constant without a division instruction. With UB checks enabled an
+/// inexact division panics.
+pub(crate) fn codegen_exact_div<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    in_lhs: CValue<'tcx>,
+    in_rhs: CValue<'tcx>,
+) -> CValue<'tcx> {
+    assert_eq!(
+        in_lhs.layout().ty,
+        in_rhs.layout().ty,
+        "exact_div requires lhs and rhs of same type"
+    );
+
+    if fx.cx.ub_checks {
+        let rem = codegen_int_binop(fx, BinOp::Rem, in_lhs, in_rhs).load_scalar(fx);
+        let is_inexact = codegen_icmp_imm(fx, IntCC::NotEqual, rem, 0);
+        crate::intrinsics::panic_nounwind_if(
+            fx,
+            is_inexact,
+            "`exact_div` called with an inexact division",
+        );
+    }
+
+    let rhs = in_rhs.load_scalar(fx);
+    // 128bit constants are not materialized using a single `iconst`.
+    match crate::optimize::peephole::maybe_known_iconst(&fx.bcx, rhs) {
+        Some(divisor) => {
+            let signed = type_sign(in_lhs.layout().ty);
+            let lhs = in_lhs.load_scalar(fx);
+            let res = codegen_exact_div_imm(fx, lhs, divisor, signed);
+            CValue::by_val(res, in_lhs.layout())
+        }
+        None => codegen_int_binop(fx, BinOp::Div, in_lhs, in_rhs),
+    }
+}
+
+/// Divides `lhs` by the constant `divisor` assuming that the division is exact.
+///
+/// For `divisor = odd << k` the quotient of an exact division is `(lhs >> k) * inverse(odd)`, where
+/// `inverse(odd)` is the multiplicative inverse of `odd` modulo `2^bits`. This avoids the division
+/// instruction. For signed divisions `odd` keeps the sign of the divisor.
+pub(crate) fn codegen_exact_div_imm(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    lhs: Value,
+    divisor: i64,
+    signed: bool,
+) -> Value {
+    let bits = fx.bcx.func.dfg.value_type(lhs).bits();
+    assert!(bits <= 64);
+    let mask = u64::MAX >> (64 - bits);
+    let divisor = if signed {
+        (divisor << (64 - bits)) >> (64 - bits)
+    } else {
+        (divisor as u64 & mask) as i64
+    };
+
+    if divisor == 0 {
+        // Dividing by zero is UB. Trap like `/` does.
+        return if signed { fx.bcx.ins().sdiv_imm(lhs, 0) } else { fx.bcx.ins().udiv_imm(lhs, 0) };
+    }
+
+    let shift = divisor.trailing_zeros();
+    let odd = if signed { divisor >> shift } else { (divisor as u64 >> shift) as i64 };
+
+    // Every odd number is its own inverse modulo 8. Each Newton iteration doubles the number of
+    // correct low bits, so five iterations give all 64 bits.
+    let mut inverse = odd;
+    for _ in 0..5 {
+        inverse = inverse.wrapping_mul(2i64.wrapping_sub(odd.wrapping_mul(inverse)));
+    }
+
+    // The low `shift` bits of `lhs` are zero for an exact division, so the shift is exact too.
+    let shifted = if shift == 0 {
+        lhs
+    } else if signed {
+        fx.bcx.ins().sshr_imm(lhs, i64::from(shift))
+    } else {
+        fx.bcx.ins().ushr_imm(lhs, i64::from(shift))
+    };
+    fx.bcx.ins().imul_imm(shifted, (inverse as u64 & mask) as i64)
+}
+
 pub(crate) fn codegen_checked_int_binop<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     bin_op: BinOp,
@@ -362,6 +485,48 @@ pub(crate) fn codegen_float_binop<'tcx>(
     CValue::by_val(res, in_lhs.layout())
 }
 
+/// Offsets `base` by `offset` elements of the pointee type.
+///
+/// An `inbounds` offset (`ptr::offset`) may not wrap around the address space, while a wrapping
+/// offset (`ptr::wrapping_offset`) may. Cranelift can't express the non-wrapping assumption, so
+/// both produce the same address computation, but with UB checks enabled an `inbounds` offset
+/// that overflows panics.
+pub(crate) fn codegen_ptr_offset<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    base: CValue<'tcx>,
+    offset: CValue<'tcx>,
+    inbounds: bool,
+) -> CValue<'tcx> {
+    let pointee_ty = base.layout().ty.builtin_deref(true).unwrap().ty;
+    let pointee_size = fx.layout_of(pointee_ty).size.bytes();
+    let offset = offset.load_scalar(fx);
+    let base_val = base.load_scalar(fx);
+
+    if !inbounds || !fx.cx.ub_checks {
+        let ptr_diff = if pointee_size != 1 {
+            fx.bcx.ins().imul_imm(offset, pointee_size as i64)
+        } else {
+            offset
+        };
+        let res = fx.bcx.ins().iadd(base_val, ptr_diff);
+        return CValue::by_val(res, base.layout());
+    }
+
+    let pointee_size_val = fx.bcx.ins().iconst(fx.pointer_type, pointee_size as i64);
+    let (ptr_diff, mul_overflow) = fx.bcx.ins().smul_overflow(offset, pointee_size_val);
+    let res = fx.bcx.ins().iadd(base_val, ptr_diff);
+
+    // Adding a non-negative offset must not decrease the address and adding a negative offset
+    // must decrease it.
+    let res_below_base = fx.bcx.ins().icmp(IntCC::UnsignedLessThan, res, base_val);
+    let diff_is_negative = fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, ptr_diff, 0);
+    let add_overflow = fx.bcx.ins().bxor(res_below_base, diff_is_negative);
+    let has_overflow = fx.bcx.ins().bor(mul_overflow, add_overflow);
+    crate::intrinsics::panic_nounwind_if(fx, has_overflow, "in-bounds pointer offset overflowed");
+
+    CValue::by_val(res, base.layout())
+}
+
 pub(crate) fn codegen_ptr_binop<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     bin_op: BinOp,
@@ -383,15 +548,7 @@ pub(crate) fn codegen_ptr_binop<'tcx>(
 
                 codegen_compare_bin_op(fx, bin_op, false, lhs, rhs)
             }
-            BinOp::Offset => {
-                let pointee_ty = in_lhs.layout().ty.builtin_deref(true).unwrap().ty;
-                let (base, offset) = (in_lhs, in_rhs.load_scalar(fx));
-                let pointee_size = fx.layout_of(pointee_ty).size.bytes();
-                let ptr_diff = fx.bcx.ins().imul_imm(offset, pointee_size as i64);
-                let base_val = base.load_scalar(fx);
-                let res = fx.bcx.ins().iadd(base_val, ptr_diff);
-                CValue::by_val(res, base.layout())
-            }
+            BinOp::Offset => codegen_ptr_offset(fx, in_lhs, in_rhs, true),
             _ => unreachable!("{:?}({:?}, {:?})", bin_op, in_lhs, in_rhs),
         }
     } else {
@@ -0,0 +1,256 @@
+//! A content addressed cache of the object files of codegen units, enabled using
+//! `CG_CLIF_OBJECT_CACHE_DIR`.
+//!
+//! The key of a codegen unit is a hash of everything that ends up in its object file: the final
+//! clif ir of every function, the contents of every data object, all function and data
+//! declarations and the target configuration. In addition it covers the rustc version and the
+//! codegen backend dylib itself, so a rebuilt backend never reuses objects of an older build. All
+//! of this is collected before any function is compiled, which is the expensive part skipped on a
+//! cache hit.
+//!
+//! Codegen units with debuginfo are never cached as the debuginfo refers to source locations and
+//! is emitted separately from the clif ir. Codegen units with global asm are not cached either as
+//! the external assembler isn't covered by the key.
+
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cranelift_codegen::control::ControlPlane;
+use cranelift_codegen::FinalizedMachReloc;
+use cranelift_module::{DataId, ModuleDeclarations, ModuleResult};
+use cranelift_object::ObjectModule;
+use rustc_data_structures::fingerprint::Fingerprint;
+use rustc_data_structures::stable_hasher::StableHasher;
+use rustc_session::config::{DebugInfo, OutputFilenames, OutputType};
+use rustc_session::Session;
+
+use crate::base::CodegenedFunction;
+use crate::prelude::*;
+use crate::BackendConfig;
+
+#[derive(Debug)]
+pub(crate) struct ObjectCache {
+    dir: PathBuf,
+    /// Hash of everything outside of the codegen unit itself which affects the object file.
+    session_hash: Fingerprint,
+}
+
+impl ObjectCache {
+    pub(crate) fn new(tcx: TyCtxt<'_>, backend_config: &BackendConfig) -> Option<Arc<Self>> {
+        let dir = backend_config.object_cache_dir.as_ref()?;
+        if tcx.sess.opts.debuginfo != DebugInfo::None
+            || crate::pretty_clif::should_write_ir(tcx)
+            || backend_config.dump_module.is_some()
+            || tcx.sess.opts.output_types.contains_key(&OutputType::Assembly)
+        {
+            return None;
+        }
+
+        if let Err(err) = fs::create_dir_all(dir) {
+            let dir = dir.display();
+            tcx.dcx().warn(format!("object cache disabled: failed to create {dir}: {err}"));
+            return None;
+        }
+
+        let mut hasher = StableHasher::new();
+        crate::debuginfo::producer(tcx.sess).hash(&mut hasher);
+        if let Err(err) = hash_codegen_backend(tcx.sess, &mut hasher) {
+            tcx.dcx().warn(format!("object cache disabled: {err}"));
+            return None;
+        }
+        format!("{:?}", tcx.sess.target).hash(&mut hasher);
+        tcx.sess.opts.unstable_opts.function_sections.hash(&mut hasher);
+        tcx.sess.must_emit_unwind_tables().hash(&mut hasher);
+
+        Some(Arc::new(ObjectCache { dir: dir.clone(), session_hash: hasher.finish() }))
+    }
+
+    /// Compute the key of a codegen unit. Must be called after all functions are codegened, but
+    /// before any of them is compiled.
+    pub(crate) fn key(
+        &self,
+        module: &mut HashingModule,
+        cgu_name: &str,
+        codegened_functions: &[CodegenedFunction],
+        used_statics: &[DataId],
+    ) -> String {
+        let mut hasher = module.hasher.take().expect("module is not hashed for the object cache");
+        self.session_hash.hash(&mut hasher);
+        cgu_name.hash(&mut hasher);
+
+        let isa = module.isa();
+        isa.triple().to_string().hash(&mut hasher);
+        isa.flags().to_string().hash(&mut hasher);
+        for isa_flag in isa.isa_flags() {
+            isa_flag.to_string().hash(&mut hasher);
+        }
+
+        let declarations = module.declarations();
+        for (func_id, decl) in declarations.get_functions() {
+            (func_id.as_u32(), format!("{decl:?}")).hash(&mut hasher);
+        }
+        for (data_id, decl) in declarations.get_data_objects() {
+            (data_id.as_u32(), format!("{decl:?}")).hash(&mut hasher);
+        }
+
+        for codegened_func in codegened_functions {
+            codegened_func.hash_for_object_cache(&mut hasher);
+        }
+        for data_id in used_statics {
+            data_id.as_u32().hash(&mut hasher);
+        }
+
+        hasher.finish::<Fingerprint>().to_hex()
+    }
+
+    /// Copy the cached object file for `key` to the location where the object file of `cgu_name`
+    /// would have been written.
+    pub(crate) fn lookup(
+        &self,
+        key: &str,
+        output_filenames: &OutputFilenames,
+        cgu_name: &str,
+    ) -> Option<PathBuf> {
+        let cached = self.dir.join(format!("{key}.o"));
+        let object = output_filenames.temp_path(OutputType::Object, Some(cgu_name));
+        // Copy rather than hard link to prevent later modifications of the output from corrupting
+        // the cache.
+        fs::copy(cached, &object).ok()?;
+        Some(object)
+    }
+
+    /// Add the object file for `key` to the cache. This is best effort as failing to populate the
+    /// cache doesn't affect the current build.
+    pub(crate) fn store(&self, key: &str, object: &Path) {
+        // Write to a temporary file first such that concurrent builds never observe a partially
+        // written object file.
+        let tmp = self.dir.join(format!("{key}.o.tmp{}", std::process::id()));
+        if fs::copy(object, &tmp).is_err()
+            || fs::rename(&tmp, self.dir.join(format!("{key}.o"))).is_err()
+        {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+}
+
+/// Hash the dylib of the codegen backend, using the same lookup as rustc.
+fn hash_codegen_backend(sess: &Session, hasher: &mut StableHasher) -> Result<(), String> {
+    let backend = sess.opts.unstable_opts.codegen_backend.as_deref().unwrap_or("cranelift");
+    let path = if backend.contains('.') {
+        PathBuf::from(backend)
+    } else {
+        let dir = sess
+            .sysroot
+            .join("lib")
+            .join("rustlib")
+            .join(rustc_session::config::host_triple())
+            .join("codegen-backends");
+        let prefix = format!("{}rustc_codegen_{backend}", std::env::consts::DLL_PREFIX);
+        fs::read_dir(&dir)
+            .ok()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .find(|path| {
+                path.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+                    name.starts_with(&prefix) && name.ends_with(std::env::consts::DLL_SUFFIX)
+                })
+            })
+            .ok_or_else(|| format!("failed to find the codegen backend in {}", dir.display()))?
+    };
+
+    let dylib = fs::read(&path)
+        .map_err(|err| format!("failed to read codegen backend {}: {err}", path.display()))?;
+    dylib.hash(hasher);
+    Ok(())
+}
+
+/// Wraps the [`ObjectModule`] of a codegen unit to hash all functions and data objects defined
+/// while the codegen unit is codegened.
+pub(crate) struct HashingModule {
+    module: ObjectModule,
+    hasher: Option<StableHasher>,
+}
+
+impl HashingModule {
+    pub(crate) fn new(module: ObjectModule, object_cache: Option<&ObjectCache>) -> Self {
+        HashingModule { module, hasher: object_cache.map(|_| StableHasher::new()) }
+    }
+
+    pub(crate) fn into_inner(self) -> ObjectModule {
+        self.module
+    }
+}
+
+impl Module for HashingModule {
+    fn isa(&self) -> &dyn isa::TargetIsa {
+        self.module.isa()
+    }
+
+    fn declarations(&self) -> &ModuleDeclarations {
+        self.module.declarations()
+    }
+
+    fn declare_function(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        signature: &Signature,
+    ) -> ModuleResult<FuncId> {
+        self.module.declare_function(name, linkage, signature)
+    }
+
+    fn declare_anonymous_function(&mut self, signature: &Signature) -> ModuleResult<FuncId> {
+        self.module.declare_anonymous_function(signature)
+    }
+
+    fn declare_data(
+        &mut self,
+        name: &str,
+        linkage: Linkage,
+        writable: bool,
+        tls: bool,
+    ) -> ModuleResult<DataId> {
+        self.module.declare_data(name, linkage, writable, tls)
+    }
+
+    fn declare_anonymous_data(&mut self, writable: bool, tls: bool) -> ModuleResult<DataId> {
+        self.module.declare_anonymous_data(writable, tls)
+    }
+
+    fn define_function_with_control_plane(
+        &mut self,
+        func: FuncId,
+        ctx: &mut Context,
+        ctrl_plane: &mut ControlPlane,
+    ) -> ModuleResult<()> {
+        if let Some(hasher) = &mut self.hasher {
+            func.as_u32().hash(hasher);
+            ctx.func.display().to_string().hash(hasher);
+        }
+        self.module.define_function_with_control_plane(func, ctx, ctrl_plane)
+    }
+
+    fn define_function_bytes(
+        &mut self,
+        func_id: FuncId,
+        func: &Function,
+        alignment: u64,
+        bytes: &[u8],
+        relocs: &[FinalizedMachReloc],
+    ) -> ModuleResult<()> {
+        if let Some(hasher) = &mut self.hasher {
+            (func_id.as_u32(), alignment, bytes, format!("{relocs:?}")).hash(hasher);
+        }
+        self.module.define_function_bytes(func_id, func, alignment, bytes, relocs)
+    }
+
+    fn define_data(&mut self, data_id: DataId, data: &DataDescription) -> ModuleResult<()> {
+        if let Some(hasher) = &mut self.hasher {
+            (data_id.as_u32(), format!("{data:?}")).hash(hasher);
+        }
+        self.module.define_data(data_id, data)
+    }
+}
@@ -45,3 +45,37 @@ pub(crate) fn maybe_known_branch_taken(
         _ => None,
     }
 }
+
+/// Returns the immediate of the given value if it was produced by an `iconst` instruction.
+pub(crate) fn maybe_known_iconst(bcx: &FunctionBuilder<'_>, arg: Value) -> Option<i64> {
+    let arg_inst = if let ValueDef::Result(arg_inst, 0) = bcx.func.dfg.value_def(arg) {
+        arg_inst
+    } else {
+        return None;
+    };
+
+    match bcx.func.dfg.insts[arg_inst] {
+        InstructionData::UnaryImm { opcode: Opcode::Iconst, imm } => Some(imm.bits()),
+        _ => None,
+    }
+}
+
+/// Returns whether the given value was produced by an `iconst`, `f32const` or `f64const`
+/// instruction or by an `iconcat` of such values.
+pub(crate) fn is_known_constant(bcx: &FunctionBuilder<'_>, arg: Value) -> bool {
+    let arg_inst = if let ValueDef::Result(arg_inst, 0) = bcx.func.dfg.value_def(arg) {
+        arg_inst
+    } else {
+        return false;
+    };
+
+    match bcx.func.dfg.insts[arg_inst] {
+        InstructionData::UnaryImm { opcode: Opcode::Iconst, .. }
+        | InstructionData::UnaryIeee32 { opcode: Opcode::F32const, .. }
+        | InstructionData::UnaryIeee64 { opcode: Opcode::F64const, .. } => true,
+        InstructionData::Binary { opcode: Opcode::Iconcat, args: [lo, hi] } => {
+            is_known_constant(bcx, lo) && is_known_constant(bcx, hi)
+        }
+        _ => false,
+    }
+}
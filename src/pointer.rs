@@ -3,6 +3,7 @@
 
 use cranelift_codegen::ir::immediates::Offset32;
 use rustc_target::abi::Align;
+use rustc_target::spec::SanitizerSet;
 
 use crate::prelude::*;
 
@@ -108,7 +109,10 @@ pub(crate) fn offset_value(self, fx: &mut FunctionCx<'_, '_, '_>, extra_offset:
 
     pub(crate) fn load(self, fx: &mut FunctionCx<'_, '_, '_>, ty: Type, flags: MemFlags) -> Value {
         match self.base {
-            PointerBase::Addr(base_addr) => fx.bcx.ins().load(ty, flags, base_addr, self.offset),
+            PointerBase::Addr(base_addr) => {
+                self.asan_check_access(fx, ty, false);
+                fx.bcx.ins().load(ty, flags, base_addr, self.offset)
+            }
             PointerBase::Stack(stack_slot) => fx.bcx.ins().stack_load(ty, stack_slot, self.offset),
             PointerBase::Dangling(_align) => unreachable!(),
         }
@@ -117,6 +121,8 @@ pub(crate) fn load(self, fx: &mut FunctionCx<'_, '_, '_>, ty: Type, flags: MemFl
     pub(crate) fn store(self, fx: &mut FunctionCx<'_, '_, '_>, value: Value, flags: MemFlags) {
         match self.base {
             PointerBase::Addr(base_addr) => {
+                let ty = fx.bcx.func.dfg.value_type(value);
+                self.asan_check_access(fx, ty, true);
                 fx.bcx.ins().store(flags, value, base_addr, self.offset);
             }
             PointerBase::Stack(stack_slot) => {
@@ -125,4 +131,34 @@ pub(crate) fn store(self, fx: &mut FunctionCx<'_, '_, '_>, value: Value, flags:
             PointerBase::Dangling(_align) => unreachable!(),
         }
     }
+
+    /// Call into the ASan runtime to check that the memory accessed through this pointer is
+    /// addressable. Stack slots are not instrumented as they don't have red zones.
+    fn asan_check_access(self, fx: &mut FunctionCx<'_, '_, '_>, ty: Type, is_store: bool) {
+        if !fx.tcx.sess.opts.unstable_opts.sanitizer.contains(SanitizerSet::ADDRESS) {
+            return;
+        }
+
+        let kind = if is_store { "store" } else { "load" };
+        let addr = self.get_addr(fx);
+        match ty.bytes() {
+            size @ (1 | 2 | 4 | 8 | 16) => {
+                fx.lib_call(
+                    &format!("__asan_{kind}{size}"),
+                    vec![AbiParam::new(fx.pointer_type)],
+                    vec![],
+                    &[addr],
+                );
+            }
+            size => {
+                let size = fx.bcx.ins().iconst(fx.pointer_type, i64::from(size));
+                fx.lib_call(
+                    &format!("__asan_{kind}N"),
+                    vec![AbiParam::new(fx.pointer_type), AbiParam::new(fx.pointer_type)],
+                    vec![],
+                    &[addr, size],
+                );
+            }
+        }
+    }
 }
@@ -57,6 +57,7 @@
 
 use std::fmt;
 use std::io::Write;
+use std::path::Path;
 
 use cranelift_codegen::entity::SecondaryMap;
 use cranelift_codegen::ir::entities::AnyEntity;
@@ -250,20 +251,51 @@ pub(crate) fn write_clif_file(
         let mut clif = String::new();
         cranelift_codegen::write::decorate_function(&mut clif_comments, &mut clif, func).unwrap();
 
-        for flag in isa.flags().iter() {
-            writeln!(file, "set {}", flag)?;
-        }
-        write!(file, "target {}", isa.triple().architecture)?;
-        for isa_flag in isa.isa_flags().iter() {
-            write!(file, " {}", isa_flag)?;
-        }
-        writeln!(file, "\n")?;
-        writeln!(file)?;
+        file.write_all(clif_header(isa).as_bytes())?;
         file.write_all(clif.as_bytes())?;
         Ok(())
     });
 }
 
+/// The isa flags and target directives at the start of every clif file.
+pub(crate) fn clif_header(isa: &dyn cranelift_codegen::isa::TargetIsa) -> String {
+    use std::fmt::Write;
+
+    let mut header = String::new();
+    for flag in isa.flags().iter() {
+        writeln!(header, "set {}", flag).unwrap();
+    }
+    write!(header, "target {}", isa.triple().architecture).unwrap();
+    for isa_flag in isa.isa_flags().iter() {
+        write!(header, " {}", isa_flag).unwrap();
+    }
+    writeln!(header, "\n").unwrap();
+    writeln!(header).unwrap();
+    header
+}
+
+/// Write the clif ir collected for `CG_CLIF_DUMP_MODULE` and a copy of the object file of the
+/// codegen unit to `dir`.
+pub(crate) fn dump_module(
+    dir: &Path,
+    cgu_name: &str,
+    module_clif: &str,
+    object: &Path,
+) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|err| format!("error creating {}: {}", dir.display(), err))?;
+
+    let clif_file = dir.join(format!("{cgu_name}.clif"));
+    std::fs::write(&clif_file, module_clif)
+        .map_err(|err| format!("error writing {}: {}", clif_file.display(), err))?;
+
+    let object_file = dir.join(format!("{cgu_name}.o"));
+    std::fs::copy(object, &object_file)
+        .map_err(|err| format!("error writing {}: {}", object_file.display(), err))?;
+
+    Ok(())
+}
+
 impl fmt::Debug for FunctionCx<'_, '_, '_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "{:?}", self.instance.args)?;
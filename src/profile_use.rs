@@ -0,0 +1,147 @@
+//! Support for `-Cprofile-use`.
+//!
+//! The indexed profile created by `llvm-profdata merge` is parsed to find the functions which were
+//! never called while profiling. Calls to them are treated like calls to `#[cold]` functions.
+//! Profiles of code compiled by LLVM are only checked to be valid, as the number of calls of a
+//! function can't be read from them directly.
+//!
+//! FIXME use the profile for more than cold calls, for example block ordering.
+
+use std::fs;
+use std::sync::Arc;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_session::Session;
+
+#[derive(Debug)]
+pub(crate) struct ProfileUse {
+    /// The number of calls of every function. `None` if the profile was not produced by cg_clif.
+    call_counts: Option<FxHashMap<String, u64>>,
+}
+
+impl ProfileUse {
+    pub(crate) fn load(sess: &Session) -> Option<Arc<Self>> {
+        let path = sess.opts.cg.profile_use.as_ref()?;
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) => {
+                sess.dcx().fatal(format!("failed to read profile {}: {err}", path.display()))
+            }
+        };
+        match parse_indexed_profile(&data) {
+            Ok(call_counts) => Some(Arc::new(ProfileUse { call_counts })),
+            Err(err) => {
+                sess.dcx().fatal(format!("failed to parse profile {}: {err}", path.display()))
+            }
+        }
+    }
+
+    /// Whether the function was never called while profiling. Functions missing from the profile
+    /// are assumed to be called.
+    pub(crate) fn is_never_called(&self, symbol_name: &str) -> bool {
+        self.call_counts
+            .as_ref()
+            .is_some_and(|call_counts| call_counts.get(symbol_name) == Some(&0))
+    }
+}
+
+/// `\xfflprofi\x81` in little endian.
+const INDEXED_PROFILE_MAGIC: u64 = 0x8169_666f_7270_6cff;
+
+/// Set in the version of profiles produced by LLVM's IR level instrumentation.
+const VARIANT_MASK_IR_PROF: u64 = 1 << 56;
+
+/// Parse an indexed profile as written by `llvm-profdata merge`. Returns the number of calls of
+/// every function if the profile was produced by cg_clif.
+fn parse_indexed_profile(data: &[u8]) -> Result<Option<FxHashMap<String, u64>>, String> {
+    let mut pos = 0;
+    if read_u64(data, &mut pos)? != INDEXED_PROFILE_MAGIC {
+        return Err("not an indexed profile, use `llvm-profdata merge` to create one".to_owned());
+    }
+    let version = read_u64(data, &mut pos)?;
+    // The upper 32 bits contain flags describing the kind of profile.
+    let format_version = version & 0xffff_ffff;
+    if !(3..=12).contains(&format_version) {
+        return Err(format!("unsupported indexed profile version {format_version}"));
+    }
+    let _unused = read_u64(data, &mut pos)?;
+    if read_u64(data, &mut pos)? != 0 {
+        return Err("unsupported hash type".to_owned());
+    }
+    let mut pos = to_usize(read_u64(data, &mut pos)?)?;
+
+    // The functions are stored in an on-disk hash table. Bucket offsets are relative to the start
+    // of the file and every bucket contains a list of functions.
+    let num_buckets = read_u64(data, &mut pos)?;
+    let _num_entries = read_u64(data, &mut pos)?;
+    let mut call_counts = FxHashMap::default();
+    for _ in 0..num_buckets {
+        let mut bucket_pos = to_usize(read_u64(data, &mut pos)?)?;
+        if bucket_pos == 0 {
+            continue;
+        }
+        let num_items =
+            u16::from_le_bytes(read_bytes(data, &mut bucket_pos, 2)?.try_into().unwrap());
+        for _ in 0..num_items {
+            let _name_hash = read_u64(data, &mut bucket_pos)?;
+            let name_len = to_usize(read_u64(data, &mut bucket_pos)?)?;
+            let records_len = to_usize(read_u64(data, &mut bucket_pos)?)?;
+            let name = std::str::from_utf8(read_bytes(data, &mut bucket_pos, name_len)?)
+                .map_err(|_| "function name is not valid UTF-8".to_owned())?;
+            let records = read_bytes(data, &mut bucket_pos, records_len)?;
+            let calls = parse_function_records(records, format_version)?;
+            call_counts.insert(name.to_owned(), calls);
+        }
+    }
+
+    // LLVM's IR level instrumentation places counters on edges of the control flow graph rather
+    // than at the start of the function, so the number of calls can't be read from them.
+    if version & VARIANT_MASK_IR_PROF != 0 { Ok(None) } else { Ok(Some(call_counts)) }
+}
+
+/// Parse the profile records of a single function and sum up the first counter of each. There is
+/// one record for every function hash.
+fn parse_function_records(records: &[u8], format_version: u64) -> Result<u64, String> {
+    let mut pos = 0;
+    let mut calls = 0u64;
+    while pos < records.len() {
+        let _function_hash = read_u64(records, &mut pos)?;
+        let num_counters = read_u64(records, &mut pos)?;
+        for i in 0..num_counters {
+            let counter = read_u64(records, &mut pos)?;
+            if i == 0 {
+                calls = calls.saturating_add(counter);
+            }
+        }
+        if format_version >= 11 {
+            // One u64 for every byte of the MC/DC bitmap.
+            let bitmap_bytes = read_u64(records, &mut pos)?;
+            for _ in 0..bitmap_bytes {
+                read_u64(records, &mut pos)?;
+            }
+        }
+        // The value profile data starts with its total size as u32.
+        let total_size = read_bytes(records, &mut pos.clone(), 4)?;
+        let total_size = u32::from_le_bytes(total_size.try_into().unwrap());
+        read_bytes(records, &mut pos, to_usize(total_size.into())?)?;
+    }
+    Ok(calls)
+}
+
+fn read_bytes<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], String> {
+    let bytes = pos
+        .checked_add(len)
+        .and_then(|end| data.get(*pos..end))
+        .ok_or_else(|| "unexpected end of profile".to_owned())?;
+    *pos += len;
+    Ok(bytes)
+}
+
+/// Indexed profiles are always little endian.
+fn read_u64(data: &[u8], pos: &mut usize) -> Result<u64, String> {
+    Ok(u64::from_le_bytes(read_bytes(data, pos, 8)?.try_into().unwrap()))
+}
+
+fn to_usize(value: u64) -> Result<usize, String> {
+    usize::try_from(value).map_err(|_| "offset out of range in profile".to_owned())
+}
@@ -0,0 +1,105 @@
+//! Compile single functions to machine code without going through the object file and linking
+//! pipeline. This is meant for testing the lowering of individual functions and is only available
+//! with the `testing` feature.
+
+use cranelift_codegen::binemit::{CodeOffset, Reloc};
+use cranelift_module::DataId;
+use rustc_span::Symbol;
+
+use crate::BackendConfig;
+use crate::base::FunctionRelocTarget;
+use crate::debuginfo::TypeDebugContext;
+use crate::prelude::*;
+
+/// The machine code of a single function.
+#[derive(Debug)]
+pub struct CompiledFunction {
+    pub code: Vec<u8>,
+    pub relocs: Vec<CompiledReloc>,
+}
+
+/// A relocation which needs to be applied to [`CompiledFunction::code`].
+#[derive(Debug)]
+pub struct CompiledReloc {
+    pub offset: CodeOffset,
+    pub kind: Reloc,
+    /// Name of the referenced symbol.
+    pub target: String,
+    pub addend: i64,
+}
+
+/// Compile an already built Cranelift function. As there is no module to resolve symbol names,
+/// references to other functions and data objects are named like in the textual CLIF format
+/// (`u<namespace>:<index>`). Like in cg_clif modules namespace 0 is used for functions and
+/// namespace 1 for data objects.
+pub fn compile_clif_function(
+    isa: &dyn isa::TargetIsa,
+    func: Function,
+) -> Result<CompiledFunction, String> {
+    let mut context = Context::for_function(func);
+    context.compile(isa, &mut Default::default()).map_err(|err| format!("{:?}", err.inner))?;
+    Ok(compiled_function(
+        &context,
+        "<function>",
+        |func_id| format!("u0:{}", func_id.as_u32()),
+        |data_id| format!("u1:{}", data_id.as_u32()),
+    ))
+}
+
+/// Codegen and compile `instance` using the codegen options of the current session.
+pub fn compile_instance<'tcx>(tcx: TyCtxt<'tcx>, instance: Instance<'tcx>) -> CompiledFunction {
+    let backend_config = BackendConfig::from_opts(&tcx.sess.opts.cg.llvm_args)
+        .unwrap_or_else(|err| tcx.dcx().fatal(err));
+    let mut module =
+        crate::driver::aot::make_module(tcx.sess, &backend_config, "testing".to_owned());
+    let mut cx =
+        crate::CodegenCx::new(tcx, backend_config, module.isa(), false, Symbol::intern("testing"));
+
+    let symbol_name = tcx.symbol_name(instance).name.to_string();
+    let sig = get_function_sig(tcx, module.target_config().default_call_conv, instance);
+    module.declare_function(&symbol_name, Linkage::Export, &sig).unwrap();
+    let codegened_func = crate::base::codegen_fn(
+        tcx,
+        &mut cx,
+        &mut TypeDebugContext::default(),
+        Function::new(),
+        &mut module,
+        instance,
+    );
+    let mut context = Context::new();
+    crate::base::compile_fn(&mut cx, &mut context, &mut module, codegened_func);
+
+    let declarations = module.declarations();
+    compiled_function(
+        &context,
+        &symbol_name,
+        |func_id| declarations.get_function_decl(func_id).linkage_name(func_id).into_owned(),
+        |data_id| declarations.get_data_decl(data_id).linkage_name(data_id).into_owned(),
+    )
+}
+
+fn compiled_function(
+    context: &Context,
+    symbol_name: &str,
+    func_name: impl Fn(FuncId) -> String,
+    data_name: impl Fn(DataId) -> String,
+) -> CompiledFunction {
+    let relocs = crate::base::function_relocs(context, symbol_name)
+        .into_iter()
+        .map(|reloc| {
+            let (target, addend) = match reloc.target {
+                FunctionRelocTarget::Func(func_id) => (func_name(func_id), reloc.addend),
+                FunctionRelocTarget::Data(data_id) => (data_name(data_id), reloc.addend),
+                FunctionRelocTarget::LibCall(libcall) => {
+                    ((cranelift_module::default_libcall_names())(libcall), reloc.addend)
+                }
+                FunctionRelocTarget::FunctionOffset(offset) => {
+                    (symbol_name.to_owned(), reloc.addend + i64::from(offset))
+                }
+            };
+            CompiledReloc { offset: reloc.offset, kind: reloc.kind, target, addend }
+        })
+        .collect();
+
+    CompiledFunction { code: context.compiled_code().unwrap().code_buffer().to_vec(), relocs }
+}
@@ -155,6 +155,15 @@ pub(crate) fn try_to_ptr(self) -> Option<(Pointer, Option<Value>)> {
         }
     }
 
+    /// Returns the value or the pair of values if this value isn't stored in memory.
+    pub(crate) fn try_to_vals(self) -> Option<(Value, Option<Value>)> {
+        match self.0 {
+            CValueInner::ByRef(_, _) => None,
+            CValueInner::ByVal(val) => Some((val, None)),
+            CValueInner::ByValPair(a, b) => Some((a, Some(b))),
+        }
+    }
+
     /// Load a value with layout.abi of scalar
     #[track_caller]
     pub(crate) fn load_scalar(self, fx: &mut FunctionCx<'_, '_, 'tcx>) -> Value {
@@ -229,7 +238,9 @@ pub(crate) fn value_field(
                 let (field_ptr, field_layout) = codegen_field(fx, ptr, None, layout, field);
                 CValue::by_ref(field_ptr, field_layout)
             }
-            CValueInner::ByRef(_, Some(_)) => todo!(),
+            CValueInner::ByRef(_, Some(_)) => {
+                fx.span_unimplemented("projecting a field of an unsized value")
+            }
         }
     }
 
@@ -689,7 +700,9 @@ fn transmute_scalar<'tcx>(
                             flags,
                         );
                     }
-                    CValueInner::ByRef(_, Some(_)) => todo!(),
+                    CValueInner::ByRef(_, Some(_)) => {
+                        fx.span_unimplemented("copying an unsized value")
+                    }
                 }
             }
         }
@@ -1015,3 +1028,54 @@ pub(crate) fn assert_assignable<'tcx>(
         }
     }
 }
+
+/// Print the layout of `layout.ty` to stderr if it matches the type name requested using the
+/// `print_layout` option. Every type is only printed once per codegen unit.
+pub(crate) fn maybe_print_layout<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    layout: TyAndLayout<'tcx>,
+) {
+    let Some(name) = &fx.cx.print_layout else {
+        return;
+    };
+    let matches = match layout.ty.kind() {
+        ty::Adt(adt_def, _) => fx.tcx.item_name(adt_def.did()).as_str() == name,
+        _ => false,
+    } || layout.ty.to_string() == *name;
+    if !matches || !fx.cx.printed_layouts.insert(layout.ty.to_string()) {
+        return;
+    }
+
+    let mut out = format!(
+        "layout of {}: size={} align={}\n",
+        layout.ty,
+        layout.size.bytes(),
+        layout.align.abi.bytes(),
+    );
+    for i in 0..layout.fields.count() {
+        let field_name = match layout.ty.kind() {
+            ty::Adt(adt_def, _) if adt_def.is_struct() => {
+                adt_def.non_enum_variant().fields[FieldIdx::from_usize(i)].name.to_string()
+            }
+            _ => i.to_string(),
+        };
+        let field_layout = layout.field(fx, i);
+        out.push_str(&format!(
+            "  field {}: {} offset={} size={}\n",
+            field_name,
+            field_layout.ty,
+            layout.fields.offset(i).bytes(),
+            field_layout.size.bytes(),
+        ));
+    }
+    match layout.largest_niche {
+        Some(niche) => out.push_str(&format!(
+            "  niche: offset={} {:?} valid_range={:?}\n",
+            niche.offset.bytes(),
+            niche.value,
+            niche.valid_range,
+        )),
+        None => out.push_str("  niche: none\n"),
+    }
+    eprint!("{}", out);
+}